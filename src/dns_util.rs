@@ -1 +1,10 @@
-pub mod dns_packet_structures;
\ No newline at end of file
+pub mod dns_packet_structures;
+pub mod dns_transports;
+pub mod dns_resolver;
+pub mod dns_lookup;
+pub mod dns_iterative;
+pub mod dns_cache;
+pub mod dns_dnssec;
+pub mod dns_mail;
+#[cfg(test)]
+pub(crate) mod test_server;
@@ -0,0 +1,616 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::dns_util::dns_packet_structures::dns_header::DNSHeader;
+use crate::dns_util::dns_packet_structures::dns_packet::DNSPacket;
+use crate::dns_util::dns_packet_structures::dns_question::DNSQuestion;
+use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::util::parse_query_type;
+use crate::dns_util::dns_lookup::cname_chain::ResolvedChain;
+
+const CNAME: u16 = 5;
+const SOA: u16 = 6;
+const OPT: u16 = 41;
+
+/// Largest number of prefetches waiting for the worker, the others being
+/// dropped
+const MAX_PENDING_PREFETCHES: usize = 64;
+
+/// Name, type and class of an RRset
+type CacheKey = (DomainName, u16, u16);
+
+/// Refresh of cached answers, run by the prefetch worker
+pub(crate) type PrefetchJob = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug, Clone)]
+enum CachedData {
+    /// Records of the RRset, all with the TTL of the entry
+    Records(Vec<DNSResourceRecord>),
+    /// The name exists without records of this type, with the SOA record
+    /// of its zone (RFC 2308 section 2.2)
+    NoData(DNSResourceRecord),
+    /// The name does not exist, with the SOA record of its zone
+    NxDomain(DNSResourceRecord),
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    data: CachedData,
+    stored_at: Instant,
+    ttl: u32,
+    /// Position in the usage order, higher is more recent
+    last_used: u64,
+    /// Number of lookups answered with the entry
+    hits: u32,
+    /// A refresh of the entry has been started
+    prefetching: bool,
+}
+
+/// How an answer of the cache can be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Freshness {
+    Fresh,
+    /// Still valid, but should be refreshed soon
+    Prefetch,
+    /// Expired, only to be used when the nameservers fail
+    Stale,
+}
+
+impl CacheEntry {
+    /// Seconds left before expiry, `None` once expired
+    fn remaining_ttl(&self, now: Instant) -> Option<u32> {
+        let elapsed = now.saturating_duration_since(self.stored_at);
+        Duration::from_secs(self.ttl as u64).checked_sub(elapsed)
+            .filter(|remaining| !remaining.is_zero())
+            .map(|remaining| remaining.as_secs() as u32)
+    }
+
+    /// Records of the entry with their TTL lowered by the time spent in
+    /// the cache
+    fn aged_records(&self, remaining_ttl: u32) -> Vec<DNSResourceRecord> {
+        let records = match &self.data {
+            CachedData::Records(records) => records.clone(),
+            CachedData::NoData(soa) | CachedData::NxDomain(soa) => vec![soa.clone()],
+        };
+        records.into_iter()
+            .map(|mut record| {
+                record.record_ttl = remaining_ttl as i32;
+                record
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Keys by last use, the least recently used first
+    usage: BTreeMap<u64, CacheKey>,
+    clock: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, key: &CacheKey) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.usage.remove(&entry.last_used);
+            entry.last_used = self.clock;
+            self.usage.insert(self.clock, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.usage.remove(&entry.last_used);
+        }
+    }
+}
+
+/// Thread-safe cache of DNS answers. RRsets are kept for their TTL and
+/// negative answers for the time given by the SOA record of the zone
+/// (RFC 2308), plus `stale_window`. Once full, the least recently used
+/// entry makes room for the new one.
+#[derive(Debug)]
+pub struct DNSCache {
+    /// Largest number of RRsets and negative answers kept
+    pub capacity: usize,
+    /// Lower bound of the TTLs, in seconds
+    pub min_ttl: u32,
+    /// Upper bound of the TTLs, in seconds
+    pub max_ttl: u32,
+    /// Upper bound of the TTLs of negative answers, in seconds
+    pub max_negative_ttl: u32,
+    /// Time during which expired entries can still be served by
+    /// `lookup_stale`, in seconds. 0 disables serve-stale.
+    pub stale_window: u32,
+    /// TTL given to the records of stale answers, in seconds
+    pub stale_answer_ttl: u32,
+    /// Refresh popular entries in the background before they expire, one at
+    /// a time on a worker thread of the cache
+    pub prefetch: bool,
+    /// Number of uses from which an entry is popular enough to prefetch
+    pub prefetch_min_hits: u32,
+    state: Mutex<CacheState>,
+    /// Queue of the thread running the prefetches, started by the first one
+    prefetcher: Mutex<Option<SyncSender<PrefetchJob>>>,
+}
+
+impl Default for DNSCache {
+    fn default() -> Self {
+        Self::new(4096)
+    }
+}
+
+impl DNSCache {
+    /// Cache of at most `capacity` entries, keeping records up to a day and
+    /// negative answers up to three hours (RFC 2308 section 5)
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            min_ttl: 0,
+            max_ttl: 86400,
+            max_negative_ttl: 10800,
+            stale_window: 0,
+            stale_answer_ttl: 30,
+            prefetch: false,
+            prefetch_min_hits: 2,
+            state: Mutex::new(CacheState::default()),
+            prefetcher: Mutex::new(None),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        *self.lock() = CacheState::default();
+    }
+
+    /// Stores the RRsets of the answer section of `response` that lie on the
+    /// alias chain of its question, or the negative answer it carries.
+    /// Truncated responses and responses with an error other than NXDOMAIN
+    /// are ignored.
+    pub fn insert(&self, response: &DNSPacket) {
+        self.insert_at(response, Instant::now());
+    }
+
+    /// Answer to `domain` and `query_type` built from the cache, following
+    /// cached CNAMEs, with TTLs lowered by the time spent in the cache.
+    /// `None` unless the whole answer is cached.
+    pub fn lookup(&self, domain: &str, query_type: &str) -> Option<DNSPacket> {
+        self.lookup_at(domain, query_type, Instant::now(), false).map(|(response, _)| response)
+    }
+
+    /// Same as `lookup`, also answering with entries expired for less than
+    /// `stale_window`, whose records get `stale_answer_ttl` (RFC 8767).
+    /// Meant for when the nameservers cannot be reached.
+    pub fn lookup_stale(&self, domain: &str, query_type: &str) -> Option<DNSPacket> {
+        self.lookup_at(domain, query_type, Instant::now(), true).map(|(response, _)| response)
+    }
+
+    /// Same as `lookup`, also telling whether the answer should be
+    /// prefetched. An entry is only reported once for prefetch.
+    pub(crate) fn lookup_with_freshness(&self, domain: &str, query_type: &str) -> Option<(DNSPacket, Freshness)> {
+        self.lookup_at(domain, query_type, Instant::now(), false)
+    }
+
+    /// Queues `job` for the prefetch worker, all the prefetches of the cache
+    /// running one after the other. Returns false if the queue is full, in
+    /// which case the job is dropped.
+    pub(crate) fn schedule_prefetch(&self, job: PrefetchJob) -> bool {
+        let mut prefetcher = self.prefetcher.lock().unwrap_or_else(|e| e.into_inner());
+        let sender = prefetcher.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::sync_channel::<PrefetchJob>(MAX_PENDING_PREFETCHES);
+            // Stops once the cache, and so the sender, is dropped
+            thread::spawn(move || {
+                for job in receiver {
+                    job();
+                }
+            });
+            sender
+        });
+        match sender.try_send(job) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => false,
+            // The worker died with a job, the next prefetch starts another
+            Err(TrySendError::Disconnected(_)) => {
+                *prefetcher = None;
+                false
+            }
+        }
+    }
+
+    /// Lets the entries answering `domain` and `query_type` be prefetched
+    /// again, once their refresh succeeded or failed
+    pub(crate) fn finish_prefetch(&self, domain: &str, query_type: &str) {
+        let query_type = parse_query_type(query_type);
+        let Ok(mut name) = domain.parse::<DomainName>() else {
+            return;
+        };
+        let mut state = self.lock();
+        for _i in 0..16 {
+            if let Some(entry) = state.entries.get_mut(&(name.clone(), query_type, 1)) {
+                entry.prefetching = false;
+                return;
+            }
+            let Some(entry) = state.entries.get_mut(&(name.clone(), CNAME, 1)) else {
+                return;
+            };
+            entry.prefetching = false;
+            let CachedData::Records(records) = &entry.data else {
+                return;
+            };
+            let Some(target) = records.first().and_then(|record| DomainName::from_labels(&record.rdata)) else {
+                return;
+            };
+            name = target;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn insert_at(&self, response: &DNSPacket, now: Instant) {
+        let response_code = response.header.flags & 0b1111;
+        // Truncation flag
+        if response.header.flags >> 9 & 1 == 1 || !matches!(response_code, 0 | 3) {
+            return;
+        }
+
+        let Some(question) = response.questions.first() else {
+            return;
+        };
+        let Some(name) = DomainName::from_labels(&question.query_name) else {
+            return;
+        };
+        // Only the RRsets of the alias chain starting at the question are
+        // kept: a server may add records for names it has no authority on
+        let mut chain = ResolvedChain::starting_at(name);
+        let Ok(complete) = chain.follow(response, question.query_type, 16) else {
+            return;
+        };
+        let chain_keys: Vec<CacheKey> = chain.links.iter().chain(&chain.records)
+            .filter_map(|record| Some((DomainName::from_labels(&record.query_name)?, record.query_type, record.query_class)))
+            .collect();
+
+        let mut rrsets: Vec<(CacheKey, Vec<DNSResourceRecord>)> = Vec::new();
+        for record in response.resource_records.iter().filter(|record| record.query_type != OPT) {
+            let Some(owner) = DomainName::from_labels(&record.query_name) else {
+                continue;
+            };
+            let key = (owner, record.query_type, record.query_class);
+            if !chain_keys.contains(&key) {
+                continue;
+            }
+            match rrsets.iter_mut().find(|(candidate, _)| *candidate == key) {
+                Some((_, records)) => records.push(record.clone()),
+                None => rrsets.push((key, vec![record.clone()])),
+            }
+        }
+
+        let mut state = self.lock();
+        for (key, mut records) in rrsets {
+            // RRsets have a single TTL, the smallest one is kept
+            let ttl = records.iter().map(|record| record.record_ttl.max(0) as u32).min().unwrap_or(0);
+            let ttl = ttl.clamp(self.min_ttl, self.max_ttl.max(self.min_ttl));
+            for record in &mut records {
+                record.record_ttl = ttl as i32;
+            }
+            self.store(&mut state, key, CachedData::Records(records), ttl, now);
+        }
+
+        // Negative answer for the name at the end of the CNAME chain
+        if complete {
+            return;
+        }
+        // Without SOA record the negative answer must not be cached
+        // (RFC 2308 section 5)
+        let Some(soa) = response.authority_records.iter().find(|record| record.query_type == SOA) else {
+            return;
+        };
+        let Some(ttl) = negative_ttl(soa) else {
+            return;
+        };
+        let ttl = ttl.clamp(self.min_ttl, self.max_negative_ttl.max(self.min_ttl));
+        let mut soa = soa.clone();
+        soa.record_ttl = ttl as i32;
+        let data = if response_code == 3 { CachedData::NxDomain(soa) } else { CachedData::NoData(soa) };
+        self.store(&mut state, (chain.canonical_name, question.query_type, question.query_class), data, ttl, now);
+    }
+
+    fn store(&self, state: &mut CacheState, key: CacheKey, data: CachedData, ttl: u32, now: Instant) {
+        if self.capacity == 0 || ttl == 0 {
+            return;
+        }
+        state.remove(&key);
+        while state.entries.len() >= self.capacity {
+            let Some((_, oldest)) = state.usage.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+        state.entries.insert(key.clone(), CacheEntry {
+            data,
+            stored_at: now,
+            ttl,
+            last_used: 0,
+            hits: 0,
+            prefetching: false,
+        });
+        state.touch(&key);
+    }
+
+    fn lookup_at(&self, domain: &str, query_type: &str, now: Instant, allow_stale: bool) -> Option<(DNSPacket, Freshness)> {
+        let query_type = parse_query_type(query_type);
+        let mut name: DomainName = domain.parse().ok()?;
+        let mut answers: Vec<DNSResourceRecord> = Vec::new();
+        let mut freshness = Freshness::Fresh;
+        let mut state = self.lock();
+
+        for _i in 0..16 {
+            // Records of the type asked, or a CNAME to follow
+            let mut candidates = vec![(name.clone(), query_type, 1)];
+            if query_type != CNAME {
+                candidates.push((name.clone(), CNAME, 1));
+            }
+            let mut found: Option<(CacheKey, Freshness, u32)> = None;
+            for key in candidates {
+                let Some(entry) = state.entries.get_mut(&key) else {
+                    continue;
+                };
+                if let Some(remaining_ttl) = entry.remaining_ttl(now) {
+                    entry.hits += 1;
+                    // Refreshed once in the last tenth of its TTL, if used
+                    // often enough
+                    let prefetch = self.prefetch && !entry.prefetching
+                        && entry.hits >= self.prefetch_min_hits
+                        && remaining_ttl as u64 * 10 <= entry.ttl as u64;
+                    entry.prefetching |= prefetch;
+                    let entry_freshness = if prefetch { Freshness::Prefetch } else { Freshness::Fresh };
+                    found = Some((key, entry_freshness, remaining_ttl));
+                    break;
+                }
+                let stale_until = entry.stored_at + Duration::from_secs(entry.ttl as u64 + self.stale_window as u64);
+                if now >= stale_until {
+                    state.remove(&key);
+                } else if allow_stale {
+                    found = Some((key, Freshness::Stale, self.stale_answer_ttl));
+                    break;
+                }
+            }
+            let (key, entry_freshness, remaining_ttl) = found?;
+            freshness = freshness.max(entry_freshness);
+            state.touch(&key);
+            let entry = state.entries.get(&key)?;
+            let records = entry.aged_records(remaining_ttl);
+
+            let (response_code, authority) = match &entry.data {
+                CachedData::Records(_) if key.1 == CNAME && query_type != CNAME => {
+                    name = DomainName::from_labels(&records.first()?.rdata)?;
+                    answers.extend(records);
+                    continue;
+                }
+                CachedData::Records(_) => {
+                    answers.extend(records);
+                    (0, Vec::new())
+                }
+                CachedData::NoData(_) => (0, records),
+                CachedData::NxDomain(_) => (3, records),
+            };
+            return Some((cached_response(domain, query_type, response_code, answers, authority), freshness));
+        }
+        None
+    }
+}
+
+/// Time to cache a negative answer: the smaller of the TTL of the SOA
+/// record and its MINIMUM field (RFC 2308 section 5)
+fn negative_ttl(soa: &DNSResourceRecord) -> Option<u32> {
+    let minimum = soa.rdata.get(soa.rdata.len().checked_sub(4)?..)?;
+    let minimum = u32::from_be_bytes(minimum.try_into().ok()?);
+    Some(minimum.min(soa.record_ttl.max(0) as u32))
+}
+
+/// Response to a query of `domain`, as a server would send it
+fn cached_response(domain: &str, query_type: u16, response_code: u16, answers: Vec<DNSResourceRecord>, authority: Vec<DNSResourceRecord>) -> DNSPacket {
+    let mut header = DNSHeader::create_query_header(1);
+    // Response with recursion available
+    header.flags |= 0b1000_0000_1000_0000 | response_code;
+    header.answers_count = answers.len() as u16;
+    header.authority_count = authority.len() as u16;
+    DNSPacket {
+        header,
+        questions: vec![DNSQuestion::create_question(domain, query_type)],
+        resource_records: answers,
+        authority_records: authority,
+        additional_records: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(owner: &str, record_type: u16, ttl: i32, rdata: Vec<u8>) -> DNSResourceRecord {
+        DNSResourceRecord {
+            query_name: owner.parse::<DomainName>().unwrap().to_labels(),
+            query_type: record_type,
+            query_class: 1,
+            record_ttl: ttl,
+            rdata_length: rdata.len() as u16,
+            rdata,
+        }
+    }
+
+    fn soa(ttl: i32, minimum: u32) -> DNSResourceRecord {
+        let mut rdata = "ns.example.test".parse::<DomainName>().unwrap().to_labels();
+        rdata.extend("admin.example.test".parse::<DomainName>().unwrap().to_labels());
+        rdata.extend([0; 16]);
+        rdata.extend(minimum.to_be_bytes());
+        record("example.test", SOA, ttl, rdata)
+    }
+
+    fn cached(cache: &DNSCache, domain: &str, query_type: &str, now: Instant) -> Option<DNSPacket> {
+        cache.lookup_at(domain, query_type, now, false).map(|(response, _)| response)
+    }
+
+    fn response(domain: &str, query_type: &str, response_code: u16, answers: Vec<DNSResourceRecord>, authority: Vec<DNSResourceRecord>) -> DNSPacket {
+        cached_response(domain, parse_query_type(query_type), response_code, answers, authority)
+    }
+
+    #[test]
+    fn test_ttl_expiry_and_decrement() {
+        let cache = DNSCache::new(16);
+        let now = Instant::now();
+        cache.insert_at(&response("www.example.test", "A", 0, vec![
+            record("www.example.test", 5, 300, "host.example.test".parse::<DomainName>().unwrap().to_labels()),
+            record("host.example.test", 1, 60, vec![192, 0, 2, 1]),
+            record("host.example.test", 1, 120, vec![192, 0, 2, 2]),
+        ], vec![]), now);
+        assert_eq!(cache.len(), 2);
+
+        let answer = cached(&cache, "WWW.example.test", "A", now + Duration::from_secs(10)).unwrap();
+        assert_eq!(answer.resource_records.len(), 3);
+        assert_eq!(answer.resource_records[0].record_ttl, 290);
+        assert_eq!(answer.resource_records[1].record_ttl, 50);
+        assert_eq!(answer.resource_records[2].record_ttl, 50);
+
+        assert!(cached(&cache, "www.example.test", "A", now + Duration::from_secs(60)).is_none());
+        assert!(cached(&cache, "www.example.test", "CNAME", now + Duration::from_secs(60)).is_some());
+        assert!(cached(&cache, "www.example.test", "AAAA", now).is_none());
+    }
+
+    #[test]
+    fn test_unrelated_rrsets_not_cached() {
+        let cache = DNSCache::new(16);
+        let now = Instant::now();
+        cache.insert_at(&response("evil.test", "A", 0, vec![
+            record("evil.test", 5, 300, "www.evil.test".parse::<DomainName>().unwrap().to_labels()),
+            record("www.evil.test", 1, 300, vec![192, 0, 2, 1]),
+            record("bank.test", 1, 300, vec![192, 0, 2, 66]),
+            record("www.evil.test", 28, 300, vec![0; 16]),
+        ], vec![]), now);
+        assert_eq!(cache.len(), 2);
+        assert!(cached(&cache, "evil.test", "A", now).is_some());
+        assert!(cached(&cache, "bank.test", "A", now).is_none());
+        assert!(cached(&cache, "www.evil.test", "AAAA", now).is_none());
+    }
+
+    #[test]
+    fn test_negative_caching() {
+        let cache = DNSCache::new(16);
+        let now = Instant::now();
+        cache.insert_at(&response("missing.example.test", "A", 3, vec![], vec![soa(3600, 300)]), now);
+        cache.insert_at(&response("www.example.test", "AAAA", 0, vec![], vec![soa(60, 300)]), now);
+        // No SOA, not cached
+        cache.insert_at(&response("other.example.test", "A", 3, vec![], vec![]), now);
+        assert_eq!(cache.len(), 2);
+
+        let answer = cached(&cache, "missing.example.test", "A", now + Duration::from_secs(100)).unwrap();
+        assert_eq!(answer.header.flags & 0b1111, 3);
+        assert_eq!(answer.authority_records[0].record_ttl, 200);
+        assert!(cached(&cache, "missing.example.test", "A", now + Duration::from_secs(300)).is_none());
+
+        let answer = cached(&cache, "www.example.test", "AAAA", now).unwrap();
+        assert_eq!(answer.header.flags & 0b1111, 0);
+        assert!(answer.resource_records.is_empty());
+        assert_eq!(answer.authority_records[0].record_ttl, 60);
+    }
+
+    #[test]
+    fn test_ttl_clamping() {
+        let mut cache = DNSCache::new(16);
+        cache.min_ttl = 30;
+        cache.max_ttl = 100;
+        let now = Instant::now();
+        cache.insert_at(&response("a.test", "A", 0, vec![record("a.test", 1, 5, vec![192, 0, 2, 1])], vec![]), now);
+        cache.insert_at(&response("b.test", "A", 0, vec![record("b.test", 1, 5000, vec![192, 0, 2, 2])], vec![]), now);
+        assert_eq!(cached(&cache, "a.test", "A", now).unwrap().resource_records[0].record_ttl, 30);
+        assert_eq!(cached(&cache, "b.test", "A", now).unwrap().resource_records[0].record_ttl, 100);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = DNSCache::new(2);
+        let now = Instant::now();
+        for name in ["a.test", "b.test"] {
+            cache.insert_at(&response(name, "A", 0, vec![record(name, 1, 60, vec![192, 0, 2, 1])], vec![]), now);
+        }
+        // a.test becomes the most recently used
+        assert!(cached(&cache, "a.test", "A", now).is_some());
+        cache.insert_at(&response("c.test", "A", 0, vec![record("c.test", 1, 60, vec![192, 0, 2, 3])], vec![]), now);
+        assert_eq!(cache.len(), 2);
+        assert!(cached(&cache, "a.test", "A", now).is_some());
+        assert!(cached(&cache, "b.test", "A", now).is_none());
+        assert!(cached(&cache, "c.test", "A", now).is_some());
+    }
+
+    #[test]
+    fn test_serve_stale() {
+        let mut cache = DNSCache::new(16);
+        cache.stale_window = 3600;
+        let now = Instant::now();
+        cache.insert_at(&response("a.test", "A", 0, vec![record("a.test", 1, 60, vec![192, 0, 2, 1])], vec![]), now);
+
+        let later = now + Duration::from_secs(120);
+        assert!(cached(&cache, "a.test", "A", later).is_none());
+        let (stale, freshness) = cache.lookup_at("a.test", "A", later, true).unwrap();
+        assert_eq!(freshness, Freshness::Stale);
+        assert_eq!(stale.resource_records[0].record_ttl, 30);
+        // Dropped once out of the stale window
+        assert!(cache.lookup_at("a.test", "A", now + Duration::from_secs(3700), true).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_prefetch() {
+        let mut cache = DNSCache::new(16);
+        cache.prefetch = true;
+        let now = Instant::now();
+        cache.insert_at(&response("a.test", "A", 0, vec![record("a.test", 1, 100, vec![192, 0, 2, 1])], vec![]), now);
+
+        let near_expiry = now + Duration::from_secs(95);
+        // Popular enough from the second use, and reported only once
+        assert_eq!(cache.lookup_at("a.test", "A", near_expiry, false).unwrap().1, Freshness::Fresh);
+        assert_eq!(cache.lookup_at("a.test", "A", near_expiry, false).unwrap().1, Freshness::Prefetch);
+        assert_eq!(cache.lookup_at("a.test", "A", near_expiry, false).unwrap().1, Freshness::Fresh);
+        // Reported again once the refresh failed
+        cache.finish_prefetch("a.test", "A");
+        assert_eq!(cache.lookup_at("a.test", "A", near_expiry, false).unwrap().1, Freshness::Prefetch);
+    }
+
+    #[test]
+    fn test_prefetch_worker() {
+        let cache = DNSCache::new(16);
+        let (release, blocked) = mpsc::channel::<()>();
+        let (sender, threads) = mpsc::channel();
+        let blocking_sender = sender.clone();
+        assert!(cache.schedule_prefetch(Box::new(move || {
+            let _ = blocked.recv();
+            blocking_sender.send(thread::current().id()).unwrap();
+        })));
+        // The queue is bounded while the worker is busy
+        let scheduled = (0..MAX_PENDING_PREFETCHES + 1)
+            .filter(|_i| {
+                let sender = sender.clone();
+                cache.schedule_prefetch(Box::new(move || sender.send(thread::current().id()).unwrap()))
+            })
+            .count();
+        assert!(scheduled <= MAX_PENDING_PREFETCHES);
+        release.send(()).unwrap();
+
+        // All the jobs run on the same thread
+        let worker = threads.recv().unwrap();
+        assert!(threads.iter().take(scheduled).all(|thread| thread == worker));
+    }
+}
@@ -0,0 +1,27 @@
+pub mod anchor_tracking;
+pub mod key_files;
+pub mod key_management;
+pub mod records;
+pub mod signatures;
+pub mod signing;
+pub mod trust_anchors;
+pub mod validation;
+pub mod zone_signing;
+mod denial;
+
+pub use records::{
+    DNSKeyRecord,
+    RRSIGRecord,
+    DSRecord,
+    NSECRecord,
+    NSEC3Record,
+    NSEC3ParamRecord,
+    nsec3_hash,
+};
+pub use trust_anchors::TrustAnchors;
+pub use anchor_tracking::{AnchorTracker, AnchorState, TrackedKey};
+pub use validation::{Validator, ValidationStatus, ValidatedResponse};
+pub use signing::SigningKey;
+pub use key_files::{KeyFile, KeyTimings};
+pub use key_management::{KeyManager, KeyPolicy, KeyEvent};
+pub use zone_signing::{ZoneSigner, DenialOfExistence, zone_file};
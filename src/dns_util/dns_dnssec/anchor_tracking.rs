@@ -0,0 +1,512 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::dns_util::dns_dnssec::key_files::write_atomically;
+use crate::dns_util::dns_dnssec::records::{format_timestamp, DNSKeyRecord, DSRecord, RRSIGRecord};
+use crate::dns_util::dns_dnssec::signatures::verify_signature;
+use crate::dns_util::dns_dnssec::trust_anchors::TrustAnchors;
+use crate::dns_util::dns_dnssec::validation::{ds_matches, in_validity_period};
+use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+use crate::dns_util::dns_resolver::{Resolver, ResolverConfig};
+
+const HOUR: u32 = 3600;
+const DAY: u32 = 86400;
+
+/// State of a key of a trust point (RFC 5011 section 4.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnchorState {
+    /// New key of the zone, trusted once the add hold-down is over
+    AddPending,
+    /// Trusted key
+    Valid,
+    /// Trusted key no longer published by the zone
+    Missing,
+    /// Key revoked by the zone, until the remove hold-down is over
+    Revoked,
+    /// Revoked key, never trusted again
+    Removed,
+}
+
+impl AnchorState {
+    /// Number of the state in the files of Unbound
+    fn number(self) -> u8 {
+        match self {
+            AnchorState::AddPending => 1,
+            AnchorState::Valid => 2,
+            AnchorState::Missing => 3,
+            AnchorState::Revoked => 4,
+            AnchorState::Removed => 5,
+        }
+    }
+
+    /// Name of the state in the files of Unbound, padded as it writes it
+    fn name(self) -> &'static str {
+        match self {
+            AnchorState::AddPending => " ADDPEND ",
+            AnchorState::Valid => "  VALID  ",
+            AnchorState::Missing => " MISSING ",
+            AnchorState::Revoked => " REVOKED ",
+            AnchorState::Removed => " REMOVED ",
+        }
+    }
+
+    fn from_number(number: u8) -> Option<Self> {
+        [AnchorState::AddPending, AnchorState::Valid, AnchorState::Missing, AnchorState::Revoked, AnchorState::Removed]
+            .into_iter()
+            .find(|state| state.number() == number)
+    }
+
+    fn is_trusted(self) -> bool {
+        matches!(self, AnchorState::Valid | AnchorState::Missing)
+    }
+}
+
+/// Key of a trust point with its state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackedKey {
+    /// The key as last published, with the REVOKE flag once revoked
+    pub key: DNSKeyRecord,
+    pub state: AnchorState,
+    /// Time of the last change of state, in seconds since 1970
+    pub last_change: u32,
+}
+
+/// Trust anchors of a zone kept up to date with the keys it publishes
+/// (RFC 5011). The state is stored in a file in the format of the
+/// `auto-trust-anchor-file` of Unbound.
+pub struct AnchorTracker {
+    pub zone: DomainName,
+    pub path: PathBuf,
+    pub keys: Vec<TrackedKey>,
+    /// DS records of the first keys, used until these keys are seen
+    pub ds: Vec<DSRecord>,
+    /// Time a new key must stay published before being trusted
+    pub add_hold_down: Duration,
+    /// Time a revoked key is kept before being removed
+    pub remove_hold_down: Duration,
+    /// Time of the last successful refresh, in seconds since 1970
+    pub last_success: Option<u32>,
+    /// Time until the next refresh after a successful one (RFC 5011
+    /// section 2.3)
+    pub query_interval: Duration,
+    /// Time until the next refresh after a failed one
+    pub retry_interval: Duration,
+}
+
+impl AnchorTracker {
+    /// Loads the state of the trust point `zone` from the file `path`.
+    /// Keys without state, as in the anchor files of other resolvers, are
+    /// valid. When the file does not exist, the tracking starts from the
+    /// anchors of `zone` in `initial`, such as `TrustAnchors::root()`.
+    pub fn open(path: impl Into<PathBuf>, zone: DomainName, initial: &TrustAnchors) -> io::Result<Self> {
+        let path = path.into();
+        let mut tracker = Self {
+            zone,
+            path,
+            keys: Vec::new(),
+            ds: Vec::new(),
+            add_hold_down: Duration::from_secs(30 * DAY as u64),
+            remove_hold_down: Duration::from_secs(30 * DAY as u64),
+            last_success: None,
+            query_interval: Duration::from_secs(HOUR as u64),
+            retry_interval: Duration::from_secs(HOUR as u64),
+        };
+        match fs::read_to_string(&tracker.path) {
+            Ok(text) => tracker.parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                tracker.ds = initial.ds_of(&tracker.zone).into_iter().cloned().collect();
+                tracker.keys = initial.keys_of(&tracker.zone).into_iter()
+                    .map(|key| TrackedKey { key: key.clone(), state: AnchorState::Valid, last_change: now() })
+                    .collect();
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(tracker)
+    }
+
+    /// Anchors to validate with: the valid and missing keys, or the DS
+    /// records before the first keys are seen
+    pub fn trust_anchors(&self) -> TrustAnchors {
+        let keys: Vec<(DomainName, DNSKeyRecord)> = self.keys.iter()
+            .filter(|key| key.state.is_trusted())
+            .map(|key| (self.zone.clone(), key.key.clone()))
+            .collect();
+        let ds = if keys.is_empty() {
+            self.ds.iter().map(|ds| (self.zone.clone(), ds.clone())).collect()
+        } else {
+            Vec::new()
+        };
+        TrustAnchors { ds, keys }
+    }
+
+    /// Fetches the DNSKEY RRset of the zone with the configuration of
+    /// `resolver`, the DO bit being set, updates the states of the keys and
+    /// saves them. The cache of `resolver` is not used, neither its fresh
+    /// nor its stale answers. Returns the time until the next refresh. On
+    /// error, the caller schedules the next attempt after `retry_interval`.
+    pub fn refresh(&mut self, resolver: &Resolver) -> io::Result<Duration> {
+        let config = ResolverConfig { dnssec_ok: true, ..resolver.config.clone() };
+        let response = Resolver::new(config).query(&self.zone.to_string(), "DNSKEY")?;
+        let updated = self.update_at(&response.resource_records, now());
+        self.save()?;
+        updated.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(self.query_interval)
+    }
+
+    /// Applies the transitions of RFC 5011 section 4 for the DNSKEY RRset
+    /// in `records`, seen at `now`
+    fn update_at(&mut self, records: &[DNSResourceRecord], now: u32) -> Result<(), String> {
+        let is_zone_record = |record: &&DNSResourceRecord| DomainName::from_labels(&record.query_name).is_some_and(|owner| owner == self.zone);
+        let rrset: Vec<&DNSResourceRecord> = records.iter()
+            .filter(is_zone_record)
+            .filter(|record| record.query_type == DNSKeyRecord::RECORD_TYPE)
+            .collect();
+        if rrset.is_empty() {
+            return Err(format!("no DNSKEY records for {}", self.zone));
+        }
+        let signatures: Vec<RRSIGRecord> = records.iter()
+            .filter(is_zone_record)
+            .filter(|record| record.query_type == RRSIGRecord::RECORD_TYPE)
+            .filter_map(|record| record.record_data::<RRSIGRecord>().ok())
+            .filter(|signature| signature.type_covered == DNSKeyRecord::RECORD_TYPE && in_validity_period(signature, now))
+            .collect();
+        let signed_by = |key: &DNSKeyRecord| signatures.iter().any(|signature| {
+            signature.key_tag == key.key_tag() && verify_signature(signature, key, &rrset)
+        });
+        let published: Vec<DNSKeyRecord> = rrset.iter()
+            .filter_map(|record| record.record_data::<DNSKeyRecord>().ok())
+            .filter(|key| key.is_zone_key() && key.is_secure_entry_point())
+            .collect();
+
+        // A revocation is accepted when the revoked key signs the RRset
+        // (RFC 5011 section 2.1), whoever else signed it
+        for revoked in published.iter().filter(|key| key.is_revoked() && signed_by(key)) {
+            if let Some(i) = self.keys.iter().position(|tracked| same_key(&tracked.key, revoked)) {
+                match self.keys[i].state {
+                    AnchorState::AddPending => {
+                        self.keys.remove(i);
+                    }
+                    AnchorState::Valid | AnchorState::Missing => {
+                        self.keys[i] = TrackedKey { key: revoked.clone(), state: AnchorState::Revoked, last_change: now };
+                    }
+                    AnchorState::Revoked | AnchorState::Removed => {}
+                }
+            }
+        }
+        self.expire_revoked(now);
+
+        let trusted = if self.keys.iter().any(|tracked| tracked.state.is_trusted()) {
+            self.keys.iter().any(|tracked| tracked.state.is_trusted() && signed_by(&tracked.key))
+        } else {
+            // First keys, matching the DS records
+            let first: Vec<&DNSKeyRecord> = published.iter()
+                .filter(|key| !key.is_revoked() && self.ds.iter().any(|ds| ds_matches(ds, &self.zone, key)))
+                .collect();
+            let trusted = first.iter().any(|key| signed_by(key));
+            if trusted {
+                self.keys.extend(first.into_iter().map(|key| TrackedKey { key: key.clone(), state: AnchorState::Valid, last_change: now }));
+                self.ds.clear();
+            }
+            trusted
+        };
+        if !trusted {
+            return Err(format!("DNSKEY RRset of {} not signed by a trust anchor", self.zone));
+        }
+
+        // A new key stays pending at least as long as the RRset without it
+        // may be cached (RFC 5011 section 2.4.1)
+        let ttl = rrset.iter().map(|record| record.record_ttl.max(0) as u32).min().unwrap_or(0);
+        let add_hold_down = (self.add_hold_down.as_secs() as u32).max(ttl);
+        for key in published.iter().filter(|key| !key.is_revoked()) {
+            match self.keys.iter_mut().find(|tracked| same_key(&tracked.key, key)) {
+                None => self.keys.push(TrackedKey { key: key.clone(), state: AnchorState::AddPending, last_change: now }),
+                Some(tracked) => {
+                    let ready = tracked.state == AnchorState::AddPending && now.saturating_sub(tracked.last_change) >= add_hold_down;
+                    if ready || tracked.state == AnchorState::Missing {
+                        tracked.state = AnchorState::Valid;
+                        tracked.last_change = now;
+                    }
+                }
+            }
+        }
+        // Keys no longer published
+        self.keys.retain(|tracked| tracked.state != AnchorState::AddPending || published.iter().any(|key| same_key(&tracked.key, key)));
+        for tracked in self.keys.iter_mut().filter(|tracked| tracked.state == AnchorState::Valid) {
+            if !published.iter().any(|key| same_key(&tracked.key, key)) {
+                tracked.state = AnchorState::Missing;
+                tracked.last_change = now;
+            }
+        }
+
+        // Refresh intervals from the TTL and the signatures expiration
+        let expiration = signatures.iter().map(|signature| signature.signature_expiration.wrapping_sub(now)).min().unwrap_or(0);
+        self.query_interval = Duration::from_secs(HOUR.max((15 * DAY).min(ttl / 2).min(expiration / 2)) as u64);
+        self.retry_interval = Duration::from_secs(HOUR.max(DAY.min(ttl / 10).min(expiration / 10)) as u64);
+        self.last_success = Some(now);
+        Ok(())
+    }
+
+    /// Revoked keys past the remove hold-down are removed
+    fn expire_revoked(&mut self, now: u32) {
+        let remove_hold_down = self.remove_hold_down.as_secs() as u32;
+        for tracked in self.keys.iter_mut() {
+            if tracked.state == AnchorState::Revoked && now.saturating_sub(tracked.last_change) >= remove_hold_down {
+                tracked.state = AnchorState::Removed;
+                tracked.last_change = now;
+            }
+        }
+    }
+
+    /// Writes the state to the file, replacing it at once
+    pub fn save(&self) -> io::Result<()> {
+        write_atomically(&self.path, &self.to_file(), 0o644)
+    }
+
+    fn to_file(&self) -> String {
+        let zone = self.zone.to_fqdn_string();
+        let mut text = format!("; autotrust trust anchor file\n;;id: {} 1\n", zone);
+        if let Some(last_success) = self.last_success {
+            text.push_str(&format!(";;last_success: {} ;;{}\n", last_success, format_timestamp(last_success)));
+        }
+        text.push_str(&format!(";;query_interval: {}\n;;retry_time: {}\n", self.query_interval.as_secs(), self.retry_interval.as_secs()));
+        for tracked in &self.keys {
+            text.push_str(&format!(
+                "{} IN DNSKEY {} ;{{id = {} (ksk)}} ;;state={} [{}] ;;count=0 ;;lastchange={} ;;{}\n",
+                zone, tracked.key, tracked.key.key_tag(), tracked.state.number(), tracked.state.name(), tracked.last_change, format_timestamp(tracked.last_change),
+            ));
+        }
+        for ds in &self.ds {
+            text.push_str(&format!("{} IN DS {}\n", zone, ds));
+        }
+        text
+    }
+
+    fn parse(&mut self, text: &str) -> Result<(), String> {
+        for line in text.lines().map(str::trim) {
+            if let Some(comment) = line.strip_prefix(";;") {
+                let Some((field, value)) = comment.split_once(':') else {
+                    continue;
+                };
+                let value = value.split_whitespace().next().and_then(|value| value.parse::<u32>().ok());
+                match (field, value) {
+                    ("last_success", Some(value)) => self.last_success = Some(value),
+                    ("query_interval", Some(value)) => self.query_interval = Duration::from_secs(value as u64),
+                    ("retry_time", Some(value)) => self.retry_interval = Duration::from_secs(value as u64),
+                    _ => {}
+                }
+                continue;
+            }
+            let (record, comments) = line.split_once(';').unwrap_or((line, ""));
+            let anchors = TrustAnchors::parse(record)?;
+            self.ds.extend(anchors.ds_of(&self.zone).into_iter().cloned());
+            for key in anchors.keys_of(&self.zone) {
+                let state = match comment_value(comments, "state") {
+                    Some(number) => AnchorState::from_number(number as u8).ok_or_else(|| format!("invalid anchor state in {}", line))?,
+                    None => AnchorState::Valid,
+                };
+                let last_change = comment_value(comments, "lastchange").unwrap_or_else(now);
+                self.keys.push(TrackedKey { key: key.clone(), state, last_change });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Number in a `;;name=value` comment
+fn comment_value(comments: &str, name: &str) -> Option<u32> {
+    comments.split(";;")
+        .filter_map(|comment| comment.trim().strip_prefix(name)?.strip_prefix('='))
+        .find_map(|value| value.split_whitespace().next()?.parse().ok())
+}
+
+/// True for the same key, revoked or not
+fn same_key(a: &DNSKeyRecord, b: &DNSKeyRecord) -> bool {
+    a.algorithm == b.algorithm && a.public_key == b.public_key
+}
+
+fn now() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use crate::dns_util::dns_cache::DNSCache;
+    use crate::dns_util::dns_dnssec::signatures::ED25519;
+    use crate::dns_util::dns_dnssec::signing::SigningKey;
+    use crate::dns_util::test_server::{self, test_resolver, Answer};
+
+    const NOW: u32 = 1_700_000_000;
+
+    fn new_key() -> SigningKey {
+        SigningKey::generate(ED25519, DNSKeyRecord::ZONE_KEY | DNSKeyRecord::SECURE_ENTRY_POINT).unwrap()
+    }
+
+    fn revoked(key: &SigningKey) -> SigningKey {
+        let mut key = key.clone();
+        key.dnskey.flags |= DNSKeyRecord::REVOKE;
+        key
+    }
+
+    /// DNSKEY RRset of `published` signed by `signers`, at `now`
+    fn dnskey_rrset(zone: &DomainName, published: &[&SigningKey], signers: &[&SigningKey], now: u32) -> Vec<DNSResourceRecord> {
+        dnskey_rrset_with_ttl(zone, published, signers, now, 86400)
+    }
+
+    fn dnskey_rrset_with_ttl(zone: &DomainName, published: &[&SigningKey], signers: &[&SigningKey], now: u32, ttl: u32) -> Vec<DNSResourceRecord> {
+        let mut records: Vec<DNSResourceRecord> = published.iter()
+            .map(|key| DNSResourceRecord::from_record_data(zone, ttl, &key.dnskey))
+            .collect();
+        let rrset: Vec<&DNSResourceRecord> = records.iter().collect();
+        let signatures: Vec<DNSResourceRecord> = signers.iter()
+            .map(|key| key.sign_rrset(zone, &rrset, now - HOUR, now + 10 * DAY).unwrap())
+            .map(|signature| DNSResourceRecord::from_record_data(zone, ttl, &signature))
+            .collect();
+        records.extend(signatures);
+        records
+    }
+
+    fn states(tracker: &AnchorTracker) -> Vec<(u16, AnchorState)> {
+        tracker.keys.iter().map(|tracked| (tracked.key.key_tag(), tracked.state)).collect()
+    }
+
+    fn anchor_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("r-dns-{}-{}.anchor", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_key_rollover_tracking() {
+        let zone: DomainName = "example".parse().unwrap();
+        let (old, new) = (new_key(), new_key());
+        let initial = TrustAnchors { ds: vec![(zone.clone(), old.dnskey.to_ds(&zone, 2).unwrap())], keys: Vec::new() };
+        let mut tracker = AnchorTracker::open(anchor_path("rollover"), zone.clone(), &initial).unwrap();
+        assert_eq!(tracker.trust_anchors().ds.len(), 1);
+
+        // The first key is found from its DS record
+        tracker.update_at(&dnskey_rrset(&zone, &[&old], &[&old], NOW), NOW).unwrap();
+        assert_eq!(states(&tracker), vec![(old.key_tag(), AnchorState::Valid)]);
+        assert!(tracker.ds.is_empty());
+        assert_eq!(tracker.query_interval, Duration::from_secs(43200));
+
+        // A new key is trusted after the add hold-down
+        tracker.update_at(&dnskey_rrset(&zone, &[&old, &new], &[&old], NOW + DAY), NOW + DAY).unwrap();
+        assert_eq!(states(&tracker)[1], (new.key_tag(), AnchorState::AddPending));
+        tracker.update_at(&dnskey_rrset(&zone, &[&old, &new], &[&old], NOW + 20 * DAY), NOW + 20 * DAY).unwrap();
+        assert_eq!(states(&tracker)[1], (new.key_tag(), AnchorState::AddPending));
+        tracker.update_at(&dnskey_rrset(&zone, &[&old, &new], &[&old], NOW + 31 * DAY), NOW + 31 * DAY).unwrap();
+        assert_eq!(states(&tracker)[1], (new.key_tag(), AnchorState::Valid));
+
+        // The old key revoked by itself is no longer trusted, then removed
+        let old_revoked = revoked(&old);
+        let time = NOW + 40 * DAY;
+        tracker.update_at(&dnskey_rrset(&zone, &[&old_revoked, &new], &[&old_revoked, &new], time), time).unwrap();
+        assert_eq!(states(&tracker)[0], (old_revoked.key_tag(), AnchorState::Revoked));
+        assert_eq!(tracker.trust_anchors().keys, vec![(zone.clone(), new.dnskey.clone())]);
+        let time = NOW + 71 * DAY;
+        tracker.update_at(&dnskey_rrset(&zone, &[&new], &[&new], time), time).unwrap();
+        assert_eq!(states(&tracker), vec![(old_revoked.key_tag(), AnchorState::Removed), (new.key_tag(), AnchorState::Valid)]);
+    }
+
+    #[test]
+    fn test_add_hold_down_from_ttl() {
+        let zone: DomainName = "example".parse().unwrap();
+        let (anchor, new) = (new_key(), new_key());
+        let initial = TrustAnchors { ds: Vec::new(), keys: vec![(zone.clone(), anchor.dnskey.clone())] };
+        let mut tracker = AnchorTracker::open(anchor_path("hold-down"), zone.clone(), &initial).unwrap();
+
+        // With a TTL of 40 days, the key is still pending after 31 days
+        let ttl = 40 * DAY;
+        tracker.update_at(&dnskey_rrset_with_ttl(&zone, &[&anchor, &new], &[&anchor], NOW, ttl), NOW).unwrap();
+        let time = NOW + 31 * DAY;
+        tracker.update_at(&dnskey_rrset_with_ttl(&zone, &[&anchor, &new], &[&anchor], time, ttl), time).unwrap();
+        assert_eq!(states(&tracker)[1], (new.key_tag(), AnchorState::AddPending));
+        let time = NOW + 40 * DAY;
+        tracker.update_at(&dnskey_rrset_with_ttl(&zone, &[&anchor, &new], &[&anchor], time, ttl), time).unwrap();
+        assert_eq!(states(&tracker)[1], (new.key_tag(), AnchorState::Valid));
+    }
+
+    #[test]
+    fn test_refresh() {
+        let zone: DomainName = "example".parse().unwrap();
+        let (anchor, new) = (new_key(), new_key());
+        let old_records = dnskey_rrset(&zone, &[&anchor], &[&anchor], now());
+        let records = dnskey_rrset(&zone, &[&anchor, &new], &[&anchor], now());
+        let queries = AtomicUsize::new(0);
+        let address = test_server::start_server(move |_| {
+            let records = if queries.fetch_add(1, Ordering::SeqCst) == 0 { &old_records } else { &records };
+            Answer::records(records.iter().map(DNSResourceRecord::prepare).collect())
+        });
+        let resolver = Resolver::with_cache(test_resolver(address).config, Arc::new(DNSCache::default()));
+        // The RRset cached before the new key was published is not used
+        resolver.query("example", "DNSKEY").unwrap();
+
+        let path = anchor_path("refresh");
+        let initial = TrustAnchors { ds: Vec::new(), keys: vec![(zone.clone(), anchor.dnskey.clone())] };
+        let mut tracker = AnchorTracker::open(&path, zone.clone(), &initial).unwrap();
+        assert_eq!(tracker.refresh(&resolver).unwrap(), Duration::from_secs(43200));
+        let read = AnchorTracker::open(&path, zone, &TrustAnchors::default()).unwrap();
+        assert_eq!(states(&read), vec![(anchor.key_tag(), AnchorState::Valid), (new.key_tag(), AnchorState::AddPending)]);
+        assert!(read.last_success.is_some());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_untrusted_changes_ignored() {
+        let zone: DomainName = "example".parse().unwrap();
+        let (anchor, other) = (new_key(), new_key());
+        let initial = TrustAnchors { ds: Vec::new(), keys: vec![(zone.clone(), anchor.dnskey.clone())] };
+        let mut tracker = AnchorTracker::open(anchor_path("untrusted"), zone.clone(), &initial).unwrap();
+
+        // Key added by an RRset not signed by the anchor
+        assert!(tracker.update_at(&dnskey_rrset(&zone, &[&anchor, &other], &[&other], NOW), NOW).is_err());
+        assert_eq!(states(&tracker), vec![(anchor.key_tag(), AnchorState::Valid)]);
+        // Revocation not signed by the revoked key
+        let anchor_revoked = revoked(&anchor);
+        assert!(tracker.update_at(&dnskey_rrset(&zone, &[&anchor_revoked, &other], &[&other], NOW), NOW).is_err());
+        assert_eq!(states(&tracker), vec![(anchor.key_tag(), AnchorState::Valid)]);
+
+        // A pending key disappearing is forgotten, a valid one is missing
+        tracker.update_at(&dnskey_rrset(&zone, &[&anchor, &other], &[&anchor], NOW), NOW).unwrap();
+        tracker.update_at(&dnskey_rrset(&zone, &[&anchor], &[&anchor], NOW + DAY), NOW + DAY).unwrap();
+        assert_eq!(states(&tracker), vec![(anchor.key_tag(), AnchorState::Valid)]);
+        let mut tracker = AnchorTracker::open(anchor_path("untrusted"), zone.clone(), &TrustAnchors {
+            ds: Vec::new(),
+            keys: vec![(zone.clone(), anchor.dnskey.clone()), (zone.clone(), other.dnskey.clone())],
+        }).unwrap();
+        tracker.update_at(&dnskey_rrset(&zone, &[&anchor], &[&anchor], NOW), NOW).unwrap();
+        assert_eq!(states(&tracker)[1], (other.key_tag(), AnchorState::Missing));
+        assert_eq!(tracker.trust_anchors().keys.len(), 2);
+    }
+
+    #[test]
+    fn test_anchor_file() {
+        let zone: DomainName = "example".parse().unwrap();
+        let (first, second) = (new_key(), new_key());
+        let path = anchor_path("file");
+        let mut tracker = AnchorTracker::open(&path, zone.clone(), &TrustAnchors {
+            ds: Vec::new(),
+            keys: vec![(zone.clone(), first.dnskey.clone())],
+        }).unwrap();
+        tracker.update_at(&dnskey_rrset(&zone, &[&first, &second], &[&first], NOW), NOW).unwrap();
+        tracker.save().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains(&format!(";;state=1 [ ADDPEND ] ;;count=0 ;;lastchange={}", NOW)));
+
+        let read = AnchorTracker::open(&path, zone.clone(), &TrustAnchors::default()).unwrap();
+        assert_eq!(read.keys, tracker.keys);
+        assert_eq!(read.last_success, Some(NOW));
+        assert_eq!((read.query_interval, read.retry_interval), (tracker.query_interval, tracker.retry_interval));
+
+        // Keys of plain anchor files are valid
+        fs::write(&path, format!("example. 3600 IN DNSKEY {}\n", second.dnskey)).unwrap();
+        let read = AnchorTracker::open(&path, zone, &TrustAnchors::default()).unwrap();
+        assert_eq!(states(&read), vec![(second.key_tag(), AnchorState::Valid)]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+use std::cmp::Ordering;
+
+use crate::dns_util::dns_dnssec::records::{base32hex, nsec3_hash, NSEC3Record, NSECRecord};
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+/// CNAME, present in the bitmap of aliases whatever the type queried
+const CNAME: u16 = 5;
+const NS: u16 = 2;
+const SOA: u16 = 6;
+const DNAME: u16 = 39;
+const DS: u16 = 43;
+
+/// NSEC3 chains with more iterations are treated as insecure (RFC 9276
+/// section 3.2), to bound the hashing work
+pub const MAX_NSEC3_ITERATIONS: u16 = 150;
+
+/// What the NSEC or NSEC3 records of a negative response prove
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Denial {
+    /// The name exists without records of the type. `delegation` if the
+    /// name is a zone cut seen from the parent.
+    NoData { delegation: bool },
+    /// The name does not exist, nor a wildcard that would match it
+    NxDomain,
+    /// Nothing can be proven: the name falls in an opt-out span of an
+    /// NSEC3 chain, or the chain is too costly to check
+    Insecure,
+}
+
+/// Proof from NSEC records that `name` has no records of `query_type`
+/// (RFC 4035 section 5.4)
+pub(crate) fn prove_with_nsec(name: &DomainName, query_type: u16, nsecs: &[(DomainName, NSECRecord)]) -> Result<Denial, String> {
+    let nsecs: Vec<&(DomainName, NSECRecord)> = nsecs.iter()
+        .filter(|(owner, nsec)| !is_ancestor_delegation(owner, name, &nsec.types))
+        .collect();
+    if let Some((owner, nsec)) = nsecs.iter().find(|(owner, _)| owner == name) {
+        return nodata_from_types(owner, query_type, &nsec.types);
+    }
+    let (owner, nsec) = nsecs.iter()
+        .find(|(owner, nsec)| nsec_covers(owner, &nsec.next_domain_name, name))
+        .ok_or_else(|| format!("no NSEC matching or covering {}", name))?;
+    // Empty non-terminal: the name only exists through its descendants
+    if nsec.next_domain_name.is_subdomain_of(name) {
+        return Ok(Denial::NoData { delegation: false });
+    }
+
+    let closest_encloser = [owner, &nsec.next_domain_name].into_iter()
+        .map(|other| common_ancestor(name, other))
+        .max_by_key(|ancestor| ancestor.label_count())
+        .unwrap_or_default();
+    let wildcard = closest_encloser.prepend(b"*").ok_or("wildcard name too long")?;
+    if let Some((owner, nsec)) = nsecs.iter().find(|(owner, _)| *owner == wildcard) {
+        return nodata_from_types(owner, query_type, &nsec.types).map(|_| Denial::NoData { delegation: false });
+    }
+    if nsecs.iter().any(|(owner, nsec)| nsec_covers(owner, &nsec.next_domain_name, &wildcard)) {
+        Ok(Denial::NxDomain)
+    } else {
+        Err(format!("no NSEC denying the wildcard {}", wildcard))
+    }
+}
+
+/// Proof from NSEC3 records of the zone `zone` that `name` has no records
+/// of `query_type` (RFC 5155 section 8)
+pub(crate) fn prove_with_nsec3(name: &DomainName, query_type: u16, zone: &DomainName, nsec3s: &[(DomainName, NSEC3Record)]) -> Result<Denial, String> {
+    let Some(chain) = Nsec3Chain::new(zone, nsec3s) else {
+        return Ok(Denial::Insecure);
+    };
+    if chain.iterations > MAX_NSEC3_ITERATIONS {
+        return Ok(Denial::Insecure);
+    }
+    if let Some(nsec3) = chain.matching(name) {
+        return nodata_from_types(name, query_type, &nsec3.types);
+    }
+
+    let (closest_encloser, covering) = chain.closest_encloser(name)?;
+    if chain.matching(&closest_encloser).is_some_and(|nsec3| is_ancestor_delegation(&closest_encloser, name, &nsec3.types)) {
+        return Err(format!("the closest encloser of {} is a delegation or a DNAME", name));
+    }
+    // An unsigned delegation may hide in an opt-out span, the DS query
+    // getting no other proof (RFC 5155 section 8.6)
+    if query_type == DS && covering.is_opt_out() {
+        return Ok(Denial::Insecure);
+    }
+    let wildcard = closest_encloser.prepend(b"*").ok_or("wildcard name too long")?;
+    if let Some(nsec3) = chain.matching(&wildcard) {
+        return nodata_from_types(&wildcard, query_type, &nsec3.types).map(|_| Denial::NoData { delegation: false });
+    }
+    if chain.covering(&wildcard).is_some() {
+        return Ok(Denial::NxDomain);
+    }
+    Err(format!("no NSEC3 denying the wildcard {}", wildcard))
+}
+
+/// Proof that no name closer to `name` than the wildcard of `labels`
+/// labels exists, needed when an answer is expanded from that wildcard
+/// (RFC 4035 section 5.3.4 and RFC 5155 section 8.8)
+pub(crate) fn prove_wildcard_expansion(name: &DomainName, labels: usize, zone: &DomainName, nsecs: &[(DomainName, NSECRecord)], nsec3s: &[(DomainName, NSEC3Record)]) -> Result<(), String> {
+    if nsecs.iter().any(|(owner, nsec)| nsec_covers(owner, &nsec.next_domain_name, name)) {
+        return Ok(());
+    }
+    let next_closer = name.suffix(labels + 1);
+    match Nsec3Chain::new(zone, nsec3s) {
+        Some(chain) if chain.covering(&next_closer).is_some() => Ok(()),
+        _ => Err(format!("no proof that {} is not an existing name", name)),
+    }
+}
+
+fn nodata_from_types(owner: &DomainName, query_type: u16, types: &[u16]) -> Result<Denial, String> {
+    if types.contains(&query_type) || (types.contains(&CNAME) && query_type != CNAME) {
+        return Err(format!("the denial proof says that {} has the type", owner));
+    }
+    let delegation = types.contains(&NS) && !types.contains(&SOA);
+    // The apex of the child cannot deny the DS records held by the parent
+    if query_type == DS && types.contains(&SOA) && !owner.is_root() {
+        return Err(format!("DS denied by the child zone {}", owner));
+    }
+    Ok(Denial::NoData { delegation })
+}
+
+/// True if the types of `owner`, an ancestor of `name`, make it a zone cut
+/// or a DNAME: its records then come from the parent zone, and cannot prove
+/// anything about the names below it (RFC 6840 section 4.1)
+fn is_ancestor_delegation(owner: &DomainName, name: &DomainName, types: &[u16]) -> bool {
+    owner != name && name.is_subdomain_of(owner)
+        && ((types.contains(&NS) && !types.contains(&SOA)) || types.contains(&DNAME))
+}
+
+/// True if `name` falls strictly between `owner` and `next`, the last NSEC
+/// of the zone pointing back to the apex
+fn nsec_covers(owner: &DomainName, next: &DomainName, name: &DomainName) -> bool {
+    let after_owner = owner.canonical_cmp(name) == Ordering::Less;
+    if owner.canonical_cmp(next) == Ordering::Less {
+        after_owner && name.canonical_cmp(next) == Ordering::Less
+    } else {
+        after_owner || name.canonical_cmp(next) == Ordering::Less
+    }
+}
+
+/// Longest common suffix of two names
+fn common_ancestor(a: &DomainName, b: &DomainName) -> DomainName {
+    let mut count = 0;
+    while count < a.label_count().min(b.label_count()) && a.suffix(count + 1) == b.suffix(count + 1) {
+        count += 1;
+    }
+    a.suffix(count)
+}
+
+/// NSEC3 records of a zone sharing the same parameters, with their hashes
+/// in base 32, whose order is the order of the hashes
+struct Nsec3Chain<'a> {
+    zone: &'a DomainName,
+    salt: &'a [u8],
+    iterations: u16,
+    records: Vec<(String, String, &'a NSEC3Record)>,
+}
+
+impl<'a> Nsec3Chain<'a> {
+    fn new(zone: &'a DomainName, nsec3s: &'a [(DomainName, NSEC3Record)]) -> Option<Self> {
+        let (_, first) = nsec3s.iter().find(|(_, nsec3)| nsec3.hash_algorithm == 1)?;
+        let records = nsec3s.iter()
+            .filter(|(owner, nsec3)| {
+                nsec3.hash_algorithm == 1 && nsec3.iterations == first.iterations && nsec3.salt == first.salt
+                    && owner.parent().as_ref() == Some(zone)
+            })
+            .map(|(owner, nsec3)| {
+                let hash = String::from_utf8_lossy(&owner.labels()[0]).to_ascii_uppercase();
+                (hash, base32hex(&nsec3.next_hashed_owner_name), nsec3)
+            })
+            .collect();
+        Some(Self { zone, salt: &first.salt, iterations: first.iterations, records })
+    }
+
+    fn hash(&self, name: &DomainName) -> String {
+        base32hex(&nsec3_hash(name, self.salt, self.iterations))
+    }
+
+    fn matching(&self, name: &DomainName) -> Option<&'a NSEC3Record> {
+        let hash = self.hash(name);
+        self.records.iter().find(|(owner, _, _)| *owner == hash).map(|(_, _, nsec3)| *nsec3)
+    }
+
+    fn covering(&self, name: &DomainName) -> Option<&'a NSEC3Record> {
+        let hash = self.hash(name);
+        self.records.iter()
+            .find(|(owner, next, _)| {
+                if owner < next {
+                    *owner < hash && hash < *next
+                } else {
+                    *owner < hash || hash < *next
+                }
+            })
+            .map(|(_, _, nsec3)| *nsec3)
+    }
+
+    /// Closest existing ancestor of `name`, with the record covering the
+    /// next closer name (RFC 5155 section 8.3)
+    fn closest_encloser(&self, name: &DomainName) -> Result<(DomainName, &'a NSEC3Record), String> {
+        for count in (self.zone.label_count()..name.label_count()).rev() {
+            let ancestor = name.suffix(count);
+            if self.matching(&ancestor).is_some() {
+                let next_closer = name.suffix(count + 1);
+                return self.covering(&next_closer)
+                    .map(|covering| (ancestor, covering))
+                    .ok_or_else(|| format!("no NSEC3 covering {}", next_closer));
+            }
+        }
+        Err(format!("no closest encloser proof for {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nsec(owner: &str, next: &str, types: Vec<u16>) -> (DomainName, NSECRecord) {
+        (owner.parse().unwrap(), NSECRecord { next_domain_name: next.parse().unwrap(), types })
+    }
+
+    #[test]
+    fn test_nsec_proofs() {
+        let nsecs = vec![
+            nsec("example", "a.example", vec![NS, SOA, 46, 47, 48]),
+            nsec("a.example", "x.y.example", vec![NS, DS, 46, 47]),
+            nsec("x.y.example", "example", vec![1, 46, 47]),
+        ];
+        let name = |name: &str| name.parse::<DomainName>().unwrap();
+        assert_eq!(prove_with_nsec(&name("b.example"), 1, &nsecs), Ok(Denial::NxDomain));
+        assert_eq!(prove_with_nsec(&name("x.y.example"), 28, &nsecs), Ok(Denial::NoData { delegation: false }));
+        assert_eq!(prove_with_nsec(&name("a.example"), 1, &nsecs), Ok(Denial::NoData { delegation: true }));
+        assert!(prove_with_nsec(&name("a.example"), DS, &nsecs).is_err());
+        // y.example only exists through x.y.example
+        assert_eq!(prove_with_nsec(&name("y.example"), 1, &nsecs), Ok(Denial::NoData { delegation: false }));
+        assert!(prove_with_nsec(&name("x.y.example"), 1, &nsecs).is_err());
+        assert!(prove_with_nsec(&name("b.example"), 1, &nsecs[2..]).is_err());
+
+        // Records of the parent zone cannot deny names below a delegation,
+        // nor can a DNAME deny the names it redirects
+        let parent = vec![nsec("example", "zzz", vec![NS, DS, 46, 47])];
+        assert!(prove_with_nsec(&name("forged.example"), 1, &parent).is_err());
+        assert_eq!(prove_with_nsec(&name("example"), 1, &parent), Ok(Denial::NoData { delegation: true }));
+        let dname = vec![nsec("b.example", "c.example", vec![DNAME, 46, 47])];
+        assert!(prove_with_nsec(&name("x.b.example"), 1, &dname).is_err());
+    }
+
+    #[test]
+    fn test_nsec3_proofs() {
+        // Zone of RFC 5155 appendix A, reduced to the names needed
+        let zone: DomainName = "example".parse().unwrap();
+        let salt = vec![0xaa, 0xbb, 0xcc, 0xdd];
+        let names = ["example", "a.example", "x.w.example", "w.example", "ns1.example"];
+        let mut hashes: Vec<(Vec<u8>, &str)> = names.iter()
+            .map(|name| (nsec3_hash(&name.parse().unwrap(), &salt, 12), *name))
+            .collect();
+        hashes.sort();
+        let nsec3s: Vec<(DomainName, NSEC3Record)> = hashes.iter().enumerate()
+            .map(|(i, (hash, name))| {
+                let types = match *name {
+                    "example" => vec![NS, SOA, 46, 48, 51],
+                    "a.example" => vec![NS, DS, 46],
+                    _ => vec![1, 46],
+                };
+                let owner = zone.prepend(base32hex(hash).as_bytes()).unwrap();
+                (owner, NSEC3Record {
+                    hash_algorithm: 1,
+                    flags: 1,
+                    iterations: 12,
+                    salt: salt.clone(),
+                    next_hashed_owner_name: hashes[(i + 1) % hashes.len()].0.clone(),
+                    types,
+                })
+            })
+            .collect();
+        let name = |name: &str| name.parse::<DomainName>().unwrap();
+        assert_eq!(prove_with_nsec3(&name("a.c.x.w.example"), 1, &zone, &nsec3s), Ok(Denial::NxDomain));
+        assert_eq!(prove_with_nsec3(&name("ns1.example"), 16, &zone, &nsec3s), Ok(Denial::NoData { delegation: false }));
+        assert_eq!(prove_with_nsec3(&name("a.example"), 1, &zone, &nsec3s), Ok(Denial::NoData { delegation: true }));
+        assert!(prove_with_nsec3(&name("b.a.example"), 1, &zone, &nsec3s).is_err());
+        assert!(prove_with_nsec3(&name("x.w.example"), 1, &zone, &nsec3s).is_err());
+        assert_eq!(prove_with_nsec3(&name("c.example"), DS, &zone, &nsec3s), Ok(Denial::Insecure));
+        assert!(prove_wildcard_expansion(&name("a.z.w.example"), 2, &zone, &[], &nsec3s).is_ok());
+        assert!(prove_wildcard_expansion(&name("a.z.w.example"), 2, &zone, &[], &[]).is_err());
+
+        let mut costly = nsec3s.clone();
+        costly.iter_mut().for_each(|(_, nsec3)| nsec3.iterations = 500);
+        assert_eq!(prove_with_nsec3(&name("c.example"), 1, &zone, &costly), Ok(Denial::Insecure));
+    }
+}
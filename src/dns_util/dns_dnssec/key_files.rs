@@ -0,0 +1,332 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::dns_util::dns_dnssec::records::{format_timestamp, parse_timestamp};
+use crate::dns_util::dns_dnssec::signatures::{ECDSAP256SHA256, ECDSAP384SHA384, ED25519, RSASHA256, RSASHA512};
+use crate::dns_util::dns_dnssec::signing::{KeySecret, SigningKey};
+use crate::dns_util::dns_dnssec::trust_anchors::TrustAnchors;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+/// Fields of RSA keys in `.private` files, in the order of `KeySecret::Rsa`
+const RSA_FIELDS: [&str; 8] = ["Modulus", "PublicExponent", "PrivateExponent", "Prime1", "Prime2", "Exponent1", "Exponent2", "Coefficient"];
+
+/// Times of the life of a key, in seconds since 1970
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyTimings {
+    pub created: Option<u32>,
+    /// The DNSKEY record is added to the zone
+    pub publish: Option<u32>,
+    /// The key starts signing
+    pub activate: Option<u32>,
+    /// The key stops signing
+    pub inactive: Option<u32>,
+    /// The DNSKEY record is removed from the zone
+    pub delete: Option<u32>,
+    /// The parent zone may publish the DS record of the key
+    pub sync_publish: Option<u32>,
+    /// The parent zone must remove the DS record of the key
+    pub sync_delete: Option<u32>,
+}
+
+impl KeyTimings {
+    pub fn is_published(&self, now: u32) -> bool {
+        between(self.publish, self.delete, now)
+    }
+
+    pub fn is_active(&self, now: u32) -> bool {
+        between(self.activate, self.inactive, now)
+    }
+
+    /// True when the DS record of the key belongs in the parent zone
+    pub fn is_ds_published(&self, now: u32) -> bool {
+        between(self.sync_publish, self.sync_delete, now)
+    }
+
+    /// Names of the timings in key files, with their values
+    fn fields(&mut self) -> [(&'static str, &mut Option<u32>); 7] {
+        [
+            ("Created", &mut self.created),
+            ("Publish", &mut self.publish),
+            ("Activate", &mut self.activate),
+            ("Inactive", &mut self.inactive),
+            ("Delete", &mut self.delete),
+            ("SyncPublish", &mut self.sync_publish),
+            ("SyncDelete", &mut self.sync_delete),
+        ]
+    }
+}
+
+fn between(start: Option<u32>, end: Option<u32>, now: u32) -> bool {
+    start.is_some_and(|start| start <= now) && end.is_none_or(|end| end > now)
+}
+
+/// Key of a zone stored as a pair of BIND key files: `K<zone>+<algorithm>+<key
+/// tag>.key` holds the DNSKEY record, `.private` the private key and the
+/// timings
+#[derive(Clone)]
+pub struct KeyFile {
+    pub zone: DomainName,
+    pub key: SigningKey,
+    pub timings: KeyTimings,
+}
+
+impl KeyFile {
+    /// Name of the files without their extension, such as
+    /// `Kexample.com.+013+12345`
+    pub fn base_name(&self) -> String {
+        format!("K{}+{:03}+{:05}", self.zone.to_lowercase().to_fqdn_string(), self.key.dnskey.algorithm, self.key.key_tag())
+    }
+
+    /// Reads the key of the files `path`, with or without its `.key` or
+    /// `.private` extension
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let base = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("key" | "private") => path.with_extension(""),
+            _ => path.to_path_buf(),
+        };
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", base.display(), e));
+        let public = TrustAnchors::parse(&fs::read_to_string(with_extension(&base, "key"))?).map_err(invalid)?;
+        let [(zone, dnskey)] = <[_; 1]>::try_from(public.keys)
+            .map_err(|_| invalid("expected a single DNSKEY record".to_string()))?;
+
+        let mut timings = KeyTimings::default();
+        let mut private_key: Option<Vec<u8>> = None;
+        let mut rsa: [Option<Vec<u8>>; 8] = Default::default();
+        for line in fs::read_to_string(with_extension(&base, "private"))?.lines() {
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            let decode = || STANDARD.decode(value).map_err(|e| invalid(format!("invalid {}: {}", field, e)));
+            if field == "Algorithm" {
+                if value.split_whitespace().next() != Some(dnskey.algorithm.to_string().as_str()) {
+                    return Err(invalid("algorithms of the key files differ".to_string()));
+                }
+            } else if field == "PrivateKey" {
+                private_key = Some(decode()?);
+            } else if let Some(position) = RSA_FIELDS.iter().position(|name| *name == field) {
+                rsa[position] = Some(decode()?);
+            } else if let Some((_, timing)) = timings.fields().into_iter().find(|(name, _)| *name == field) {
+                *timing = Some(parse_timestamp(value).ok_or_else(|| invalid(format!("invalid {} time {}", field, value)))?);
+            }
+        }
+        let secret = match (private_key, rsa) {
+            (Some(private_key), _) => KeySecret::PrivateKey(private_key),
+            (None, [Some(n), Some(e), Some(d), Some(p), Some(q), Some(dp), Some(dq), Some(q_inv)]) => KeySecret::Rsa([n, e, d, p, q, dp, dq, q_inv]),
+            _ => return Err(invalid("missing private key".to_string())),
+        };
+        Ok(Self { zone, key: SigningKey::from_secret(&dnskey, secret)?, timings })
+    }
+
+    /// Writes the `.key` and `.private` files in `directory`, replacing
+    /// each one at once so that readers never see a partial file. As with
+    /// BIND, only the owner may read the `.private` file.
+    pub fn write(&self, directory: impl AsRef<Path>) -> io::Result<()> {
+        let base = directory.as_ref().join(self.base_name());
+        write_atomically(&with_extension(&base, "private"), &self.private_key_file()?, 0o600)?;
+        write_atomically(&with_extension(&base, "key"), &self.public_key_file(), 0o644)
+    }
+
+    /// Removes the files of the key from `directory`
+    pub fn remove(&self, directory: impl AsRef<Path>) -> io::Result<()> {
+        let base = directory.as_ref().join(self.base_name());
+        for extension in ["key", "private"] {
+            match fs::remove_file(with_extension(&base, extension)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Content of the `.key` file: the timings as comments, then the
+    /// DNSKEY record
+    pub fn public_key_file(&self) -> String {
+        let kind = if self.key.is_key_signing_key() { "key-signing" } else { "zone-signing" };
+        let zone = self.zone.to_fqdn_string();
+        let mut text = format!("; This is a {} key, keyid {}, for {}\n", kind, self.key.key_tag(), zone);
+        let mut timings = self.timings;
+        for (name, timing) in timings.fields() {
+            if let Some(timing) = timing {
+                text.push_str(&format!("; {}: {}\n", name, format_timestamp(*timing)));
+            }
+        }
+        text.push_str(&format!("{} IN DNSKEY {}\n", zone, self.key.dnskey));
+        text
+    }
+
+    /// Content of the `.private` file, in the format v1.3 of BIND. Fails
+    /// for RSA keys loaded from PKCS#8, whose private fields are unknown.
+    pub fn private_key_file(&self) -> io::Result<String> {
+        let algorithm = self.key.dnskey.algorithm;
+        let mut text = format!("Private-key-format: v1.3\nAlgorithm: {} ({})\n", algorithm, algorithm_mnemonic(algorithm));
+        match &self.key.secret {
+            Some(KeySecret::PrivateKey(private_key)) => text.push_str(&format!("PrivateKey: {}\n", STANDARD.encode(private_key))),
+            Some(KeySecret::Rsa(fields)) => {
+                for (name, value) in RSA_FIELDS.iter().zip(fields) {
+                    text.push_str(&format!("{}: {}\n", name, STANDARD.encode(value)));
+                }
+            }
+            None => return Err(io::Error::new(io::ErrorKind::Unsupported, "private key cannot be exported")),
+        }
+        let mut timings = self.timings;
+        for (name, timing) in timings.fields() {
+            if let Some(timing) = timing {
+                text.push_str(&format!("{}: {}\n", name, format_timestamp(*timing)));
+            }
+        }
+        Ok(text)
+    }
+}
+
+fn algorithm_mnemonic(algorithm: u8) -> &'static str {
+    match algorithm {
+        RSASHA256 => "RSASHA256",
+        RSASHA512 => "RSASHA512",
+        ECDSAP256SHA256 => "ECDSAP256SHA256",
+        ECDSAP384SHA384 => "ECDSAP384SHA384",
+        ED25519 => "ED25519",
+        _ => "UNKNOWN",
+    }
+}
+
+/// `base` with an extension added, as its name already contains dots
+fn with_extension(base: &Path, extension: &str) -> PathBuf {
+    let mut path = base.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Writes `content` to a new temporary file renamed to `path`. On Unix the
+/// file gets the permissions `mode`, before anything is written to it.
+pub(crate) fn write_atomically(path: &Path, content: &str, mode: u32) -> io::Result<()> {
+    static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+    let temporary = with_extension(path, &format!("{}.{}.tmp", std::process::id(), TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    let mut file = options.open(&temporary)?;
+    let written = file.write_all(content.as_bytes())
+        .and_then(|_| file.sync_all())
+        .and_then(|_| fs::rename(&temporary, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_util::dns_dnssec::records::DNSKeyRecord;
+    use crate::dns_util::dns_dnssec::signatures::verify_signature;
+    use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+
+    /// RSA key in the files written by BIND for the zone `example.`
+    const RSA_PUBLIC_KEY: &str = "; This is a zone-signing key, keyid 0, for example.
+example. 3600 IN DNSKEY 256 3 8 (
+    AwEAAaxEqNdAIk+nAL8UwpKRphA5d1UQm51FOYuH3TryP5Z980EhqKTTIAQZevZsmb5eUT2e6BAV11g7HvvkPQNXU/nc
+    gHnuJEuTcNS1TcmIQQjoQdq4CmrCnAp5eCzrH55Syi3PHNDR6OkuYbhoPfcm8fYZiovFKx9KItJP87m8XAIK1r0k+uFr
+    F8If4Dy7CS0Fw8rxUKJBNjmL+e9fW4iPSznf3cn7l7xPs/K/yFkV7bF53DxE8p5B6hWXDG6g0JrBiqVFgMj/8Tb0trzs
+    UO8Pte5fQzCJWQCFx1msPvyLoS4lmT+eII9Rlzo0tmntoa87P6tISoVrBRswanXLXDJ1vaM= ) ; ZSK
+";
+    const RSA_PRIVATE_KEY: &str = "Private-key-format: v1.3
+Algorithm: 8 (RSASHA256)
+Modulus: rESo10AiT6cAvxTCkpGmEDl3VRCbnUU5i4fdOvI/ln3zQSGopNMgBBl69myZvl5RPZ7oEBXXWDse++Q9A1dT+dyAee4kS5Nw1LVNyYhBCOhB2rgKasKcCnl4LOsfnlLKLc8c0NHo6S5huGg99ybx9hmKi8UrH0oi0k/zubxcAgrWvST64WsXwh/gPLsJLQXDyvFQokE2OYv5719biI9LOd/dyfuXvE+z8r/IWRXtsXncPETynkHqFZcMbqDQmsGKpUWAyP/xNvS2vOxQ7w+17l9DMIlZAIXHWaw+/IuhLiWZP54gj1GXOjS2ae2hrzs/q0hKhWsFGzBqdctcMnW9ow==
+PublicExponent: AQAB
+PrivateExponent: aQAOEP3o2EGGUHuIHURuc9emxzx1xL8UXgGjQxlk94bNjQMA5XYY8Bf6Rzlgy09jDec57fZ1y5aMVfxvW1UQbh4CgACJvHNTNRhg+M5srh67ju2ef4fj9vScy2HAAXucSe04hZlFBSr7PcP9PBRPogoDD9+xrfRkk9/spMlIEGvF7JZNTp2RSlCigeasIt4YBa1WAr9aPu4bTcXXn621a1dCguOlPEzRm8PsUgbj7/KIiLLW0L1vnGF0MVSOJphw1rTYpiy96XQhh08Ra80YbGANkqW7YH4TlTTSSNRdVq9Me8yvUDLh3Vxge5/GzQ6iMi3C9jMeUYfwEQeXohe5
+Prime1: 0vPSO62Vs4ZrIQxJi1m0go48hzB2ISX7143Z34XV/ZdwjyEXVxAoq7OaH833KYs6GJrTkuN+uV1AbR12T2IslskilXiFvKuu77Y76teQ47zFO61JIcQpTIHxTLf1cMiRrysZkoKolMFk/EGYLP70+qXpT+Xj/c+R6LiOcMVyn3s=
+Prime2: 0Q4UeVWJ4FFaYhdlhddaeAAQQ2z8psmGF0UibcsZDLuH4Ai/5t1VkxeiW1tPAgwDTHa4woXBEv3sgbqFFt03ihWx+xL7jS9EMVEvAoUgdVHU0KMafHsH3/70513U60clMxBhkrkdzc4KVIGOg9RTUAbguIPz72naCDyAroTQLfk=
+Exponent1: NxzgVhsZu1do8a9mH3QGRv2mLqOomSx1SXGT9GPg7mn8TEiW8nYUSheYMqr64e2v9fGtSPkKiJ8mT9ucp0y83YAa+vYL6qjoAVSY/tfm0LjmxpqVOq8nIwfn5OVCyH5+IUefXLmqFYMZQRZaDZdmpX1nISqskAkE08oz3J6ZPbU=
+Exponent2: gJ2o+v0lVly2vqKmUzGq9ykS2Gi1B+f0F1zxUZsiTjMR5UN8zQxfGZ/ww0Pp19iMLv7brNGdd5HBcA7Q0sBG6y1t0opbOQFbcvv/cA13arZtt+6g3PTUY3r4MWAJHrcmq8PoQ9yf+X5J3LPYekKm/xU8LEkCSslU2rxebwBHFAE=
+Coefficient: px+89o2+USVSuUAv3LC8vOPSSHKGI3qkpU9GVHwgIOpUCDIRApiHW7ufGXU2sy7cULEa92HBNzDdlcR/UFvt6CGIzPi3xqJ9DDsddhSVTHGh46I0i/ecSNaZ2A2dEqITzd66T4zybzdkS8wX8vzJlicqt3JtQsQwFG28OuVCzQ8=
+Created: 20240101000000
+Publish: 20240101000000
+Activate: 20240101000000
+";
+
+    /// Checks that `key` signs for its DNSKEY record
+    fn assert_signs(key: &SigningKey) {
+        let zone: DomainName = "example".parse().unwrap();
+        let record = DNSResourceRecord::from_record_data(&zone, 3600, &key.dnskey);
+        let signature = key.sign_rrset(&zone, &[&record], 0, u32::MAX).unwrap();
+        assert!(verify_signature(&signature, &key.dnskey, &[&record]));
+    }
+
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("r-dns-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_key_file_round_trip() {
+        let directory = temporary_directory("key-files");
+        for algorithm in [ECDSAP256SHA256, ECDSAP384SHA384, ED25519] {
+            let key_file = KeyFile {
+                zone: "Example.com".parse().unwrap(),
+                key: SigningKey::generate(algorithm, DNSKeyRecord::ZONE_KEY | DNSKeyRecord::SECURE_ENTRY_POINT).unwrap(),
+                timings: KeyTimings { created: Some(1_700_000_000), publish: Some(1_700_000_000), activate: Some(1_700_003_600), ..Default::default() },
+            };
+            key_file.write(&directory).unwrap();
+            assert!(key_file.base_name().starts_with(&format!("Kexample.com.+{:03}+", algorithm)));
+            let base = directory.join(key_file.base_name());
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = |extension| fs::metadata(with_extension(&base, extension)).unwrap().permissions().mode() & 0o777;
+                assert_eq!(mode("private"), 0o600);
+                assert_eq!(mode("key") & 0o600, 0o600);
+                // Rewriting replaces the file without widening its permissions
+                key_file.write(&directory).unwrap();
+                assert_eq!(mode("private"), 0o600);
+            }
+            let private = fs::read_to_string(with_extension(&base, "private")).unwrap();
+            assert!(private.starts_with("Private-key-format: v1.3\n"));
+            assert!(private.contains("Activate: 20231114231320\n"));
+
+            let read = KeyFile::read(with_extension(&base, "private")).unwrap();
+            assert_eq!(read.key.dnskey, key_file.key.dnskey);
+            assert_eq!(read.timings, key_file.timings);
+            assert!(read.timings.is_published(1_700_000_000) && !read.timings.is_active(1_700_000_000));
+            assert_signs(&read.key);
+
+            key_file.remove(&directory).unwrap();
+            assert!(KeyFile::read(&base).is_err());
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_read_bind_rsa_key() {
+        let directory = temporary_directory("bind-key");
+        let base = directory.join("Kexample.+008+00000");
+        fs::write(with_extension(&base, "key"), RSA_PUBLIC_KEY).unwrap();
+        fs::write(with_extension(&base, "private"), RSA_PRIVATE_KEY).unwrap();
+        let key_file = KeyFile::read(&base).unwrap();
+        assert_eq!(key_file.zone, "example".parse().unwrap());
+        assert!(!key_file.key.is_key_signing_key());
+        assert_eq!(key_file.timings.activate, Some(1704067200));
+        assert_signs(&key_file.key);
+        assert_eq!(key_file.private_key_file().unwrap(), RSA_PRIVATE_KEY);
+
+        // A private key of another key is rejected
+        let other = RSA_PUBLIC_KEY.replace("AwEAAaxE", "AwEAAaxF");
+        fs::write(with_extension(&base, "key"), other).unwrap();
+        assert!(KeyFile::read(&base).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
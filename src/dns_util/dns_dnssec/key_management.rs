@@ -0,0 +1,374 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::dns_util::dns_dnssec::key_files::{KeyFile, KeyTimings};
+use crate::dns_util::dns_dnssec::records::{DNSKeyRecord, DSRecord};
+use crate::dns_util::dns_dnssec::signatures::ECDSAP256SHA256;
+use crate::dns_util::dns_dnssec::signing::SigningKey;
+use crate::dns_util::dns_dnssec::zone_signing::ZoneSigner;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+/// Lifetimes of the keys of a zone, and the delays of their rollovers
+/// (RFC 7583). Zone signing keys are replaced by pre-publication, key
+/// signing keys by double signature (RFC 6781 section 4.1).
+#[derive(Debug, Clone)]
+pub struct KeyPolicy {
+    /// Algorithm of the keys generated
+    pub algorithm: u8,
+    /// Time a zone signing key signs before its replacement starts
+    pub zsk_lifetime: Duration,
+    /// Time a key signing key signs before its replacement starts
+    pub ksk_lifetime: Duration,
+    /// Time for a new DNSKEY record to reach the validators: the TTL of
+    /// the DNSKEY RRset and the propagation delay to the secondaries
+    pub publish_delay: Duration,
+    /// Time for the signatures of a retired key to leave the caches: the
+    /// largest TTL of the zone and the propagation delay
+    pub retire_delay: Duration,
+    /// Time for a change of the DS records to reach the validators once
+    /// submitted: the delay of the parent zone and the TTL of its DS RRset
+    pub parent_delay: Duration,
+}
+
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        Self {
+            algorithm: ECDSAP256SHA256,
+            zsk_lifetime: Duration::from_secs(90 * 86400),
+            ksk_lifetime: Duration::from_secs(365 * 86400),
+            publish_delay: Duration::from_secs(2 * 3600),
+            retire_delay: Duration::from_secs(86400 + 3600),
+            parent_delay: Duration::from_secs(2 * 86400),
+        }
+    }
+}
+
+/// Change made by `KeyManager::update`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// Key generated, to be published and activated at its timings
+    Created { key_tag: u16, key_signing: bool },
+    /// Key past its deletion time, whose files were removed
+    Removed { key_tag: u16, key_signing: bool },
+}
+
+/// Keys of a zone kept as BIND key files in a directory, whose timings
+/// drive their rollovers
+pub struct KeyManager {
+    pub zone: DomainName,
+    pub directory: PathBuf,
+    pub policy: KeyPolicy,
+    pub keys: Vec<KeyFile>,
+}
+
+impl KeyManager {
+    /// Loads the keys of `zone` found in `directory`
+    pub fn open(zone: DomainName, directory: impl Into<PathBuf>, policy: KeyPolicy) -> io::Result<Self> {
+        let directory = directory.into();
+        let prefix = format!("K{}+", zone.to_lowercase().to_fqdn_string());
+        let mut keys: Vec<KeyFile> = Vec::new();
+        for entry in fs::read_dir(&directory)? {
+            let path = entry?.path();
+            let is_key_file = path.file_name().and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(".private"));
+            if is_key_file {
+                keys.push(KeyFile::read(&path)?);
+            }
+        }
+        keys.sort_by_key(|key| key.timings.created);
+        Ok(Self { zone, directory, policy, keys })
+    }
+
+    /// Generates the keys needed, starts the rollovers of the keys at the
+    /// end of their lifetime and removes the keys past their deletion.
+    /// The timings are saved in the key files.
+    pub fn update(&mut self) -> io::Result<Vec<KeyEvent>> {
+        self.update_at(now())
+    }
+
+    fn update_at(&mut self, now: u32) -> io::Result<Vec<KeyEvent>> {
+        let mut events: Vec<KeyEvent> = Vec::new();
+        let (removed, kept) = self.keys.drain(..).partition(|key| key.timings.delete.is_some_and(|delete| delete <= now));
+        self.keys = kept;
+        for key in removed {
+            key.remove(&self.directory)?;
+            events.push(KeyEvent::Removed { key_tag: key.key.key_tag(), key_signing: key.key.is_key_signing_key() });
+        }
+
+        for key_signing in [true, false] {
+            // Keys not retiring yet, the current one first, then its successor
+            let mut current: Vec<usize> = (0..self.keys.len())
+                .filter(|&i| self.keys[i].key.is_key_signing_key() == key_signing && self.keys[i].timings.inactive.is_none())
+                .collect();
+            current.sort_by_key(|&i| self.keys[i].timings.activate);
+            let key = match current.as_slice() {
+                [] => {
+                    let mut key = self.generate(key_signing, now)?;
+                    key.timings.activate = Some(now);
+                    if key_signing {
+                        key.timings.sync_publish = Some(now + self.policy.publish_delay.as_secs() as u32);
+                    }
+                    key
+                }
+                &[i] => {
+                    let lifetime = if key_signing { self.policy.ksk_lifetime } else { self.policy.zsk_lifetime };
+                    let end = self.keys[i].timings.activate.unwrap_or(now).saturating_add(lifetime.as_secs() as u32);
+                    if key_signing && now >= end {
+                        self.double_signature_rollover(i, now)?
+                    } else if !key_signing && now.saturating_add(self.policy.publish_delay.as_secs() as u32) >= end {
+                        self.pre_publication_rollover(i, end, now)?
+                    } else {
+                        continue;
+                    }
+                }
+                _ => continue,
+            };
+            events.push(KeyEvent::Created { key_tag: key.key.key_tag(), key_signing });
+            self.keys.push(key);
+        }
+
+        for key in &self.keys {
+            key.write(&self.directory)?;
+        }
+        Ok(events)
+    }
+
+    /// Successor of the zone signing key `i`, published now and signing
+    /// once it reached the validators, from the `end` of the lifetime of
+    /// the key it replaces. The old key stays published until its
+    /// signatures left the caches.
+    fn pre_publication_rollover(&mut self, i: usize, end: u32, now: u32) -> io::Result<KeyFile> {
+        let mut key = self.generate(false, now)?;
+        let activate = end.max(now + self.policy.publish_delay.as_secs() as u32);
+        key.timings.activate = Some(activate);
+        let old = &mut self.keys[i].timings;
+        old.inactive = Some(activate);
+        old.delete = Some(activate + self.policy.retire_delay.as_secs() as u32);
+        Ok(key)
+    }
+
+    /// Successor of the key signing key `i`, signing the DNSKEY RRset along
+    /// with it at once. Once the new key reached the validators, its DS
+    /// record replaces the old one at the parent, and the old key is
+    /// removed when the DS change reached the validators too.
+    fn double_signature_rollover(&mut self, i: usize, now: u32) -> io::Result<KeyFile> {
+        let mut key = self.generate(true, now)?;
+        let sync = now + self.policy.publish_delay.as_secs() as u32;
+        key.timings.activate = Some(now);
+        key.timings.sync_publish = Some(sync);
+        let old = &mut self.keys[i].timings;
+        old.sync_delete = Some(sync);
+        old.inactive = Some(sync + self.policy.parent_delay.as_secs() as u32);
+        old.delete = old.inactive;
+        Ok(key)
+    }
+
+    /// New key published now, whose key tag differs from those of the
+    /// other keys, revoked or not
+    fn generate(&self, key_signing: bool, now: u32) -> io::Result<KeyFile> {
+        let flags = if key_signing { DNSKeyRecord::ZONE_KEY | DNSKeyRecord::SECURE_ENTRY_POINT } else { DNSKeyRecord::ZONE_KEY };
+        loop {
+            let key = SigningKey::generate(self.policy.algorithm, flags)?;
+            let mut revoked = key.dnskey.clone();
+            revoked.flags |= DNSKeyRecord::REVOKE;
+            let tags = [key.key_tag(), revoked.key_tag()];
+            if !self.keys.iter().any(|other| tags.contains(&other.key.key_tag())) {
+                let timings = KeyTimings { created: Some(now), publish: Some(now), ..Default::default() };
+                return Ok(KeyFile { zone: self.zone.clone(), key, timings });
+            }
+        }
+    }
+
+    /// DS records of the key signing keys that the parent zone should
+    /// publish now
+    pub fn ds_records(&self, digest_type: u8) -> Vec<DSRecord> {
+        self.ds_records_at(digest_type, now())
+    }
+
+    fn ds_records_at(&self, digest_type: u8, now: u32) -> Vec<DSRecord> {
+        self.keys.iter()
+            .filter(|key| key.key.is_key_signing_key() && key.timings.is_ds_published(now))
+            .filter_map(|key| key.key.dnskey.to_ds(&self.zone, digest_type))
+            .collect()
+    }
+
+    /// Signer of the zone with the keys active now, publishing the DNSKEY
+    /// records of the other keys in rollover
+    pub fn zone_signer(&self) -> ZoneSigner {
+        self.zone_signer_at(now())
+    }
+
+    fn zone_signer_at(&self, now: u32) -> ZoneSigner {
+        let active = self.keys.iter()
+            .filter(|key| key.timings.is_active(now) && key.timings.is_published(now))
+            .map(|key| key.key.clone())
+            .collect();
+        let mut signer = ZoneSigner::new(self.zone.clone(), active);
+        signer.published_keys = self.keys.iter()
+            .filter(|key| key.timings.is_published(now) && !key.timings.is_active(now))
+            .map(|key| key.key.dnskey.clone())
+            .collect();
+        signer
+    }
+}
+
+fn now() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_util::dns_dnssec::records::RRSIGRecord;
+    use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+    use crate::dns_util::dns_packet_structures::record_data::RecordData;
+
+    const NOW: u32 = 1_700_000_000;
+    const DAY: u32 = 86400;
+
+    /// Sorted key tags of the active and published keys of `signer`
+    fn key_tags(signer: &ZoneSigner) -> (Vec<u16>, Vec<u16>) {
+        let mut active: Vec<u16> = signer.keys.iter().map(|key| key.key_tag()).collect();
+        let mut published: Vec<u16> = signer.published_keys.iter().map(|key| key.key_tag()).collect();
+        active.sort();
+        published.sort();
+        (active, published)
+    }
+
+    fn sorted(mut tags: Vec<u16>) -> Vec<u16> {
+        tags.sort();
+        tags
+    }
+
+    /// Sorted key tags of the DNSKEY records and of the signatures of a
+    /// signed zone, without duplicates
+    fn signed_key_tags(signed: &[DNSResourceRecord]) -> (Vec<u16>, Vec<u16>) {
+        let mut dnskeys: Vec<u16> = signed.iter()
+            .filter(|record| record.query_type == DNSKeyRecord::RECORD_TYPE)
+            .map(|record| record.record_data::<DNSKeyRecord>().unwrap().key_tag())
+            .collect();
+        let mut signatures: Vec<u16> = signed.iter()
+            .filter(|record| record.query_type == RRSIGRecord::RECORD_TYPE)
+            .map(|record| record.record_data::<RRSIGRecord>().unwrap().key_tag)
+            .collect();
+        dnskeys.sort();
+        signatures.sort();
+        signatures.dedup();
+        (dnskeys, signatures)
+    }
+
+    #[test]
+    fn test_rollovers() {
+        let directory = std::env::temp_dir().join(format!("r-dns-key-manager-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let zone: DomainName = "example".parse().unwrap();
+        let policy = KeyPolicy { zsk_lifetime: Duration::from_secs(30 * DAY as u64), ..Default::default() };
+        let mut manager = KeyManager::open(zone.clone(), &directory, policy.clone()).unwrap();
+
+        // Initial keys, the DS record of the KSK submitted once published
+        let events = manager.update_at(NOW).unwrap();
+        let [KeyEvent::Created { key_tag: ksk, key_signing: true }, KeyEvent::Created { key_tag: zsk, key_signing: false }] = events[..] else {
+            panic!("unexpected events {:?}", events);
+        };
+        assert_eq!(key_tags(&manager.zone_signer_at(NOW)), (sorted(vec![ksk, zsk]), vec![]));
+        assert!(manager.ds_records_at(2, NOW).is_empty());
+        assert_eq!(manager.ds_records_at(2, NOW + 3 * 3600)[0].key_tag, ksk);
+        assert!(manager.update_at(NOW + DAY).unwrap().is_empty());
+
+        // The timings are kept in the key files
+        let mut manager = KeyManager::open(zone.clone(), &directory, policy).unwrap();
+        assert_eq!(manager.keys.len(), 2);
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 4);
+
+        // ZSK rollover: the new key is published ahead of its activation
+        let start = NOW + 30 * DAY - 3600;
+        let events = manager.update_at(start).unwrap();
+        let [KeyEvent::Created { key_tag: new_zsk, key_signing: false }] = events[..] else {
+            panic!("unexpected events {:?}", events);
+        };
+        assert_eq!(key_tags(&manager.zone_signer_at(start)), (sorted(vec![ksk, zsk]), vec![new_zsk]));
+        let switch = NOW + 30 * DAY + 3600;
+        assert_eq!(key_tags(&manager.zone_signer_at(switch)), (sorted(vec![ksk, new_zsk]), vec![zsk]));
+        assert!(manager.update_at(switch).unwrap().is_empty());
+        let removal = switch + DAY + 3600;
+        assert_eq!(manager.update_at(removal).unwrap(), vec![KeyEvent::Removed { key_tag: zsk, key_signing: false }]);
+        assert_eq!(key_tags(&manager.zone_signer_at(removal)), (sorted(vec![ksk, new_zsk]), vec![]));
+
+        // KSK rollover: both keys sign, then the DS records are swapped
+        let start = NOW + 365 * DAY;
+        let events = manager.update_at(start).unwrap();
+        let new_ksk = events.iter().find_map(|event| match event {
+            KeyEvent::Created { key_tag, key_signing: true } => Some(*key_tag),
+            _ => None,
+        }).unwrap();
+        let signer = manager.zone_signer_at(start);
+        assert!(signer.keys.iter().filter(|key| key.is_key_signing_key()).count() == 2);
+        let ds_tags = |time: u32| manager.ds_records_at(2, time).iter().map(|ds| ds.key_tag).collect::<Vec<u16>>();
+        assert_eq!(ds_tags(start), vec![ksk]);
+        assert_eq!(ds_tags(start + 2 * 3600), vec![new_ksk]);
+        let removal = start + 2 * 3600 + 2 * DAY;
+        let events = manager.update_at(removal).unwrap();
+        assert!(events.contains(&KeyEvent::Removed { key_tag: ksk, key_signing: true }));
+        assert!(!manager.keys.iter().any(|key| key.key.key_tag() == ksk));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_rollovers_of_signed_zone() {
+        let directory = std::env::temp_dir().join(format!("r-dns-key-manager-signed-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let zone: DomainName = "example".parse().unwrap();
+        let policy = KeyPolicy { zsk_lifetime: Duration::from_secs(30 * DAY as u64), ..Default::default() };
+        let mut manager = KeyManager::open(zone.clone(), &directory, policy).unwrap();
+        let soa = [zone.to_labels(), zone.to_labels(), [0, 0, 0, 1, 0, 0, 14, 16, 0, 0, 7, 8, 0, 9, 58, 128, 0, 0, 1, 44].to_vec()].concat();
+        let records = vec![DNSResourceRecord {
+            query_name: zone.to_labels(),
+            query_type: 6,
+            query_class: 1,
+            record_ttl: 3600,
+            rdata_length: soa.len() as u16,
+            rdata: soa,
+        }];
+
+        let events = manager.update_at(NOW).unwrap();
+        let [KeyEvent::Created { key_tag: ksk, .. }, KeyEvent::Created { key_tag: zsk, .. }] = events[..] else {
+            panic!("unexpected events {:?}", events);
+        };
+        let mut signed = manager.zone_signer_at(NOW).sign(&records).unwrap();
+
+        // Each phase of the rollovers signs the zone signed by the previous
+        // one, until the old keys and their signatures are gone
+        let zsk_start = NOW + 30 * DAY - 3600;
+        let zsk_switch = NOW + 30 * DAY + 3600;
+        let zsk_removal = zsk_switch + DAY + 3600;
+        let mut new_zsk = None;
+        for time in [zsk_start, zsk_switch, zsk_removal] {
+            for event in manager.update_at(time).unwrap() {
+                if let KeyEvent::Created { key_tag, key_signing: false } = event {
+                    new_zsk = Some(key_tag);
+                }
+            }
+            signed = manager.zone_signer_at(time).sign(&signed).unwrap();
+        }
+        let new_zsk = new_zsk.unwrap();
+        assert_eq!(signed_key_tags(&signed), (sorted(vec![ksk, new_zsk]), sorted(vec![ksk, new_zsk])));
+
+        let ksk_start = NOW + 365 * DAY;
+        let ksk_removal = ksk_start + 2 * 3600 + 2 * DAY;
+        let mut new_ksk = None;
+        for time in [ksk_start, ksk_removal] {
+            for event in manager.update_at(time).unwrap() {
+                if let KeyEvent::Created { key_tag, key_signing: true } = event {
+                    new_ksk = Some(key_tag);
+                }
+            }
+            signed = manager.zone_signer_at(time).sign(&signed).unwrap();
+        }
+        let (dnskeys, signatures) = signed_key_tags(&signed);
+        assert!(!dnskeys.contains(&ksk) && !signatures.contains(&ksk) && !dnskeys.contains(&zsk) && !signatures.contains(&zsk));
+        assert!(dnskeys.contains(&new_ksk.unwrap()) && signatures.contains(&new_ksk.unwrap()));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,709 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::digest;
+
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+use crate::dns_util::dns_packet_structures::util::query_type_name;
+
+/// Public key of a zone (RFC 4034 section 2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DNSKeyRecord {
+    /// `ZONE_KEY`, `SECURE_ENTRY_POINT` and `REVOKE` bits
+    pub flags: u16,
+    /// Always 3
+    pub protocol: u8,
+    /// Algorithm number, as listed by IANA (8 = RSA/SHA-256, 13 = ECDSA
+    /// P-256, 15 = Ed25519...)
+    pub algorithm: u8,
+    pub public_key: Vec<u8>,
+}
+
+/// Signature of an RRset (RFC 4034 section 3)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RRSIGRecord {
+    /// Type of the RRset signed
+    pub type_covered: u16,
+    pub algorithm: u8,
+    /// Number of labels of the owner name, without the root nor a leading
+    /// wildcard
+    pub labels: u8,
+    /// TTL of the RRset as in the zone
+    pub original_ttl: u32,
+    /// End of validity, in seconds since 1970 modulo 2^32
+    pub signature_expiration: u32,
+    /// Start of validity, in seconds since 1970 modulo 2^32
+    pub signature_inception: u32,
+    /// Key tag of the DNSKEY that made the signature
+    pub key_tag: u16,
+    /// Zone of the DNSKEY that made the signature
+    pub signer_name: DomainName,
+    pub signature: Vec<u8>,
+}
+
+/// Digest of a DNSKEY of a child zone, published by its parent (RFC 4034
+/// section 5)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DSRecord {
+    pub key_tag: u16,
+    pub algorithm: u8,
+    /// 1 = SHA-1, 2 = SHA-256, 4 = SHA-384
+    pub digest_type: u8,
+    pub digest: Vec<u8>,
+}
+
+/// Next owner name of the zone in canonical order, with the types of the
+/// owner (RFC 4034 section 4)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NSECRecord {
+    pub next_domain_name: DomainName,
+    /// Types present at the owner name, in increasing order
+    pub types: Vec<u16>,
+}
+
+/// Hashed version of NSEC (RFC 5155 section 3)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NSEC3Record {
+    /// 1 = SHA-1
+    pub hash_algorithm: u8,
+    /// Opt-out bit
+    pub flags: u8,
+    /// Additional hash iterations
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+    /// Hash of the next owner name in hash order
+    pub next_hashed_owner_name: Vec<u8>,
+    /// Types present at the original owner name, in increasing order
+    pub types: Vec<u16>,
+}
+
+/// Parameters of the NSEC3 chain of a zone, at its apex (RFC 5155
+/// section 4)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NSEC3ParamRecord {
+    pub hash_algorithm: u8,
+    pub flags: u8,
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+}
+
+impl DNSKeyRecord {
+    /// Key used to sign the zone
+    pub const ZONE_KEY: u16 = 0x0100;
+    /// Key signing key, pointed to by the DS records of the parent
+    pub const SECURE_ENTRY_POINT: u16 = 0x0001;
+    /// Key revoked by its owner (RFC 5011)
+    pub const REVOKE: u16 = 0x0080;
+
+    pub fn is_zone_key(&self) -> bool {
+        self.flags & Self::ZONE_KEY != 0
+    }
+
+    pub fn is_secure_entry_point(&self) -> bool {
+        self.flags & Self::SECURE_ENTRY_POINT != 0
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.flags & Self::REVOKE != 0
+    }
+
+    /// Key tag identifying the key in RRSIG and DS records (RFC 4034
+    /// appendix B)
+    pub fn key_tag(&self) -> u16 {
+        // RSA/MD5 uses the end of the modulus instead
+        if self.algorithm == 1 {
+            let length = self.public_key.len();
+            if length < 3 {
+                return 0;
+            }
+            return u16::from_be_bytes([self.public_key[length - 3], self.public_key[length - 2]]);
+        }
+        let mut accumulator: u32 = 0;
+        for (i, byte) in self.to_rdata().iter().enumerate() {
+            accumulator += if i & 1 == 0 { (*byte as u32) << 8 } else { *byte as u32 };
+        }
+        accumulator += accumulator >> 16 & 0xffff;
+        (accumulator & 0xffff) as u16
+    }
+
+    /// DS record of the key for the parent of `owner` (RFC 4034 section
+    /// 5.1.4). `None` for an unknown digest type.
+    pub fn to_ds(&self, owner: &DomainName, digest_type: u8) -> Option<DSRecord> {
+        let algorithm = match digest_type {
+            1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            2 => &digest::SHA256,
+            4 => &digest::SHA384,
+            _ => return None,
+        };
+        let mut context = digest::Context::new(algorithm);
+        context.update(&owner.to_lowercase().to_labels());
+        context.update(&self.to_rdata());
+        Some(DSRecord {
+            key_tag: self.key_tag(),
+            algorithm: self.algorithm,
+            digest_type,
+            digest: context.finish().as_ref().to_vec(),
+        })
+    }
+}
+
+impl RecordData for DNSKeyRecord {
+    const RECORD_TYPE: u16 = 48;
+
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self> {
+        let fixed = fixed_fields::<4>(rdata, "DNSKEY")?;
+        Ok(Self {
+            flags: u16::from_be_bytes([fixed[0], fixed[1]]),
+            protocol: fixed[2],
+            algorithm: fixed[3],
+            public_key: rdata[4..].to_vec(),
+        })
+    }
+
+    fn to_rdata(&self) -> Vec<u8> {
+        let mut rdata: Vec<u8> = Vec::with_capacity(4 + self.public_key.len());
+        rdata.extend(self.flags.to_be_bytes());
+        rdata.push(self.protocol);
+        rdata.push(self.algorithm);
+        rdata.extend(&self.public_key);
+        rdata
+    }
+}
+
+impl RRSIGRecord {
+    /// Rdata without the signature, which is what gets signed before the
+    /// RRset (RFC 4034 section 3.1.8.1)
+    pub fn rdata_without_signature(&self) -> Vec<u8> {
+        let mut rdata: Vec<u8> = Vec::new();
+        rdata.extend(self.type_covered.to_be_bytes());
+        rdata.push(self.algorithm);
+        rdata.push(self.labels);
+        rdata.extend(self.original_ttl.to_be_bytes());
+        rdata.extend(self.signature_expiration.to_be_bytes());
+        rdata.extend(self.signature_inception.to_be_bytes());
+        rdata.extend(self.key_tag.to_be_bytes());
+        rdata.extend(self.signer_name.to_lowercase().to_labels());
+        rdata
+    }
+}
+
+impl RecordData for RRSIGRecord {
+    const RECORD_TYPE: u16 = 46;
+
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self> {
+        let fixed = fixed_fields::<18>(rdata, "RRSIG")?;
+        let (signer_name, name_length) = read_name(&rdata[18..], "RRSIG")?;
+        Ok(Self {
+            type_covered: u16::from_be_bytes([fixed[0], fixed[1]]),
+            algorithm: fixed[2],
+            labels: fixed[3],
+            original_ttl: u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]),
+            signature_expiration: u32::from_be_bytes([fixed[8], fixed[9], fixed[10], fixed[11]]),
+            signature_inception: u32::from_be_bytes([fixed[12], fixed[13], fixed[14], fixed[15]]),
+            key_tag: u16::from_be_bytes([fixed[16], fixed[17]]),
+            signer_name,
+            signature: rdata[18 + name_length..].to_vec(),
+        })
+    }
+
+    fn to_rdata(&self) -> Vec<u8> {
+        let mut rdata: Vec<u8> = Vec::new();
+        rdata.extend(self.type_covered.to_be_bytes());
+        rdata.push(self.algorithm);
+        rdata.push(self.labels);
+        rdata.extend(self.original_ttl.to_be_bytes());
+        rdata.extend(self.signature_expiration.to_be_bytes());
+        rdata.extend(self.signature_inception.to_be_bytes());
+        rdata.extend(self.key_tag.to_be_bytes());
+        rdata.extend(self.signer_name.to_labels());
+        rdata.extend(&self.signature);
+        rdata
+    }
+}
+
+impl RecordData for DSRecord {
+    const RECORD_TYPE: u16 = 43;
+
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self> {
+        let fixed = fixed_fields::<4>(rdata, "DS")?;
+        Ok(Self {
+            key_tag: u16::from_be_bytes([fixed[0], fixed[1]]),
+            algorithm: fixed[2],
+            digest_type: fixed[3],
+            digest: rdata[4..].to_vec(),
+        })
+    }
+
+    fn to_rdata(&self) -> Vec<u8> {
+        let mut rdata: Vec<u8> = Vec::with_capacity(4 + self.digest.len());
+        rdata.extend(self.key_tag.to_be_bytes());
+        rdata.push(self.algorithm);
+        rdata.push(self.digest_type);
+        rdata.extend(&self.digest);
+        rdata
+    }
+}
+
+impl RecordData for NSECRecord {
+    const RECORD_TYPE: u16 = 47;
+
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self> {
+        let (next_domain_name, name_length) = read_name(rdata, "NSEC")?;
+        Ok(Self {
+            next_domain_name,
+            types: decode_type_bitmap(&rdata[name_length..])?,
+        })
+    }
+
+    fn to_rdata(&self) -> Vec<u8> {
+        let mut rdata = self.next_domain_name.to_labels();
+        rdata.extend(encode_type_bitmap(&self.types));
+        rdata
+    }
+}
+
+impl NSEC3Record {
+    /// Opt-out flag: unsigned delegations may be missing from the chain
+    pub const OPT_OUT: u8 = 0x01;
+
+    pub fn is_opt_out(&self) -> bool {
+        self.flags & Self::OPT_OUT != 0
+    }
+}
+
+impl RecordData for NSEC3Record {
+    const RECORD_TYPE: u16 = 50;
+
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self> {
+        let (parameters, position) = read_nsec3_parameters(rdata, "NSEC3")?;
+        let hash_length = *rdata.get(position).ok_or_else(|| truncated("NSEC3"))? as usize;
+        let next_hashed_owner_name = rdata.get(position + 1..position + 1 + hash_length)
+            .ok_or_else(|| truncated("NSEC3"))?
+            .to_vec();
+        Ok(Self {
+            hash_algorithm: parameters.hash_algorithm,
+            flags: parameters.flags,
+            iterations: parameters.iterations,
+            salt: parameters.salt,
+            next_hashed_owner_name,
+            types: decode_type_bitmap(&rdata[position + 1 + hash_length..])?,
+        })
+    }
+
+    fn to_rdata(&self) -> Vec<u8> {
+        let mut rdata = NSEC3ParamRecord {
+            hash_algorithm: self.hash_algorithm,
+            flags: self.flags,
+            iterations: self.iterations,
+            salt: self.salt.clone(),
+        }.to_rdata();
+        rdata.push(self.next_hashed_owner_name.len() as u8);
+        rdata.extend(&self.next_hashed_owner_name);
+        rdata.extend(encode_type_bitmap(&self.types));
+        rdata
+    }
+}
+
+impl RecordData for NSEC3ParamRecord {
+    const RECORD_TYPE: u16 = 51;
+
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self> {
+        Ok(read_nsec3_parameters(rdata, "NSEC3PARAM")?.0)
+    }
+
+    fn to_rdata(&self) -> Vec<u8> {
+        let mut rdata: Vec<u8> = Vec::with_capacity(5 + self.salt.len());
+        rdata.push(self.hash_algorithm);
+        rdata.push(self.flags);
+        rdata.extend(self.iterations.to_be_bytes());
+        rdata.push(self.salt.len() as u8);
+        rdata.extend(&self.salt);
+        rdata
+    }
+}
+
+/// Hashed owner name of `name` for NSEC3 (RFC 5155 section 5), with SHA-1,
+/// the only algorithm defined
+pub fn nsec3_hash(name: &DomainName, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let hash_round = |input: &[u8]| {
+        let mut context = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+        context.update(input);
+        context.update(salt);
+        context.finish().as_ref().to_vec()
+    };
+    let mut hash = hash_round(&name.to_lowercase().to_labels());
+    for _i in 0..iterations {
+        hash = hash_round(&hash);
+    }
+    hash
+}
+
+/// Encodes a list of types in the window blocks of NSEC and NSEC3 records
+/// (RFC 4034 section 4.1.2)
+pub fn encode_type_bitmap(types: &[u16]) -> Vec<u8> {
+    let mut types = types.to_vec();
+    types.sort_unstable();
+    types.dedup();
+
+    let mut bitmap: Vec<u8> = Vec::new();
+    let mut position = 0;
+    while position < types.len() {
+        let window = types[position] >> 8;
+        let mut block: Vec<u8> = Vec::new();
+        while position < types.len() && types[position] >> 8 == window {
+            let low = (types[position] & 0xff) as usize;
+            if block.len() <= low / 8 {
+                block.resize(low / 8 + 1, 0);
+            }
+            block[low / 8] |= 0x80 >> (low % 8);
+            position += 1;
+        }
+        bitmap.push(window as u8);
+        bitmap.push(block.len() as u8);
+        bitmap.extend(block);
+    }
+    bitmap
+}
+
+/// Decodes the window blocks of NSEC and NSEC3 records
+pub fn decode_type_bitmap(bitmap: &[u8]) -> io::Result<Vec<u16>> {
+    let mut types: Vec<u16> = Vec::new();
+    let mut position = 0;
+    while position < bitmap.len() {
+        let window = bitmap[position] as u16;
+        let length = *bitmap.get(position + 1).ok_or_else(|| truncated("type bitmap"))? as usize;
+        if length == 0 || length > 32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid type bitmap block length"));
+        }
+        let block = bitmap.get(position + 2..position + 2 + length).ok_or_else(|| truncated("type bitmap"))?;
+        for (i, byte) in block.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    types.push(window << 8 | (i * 8 + bit) as u16);
+                }
+            }
+        }
+        position += 2 + length;
+    }
+    Ok(types)
+}
+
+fn read_nsec3_parameters(rdata: &[u8], record_type: &str) -> io::Result<(NSEC3ParamRecord, usize)> {
+    let fixed = fixed_fields::<5>(rdata, record_type)?;
+    let salt_length = fixed[4] as usize;
+    let salt = rdata.get(5..5 + salt_length).ok_or_else(|| truncated(record_type))?.to_vec();
+    Ok((NSEC3ParamRecord {
+        hash_algorithm: fixed[0],
+        flags: fixed[1],
+        iterations: u16::from_be_bytes([fixed[2], fixed[3]]),
+        salt,
+    }, 5 + salt_length))
+}
+
+fn fixed_fields<const N: usize>(rdata: &[u8], record_type: &str) -> io::Result<[u8; N]> {
+    rdata.get(..N)
+        .and_then(|fixed| fixed.try_into().ok())
+        .ok_or_else(|| truncated(record_type))
+}
+
+/// Reads an uncompressed name, returning it with its length
+fn read_name(rdata: &[u8], record_type: &str) -> io::Result<(DomainName, usize)> {
+    let name = DomainName::from_labels(rdata)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("invalid name in {} record", record_type)))?;
+    let length = name.wire_length();
+    Ok((name, length))
+}
+
+fn truncated(record_type: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("truncated {} record", record_type))
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+fn type_list(types: &[u16]) -> String {
+    types.iter().map(|record_type| query_type_name(*record_type)).collect::<Vec<String>>().join(" ")
+}
+
+/// Base 32 with the extended hex alphabet, without padding (RFC 4648
+/// section 7), as used for NSEC3 hashes
+pub fn base32hex(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+    let mut encoded = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = buffer << 8 | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[(buffer >> bits & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[(buffer << (5 - bits) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+/// Timestamp of RRSIG records in the `YYYYMMDDHHmmSS` format (RFC 4034
+/// section 3.2)
+pub fn format_timestamp(timestamp: u32) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+    // Civil date from the days since 1970, by Howard Hinnant's algorithm
+    let shifted = days + 719468;
+    let era = shifted / 146097;
+    let day_of_era = shifted - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}{:02}{:02}{:02}{:02}{:02}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Seconds since 1970 of a `YYYYMMDDHHmmSS` timestamp, the inverse of
+/// `format_timestamp`
+pub fn parse_timestamp(text: &str) -> Option<u32> {
+    if text.len() != 14 || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| text[range].parse::<i64>().ok();
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hours, minutes, seconds) = (field(8..10)?, field(10..12)?, field(12..14)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+    // Days since 1970 of a civil date, by Howard Hinnant's algorithm
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    u32::try_from(days * 86400 + hours * 3600 + minutes * 60 + seconds).ok()
+}
+
+impl fmt::Display for DNSKeyRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.flags, self.protocol, self.algorithm, STANDARD.encode(&self.public_key))
+    }
+}
+
+impl fmt::Display for RRSIGRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{} {} {} {} {} {} {} {} {}",
+            query_type_name(self.type_covered),
+            self.algorithm,
+            self.labels,
+            self.original_ttl,
+            format_timestamp(self.signature_expiration),
+            format_timestamp(self.signature_inception),
+            self.key_tag,
+            self.signer_name.to_fqdn_string(),
+            STANDARD.encode(&self.signature),
+        )
+    }
+}
+
+impl fmt::Display for DSRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.key_tag, self.algorithm, self.digest_type, hex(&self.digest))
+    }
+}
+
+impl fmt::Display for NSECRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.next_domain_name.to_fqdn_string(), type_list(&self.types))
+    }
+}
+
+impl fmt::Display for NSEC3Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parameters = NSEC3ParamRecord {
+            hash_algorithm: self.hash_algorithm,
+            flags: self.flags,
+            iterations: self.iterations,
+            salt: self.salt.clone(),
+        };
+        write!(f, "{} {} {}", parameters, base32hex(&self.next_hashed_owner_name), type_list(&self.types))
+    }
+}
+
+impl fmt::Display for NSEC3ParamRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let salt = if self.salt.is_empty() { "-".to_string() } else { hex(&self.salt) };
+        write!(f, "{} {} {} {}", self.hash_algorithm, self.flags, self.iterations, salt)
+    }
+}
+
+/// Parses the presentation format of the rdata, the key possibly split
+/// in several blocks
+impl FromStr for DNSKeyRecord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(format!("missing fields in DNSKEY {}", text));
+        }
+        Ok(Self {
+            flags: fields[0].parse().map_err(|_| format!("invalid DNSKEY flags {}", fields[0]))?,
+            protocol: fields[1].parse().map_err(|_| format!("invalid DNSKEY protocol {}", fields[1]))?,
+            algorithm: fields[2].parse().map_err(|_| format!("invalid DNSKEY algorithm {}", fields[2]))?,
+            public_key: STANDARD.decode(fields[3..].concat()).map_err(|e| format!("invalid DNSKEY public key: {}", e))?,
+        })
+    }
+}
+
+/// Parses the presentation format of the rdata, the digest possibly split
+/// in several blocks
+impl FromStr for DSRecord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(format!("missing fields in DS {}", text));
+        }
+        Ok(Self {
+            key_tag: fields[0].parse().map_err(|_| format!("invalid DS key tag {}", fields[0]))?,
+            algorithm: fields[1].parse().map_err(|_| format!("invalid DS algorithm {}", fields[1]))?,
+            digest_type: fields[2].parse().map_err(|_| format!("invalid DS digest type {}", fields[2]))?,
+            digest: from_hex(&fields[3..].concat()).ok_or_else(|| format!("invalid DS digest {}", fields[3..].concat()))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // DNSKEY of RFC 4034 section 2.3, whose key tag is given in section 5.4
+    const RFC4034_DNSKEY: &str = "AQOeiiR0GOMYkDshWoSKz9XzfwJr1AYtsmx3TGkJaNXVbfi/2pHm822aJ5iI9BMzNXxeYCmZDRD99WYwYqUSdjMmmAphXdvxegXd/M5+X7OrzKBaMbCVdFLUUh6DhweJBjEVv5f2wwjM9XzcnOf+EPbtG9DMBmADjFDc2w/rljwvFw==";
+
+    #[test]
+    fn test_dnskey_round_trip_and_key_tag() {
+        let key = DNSKeyRecord {
+            flags: 256,
+            protocol: 3,
+            algorithm: 5,
+            public_key: STANDARD.decode(RFC4034_DNSKEY).unwrap(),
+        };
+        assert_eq!(DNSKeyRecord::from_rdata(&key.to_rdata()).unwrap(), key);
+        assert_eq!(key.key_tag(), 60485);
+        assert!(key.is_zone_key() && !key.is_secure_entry_point() && !key.is_revoked());
+        assert_eq!(key.to_string(), format!("256 3 5 {}", RFC4034_DNSKEY));
+    }
+
+    #[test]
+    fn test_rrsig_round_trip() {
+        let signature = RRSIGRecord {
+            type_covered: 1,
+            algorithm: 5,
+            labels: 3,
+            original_ttl: 86400,
+            signature_expiration: 1081535777,
+            signature_inception: 1078857377,
+            key_tag: 2642,
+            signer_name: "example.com".parse().unwrap(),
+            signature: vec![1, 2, 3],
+        };
+        assert_eq!(RRSIGRecord::from_rdata(&signature.to_rdata()).unwrap(), signature);
+        // Example of RFC 4034 section 3.3
+        assert_eq!(signature.to_string(), "A 5 3 86400 20040409183617 20040309183617 2642 example.com. AQID");
+        assert!(RRSIGRecord::from_rdata(&signature.to_rdata()[..10]).is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("20040409183617"), Some(1081535777));
+        assert_eq!(parse_timestamp("19700101000000"), Some(0));
+        assert_eq!(parse_timestamp("20240229120000").map(format_timestamp).as_deref(), Some("20240229120000"));
+        assert_eq!(parse_timestamp("20041309183617"), None);
+        assert_eq!(parse_timestamp("2004040918361"), None);
+    }
+
+    #[test]
+    fn test_ds_round_trip() {
+        let ds = DSRecord {
+            key_tag: 60485,
+            algorithm: 5,
+            digest_type: 1,
+            digest: vec![0x2b, 0xb1, 0x83, 0xaf],
+        };
+        assert_eq!(DSRecord::from_rdata(&ds.to_rdata()).unwrap(), ds);
+        assert_eq!(ds.to_string(), "60485 5 1 2BB183AF");
+        assert_eq!("60485 5 1 2BB1 83af".parse::<DSRecord>().unwrap(), ds);
+        assert!("60485 5 1 2BB18".parse::<DSRecord>().is_err());
+    }
+
+    #[test]
+    fn test_ds_of_key() {
+        // DNSKEY and DS of RFC 4034 section 5.4
+        let key: DNSKeyRecord = format!("256 3 5 {}", RFC4034_DNSKEY).parse().unwrap();
+        let ds = key.to_ds(&"dskey.example.com".parse().unwrap(), 1).unwrap();
+        assert_eq!(ds.to_string(), "60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118");
+        assert!(key.to_ds(&"dskey.example.com".parse().unwrap(), 3).is_none());
+    }
+
+    #[test]
+    fn test_nsec_type_bitmap() {
+        // Example of RFC 4034 section 4.3
+        let nsec = NSECRecord {
+            next_domain_name: "host.example.com".parse().unwrap(),
+            types: vec![1, 15, 46, 47, 1234],
+        };
+        let rdata = nsec.to_rdata();
+        assert_eq!(rdata[18..], [
+            0x00, 0x06, 0x40, 0x01, 0x00, 0x00, 0x00, 0x03,
+            0x04, 0x1b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x20,
+        ]);
+        assert_eq!(NSECRecord::from_rdata(&rdata).unwrap(), nsec);
+        assert_eq!(nsec.to_string(), "host.example.com. A MX RRSIG NSEC TYPE1234");
+    }
+
+    #[test]
+    fn test_nsec3_hash_and_round_trip() {
+        // Hashes of RFC 5155 appendix A, salt AABBCCDD and 12 iterations
+        let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+        assert_eq!(base32hex(&nsec3_hash(&"example".parse().unwrap(), &salt, 12)), "0P9MHAVEQVM6T7VBL5LOP2U3T2RP3TOM");
+        assert_eq!(base32hex(&nsec3_hash(&"a.example".parse().unwrap(), &salt, 12)), "35MTHGPGCU1QG68FAB165KLNSNK3DPVL");
+
+        let nsec3 = NSEC3Record {
+            hash_algorithm: 1,
+            flags: 1,
+            iterations: 12,
+            salt: salt.to_vec(),
+            next_hashed_owner_name: nsec3_hash(&"a.example".parse().unwrap(), &salt, 12),
+            types: vec![2, 6, 46, 48, 51],
+        };
+        assert_eq!(NSEC3Record::from_rdata(&nsec3.to_rdata()).unwrap(), nsec3);
+        assert!(nsec3.is_opt_out());
+        assert_eq!(nsec3.to_string(), "1 1 12 AABBCCDD 35MTHGPGCU1QG68FAB165KLNSNK3DPVL NS SOA RRSIG DNSKEY NSEC3PARAM");
+
+        let parameters = NSEC3ParamRecord { hash_algorithm: 1, flags: 0, iterations: 0, salt: Vec::new() };
+        assert_eq!(NSEC3ParamRecord::from_rdata(&parameters.to_rdata()).unwrap(), parameters);
+        assert_eq!(parameters.to_string(), "1 0 0 -");
+    }
+}
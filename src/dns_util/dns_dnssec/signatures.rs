@@ -0,0 +1,103 @@
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+
+use crate::dns_util::dns_dnssec::records::{DNSKeyRecord, RRSIGRecord};
+use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::util::canonical_rdata;
+
+/// RSA/SHA-1 (RFC 3110)
+pub const RSASHA1: u8 = 5;
+/// RSA/SHA-1 with NSEC3 (RFC 5155)
+pub const RSASHA1_NSEC3_SHA1: u8 = 7;
+/// RSA/SHA-256 (RFC 5702)
+pub const RSASHA256: u8 = 8;
+/// RSA/SHA-512 (RFC 5702)
+pub const RSASHA512: u8 = 10;
+/// ECDSA on P-256 with SHA-256 (RFC 6605)
+pub const ECDSAP256SHA256: u8 = 13;
+/// ECDSA on P-384 with SHA-384 (RFC 6605)
+pub const ECDSAP384SHA384: u8 = 14;
+/// Ed25519 (RFC 8080)
+pub const ED25519: u8 = 15;
+
+/// True if signatures of `algorithm` can be verified
+pub fn is_supported_algorithm(algorithm: u8) -> bool {
+    matches!(algorithm, RSASHA1 | RSASHA1_NSEC3_SHA1 | RSASHA256 | RSASHA512 | ECDSAP256SHA256 | ECDSAP384SHA384 | ED25519)
+}
+
+/// Data covered by `signature` over the RRset `records` (RFC 4034 section
+/// 3.1.8.1): the RRSIG rdata without the signature, followed by the records
+/// in canonical form and order, with the original TTL. The owner of a
+/// record expanded from a wildcard is the wildcard itself.
+pub fn signed_data(signature: &RRSIGRecord, records: &[&DNSResourceRecord]) -> Vec<u8> {
+    let mut data = signature.rdata_without_signature();
+    let mut canonical_records: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for record in records {
+        let owner = DomainName::from_labels(&record.query_name).unwrap_or_default().to_lowercase();
+        let owner = if (signature.labels as usize) < owner.label_count() {
+            owner.suffix(signature.labels as usize).prepend(b"*").unwrap_or(owner)
+        } else {
+            owner
+        };
+        canonical_records.push((owner.to_labels(), canonical_rdata(record.query_type, &record.rdata)));
+    }
+    canonical_records.sort_by(|a, b| a.1.cmp(&b.1));
+    canonical_records.dedup();
+    for (owner, rdata) in canonical_records {
+        data.extend(owner);
+        data.extend(signature.type_covered.to_be_bytes());
+        data.extend(records[0].query_class.to_be_bytes());
+        data.extend(signature.original_ttl.to_be_bytes());
+        data.extend((rdata.len() as u16).to_be_bytes());
+        data.extend(rdata);
+    }
+    data
+}
+
+/// Checks the cryptographic signature of `records` by `key`. The validity
+/// period and the fields of the RRSIG are left to the caller.
+pub fn verify_signature(signature: &RRSIGRecord, key: &DNSKeyRecord, records: &[&DNSResourceRecord]) -> bool {
+    if records.is_empty() || signature.algorithm != key.algorithm {
+        return false;
+    }
+    let data = signed_data(signature, records);
+    let public_key = &key.public_key;
+    match key.algorithm {
+        RSASHA1 | RSASHA1_NSEC3_SHA1 | RSASHA256 | RSASHA512 => {
+            let parameters = match key.algorithm {
+                RSASHA256 => &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+                RSASHA512 => &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+                _ => &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+            };
+            let Some((e, n)) = rsa_components(public_key) else {
+                return false;
+            };
+            RsaPublicKeyComponents { n, e }.verify(parameters, &data, &signature.signature).is_ok()
+        }
+        ECDSAP256SHA256 | ECDSAP384SHA384 => {
+            let parameters = if key.algorithm == ECDSAP256SHA256 {
+                &signature::ECDSA_P256_SHA256_FIXED
+            } else {
+                &signature::ECDSA_P384_SHA384_FIXED
+            };
+            // Uncompressed point, whose prefix is left out of DNSKEY records
+            let point = [&[4], public_key.as_slice()].concat();
+            UnparsedPublicKey::new(parameters, point).verify(&data, &signature.signature).is_ok()
+        }
+        ED25519 => UnparsedPublicKey::new(&signature::ED25519, public_key).verify(&data, &signature.signature).is_ok(),
+        _ => false,
+    }
+}
+
+/// Exponent and modulus of an RSA key in DNSKEY format (RFC 3110 section 2)
+fn rsa_components(public_key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (exponent_length, exponent_start) = match *public_key.first()? {
+        0 => (u16::from_be_bytes([*public_key.get(1)?, *public_key.get(2)?]) as usize, 3),
+        length => (length as usize, 1),
+    };
+    let modulus_start = exponent_start + exponent_length;
+    if modulus_start >= public_key.len() {
+        return None;
+    }
+    Some((&public_key[exponent_start..modulus_start], &public_key[modulus_start..]))
+}
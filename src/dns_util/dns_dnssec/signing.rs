@@ -0,0 +1,205 @@
+use std::io;
+use std::sync::Arc;
+
+use ring::rand::SystemRandom;
+use ring::rsa::{KeyPairComponents, PublicKeyComponents};
+use ring::signature::{self, EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaKeyPair};
+
+use crate::dns_util::dns_dnssec::records::{DNSKeyRecord, RRSIGRecord};
+use crate::dns_util::dns_dnssec::signatures::{signed_data, ECDSAP256SHA256, ECDSAP384SHA384, ED25519, RSASHA256, RSASHA512};
+use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+pub(crate) enum PrivateKey {
+    Rsa(RsaKeyPair),
+    Ecdsa(EcdsaKeyPair),
+    Ed25519(Ed25519KeyPair),
+}
+
+/// Secret part of a private key, as stored in key files
+#[derive(Clone)]
+pub(crate) enum KeySecret {
+    /// Private scalar of ECDSA, seed of Ed25519
+    PrivateKey(Vec<u8>),
+    /// Fields of an RSA key: modulus, public exponent, private exponent,
+    /// prime 1, prime 2, exponent 1, exponent 2 and coefficient
+    Rsa([Vec<u8>; 8]),
+}
+
+/// Private key of a zone, with the DNSKEY record publishing it
+#[derive(Clone)]
+pub struct SigningKey {
+    pub dnskey: DNSKeyRecord,
+    private_key: Arc<PrivateKey>,
+    /// Unknown for RSA keys loaded from PKCS#8
+    pub(crate) secret: Option<KeySecret>,
+}
+
+impl SigningKey {
+    /// Loads a private key in PKCS#8 format, for RSA/SHA-256, RSA/SHA-512,
+    /// ECDSA P-256 and P-384, and Ed25519. `flags` are those of the DNSKEY,
+    /// with `SECURE_ENTRY_POINT` for a key signing key.
+    pub fn from_pkcs8(algorithm: u8, flags: u16, pkcs8: &[u8]) -> io::Result<Self> {
+        let rejected = |e: ring::error::KeyRejected| io::Error::new(io::ErrorKind::InvalidData, format!("invalid private key: {}", e));
+        let private_key = match algorithm {
+            RSASHA256 | RSASHA512 => PrivateKey::Rsa(RsaKeyPair::from_pkcs8(pkcs8).map_err(rejected)?),
+            ECDSAP256SHA256 | ECDSAP384SHA384 => PrivateKey::Ecdsa(
+                EcdsaKeyPair::from_pkcs8(ecdsa_signing_algorithm(algorithm), pkcs8, &SystemRandom::new()).map_err(rejected)?,
+            ),
+            ED25519 => PrivateKey::Ed25519(Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8).map_err(rejected)?),
+            _ => return Err(unsupported(algorithm)),
+        };
+        let mut key = Self::from_private_key(algorithm, flags, private_key);
+        key.secret = pkcs8_secret(algorithm, pkcs8).map(KeySecret::PrivateKey);
+        Ok(key)
+    }
+
+    /// Generates a new key for ECDSA P-256 and P-384 or Ed25519. RSA keys
+    /// cannot be generated, they must be made by other tools and loaded.
+    pub fn generate(algorithm: u8, flags: u16) -> io::Result<Self> {
+        let rng = SystemRandom::new();
+        let failed = |_| io::Error::other("key generation failed");
+        let pkcs8 = match algorithm {
+            ECDSAP256SHA256 | ECDSAP384SHA384 => EcdsaKeyPair::generate_pkcs8(ecdsa_signing_algorithm(algorithm), &rng).map_err(failed)?,
+            ED25519 => Ed25519KeyPair::generate_pkcs8(&rng).map_err(failed)?,
+            _ => return Err(io::Error::new(io::ErrorKind::Unsupported, format!("generating keys of algorithm {} not supported", algorithm))),
+        };
+        Self::from_pkcs8(algorithm, flags, pkcs8.as_ref())
+    }
+
+    /// Loads the private key whose public part is `dnskey`
+    pub(crate) fn from_secret(dnskey: &DNSKeyRecord, secret: KeySecret) -> io::Result<Self> {
+        let rejected = |e: ring::error::KeyRejected| io::Error::new(io::ErrorKind::InvalidData, format!("invalid private key: {}", e));
+        let private_key = match (dnskey.algorithm, &secret) {
+            (RSASHA256 | RSASHA512, KeySecret::Rsa([n, e, d, p, q, dp, dq, q_inv])) => PrivateKey::Rsa(
+                RsaKeyPair::from_components(&KeyPairComponents {
+                    public_key: PublicKeyComponents { n, e },
+                    d, p, q, dP: dp, dQ: dq, qInv: q_inv,
+                }).map_err(rejected)?,
+            ),
+            (ECDSAP256SHA256 | ECDSAP384SHA384, KeySecret::PrivateKey(private)) => {
+                let public = [&[4], dnskey.public_key.as_slice()].concat();
+                PrivateKey::Ecdsa(
+                    EcdsaKeyPair::from_private_key_and_public_key(ecdsa_signing_algorithm(dnskey.algorithm), private, &public, &SystemRandom::new())
+                        .map_err(rejected)?,
+                )
+            }
+            (ED25519, KeySecret::PrivateKey(seed)) => PrivateKey::Ed25519(
+                Ed25519KeyPair::from_seed_and_public_key(seed, &dnskey.public_key).map_err(rejected)?,
+            ),
+            (RSASHA256 | RSASHA512 | ECDSAP256SHA256 | ECDSAP384SHA384 | ED25519, _) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "private key does not match the algorithm"));
+            }
+            (algorithm, _) => return Err(unsupported(algorithm)),
+        };
+        let mut key = Self::from_private_key(dnskey.algorithm, dnskey.flags, private_key);
+        if key.dnskey.public_key != dnskey.public_key {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "private key does not match the public key"));
+        }
+        key.secret = Some(secret);
+        Ok(key)
+    }
+
+    pub(crate) fn from_private_key(algorithm: u8, flags: u16, private_key: PrivateKey) -> Self {
+        let public_key = match &private_key {
+            PrivateKey::Rsa(key_pair) => {
+                // RFC 3110 section 2: exponent length, exponent, modulus
+                let components = PublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+                let mut public_key: Vec<u8> = if components.e.len() < 256 {
+                    vec![components.e.len() as u8]
+                } else {
+                    [vec![0], (components.e.len() as u16).to_be_bytes().to_vec()].concat()
+                };
+                public_key.extend(&components.e);
+                public_key.extend(&components.n);
+                public_key
+            }
+            // Uncompressed point without its 0x04 prefix (RFC 6605 section 4)
+            PrivateKey::Ecdsa(key_pair) => key_pair.public_key().as_ref()[1..].to_vec(),
+            PrivateKey::Ed25519(key_pair) => key_pair.public_key().as_ref().to_vec(),
+        };
+        Self {
+            dnskey: DNSKeyRecord { flags, protocol: 3, algorithm, public_key },
+            private_key: Arc::new(private_key),
+            secret: None,
+        }
+    }
+
+    pub fn key_tag(&self) -> u16 {
+        self.dnskey.key_tag()
+    }
+
+    /// True for a key signing key, which only signs the DNSKEY RRset
+    pub fn is_key_signing_key(&self) -> bool {
+        self.dnskey.is_secure_entry_point()
+    }
+
+    /// Signs `data` in the format of RRSIG records
+    pub fn sign(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let failed = |_| io::Error::other("signing failed");
+        match self.private_key.as_ref() {
+            PrivateKey::Rsa(key_pair) => {
+                let padding = if self.dnskey.algorithm == RSASHA512 {
+                    &signature::RSA_PKCS1_SHA512
+                } else {
+                    &signature::RSA_PKCS1_SHA256
+                };
+                let mut signature = vec![0; key_pair.public().modulus_len()];
+                key_pair.sign(padding, &SystemRandom::new(), data, &mut signature).map_err(failed)?;
+                Ok(signature)
+            }
+            PrivateKey::Ecdsa(key_pair) => Ok(key_pair.sign(&SystemRandom::new(), data).map_err(failed)?.as_ref().to_vec()),
+            PrivateKey::Ed25519(key_pair) => Ok(key_pair.sign(data).as_ref().to_vec()),
+        }
+    }
+
+    /// Signature of the RRset `records` by this key of the zone `signer`,
+    /// valid from `inception` to `expiration` (seconds since 1970)
+    pub fn sign_rrset(&self, signer: &DomainName, records: &[&DNSResourceRecord], inception: u32, expiration: u32) -> io::Result<RRSIGRecord> {
+        let first = records.first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty RRset"))?;
+        let owner = DomainName::from_labels(&first.query_name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid owner name"))?;
+        // A wildcard label is not counted (RFC 4034 section 3.1.3)
+        let labels = owner.label_count() - usize::from(owner.labels().first().is_some_and(|label| label == b"*"));
+        let mut signature = RRSIGRecord {
+            type_covered: first.query_type,
+            algorithm: self.dnskey.algorithm,
+            labels: labels as u8,
+            original_ttl: records.iter().map(|record| record.record_ttl.max(0) as u32).min().unwrap_or(0),
+            signature_expiration: expiration,
+            signature_inception: inception,
+            key_tag: self.key_tag(),
+            signer_name: signer.to_lowercase(),
+            signature: Vec::new(),
+        };
+        signature.signature = self.sign(&signed_data(&signature, records))?;
+        Ok(signature)
+    }
+}
+
+fn unsupported(algorithm: u8) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("signing with algorithm {} not supported", algorithm))
+}
+
+/// Private scalar or seed in the PKCS#8 documents of ECDSA and Ed25519 keys:
+/// the `privateKey` octet string of the ECPrivateKey (RFC 5915), or the
+/// CurvePrivateKey (RFC 8410)
+fn pkcs8_secret(algorithm: u8, pkcs8: &[u8]) -> Option<Vec<u8>> {
+    let (marker, length): (&[u8], usize) = match algorithm {
+        ECDSAP256SHA256 => (&[0x02, 0x01, 0x01, 0x04, 32], 32),
+        ECDSAP384SHA384 => (&[0x02, 0x01, 0x01, 0x04, 48], 48),
+        ED25519 => (&[0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20], 32),
+        _ => return None,
+    };
+    let start = pkcs8.windows(marker.len()).position(|window| window == marker)? + marker.len();
+    pkcs8.get(start..start + length).map(|secret| secret.to_vec())
+}
+
+pub(crate) fn ecdsa_signing_algorithm(algorithm: u8) -> &'static signature::EcdsaSigningAlgorithm {
+    if algorithm == ECDSAP384SHA384 {
+        &signature::ECDSA_P384_SHA384_FIXED_SIGNING
+    } else {
+        &signature::ECDSA_P256_SHA256_FIXED_SIGNING
+    }
+}
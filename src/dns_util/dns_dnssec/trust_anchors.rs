@@ -0,0 +1,141 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::dns_util::dns_dnssec::records::{DNSKeyRecord, DSRecord};
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+/// DS records of the root key signing keys published by IANA
+/// (https://data.iana.org/root-anchors/root-anchors.xml): KSK-2017 and
+/// KSK-2024
+pub const ROOT_ANCHORS: [&str; 2] = [
+    "20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D",
+    "38696 8 2 683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16",
+];
+
+/// Keys trusted without validation, from which the chain of trust starts
+#[derive(Debug, Clone, Default)]
+pub struct TrustAnchors {
+    /// Anchors given as the DS record of a key
+    pub ds: Vec<(DomainName, DSRecord)>,
+    /// Anchors given as the key itself
+    pub keys: Vec<(DomainName, DNSKeyRecord)>,
+}
+
+impl TrustAnchors {
+    /// The keys of the root zone
+    pub fn root() -> Self {
+        Self {
+            ds: ROOT_ANCHORS.iter()
+                .map(|anchor| (DomainName::root(), anchor.parse().expect("invalid root anchor")))
+                .collect(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Reads a file of DS and DNSKEY records in zone file format, such as
+    /// the `trust-anchor-file` of Unbound or the output of `dig DNSKEY`
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Parses records written as `owner [ttl] [class] type rdata`, possibly
+    /// split over several lines with parentheses. Records of other types
+    /// are ignored.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut anchors = Self::default();
+        for entry in zone_file_entries(text) {
+            let fields: Vec<&str> = entry.split_whitespace().collect();
+            let Some(type_position) = fields.iter().position(|field| matches!(field.to_ascii_uppercase().as_str(), "DS" | "DNSKEY")) else {
+                continue;
+            };
+            if type_position == 0 || type_position > 3 {
+                return Err(format!("invalid trust anchor {}", entry));
+            }
+            let owner: DomainName = fields[0].parse()?;
+            let rdata = fields[type_position + 1..].join(" ");
+            if fields[type_position].eq_ignore_ascii_case("DS") {
+                anchors.ds.push((owner, rdata.parse()?));
+            } else {
+                anchors.keys.push((owner, rdata.parse()?));
+            }
+        }
+        Ok(anchors)
+    }
+
+    /// Deepest zone with an anchor among `name` and its ancestors
+    pub fn closest_zone(&self, name: &DomainName) -> Option<DomainName> {
+        self.ds.iter().map(|(zone, _)| zone)
+            .chain(self.keys.iter().map(|(zone, _)| zone))
+            .filter(|zone| name.is_subdomain_of(zone))
+            .max_by_key(|zone| zone.label_count())
+            .cloned()
+    }
+
+    /// DS records anchoring `zone`
+    pub fn ds_of(&self, zone: &DomainName) -> Vec<&DSRecord> {
+        self.ds.iter().filter(|(owner, _)| owner == zone).map(|(_, ds)| ds).collect()
+    }
+
+    /// Keys anchoring `zone`
+    pub fn keys_of(&self, zone: &DomainName) -> Vec<&DNSKeyRecord> {
+        self.keys.iter().filter(|(owner, _)| owner == zone).map(|(_, key)| key).collect()
+    }
+}
+
+/// Entries of a zone file without comments, the lines between parentheses
+/// being joined
+fn zone_file_entries(text: &str) -> Vec<String> {
+    let mut entries: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for line in text.lines() {
+        let line = line.split(';').next().unwrap_or("");
+        for character in line.chars() {
+            match character {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                character => current.push(character),
+            }
+        }
+        current.push(' ');
+        if depth <= 0 {
+            if !current.trim().is_empty() {
+                entries.push(current.trim().to_string());
+            }
+            current.clear();
+            depth = 0;
+        }
+    }
+    if !current.trim().is_empty() {
+        entries.push(current.trim().to_string());
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_anchor_file() {
+        let anchors = TrustAnchors::parse("\
+            ; root anchors\n\
+            . 3600 IN DS 20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D\n\
+            example.com. IN DNSKEY 257 3 13 (\n\
+                mdsswUyr3DPW132mOi8V9xESWE8jTo0dxCjjnopKl+GqJxpVXckHAeF+\n\
+                KkxLbxILfDLUT0rAK9iUzy1L53eKGQ== ) ; KSK\n\
+            example.com. IN NS ns.example.com.\n\
+        ").unwrap();
+        assert_eq!(anchors.ds.len(), 1);
+        assert_eq!(anchors.ds[0].1.key_tag, 20326);
+        assert_eq!(anchors.keys.len(), 1);
+        assert_eq!(anchors.keys[0].1.public_key.len(), 64);
+        assert_eq!(anchors.closest_zone(&"www.example.com".parse().unwrap()), Some("example.com".parse().unwrap()));
+        assert_eq!(anchors.closest_zone(&"example.org".parse().unwrap()), Some(DomainName::root()));
+
+        assert!(TrustAnchors::parse(". IN DS 20326 8 2 E06D4").is_err());
+        assert_eq!(TrustAnchors::root().ds.len(), 2);
+    }
+}
@@ -0,0 +1,583 @@
+use std::collections::HashMap;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dns_util::dns_dnssec::denial::{prove_wildcard_expansion, prove_with_nsec, prove_with_nsec3, Denial};
+use crate::dns_util::dns_dnssec::records::{DNSKeyRecord, DSRecord, NSEC3Record, NSECRecord, RRSIGRecord};
+use crate::dns_util::dns_dnssec::signatures::{is_supported_algorithm, verify_signature};
+use crate::dns_util::dns_dnssec::trust_anchors::TrustAnchors;
+use crate::dns_util::dns_lookup::cname_chain::ResolvedChain;
+use crate::dns_util::dns_packet_structures::{dns_packet::DNSPacket, dns_resource_record::DNSResourceRecord, domain_name::DomainName, record_data::RecordData};
+use crate::dns_util::dns_resolver::{Resolver, ResolverConfig};
+
+const CNAME: u16 = 5;
+const DNAME: u16 = 39;
+const RRSIG: u16 = 46;
+const NSEC: u16 = 47;
+const NSEC3: u16 = 50;
+
+/// Outcome of the validation of a response (RFC 4033 section 5)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationStatus {
+    /// Every record and denial is signed along a chain of trust from an
+    /// anchor
+    Secure,
+    /// The records are proven to come from an unsigned zone, or from a
+    /// zone signed with algorithms not supported
+    Insecure,
+    /// The records should be signed but signatures or proofs are missing,
+    /// expired or wrong. Holds the reason.
+    Bogus(String),
+    /// No trust anchor covers the records
+    Indeterminate,
+}
+
+impl ValidationStatus {
+    /// Status of a response made of parts of statuses `self` and `other`
+    fn and(self, other: ValidationStatus) -> ValidationStatus {
+        match (self, other) {
+            (bogus @ ValidationStatus::Bogus(_), _) | (_, bogus @ ValidationStatus::Bogus(_)) => bogus,
+            (ValidationStatus::Indeterminate, _) | (_, ValidationStatus::Indeterminate) => ValidationStatus::Indeterminate,
+            (ValidationStatus::Insecure, _) | (_, ValidationStatus::Insecure) => ValidationStatus::Insecure,
+            _ => ValidationStatus::Secure,
+        }
+    }
+}
+
+/// Response of a nameserver with the result of its validation
+#[derive(Debug)]
+pub struct ValidatedResponse {
+    pub response: DNSPacket,
+    pub status: ValidationStatus,
+}
+
+/// Validating stub resolver: asks the nameservers for the DNSSEC records
+/// (DO bit), then checks the signatures of the answers along the chain of
+/// DS and DNSKEY records down from a trust anchor
+pub struct Validator {
+    pub resolver: Resolver,
+    pub trust_anchors: TrustAnchors,
+}
+
+impl Validator {
+    /// Validator querying the nameservers of `config`, the DO bit being set
+    pub fn new(mut config: ResolverConfig, trust_anchors: TrustAnchors) -> Self {
+        config.dnssec_ok = true;
+        Self::with_resolver(Resolver::new(config), trust_anchors)
+    }
+
+    /// Validator using an existing resolver, for instance to share its
+    /// cache. The DO bit is set on its queries.
+    pub fn with_resolver(mut resolver: Resolver, trust_anchors: TrustAnchors) -> Self {
+        resolver.config.dnssec_ok = true;
+        Self { resolver, trust_anchors }
+    }
+
+    /// Queries `domain` for records of `query_type` and validates the
+    /// response. Network errors are returned, validation failures are in
+    /// the status.
+    pub fn query(&self, domain: &str, query_type: &str) -> io::Result<ValidatedResponse> {
+        let response = self.resolver.query(domain, query_type)?;
+        let status = self.validate(&response);
+        Ok(ValidatedResponse { response, status })
+    }
+
+    /// Validates a response to a DNSSEC query, fetching the keys needed
+    pub fn validate(&self, response: &DNSPacket) -> ValidationStatus {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32;
+        Validation { validator: self, now, zone_cuts: HashMap::new() }.validate_response(response)
+    }
+}
+
+/// Verified keys of a zone, or why there are none
+type ZoneKeys = Result<Vec<DNSKeyRecord>, ValidationStatus>;
+
+/// NSEC and NSEC3 records of a response, with their owners
+type DenialRecords = (Vec<(DomainName, NSECRecord)>, Vec<(DomainName, NSEC3Record)>);
+
+/// State of the validation of one response: the zone cuts found on the
+/// way are kept to avoid fetching them again
+struct Validation<'a> {
+    validator: &'a Validator,
+    /// Time at which the signatures must be valid, in seconds since 1970
+    now: u32,
+    /// Keys of the names looked up for DS records, `None` if the name is
+    /// not a zone cut
+    zone_cuts: HashMap<DomainName, Result<Option<Vec<DNSKeyRecord>>, ValidationStatus>>,
+}
+
+impl Validation<'_> {
+    fn validate_response(&mut self, response: &DNSPacket) -> ValidationStatus {
+        let Some(question) = response.questions.first() else {
+            return ValidationStatus::Bogus("response without question".to_string());
+        };
+        let Some(query_name) = DomainName::from_labels(&question.query_name) else {
+            return ValidationStatus::Bogus("invalid question name".to_string());
+        };
+        let response_code = response.header.flags & 0b1111;
+        if response_code != 0 && response_code != 3 {
+            return ValidationStatus::Bogus(format!("response code {}", response_code));
+        }
+
+        let answers = rrsets(&response.resource_records);
+        let mut status = ValidationStatus::Secure;
+        for ((owner, record_type), records) in &answers.records {
+            let signatures = answers.signatures_of(owner, *record_type);
+            // CNAME synthesized from a signed DNAME (RFC 6672 section 5.3.1)
+            if *record_type == CNAME && signatures.is_empty() && answers.records.keys()
+                .any(|(dname_owner, dname_type)| *dname_type == DNAME && owner.is_subdomain_of(dname_owner) && owner != dname_owner)
+            {
+                continue;
+            }
+            status = status.and(self.validate_answer(owner, records, &signatures, response));
+        }
+
+        let mut chain = ResolvedChain::starting_at(query_name);
+        match chain.follow(response, question.query_type, usize::MAX) {
+            Ok(true) if response_code == 0 => status,
+            Ok(_) => {
+                let denial = self.validate_denial(&chain.canonical_name, question.query_type, response_code == 3, response);
+                status.and(denial)
+            }
+            Err(e) => ValidationStatus::Bogus(e.to_string()),
+        }
+    }
+
+    /// Validates an RRset of the answer section, expanded from a wildcard
+    /// or not
+    fn validate_answer(&mut self, owner: &DomainName, records: &[&DNSResourceRecord], signatures: &[RRSIGRecord], response: &DNSPacket) -> ValidationStatus {
+        let (signature, keys) = match self.verify_rrset(owner, records, signatures) {
+            Ok(verified) => verified,
+            Err(status) => return status,
+        };
+        if (signature.labels as usize) < owner.label_count() && !owner.labels()[0].eq(b"*") {
+            let proof = self.verified_denial_records(response, &signature.signer_name, &keys)
+                .and_then(|(nsecs, nsec3s)| {
+                    prove_wildcard_expansion(owner, signature.labels as usize, &signature.signer_name, &nsecs, &nsec3s)
+                });
+            if let Err(e) = proof {
+                return ValidationStatus::Bogus(e);
+            }
+        }
+        ValidationStatus::Secure
+    }
+
+    /// Validates the NSEC or NSEC3 records proving that `name` has no
+    /// records of `query_type`, or does not exist if `nxdomain`
+    fn validate_denial(&mut self, name: &DomainName, query_type: u16, nxdomain: bool, response: &DNSPacket) -> ValidationStatus {
+        // The proof must be signed by the zone holding the name along the
+        // chain of trust, whatever the signers in the response: that of its
+        // parent for a DS query, or for a name that does not exist and so
+        // cannot be a zone apex
+        let holder = match name.parent() {
+            Some(parent) if nxdomain || query_type == DSRecord::RECORD_TYPE => parent,
+            _ => name.clone(),
+        };
+        let (zone, keys) = match self.closest_zone_keys(&holder) {
+            Ok(zone_keys) => zone_keys,
+            Err(status) => return status,
+        };
+        let (nsecs, nsec3s) = match self.verified_denial_records(response, &zone, &keys) {
+            Ok(records) => records,
+            Err(e) => return ValidationStatus::Bogus(e),
+        };
+        let denial = if !nsecs.is_empty() {
+            prove_with_nsec(name, query_type, &nsecs)
+        } else if !nsec3s.is_empty() {
+            prove_with_nsec3(name, query_type, &zone, &nsec3s)
+        } else {
+            Err(format!("no NSEC or NSEC3 records denying {}", name))
+        };
+        match denial {
+            Ok(Denial::NxDomain) if nxdomain => ValidationStatus::Secure,
+            Ok(Denial::NoData { .. }) if !nxdomain => ValidationStatus::Secure,
+            Ok(Denial::Insecure) => ValidationStatus::Insecure,
+            Ok(denial) => ValidationStatus::Bogus(format!("denial proof of {} does not match the response: {:?}", name, denial)),
+            Err(e) => ValidationStatus::Bogus(e),
+        }
+    }
+
+    /// NSEC and NSEC3 records of the authority section of `response`
+    /// signed by `keys` of `zone`
+    fn verified_denial_records(&self, response: &DNSPacket, zone: &DomainName, keys: &[DNSKeyRecord]) -> Result<DenialRecords, String> {
+        let authority = rrsets(&response.authority_records);
+        let mut nsecs: Vec<(DomainName, NSECRecord)> = Vec::new();
+        let mut nsec3s: Vec<(DomainName, NSEC3Record)> = Vec::new();
+        for ((owner, record_type), records) in &authority.records {
+            if *record_type != NSEC && *record_type != NSEC3 {
+                continue;
+            }
+            self.verify_with_keys(owner, records, &authority.signatures_of(owner, *record_type), zone, keys)?;
+            for record in records {
+                let invalid = |e: io::Error| e.to_string();
+                if *record_type == NSEC {
+                    nsecs.push((owner.clone(), record.record_data().map_err(invalid)?));
+                } else {
+                    nsec3s.push((owner.clone(), record.record_data().map_err(invalid)?));
+                }
+            }
+        }
+        Ok((nsecs, nsec3s))
+    }
+
+    /// Verifies an RRset with the keys of the zone that signed it, returning
+    /// the signature that matched with the keys of its zone
+    fn verify_rrset(&mut self, owner: &DomainName, records: &[&DNSResourceRecord], signatures: &[RRSIGRecord]) -> Result<(RRSIGRecord, Vec<DNSKeyRecord>), ValidationStatus> {
+        let Some(signer) = signatures.iter()
+            .map(|signature| &signature.signer_name)
+            .find(|signer| owner.is_subdomain_of(signer))
+        else {
+            return Err(self.unsigned_status(owner));
+        };
+        let keys = self.zone_keys(signer)?;
+        self.verify_with_keys(owner, records, signatures, signer, &keys)
+            .map(|signature| (signature, keys))
+            .map_err(ValidationStatus::Bogus)
+    }
+
+    /// Finds a signature of `records` made by one of `keys` of `zone`, valid
+    /// now
+    fn verify_with_keys(&self, owner: &DomainName, records: &[&DNSResourceRecord], signatures: &[RRSIGRecord], zone: &DomainName, keys: &[DNSKeyRecord]) -> Result<RRSIGRecord, String> {
+        let Some(first) = records.first() else {
+            return Err(format!("empty RRset at {}", owner));
+        };
+        let wildcard_labels = owner.label_count() - usize::from(owner.labels().first().is_some_and(|label| label == b"*"));
+        let mut reason = format!("no signature of {} {} by {}", owner, first.query_type, zone);
+        for signature in signatures {
+            if signature.signer_name != *zone || signature.type_covered != first.query_type || signature.labels as usize > wildcard_labels {
+                continue;
+            }
+            if !in_validity_period(signature, self.now) {
+                reason = format!("signature of {} {} expired or not yet valid", owner, first.query_type);
+                continue;
+            }
+            let verified = keys.iter()
+                .filter(|key| key.algorithm == signature.algorithm && key.key_tag() == signature.key_tag)
+                .any(|key| verify_signature(signature, key, records));
+            if verified {
+                return Ok(signature.clone());
+            }
+            reason = format!("wrong signature of {} {} by {}", owner, first.query_type, zone);
+        }
+        Err(reason)
+    }
+
+    /// Status of records found without signature at `name`: insecure if an
+    /// unsigned delegation is proven above it, bogus if its zone is signed
+    fn unsigned_status(&mut self, name: &DomainName) -> ValidationStatus {
+        match self.closest_zone_keys(name) {
+            Ok((zone, _)) => ValidationStatus::Bogus(format!("missing signatures at {} in the signed zone {}", name, zone)),
+            Err(status) => status,
+        }
+    }
+
+    /// Verified keys of `zone`
+    fn zone_keys(&mut self, zone: &DomainName) -> ZoneKeys {
+        let (closest_zone, keys) = self.closest_zone_keys(zone)?;
+        if closest_zone != *zone {
+            return Err(ValidationStatus::Bogus(format!("{} is not a signed zone", zone)));
+        }
+        Ok(keys)
+    }
+
+    /// Walks down from the closest trust anchor to `name`, looking for the
+    /// DS records of each name on the way. Returns the deepest zone holding
+    /// `name` with its verified keys, or the status found on the way.
+    fn closest_zone_keys(&mut self, name: &DomainName) -> Result<(DomainName, Vec<DNSKeyRecord>), ValidationStatus> {
+        let anchors = &self.validator.trust_anchors;
+        let Some(mut zone) = anchors.closest_zone(name) else {
+            return Err(ValidationStatus::Indeterminate);
+        };
+        let anchor_ds: Vec<DSRecord> = anchors.ds_of(&zone).into_iter().cloned().collect();
+        let anchor_keys: Vec<DNSKeyRecord> = anchors.keys_of(&zone).into_iter().cloned().collect();
+        if anchor_keys.is_empty() && !anchor_ds.iter().any(is_supported_ds) {
+            return Err(ValidationStatus::Insecure);
+        }
+        let mut keys = self.fetch_keys(&zone, |key| {
+            anchor_keys.contains(key) || anchor_ds.iter().any(|ds| ds_matches(ds, &zone, key))
+        })?;
+
+        for count in zone.label_count() + 1..=name.label_count() {
+            let child = name.suffix(count);
+            let cut = match self.zone_cuts.get(&child) {
+                Some(cut) => cut.clone(),
+                None => {
+                    let cut = self.find_zone_cut(&zone, &keys, &child);
+                    self.zone_cuts.insert(child.clone(), cut.clone());
+                    cut
+                }
+            };
+            if let Some(child_keys) = cut? {
+                zone = child;
+                keys = child_keys;
+            }
+        }
+        Ok((zone, keys))
+    }
+
+    /// Looks for the DS records of `child` in `zone`. Returns the keys of
+    /// `child` if it is a signed zone, `None` if it is not a zone cut, and
+    /// an insecure status for an unsigned delegation.
+    fn find_zone_cut(&mut self, zone: &DomainName, keys: &[DNSKeyRecord], child: &DomainName) -> Result<Option<Vec<DNSKeyRecord>>, ValidationStatus> {
+        let response = self.query(child, "DS")?;
+        let answers = rrsets(&response.resource_records);
+        if let Some(records) = answers.records.get(&(child.clone(), DSRecord::RECORD_TYPE)) {
+            self.verify_with_keys(child, records, &answers.signatures_of(child, DSRecord::RECORD_TYPE), zone, keys)
+                .map_err(ValidationStatus::Bogus)?;
+            let ds: Vec<DSRecord> = records.iter().filter_map(|record| record.record_data().ok()).collect();
+            if !ds.iter().any(is_supported_ds) {
+                return Err(ValidationStatus::Insecure);
+            }
+            return self.fetch_keys(child, |key| ds.iter().any(|ds| ds_matches(ds, child, key))).map(Some);
+        }
+        // An alias is not a zone cut
+        if answers.records.keys().any(|(owner, record_type)| owner == child && *record_type == CNAME) {
+            return Ok(None);
+        }
+
+        let (nsecs, nsec3s) = self.verified_denial_records(&response, zone, keys).map_err(ValidationStatus::Bogus)?;
+        let denial = if !nsecs.is_empty() {
+            prove_with_nsec(child, DSRecord::RECORD_TYPE, &nsecs)
+        } else if !nsec3s.is_empty() {
+            prove_with_nsec3(child, DSRecord::RECORD_TYPE, zone, &nsec3s)
+        } else {
+            Err(format!("no proof that {} has no DS records", child))
+        };
+        match denial.map_err(ValidationStatus::Bogus)? {
+            Denial::NoData { delegation: true } | Denial::Insecure => Err(ValidationStatus::Insecure),
+            Denial::NoData { delegation: false } | Denial::NxDomain => Ok(None),
+        }
+    }
+
+    /// Fetches the DNSKEY RRset of `zone` and checks that it is signed by a
+    /// `trusted` key. Returns the zone keys of the RRset.
+    fn fetch_keys(&self, zone: &DomainName, trusted: impl Fn(&DNSKeyRecord) -> bool) -> ZoneKeys {
+        let response = self.query(zone, "DNSKEY")?;
+        let answers = rrsets(&response.resource_records);
+        let records = answers.records.get(&(zone.clone(), DNSKeyRecord::RECORD_TYPE))
+            .ok_or_else(|| ValidationStatus::Bogus(format!("no DNSKEY records for {}", zone)))?;
+        let keys: Vec<DNSKeyRecord> = records.iter()
+            .filter_map(|record| record.record_data::<DNSKeyRecord>().ok())
+            .filter(|key| key.is_zone_key() && !key.is_revoked())
+            .collect();
+        let trusted_keys: Vec<DNSKeyRecord> = keys.iter().filter(|key| trusted(key)).cloned().collect();
+        if trusted_keys.is_empty() {
+            return Err(ValidationStatus::Bogus(format!("no DNSKEY of {} matches its DS records or anchors", zone)));
+        }
+        self.verify_with_keys(zone, records, &answers.signatures_of(zone, DNSKeyRecord::RECORD_TYPE), zone, &trusted_keys)
+            .map_err(ValidationStatus::Bogus)?;
+        Ok(keys)
+    }
+
+    fn query(&self, name: &DomainName, query_type: &str) -> Result<DNSPacket, ValidationStatus> {
+        self.validator.resolver.query(&name.to_string(), query_type)
+            .map_err(|e| ValidationStatus::Bogus(format!("{} query for {} failed: {}", query_type, name, e)))
+    }
+}
+
+/// Records of a section grouped by owner and type, with the signatures
+struct RRsets<'a> {
+    records: HashMap<(DomainName, u16), Vec<&'a DNSResourceRecord>>,
+    signatures: Vec<(DomainName, RRSIGRecord)>,
+}
+
+impl RRsets<'_> {
+    fn signatures_of(&self, owner: &DomainName, record_type: u16) -> Vec<RRSIGRecord> {
+        self.signatures.iter()
+            .filter(|(signed, signature)| signed == owner && signature.type_covered == record_type)
+            .map(|(_, signature)| signature.clone())
+            .collect()
+    }
+}
+
+fn rrsets(records: &[DNSResourceRecord]) -> RRsets<'_> {
+    let mut rrsets = RRsets { records: HashMap::new(), signatures: Vec::new() };
+    for record in records {
+        let Some(owner) = DomainName::from_labels(&record.query_name) else {
+            continue;
+        };
+        if record.query_type == RRSIG {
+            if let Ok(signature) = record.record_data() {
+                rrsets.signatures.push((owner, signature));
+            }
+        } else if record.query_type != 41 {
+            rrsets.records.entry((owner, record.query_type)).or_default().push(record);
+        }
+    }
+    rrsets
+}
+
+/// Inception and expiration compared with serial number arithmetic (RFC
+/// 4034 section 3.1.5)
+pub(crate) fn in_validity_period(signature: &RRSIGRecord, now: u32) -> bool {
+    (now.wrapping_sub(signature.signature_inception) as i32) >= 0
+        && (signature.signature_expiration.wrapping_sub(now) as i32) >= 0
+}
+
+fn is_supported_ds(ds: &DSRecord) -> bool {
+    is_supported_algorithm(ds.algorithm) && matches!(ds.digest_type, 1 | 2 | 4)
+}
+
+pub(crate) fn ds_matches(ds: &DSRecord, zone: &DomainName, key: &DNSKeyRecord) -> bool {
+    ds.algorithm == key.algorithm && ds.key_tag == key.key_tag()
+        && key.to_ds(zone, ds.digest_type).is_some_and(|digest| digest.digest == ds.digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use crate::dns_util::dns_dnssec::signatures::{signed_data, ED25519};
+    use crate::dns_util::test_server::{self, test_resolver, Answer};
+
+    /// Response of the test server: response code, answers and authority
+    type Records = (u8, Vec<DNSResourceRecord>, Vec<DNSResourceRecord>);
+
+    struct TestZone {
+        name: DomainName,
+        key_pair: Ed25519KeyPair,
+        key: DNSKeyRecord,
+    }
+
+    impl TestZone {
+        fn new(name: &str) -> Self {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+            let key = DNSKeyRecord {
+                flags: DNSKeyRecord::ZONE_KEY | DNSKeyRecord::SECURE_ENTRY_POINT,
+                protocol: 3,
+                algorithm: ED25519,
+                public_key: key_pair.public_key().as_ref().to_vec(),
+            };
+            Self { name: name.parse().unwrap(), key_pair, key }
+        }
+
+        /// `records` followed by their signature, valid from `inception`
+        /// to `expiration` relative to now
+        fn sign_between(&self, records: Vec<DNSResourceRecord>, inception: i64, expiration: i64) -> Vec<DNSResourceRecord> {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+            let owner = DomainName::from_labels(&records[0].query_name).unwrap();
+            let mut signature = RRSIGRecord {
+                type_covered: records[0].query_type,
+                algorithm: ED25519,
+                labels: owner.label_count() as u8,
+                original_ttl: 3600,
+                signature_expiration: (now + expiration) as u32,
+                signature_inception: (now + inception) as u32,
+                key_tag: self.key.key_tag(),
+                signer_name: self.name.clone(),
+                signature: Vec::new(),
+            };
+            let data = signed_data(&signature, &records.iter().collect::<Vec<&DNSResourceRecord>>());
+            signature.signature = self.key_pair.sign(&data).as_ref().to_vec();
+            let mut signed = records;
+            signed.push(DNSResourceRecord::from_record_data(&owner, 3600, &signature));
+            signed
+        }
+
+        fn sign(&self, records: Vec<DNSResourceRecord>) -> Vec<DNSResourceRecord> {
+            self.sign_between(records, -3600, 3600)
+        }
+
+        fn nsec(&self, owner: &str, next: &str, types: Vec<u16>) -> Vec<DNSResourceRecord> {
+            let nsec = NSECRecord { next_domain_name: next.parse().unwrap(), types };
+            self.sign(vec![DNSResourceRecord::from_record_data(&owner.parse().unwrap(), 3600, &nsec)])
+        }
+    }
+
+    fn a_record(owner: &str, address: [u8; 4]) -> DNSResourceRecord {
+        DNSResourceRecord {
+            query_name: owner.parse::<DomainName>().unwrap().to_labels(),
+            query_type: 1,
+            query_class: 1,
+            record_ttl: 3600,
+            rdata_length: 4,
+            rdata: address.to_vec(),
+        }
+    }
+
+    /// Serves the signed root and `example.` zones, and the unsigned
+    /// delegation `insecure.`
+    fn start_server(root: &TestZone, example: &TestZone) -> SocketAddr {
+        let mut answers: HashMap<(String, u16), Records> = HashMap::new();
+        answers.insert((".".to_string(), 48), (0, root.sign(vec![DNSResourceRecord::from_record_data(&root.name, 3600, &root.key)]), Vec::new()));
+        let example_ds = example.key.to_ds(&example.name, 2).unwrap();
+        answers.insert(("example".to_string(), 43), (0, root.sign(vec![DNSResourceRecord::from_record_data(&example.name, 3600, &example_ds)]), Vec::new()));
+        answers.insert(("insecure".to_string(), 43), (0, Vec::new(), root.nsec("insecure", ".", vec![2, 46, 47])));
+
+        answers.insert(("example".to_string(), 48), (0, example.sign(vec![DNSResourceRecord::from_record_data(&example.name, 3600, &example.key)]), Vec::new()));
+        answers.insert(("www.example".to_string(), 1), (0, example.sign(vec![a_record("www.example", [192, 0, 2, 1])]), Vec::new()));
+        answers.insert(("www.example".to_string(), 28), (0, Vec::new(), example.nsec("www.example", "example", vec![1, 46, 47])));
+        answers.insert(("www.example".to_string(), 43), (0, Vec::new(), example.nsec("www.example", "example", vec![1, 46, 47])));
+        let mut forged = example.sign(vec![a_record("bad.example", [192, 0, 2, 2])]);
+        forged[0].rdata = vec![192, 0, 2, 3];
+        answers.insert(("bad.example".to_string(), 1), (0, forged, Vec::new()));
+        answers.insert(("old.example".to_string(), 1), (0, example.sign_between(vec![a_record("old.example", [192, 0, 2, 4])], -7200, -3600), Vec::new()));
+        answers.insert(("unsigned.example".to_string(), 1), (0, vec![a_record("unsigned.example", [192, 0, 2, 5])], Vec::new()));
+        answers.insert(("unsigned.example".to_string(), 43), (0, Vec::new(), example.nsec("unsigned.example", "www.example", vec![1, 46, 47])));
+        answers.insert(("nothere.example".to_string(), 1), (3, Vec::new(), [
+            example.nsec("example", "bad.example", vec![2, 6, 46, 47, 48]),
+            example.nsec("bad.example", "unsigned.example", vec![1, 46, 47]),
+        ].concat()));
+        answers.insert(("forged.example".to_string(), 1), (3, Vec::new(), example.nsec("bad.example", "unsigned.example", vec![1, 46, 47])));
+        // NSEC of the root for the delegation to example, replayed to deny
+        // a name of the example zone
+        answers.insert(("replayed.example".to_string(), 1), (3, Vec::new(), root.nsec("example", "zzz", vec![2, 43, 46, 47])));
+        answers.insert(("host.insecure".to_string(), 1), (0, vec![a_record("host.insecure", [192, 0, 2, 6])], Vec::new()));
+
+        test_server::start_server(move |query| {
+            let Some((response_code, answer, authority)) = answers.get(&(query.name.to_lowercase(), query.query_type)) else {
+                return Answer::response_code(3);
+            };
+            Answer {
+                response_code: *response_code,
+                answers: answer.iter().map(DNSResourceRecord::prepare).collect(),
+                authority: authority.iter().map(DNSResourceRecord::prepare).collect(),
+                ..Answer::default()
+            }
+        })
+    }
+
+    fn start_validator(anchors: impl Fn(&TestZone) -> TrustAnchors) -> Validator {
+        let root = TestZone::new(".");
+        let example = TestZone::new("example");
+        Validator::new(test_resolver(start_server(&root, &example)).config, anchors(&root))
+    }
+
+    fn root_anchor(root: &TestZone) -> TrustAnchors {
+        TrustAnchors { ds: vec![(DomainName::root(), root.key.to_ds(&root.name, 2).unwrap())], keys: Vec::new() }
+    }
+
+    #[test]
+    fn test_secure_answers_and_denials() {
+        let validator = start_validator(root_anchor);
+        let response = validator.query("www.example", "A").unwrap();
+        assert_eq!(response.status, ValidationStatus::Secure);
+        assert_eq!(response.response.resource_records[0].rdata, vec![192, 0, 2, 1]);
+        assert_eq!(validator.query("www.example", "AAAA").unwrap().status, ValidationStatus::Secure);
+        assert_eq!(validator.query("nothere.example", "A").unwrap().status, ValidationStatus::Secure);
+        assert_eq!(validator.query("host.insecure", "A").unwrap().status, ValidationStatus::Insecure);
+    }
+
+    #[test]
+    fn test_bogus_answers() {
+        let validator = start_validator(root_anchor);
+        for name in ["bad.example", "old.example", "unsigned.example", "forged.example", "replayed.example"] {
+            let status = validator.query(name, "A").unwrap().status;
+            assert!(matches!(status, ValidationStatus::Bogus(_)), "{} is {:?}", name, status);
+        }
+    }
+
+    #[test]
+    fn test_trust_anchors() {
+        // No anchor above the name
+        let validator = start_validator(|_| TrustAnchors::default());
+        assert_eq!(validator.query("www.example", "A").unwrap().status, ValidationStatus::Indeterminate);
+
+        // Anchor of another key
+        let validator = start_validator(|_| root_anchor(&TestZone::new(".")));
+        assert!(matches!(validator.query("www.example", "A").unwrap().status, ValidationStatus::Bogus(_)));
+    }
+}
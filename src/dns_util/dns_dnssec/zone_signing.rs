@@ -0,0 +1,510 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+
+use crate::dns_util::dns_dnssec::records::{base32hex, nsec3_hash, DNSKeyRecord, NSEC3ParamRecord, NSEC3Record, NSECRecord, RRSIGRecord};
+use crate::dns_util::dns_dnssec::signatures::verify_signature;
+use crate::dns_util::dns_dnssec::signing::SigningKey;
+use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+
+const NS: u16 = 2;
+const SOA: u16 = 6;
+const DS: u16 = 43;
+
+/// Records proving that names and types do not exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DenialOfExistence {
+    /// NSEC chain of the owner names in canonical order (RFC 4034)
+    Nsec,
+    /// NSEC3 chain of the hashed owner names (RFC 5155). With `opt_out`,
+    /// delegations without DS records are left out of the chain.
+    Nsec3 { salt: Vec<u8>, iterations: u16, opt_out: bool },
+}
+
+/// Signs the records of a zone: adds the DNSKEY records of the keys, the
+/// NSEC or NSEC3 chain and the RRSIG records
+pub struct ZoneSigner {
+    pub zone: DomainName,
+    /// Key signing keys sign the DNSKEY RRset, the other keys sign the rest
+    /// of the zone. The keys of an algorithm without keys of both kinds
+    /// sign everything.
+    pub keys: Vec<SigningKey>,
+    /// DNSKEY records published without signing with them, such as those
+    /// of the keys before and after a rollover
+    pub published_keys: Vec<DNSKeyRecord>,
+    pub denial: DenialOfExistence,
+    /// Time from the inception of new signatures to their expiration
+    pub validity: Duration,
+    /// New signatures start this long before the signing time, for the
+    /// validators whose clock is late
+    pub inception_offset: Duration,
+    /// Up to this much is removed at random from the validity of each new
+    /// signature, so that they do not all expire together
+    pub jitter: Duration,
+    /// Existing signatures expiring within this time are replaced when
+    /// signing again
+    pub refresh: Duration,
+    /// TTL of the DNSKEY records added for the keys
+    pub dnskey_ttl: u32,
+}
+
+impl ZoneSigner {
+    pub fn new(zone: DomainName, keys: Vec<SigningKey>) -> Self {
+        Self {
+            zone,
+            keys,
+            published_keys: Vec::new(),
+            denial: DenialOfExistence::Nsec,
+            validity: Duration::from_secs(30 * 86400),
+            inception_offset: Duration::from_secs(3600),
+            jitter: Duration::from_secs(12 * 3600),
+            refresh: Duration::from_secs(7 * 86400),
+            dnskey_ttl: 3600,
+        }
+    }
+
+    /// Signs the records of the zone, which must include its SOA record.
+    /// The RRSIG records already present are kept when they still match
+    /// their RRset, were made by one of the keys and are not about to
+    /// expire, so that signing an updated zone only signs what changed.
+    /// NSEC, NSEC3 and NSEC3PARAM records are always built again, as is the
+    /// DNSKEY RRset of the apex, from `keys` and `published_keys` only.
+    pub fn sign(&self, records: &[DNSResourceRecord]) -> io::Result<Vec<DNSResourceRecord>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32;
+        self.sign_at(records, now)
+    }
+
+    fn sign_at(&self, records: &[DNSResourceRecord], now: u32) -> io::Result<Vec<DNSResourceRecord>> {
+        if self.keys.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no key to sign the zone with"));
+        }
+        let mut zone = ZoneContent::new(&self.zone, records)?;
+        for dnskey in self.keys.iter().map(|key| &key.dnskey).chain(&self.published_keys) {
+            let record = DNSResourceRecord::from_record_data(&self.zone, self.dnskey_ttl, dnskey);
+            let dnskeys = zone.rrsets.entry((self.zone.clone(), DNSKeyRecord::RECORD_TYPE)).or_default();
+            if !dnskeys.iter().any(|existing| existing.rdata == record.rdata) {
+                dnskeys.push(record);
+            }
+        }
+        match &self.denial {
+            DenialOfExistence::Nsec => zone.add_nsec_chain(),
+            DenialOfExistence::Nsec3 { salt, iterations, opt_out } => zone.add_nsec3_chain(salt, *iterations, *opt_out)?,
+        }
+
+        let mut rrsets: Vec<((DomainName, u16), Vec<DNSResourceRecord>)> = zone.rrsets.into_iter().collect();
+        // Canonical order, the SOA record first as zone files expect
+        rrsets.sort_by(|((a, a_type), _), ((b, b_type), _)| {
+            a.canonical_cmp(b).then((*a_type != SOA, a_type).cmp(&(*b_type != SOA, b_type)))
+        });
+        let mut signed: Vec<DNSResourceRecord> = Vec::new();
+        for ((owner, record_type), mut rrset) in rrsets {
+            let ttl = rrset.iter().map(|record| record.record_ttl).min().unwrap_or(0);
+            rrset.iter_mut().for_each(|record| record.record_ttl = ttl);
+            let authoritative = !zone.cuts.iter().any(|cut| owner.is_subdomain_of(cut))
+                || (zone.cuts.contains(&owner) && matches!(record_type, DS | NSECRecord::RECORD_TYPE));
+            if authoritative {
+                let previous = zone.signatures.remove(&(owner.clone(), record_type)).unwrap_or_default();
+                let signatures = self.sign_rrset(&rrset, &previous, now)?;
+                rrset.extend(signatures.iter().map(|signature| DNSResourceRecord::from_record_data(&owner, ttl.max(0) as u32, signature)));
+            }
+            signed.extend(rrset);
+        }
+        Ok(signed)
+    }
+
+    /// Signatures of `rrset` by the keys that must sign it, reusing the
+    /// `previous` ones that are still good
+    fn sign_rrset(&self, rrset: &[DNSResourceRecord], previous: &[RRSIGRecord], now: u32) -> io::Result<Vec<RRSIGRecord>> {
+        let records: Vec<&DNSResourceRecord> = rrset.iter().collect();
+        let key_signing = rrset[0].query_type == DNSKeyRecord::RECORD_TYPE;
+        let mut signers: Vec<&SigningKey> = self.keys.iter().filter(|key| key.is_key_signing_key() == key_signing).collect();
+        // Every algorithm of the DNSKEY RRset signs every RRset (RFC 4035
+        // section 2.2), with a key of the other kind when it has none of this
+        // kind
+        for key in &self.keys {
+            if !signers.iter().any(|signer| signer.dnskey.algorithm == key.dnskey.algorithm) {
+                signers.push(key);
+            }
+        }
+
+        let mut signatures: Vec<RRSIGRecord> = Vec::new();
+        for key in signers {
+            let reusable = previous.iter().find(|signature| {
+                signature.key_tag == key.key_tag() && signature.algorithm == key.dnskey.algorithm
+                    && signature.signer_name == self.zone
+                    && (now.wrapping_sub(signature.signature_inception) as i32) >= 0
+                    && (signature.signature_expiration.wrapping_sub(now) as i32) > self.refresh.as_secs() as i32
+                    && verify_signature(signature, &key.dnskey, &records)
+            });
+            match reusable {
+                Some(signature) => signatures.push(signature.clone()),
+                None => {
+                    let jitter = rand::thread_rng().gen_range(0..=self.jitter.as_secs()) as u32;
+                    let inception = now.wrapping_sub(self.inception_offset.as_secs() as u32);
+                    let expiration = now.wrapping_add(self.validity.as_secs() as u32).wrapping_sub(jitter);
+                    signatures.push(key.sign_rrset(&self.zone, &records, inception, expiration)?);
+                }
+            }
+        }
+        Ok(signatures)
+    }
+}
+
+/// Records of the zone being signed, grouped in RRsets
+struct ZoneContent<'a> {
+    apex: &'a DomainName,
+    rrsets: HashMap<(DomainName, u16), Vec<DNSResourceRecord>>,
+    /// Existing signatures, by owner and type covered
+    signatures: HashMap<(DomainName, u16), Vec<RRSIGRecord>>,
+    /// Delegations to child zones
+    cuts: Vec<DomainName>,
+    /// TTL of the denial records (RFC 9077)
+    negative_ttl: u32,
+}
+
+impl<'a> ZoneContent<'a> {
+    fn new(apex: &'a DomainName, records: &[DNSResourceRecord]) -> io::Result<Self> {
+        let mut rrsets: HashMap<(DomainName, u16), Vec<DNSResourceRecord>> = HashMap::new();
+        let mut signatures: HashMap<(DomainName, u16), Vec<RRSIGRecord>> = HashMap::new();
+        for record in records {
+            let owner = DomainName::from_labels(&record.query_name)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid owner name"))?;
+            if !owner.is_subdomain_of(apex) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is outside of the zone {}", owner, apex)));
+            }
+            match record.query_type {
+                RRSIGRecord::RECORD_TYPE => {
+                    let signature: RRSIGRecord = record.record_data()?;
+                    signatures.entry((owner, signature.type_covered)).or_default().push(signature);
+                }
+                NSECRecord::RECORD_TYPE | NSEC3Record::RECORD_TYPE | NSEC3ParamRecord::RECORD_TYPE => (),
+                // Replaced by the keys of the signer
+                DNSKeyRecord::RECORD_TYPE if owner == *apex => (),
+                record_type => {
+                    let rrset = rrsets.entry((owner, record_type)).or_default();
+                    if !rrset.iter().any(|existing| existing.rdata == record.rdata) {
+                        rrset.push(record.clone());
+                    }
+                }
+            }
+        }
+
+        let soa = rrsets.get(&(apex.clone(), SOA))
+            .and_then(|soa| soa.first())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no SOA record at {}", apex)))?;
+        let minimum = soa.rdata.len().checked_sub(4)
+            .map(|start| u32::from_be_bytes([soa.rdata[start], soa.rdata[start + 1], soa.rdata[start + 2], soa.rdata[start + 3]]))
+            .unwrap_or(0);
+        let negative_ttl = minimum.min(soa.record_ttl.max(0) as u32);
+        let cuts = rrsets.keys()
+            .filter(|(owner, record_type)| *record_type == NS && owner != apex)
+            .map(|(owner, _)| owner.clone())
+            .collect();
+        Ok(Self { apex, rrsets, signatures, cuts, negative_ttl })
+    }
+
+    /// Names that are neither glue nor below a delegation, in canonical
+    /// order
+    fn authoritative_names(&self) -> Vec<DomainName> {
+        let mut names: Vec<DomainName> = self.rrsets.keys()
+            .map(|(owner, _)| owner)
+            .filter(|owner| !self.cuts.iter().any(|cut| owner.is_subdomain_of(cut) && *owner != cut))
+            .cloned()
+            .collect::<HashSet<DomainName>>()
+            .into_iter()
+            .collect();
+        names.sort_by(|a, b| a.canonical_cmp(b));
+        names
+    }
+
+    /// Types present at `name`, as listed in NSEC and NSEC3 bitmaps
+    fn types_at(&self, name: &DomainName) -> Vec<u16> {
+        let mut types: Vec<u16> = self.rrsets.keys()
+            .filter(|(owner, _)| owner == name)
+            .map(|(_, record_type)| *record_type)
+            .collect();
+        types.sort_unstable();
+        types
+    }
+
+    fn is_unsigned_delegation(&self, name: &DomainName) -> bool {
+        self.cuts.contains(name) && !self.rrsets.contains_key(&(name.clone(), DS))
+    }
+
+    fn add_nsec_chain(&mut self) {
+        let names = self.authoritative_names();
+        for (i, name) in names.iter().enumerate() {
+            let mut types = self.types_at(name);
+            types.extend([RRSIGRecord::RECORD_TYPE, NSECRecord::RECORD_TYPE]);
+            let nsec = NSECRecord {
+                next_domain_name: names[(i + 1) % names.len()].clone(),
+                types,
+            };
+            self.rrsets.insert(
+                (name.clone(), NSECRecord::RECORD_TYPE),
+                vec![DNSResourceRecord::from_record_data(name, self.negative_ttl, &nsec)],
+            );
+        }
+    }
+
+    /// NSEC3 chain of the names and of the empty non-terminals above them
+    /// (RFC 5155 section 7.1)
+    fn add_nsec3_chain(&mut self, salt: &[u8], iterations: u16, opt_out: bool) -> io::Result<()> {
+        let parameters = NSEC3ParamRecord { hash_algorithm: 1, flags: 0, iterations, salt: salt.to_vec() };
+        self.rrsets.insert(
+            (self.apex.clone(), NSEC3ParamRecord::RECORD_TYPE),
+            vec![DNSResourceRecord::from_record_data(self.apex, 0, &parameters)],
+        );
+
+        let mut names: HashSet<DomainName> = HashSet::new();
+        for name in self.authoritative_names() {
+            if opt_out && self.is_unsigned_delegation(&name) {
+                continue;
+            }
+            let mut ancestor = Some(name);
+            while let Some(name) = ancestor.filter(|name| name.is_subdomain_of(self.apex)) {
+                ancestor = name.parent();
+                names.insert(name);
+            }
+        }
+        let mut hashed: Vec<(Vec<u8>, Vec<u16>)> = names.iter()
+            .map(|name| {
+                let mut types = self.types_at(name);
+                if !types.is_empty() && !self.is_unsigned_delegation(name) {
+                    types.push(RRSIGRecord::RECORD_TYPE);
+                }
+                (nsec3_hash(name, salt, iterations), types)
+            })
+            .collect();
+        hashed.sort();
+
+        for (i, (hash, types)) in hashed.iter().enumerate() {
+            let nsec3 = NSEC3Record {
+                hash_algorithm: 1,
+                flags: u8::from(opt_out) * NSEC3Record::OPT_OUT,
+                iterations,
+                salt: salt.to_vec(),
+                next_hashed_owner_name: hashed[(i + 1) % hashed.len()].0.clone(),
+                types: types.clone(),
+            };
+            let owner = self.apex.prepend(base32hex(hash).to_ascii_lowercase().as_bytes())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} too long for NSEC3 owner names", self.apex)))?;
+            self.rrsets.insert(
+                (owner.clone(), NSEC3Record::RECORD_TYPE),
+                vec![DNSResourceRecord::from_record_data(&owner, self.negative_ttl, &nsec3)],
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Zone file of `records`, one record per line
+pub fn zone_file(records: &[DNSResourceRecord]) -> String {
+    records.iter().map(|record| format!("{}\n", record)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use crate::dns_util::dns_dnssec::denial::{prove_with_nsec, prove_with_nsec3, Denial};
+    use crate::dns_util::dns_dnssec::signatures::{ECDSAP256SHA256, ED25519};
+
+    const NOW: u32 = 1_700_000_000;
+
+    fn record(owner: &str, record_type: u16, rdata: Vec<u8>) -> DNSResourceRecord {
+        DNSResourceRecord {
+            query_name: owner.parse::<DomainName>().unwrap().to_labels(),
+            query_type: record_type,
+            query_class: 1,
+            record_ttl: 3600,
+            rdata_length: rdata.len() as u16,
+            rdata,
+        }
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        name.parse::<DomainName>().unwrap().to_labels()
+    }
+
+    /// Zone `example.` with a signed delegation `sub` and an unsigned one
+    /// `insecure`, both with glue
+    fn zone_records() -> Vec<DNSResourceRecord> {
+        let soa = [name("ns.example"), name("admin.example"), [0, 0, 0, 1, 0, 0, 14, 16, 0, 0, 7, 8, 0, 9, 58, 128, 0, 0, 1, 44].to_vec()].concat();
+        vec![
+            record("example", SOA, soa),
+            record("example", NS, name("ns.example")),
+            record("ns.example", 1, vec![192, 0, 2, 1]),
+            record("www.example", 1, vec![192, 0, 2, 2]),
+            record("sub.example", NS, name("ns.sub.example")),
+            record("sub.example", DS, vec![1, 2, 13, 2, 0xaa, 0xbb]),
+            record("ns.sub.example", 1, vec![192, 0, 2, 3]),
+            record("insecure.example", NS, name("ns.insecure.example")),
+            record("ns.insecure.example", 1, vec![192, 0, 2, 4]),
+        ]
+    }
+
+    fn signer(denial: DenialOfExistence) -> ZoneSigner {
+        let rng = SystemRandom::new();
+        let ksk = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let zsk = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let mut signer = ZoneSigner::new("example".parse().unwrap(), vec![
+            SigningKey::from_pkcs8(ED25519, DNSKeyRecord::ZONE_KEY | DNSKeyRecord::SECURE_ENTRY_POINT, ksk.as_ref()).unwrap(),
+            SigningKey::from_pkcs8(ECDSAP256SHA256, DNSKeyRecord::ZONE_KEY, zsk.as_ref()).unwrap(),
+        ]);
+        signer.denial = denial;
+        signer
+    }
+
+    /// Signatures of the signed zone, with the RRset each one covers
+    fn signed_rrsets(signed: &[DNSResourceRecord]) -> Vec<(RRSIGRecord, Vec<&DNSResourceRecord>)> {
+        signed.iter()
+            .filter(|record| record.query_type == RRSIGRecord::RECORD_TYPE)
+            .map(|record| {
+                let signature: RRSIGRecord = record.record_data().unwrap();
+                let rrset = signed.iter()
+                    .filter(|covered| covered.query_name == record.query_name && covered.query_type == signature.type_covered)
+                    .collect();
+                (signature, rrset)
+            })
+            .collect()
+    }
+
+    fn denial_records<T: RecordData>(signed: &[DNSResourceRecord]) -> Vec<(DomainName, T)> {
+        signed.iter()
+            .filter(|record| record.query_type == T::RECORD_TYPE)
+            .map(|record| (DomainName::from_labels(&record.query_name).unwrap(), record.record_data().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_sign_zone_with_nsec() {
+        let signer = signer(DenialOfExistence::Nsec);
+        let signed = signer.sign_at(&zone_records(), NOW).unwrap();
+        let (ksk, zsk) = (&signer.keys[0], &signer.keys[1]);
+
+        let signatures = signed_rrsets(&signed);
+        for (signature, rrset) in &signatures {
+            // Each algorithm has a single key, which signs everything
+            let key = if signature.algorithm == ED25519 { ksk } else { zsk };
+            assert_eq!(signature.key_tag, key.key_tag());
+            assert!(verify_signature(signature, &key.dnskey, rrset));
+            assert_eq!(signature.signature_inception, NOW - 3600);
+        }
+        // Delegation NS records and glue are not signed, unlike the DS
+        let covered = |owner: &str, record_type: u16| signatures.iter()
+            .any(|(signature, rrset)| rrset[0].query_name == name(owner) && signature.type_covered == record_type);
+        assert!(covered("sub.example", DS) && covered("sub.example", NSECRecord::RECORD_TYPE));
+        assert!(!covered("sub.example", NS) && !covered("ns.sub.example", 1));
+        assert!(covered("example", DNSKeyRecord::RECORD_TYPE) && covered("example", NS));
+
+        let nsecs = denial_records::<NSECRecord>(&signed);
+        assert_eq!(nsecs.len(), 5);
+        assert_eq!(prove_with_nsec(&"nothere.example".parse().unwrap(), 1, &nsecs), Ok(Denial::NxDomain));
+        assert_eq!(prove_with_nsec(&"insecure.example".parse().unwrap(), DS, &nsecs), Ok(Denial::NoData { delegation: true }));
+
+        let zone_file = zone_file(&signed);
+        assert!(zone_file.starts_with("example. 3600 IN SOA ns.example. admin.example. 1 3600 1800 604800 300\n"));
+        assert!(zone_file.contains("\nwww.example. 3600 IN A 192.0.2.2\nwww.example. 3600 IN RRSIG A 13 2 3600 "));
+        assert!(zone_file.contains("\nwww.example. 300 IN NSEC example. A RRSIG NSEC\n"));
+    }
+
+    #[test]
+    fn test_sign_zone_with_nsec3_opt_out() {
+        let signer = signer(DenialOfExistence::Nsec3 { salt: vec![0xaa, 0xbb], iterations: 0, opt_out: true });
+        let signed = signer.sign_at(&zone_records(), NOW).unwrap();
+        let zone: DomainName = "example".parse().unwrap();
+
+        let nsec3s = denial_records::<NSEC3Record>(&signed);
+        // The apex, ns, www and sub, but not the unsigned delegation
+        assert_eq!(nsec3s.len(), 4);
+        assert!(nsec3s.iter().all(|(_, nsec3)| nsec3.is_opt_out()));
+        assert_eq!(denial_records::<NSEC3ParamRecord>(&signed).len(), 1);
+        assert_eq!(prove_with_nsec3(&"nothere.example".parse().unwrap(), 1, &zone, &nsec3s), Ok(Denial::NxDomain));
+        assert_eq!(prove_with_nsec3(&"insecure.example".parse().unwrap(), DS, &zone, &nsec3s), Ok(Denial::Insecure));
+        assert_eq!(prove_with_nsec3(&"sub.example".parse().unwrap(), 1, &zone, &nsec3s), Ok(Denial::NoData { delegation: true }));
+        assert!(signed_rrsets(&signed).iter().all(|(signature, rrset)| {
+            signer.keys.iter().any(|key| verify_signature(signature, &key.dnskey, rrset))
+        }));
+    }
+
+    #[test]
+    fn test_every_algorithm_signs_every_rrset() {
+        let signer = signer(DenialOfExistence::Nsec3 { salt: Vec::new(), iterations: 0, opt_out: false });
+        let signed = signer.sign_at(&zone_records(), NOW).unwrap();
+        let mut algorithms: Vec<u8> = signer.keys.iter().map(|key| key.dnskey.algorithm).collect();
+        algorithms.sort();
+
+        let signatures = signed_rrsets(&signed);
+        let rrsets: HashSet<(Vec<u8>, u16)> = signatures.iter()
+            .map(|(signature, rrset)| (rrset[0].query_name.clone(), signature.type_covered))
+            .collect();
+        for (owner, record_type) in rrsets {
+            let mut signed_with: Vec<u8> = signatures.iter()
+                .filter(|(signature, rrset)| rrset[0].query_name == owner && signature.type_covered == record_type)
+                .map(|(signature, _)| signature.algorithm)
+                .collect();
+            signed_with.sort();
+            assert_eq!(signed_with, algorithms, "{:?} {}", owner, record_type);
+        }
+    }
+
+    #[test]
+    fn test_nsec3_owner_name_too_long() {
+        // Valid, but without room for a label of 32 characters
+        let apex = ["a".repeat(63), "b".repeat(63), "c".repeat(63), "d".repeat(30)].join(".");
+        let mut signer = signer(DenialOfExistence::Nsec3 { salt: Vec::new(), iterations: 0, opt_out: false });
+        signer.zone = apex.parse().unwrap();
+        let records = vec![record(&apex, SOA, zone_records()[0].rdata.clone())];
+        assert_eq!(signer.sign_at(&records, NOW).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_incremental_resigning() {
+        let signer = signer(DenialOfExistence::Nsec);
+        let signed = signer.sign_at(&zone_records(), NOW).unwrap();
+
+        let mut updated = signed.clone();
+        updated.iter_mut()
+            .filter(|record| record.query_name == name("www.example") && record.query_type == 1)
+            .for_each(|record| record.rdata = vec![192, 0, 2, 5]);
+        let later = NOW + 86400;
+        let resigned = signer.sign_at(&updated, later).unwrap();
+        let inception_of = |signed: &[DNSResourceRecord], owner: &str, record_type: u16| signed_rrsets(signed).iter()
+            .find(|(signature, rrset)| rrset[0].query_name == name(owner) && signature.type_covered == record_type)
+            .map(|(signature, _)| signature.signature_inception)
+            .unwrap();
+        assert_eq!(inception_of(&resigned, "example", SOA), NOW - 3600);
+        assert_eq!(inception_of(&resigned, "www.example", 1), later - 3600);
+        assert_eq!(resigned.len(), signed.len());
+
+        // Signatures close to their expiration are all replaced
+        let much_later = NOW + 25 * 86400;
+        let refreshed = signer.sign_at(&resigned, much_later).unwrap();
+        assert!(signed_rrsets(&refreshed).iter().all(|(signature, _)| signature.signature_inception == much_later - 3600));
+
+        assert_eq!(signer.sign_at(&zone_records()[1..], NOW).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_resigning_with_other_keys() {
+        let old_signer = signer(DenialOfExistence::Nsec);
+        let signed = old_signer.sign_at(&zone_records(), NOW).unwrap();
+        let new_signer = signer(DenialOfExistence::Nsec);
+        let resigned = new_signer.sign_at(&signed, NOW).unwrap();
+
+        let dnskeys: Vec<DNSKeyRecord> = resigned.iter()
+            .filter(|record| record.query_type == DNSKeyRecord::RECORD_TYPE)
+            .map(|record| record.record_data().unwrap())
+            .collect();
+        assert_eq!(dnskeys, vec![new_signer.keys[0].dnskey.clone(), new_signer.keys[1].dnskey.clone()]);
+        assert!(signed_rrsets(&resigned).iter().all(|(signature, rrset)| {
+            new_signer.keys.iter().any(|key| verify_signature(signature, &key.dnskey, rrset))
+        }));
+    }
+}
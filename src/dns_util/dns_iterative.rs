@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::dns_util::dns_lookup::cname_chain::ResolvedChain;
+use crate::dns_util::dns_packet_structures::dns_header::DNSHeader;
+use crate::dns_util::dns_packet_structures::dns_packet::DNSPacket;
+use crate::dns_util::dns_packet_structures::dns_question::DNSQuestion;
+use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::util::parse_query_type;
+use crate::dns_util::dns_transports::{tcp::TCPTransport, udp::UDPTransport, DNS_PORT};
+
+/// Server of the root zone
+pub struct RootHint {
+    pub name: &'static str,
+    pub ipv4: Ipv4Addr,
+    pub ipv6: Ipv6Addr,
+}
+
+/// Root servers, from the `named.root` file published by IANA
+pub const ROOT_HINTS: [RootHint; 13] = [
+    RootHint { name: "a.root-servers.net", ipv4: Ipv4Addr::new(198, 41, 0, 4), ipv6: Ipv6Addr::new(0x2001, 0x503, 0xba3e, 0, 0, 0, 0x2, 0x30) },
+    RootHint { name: "b.root-servers.net", ipv4: Ipv4Addr::new(170, 247, 170, 2), ipv6: Ipv6Addr::new(0x2801, 0x1b8, 0x10, 0, 0, 0, 0, 0xb) },
+    RootHint { name: "c.root-servers.net", ipv4: Ipv4Addr::new(192, 33, 4, 12), ipv6: Ipv6Addr::new(0x2001, 0x500, 0x2, 0, 0, 0, 0, 0xc) },
+    RootHint { name: "d.root-servers.net", ipv4: Ipv4Addr::new(199, 7, 91, 13), ipv6: Ipv6Addr::new(0x2001, 0x500, 0x2d, 0, 0, 0, 0, 0xd) },
+    RootHint { name: "e.root-servers.net", ipv4: Ipv4Addr::new(192, 203, 230, 10), ipv6: Ipv6Addr::new(0x2001, 0x500, 0xa8, 0, 0, 0, 0, 0xe) },
+    RootHint { name: "f.root-servers.net", ipv4: Ipv4Addr::new(192, 5, 5, 241), ipv6: Ipv6Addr::new(0x2001, 0x500, 0x2f, 0, 0, 0, 0, 0xf) },
+    RootHint { name: "g.root-servers.net", ipv4: Ipv4Addr::new(192, 112, 36, 4), ipv6: Ipv6Addr::new(0x2001, 0x500, 0x12, 0, 0, 0, 0, 0xd0d) },
+    RootHint { name: "h.root-servers.net", ipv4: Ipv4Addr::new(198, 97, 190, 53), ipv6: Ipv6Addr::new(0x2001, 0x500, 0x1, 0, 0, 0, 0, 0x53) },
+    RootHint { name: "i.root-servers.net", ipv4: Ipv4Addr::new(192, 36, 148, 17), ipv6: Ipv6Addr::new(0x2001, 0x7fe, 0, 0, 0, 0, 0, 0x53) },
+    RootHint { name: "j.root-servers.net", ipv4: Ipv4Addr::new(192, 58, 128, 30), ipv6: Ipv6Addr::new(0x2001, 0x503, 0xc27, 0, 0, 0, 0x2, 0x30) },
+    RootHint { name: "k.root-servers.net", ipv4: Ipv4Addr::new(193, 0, 14, 129), ipv6: Ipv6Addr::new(0x2001, 0x7fd, 0, 0, 0, 0, 0, 0x1) },
+    RootHint { name: "l.root-servers.net", ipv4: Ipv4Addr::new(199, 7, 83, 42), ipv6: Ipv6Addr::new(0x2001, 0x500, 0x9f, 0, 0, 0, 0, 0x42) },
+    RootHint { name: "m.root-servers.net", ipv4: Ipv4Addr::new(202, 12, 27, 33), ipv6: Ipv6Addr::new(0x2001, 0xdc3, 0, 0, 0, 0, 0, 0x35) },
+];
+
+const RECURSION_DESIRED: u16 = 0b0000_0001_0000_0000;
+const AUTHORITATIVE_ANSWER: u16 = 0b0000_0100_0000_0000;
+
+/// Resolves names by itself, starting from the root servers and following
+/// the referrals down to the authoritative servers, instead of asking a
+/// recursive server.
+pub struct IterativeResolver {
+    /// Servers of the root zone the resolution starts from
+    pub root_servers: Vec<SocketAddr>,
+    /// Port of the nameservers found in referrals
+    pub port: u16,
+    /// Servers to query instead of the nameservers found at these
+    /// addresses, such as local servers standing for them in tests
+    pub address_overrides: HashMap<IpAddr, SocketAddr>,
+    /// Time given to a server to answer a single query
+    pub timeout: Duration,
+    /// Also query the nameservers over IPv6
+    pub use_ipv6: bool,
+    /// Queries allowed for one resolution, lookups of nameservers included
+    pub max_queries: usize,
+    /// Referrals followed from the root for one name
+    pub max_referrals: usize,
+    /// Nesting of the lookups of nameservers given without glue
+    pub max_depth: usize,
+    /// Number of CNAME and DNAME links followed before giving up
+    pub max_chain_length: usize,
+}
+
+impl Default for IterativeResolver {
+    fn default() -> Self {
+        Self {
+            root_servers: ROOT_HINTS.iter()
+                .map(|hint| SocketAddr::new(hint.ipv4.into(), DNS_PORT))
+                .collect(),
+            port: DNS_PORT,
+            address_overrides: HashMap::new(),
+            timeout: Duration::new(2, 0),
+            use_ipv6: false,
+            max_queries: 64,
+            max_referrals: 16,
+            max_depth: 4,
+            max_chain_length: 16,
+        }
+    }
+}
+
+impl IterativeResolver {
+    /// Resolver starting from the IPv4 addresses of `ROOT_HINTS`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolves `domain` for records of `query_type`. The response is the
+    /// one of the authoritative server, its answer section gathering the
+    /// CNAME and DNAME records followed on the way. Records outside of the
+    /// zone of the server giving them are dropped.
+    pub fn resolve(&self, domain: &str, query_type: &str) -> io::Result<DNSPacket> {
+        let name: DomainName = domain.parse()
+            .map_err(|e: String| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut queries_left = self.max_queries;
+        self.resolve_name(&name, query_type, 0, &mut queries_left)
+    }
+
+    fn resolve_name(&self, name: &DomainName, query_type: &str, depth: usize, queries_left: &mut usize) -> io::Result<DNSPacket> {
+        let mut chain = ResolvedChain::starting_at(name.clone());
+        let mut answers: Vec<DNSResourceRecord> = Vec::new();
+        loop {
+            let queried_name = chain.canonical_name.clone();
+            let mut response = self.resolve_from_root(&queried_name, query_type, depth, queries_left)?;
+            let complete = chain.follow(&response, parse_query_type(query_type), self.max_chain_length)?;
+            answers.append(&mut response.resource_records);
+            // The chain left the zone, start again from the root for its end
+            if !complete && chain.canonical_name != queried_name && response.header.flags & 0b1111 == 0 {
+                continue;
+            }
+            response.header.answers_count = answers.len() as u16;
+            response.resource_records = answers;
+            return Ok(response);
+        }
+    }
+
+    /// Follows the referrals from the root down to a server with an answer
+    fn resolve_from_root(&self, name: &DomainName, query_type: &str, depth: usize, queries_left: &mut usize) -> io::Result<DNSPacket> {
+        let mut zone = DomainName::root();
+        let mut servers = self.root_servers.clone();
+
+        for _i in 0..self.max_referrals {
+            let mut response = self.query_servers(&servers, name, query_type, queries_left)?;
+            // Bailiwick: a server can only speak for its own zone
+            let in_zone = |record: &DNSResourceRecord| DomainName::from_labels(&record.query_name)
+                .is_some_and(|owner| owner.is_subdomain_of(&zone));
+            response.resource_records.retain(in_zone);
+            response.authority_records.retain(in_zone);
+            response.additional_records.retain(in_zone);
+            response.header.answers_count = response.resource_records.len() as u16;
+
+            if response.header.flags & 0b1111 == 3 || !response.resource_records.is_empty() {
+                return Ok(response);
+            }
+
+            // A referral delegates a zone below the current one, closer to
+            // the name
+            let child = response.authority_records.iter()
+                .filter(|record| record.query_type == 2)
+                .filter_map(|record| DomainName::from_labels(&record.query_name))
+                .filter(|owner| name.is_subdomain_of(owner) && *owner != zone)
+                .max_by_key(|owner| owner.label_count());
+            let Some(child) = child else {
+                let has_soa = response.authority_records.iter().any(|record| record.query_type == 6);
+                if response.header.flags & AUTHORITATIVE_ANSWER != 0 || has_soa {
+                    // No records of this type
+                    return Ok(response);
+                }
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("lame delegation for {}", zone)));
+            };
+
+            let nameservers: Vec<DomainName> = response.authority_records.iter()
+                .filter(|record| record.query_type == 2)
+                .filter(|record| DomainName::from_labels(&record.query_name).as_ref() == Some(&child))
+                .filter_map(|record| DomainName::from_labels(&record.rdata))
+                .collect();
+            let mut addresses: Vec<SocketAddr> = response.additional_records.iter()
+                .filter(|record| DomainName::from_labels(&record.query_name).is_some_and(|owner| nameservers.contains(&owner)))
+                .filter_map(|record| self.server_address(record))
+                .collect();
+            if addresses.is_empty() {
+                addresses = self.resolve_nameservers(&nameservers, &child, depth, queries_left)?;
+            }
+            if addresses.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("no reachable nameserver for {}", child)));
+            }
+
+            zone = child;
+            servers = addresses;
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, format!("too many referrals for {}", name)))
+    }
+
+    /// Looks up the addresses of nameservers given without glue, stopping at
+    /// the first one found. Nameservers inside the delegated zone cannot be
+    /// found without glue and are skipped.
+    fn resolve_nameservers(&self, nameservers: &[DomainName], zone: &DomainName, depth: usize, queries_left: &mut usize) -> io::Result<Vec<SocketAddr>> {
+        if depth >= self.max_depth {
+            return Err(io::Error::other(format!("nameservers of {} nested too deep", zone)));
+        }
+        for nameserver in nameservers.iter().filter(|nameserver| !nameserver.is_subdomain_of(zone)) {
+            if let Ok(response) = self.resolve_name(nameserver, "A", depth + 1, queries_left) {
+                let addresses: Vec<SocketAddr> = response.resource_records.iter()
+                    .filter_map(|record| self.server_address(record))
+                    .collect();
+                if !addresses.is_empty() {
+                    return Ok(addresses);
+                }
+            }
+            if *queries_left == 0 {
+                break;
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    /// Nameserver address carried by an A or AAAA record
+    fn server_address(&self, record: &DNSResourceRecord) -> Option<SocketAddr> {
+        let address = match (record.query_type, record.rdata.len()) {
+            (1, 4) => IpAddr::from(<[u8; 4]>::try_from(record.rdata.as_slice()).ok()?),
+            (28, 16) if self.use_ipv6 => IpAddr::from(<[u8; 16]>::try_from(record.rdata.as_slice()).ok()?),
+            _ => return None,
+        };
+        Some(self.address_overrides.get(&address).copied().unwrap_or(SocketAddr::new(address, self.port)))
+    }
+
+    /// Sends a non-recursive query to each server in turn until one gives a
+    /// usable response
+    fn query_servers(&self, servers: &[SocketAddr], name: &DomainName, query_type: &str, queries_left: &mut usize) -> io::Result<DNSPacket> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("no nameserver to ask for {}", name));
+        for server in servers.iter().filter(|server| self.use_ipv6 || server.is_ipv4()) {
+            if *queries_left == 0 {
+                return Err(io::Error::other(format!("query limit reached resolving {}", name)));
+            }
+            *queries_left -= 1;
+
+            let mut query = DNSPacket {
+                header: DNSHeader::create_query_header(1),
+                questions: vec![DNSQuestion {
+                    query_name: name.to_labels(),
+                    query_type: parse_query_type(query_type),
+                    query_class: 1,
+                }],
+                resource_records: Vec::new(),
+                authority_records: Vec::new(),
+                additional_records: Vec::new(),
+            };
+            query.header.flags &= !RECURSION_DESIRED;
+
+            let mut udp_transport = UDPTransport::new(*server);
+            udp_transport.timeout = self.timeout;
+            let response = crate::send_dns_query(&query, &udp_transport).and_then(|response| {
+                // Truncation flag
+                if response.header.flags >> 9 & 1 == 1 {
+                    let mut tcp_transport = TCPTransport::new(*server);
+                    tcp_transport.timeout = self.timeout;
+                    crate::send_dns_query(&query, &tcp_transport)
+                } else {
+                    Ok(response)
+                }
+            });
+            match response {
+                Ok(response) if matches!(response.header.flags & 0b1111, 0 | 3) => return Ok(response),
+                Ok(response) => last_error = io::Error::other(format!(
+                    "{} answered with response code {}", server, response.header.flags & 0b1111
+                )),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_util::test_server::{self, name, record, Answer};
+
+    /// Address of the nameserver of test.
+    const TEST_SERVER: [u8; 4] = [198, 51, 100, 3];
+    /// Address of the nameserver of example.test
+    const EXAMPLE_SERVER: [u8; 4] = [198, 51, 100, 4];
+
+    /// Starts a server answering with `answer`. Queries asking for
+    /// recursion are refused.
+    fn start_server(answer: fn(&str) -> Answer) -> SocketAddr {
+        test_server::start_server(move |query| {
+            if query.recursion_desired {
+                Answer::response_code(5)
+            } else {
+                answer(&query.name)
+            }
+        })
+    }
+
+    /// Authoritative answer
+    fn authoritative(answers: Vec<Vec<u8>>) -> Answer {
+        Answer { authoritative: true, answers, ..Answer::default() }
+    }
+
+    /// Referral to the nameservers in `authority`
+    fn referral(authority: Vec<Vec<u8>>, additional: Vec<Vec<u8>>) -> Answer {
+        Answer { authority, additional, ..Answer::default() }
+    }
+
+    /// Root delegating test. to `TEST_SERVER`, which delegates example.test
+    /// to `EXAMPLE_SERVER`. other. is delegated by the root to
+    /// ns.example.test without glue. The servers run on local ports standing
+    /// for these addresses.
+    fn start_servers() -> IterativeResolver {
+        let root = start_server(|query_name| {
+            if query_name.ends_with("other") {
+                referral(vec![record("other", 2, name("ns.example.test"))], vec![])
+            } else {
+                referral(vec![record("test", 2, name("ns.test"))], vec![record("ns.test", 1, TEST_SERVER.to_vec())])
+            }
+        });
+        let test = start_server(|_| {
+            referral(vec![record("example.test", 2, name("ns.example.test"))], vec![
+                record("ns.example.test", 1, EXAMPLE_SERVER.to_vec()),
+                // Out of bailiwick, must be ignored
+                record("ns.other", 1, vec![192, 0, 2, 66]),
+            ])
+        });
+        let example = start_server(|query_name| match query_name {
+            "www.example.test" => authoritative(vec![
+                record("www.example.test", 1, vec![192, 0, 2, 1]),
+                record("www.other", 1, vec![192, 0, 2, 66]),
+            ]),
+            "ns.example.test" => authoritative(vec![record("ns.example.test", 1, EXAMPLE_SERVER.to_vec())]),
+            "alias.example.test" => authoritative(vec![record("alias.example.test", 5, name("host.other"))]),
+            "host.other" => authoritative(vec![record("host.other", 1, vec![192, 0, 2, 9])]),
+            _ => Answer { response_code: 3, ..authoritative(Vec::new()) },
+        });
+        IterativeResolver {
+            root_servers: vec![root],
+            address_overrides: HashMap::from([(IpAddr::from(TEST_SERVER), test), (IpAddr::from(EXAMPLE_SERVER), example)]),
+            timeout: Duration::from_millis(500),
+            ..IterativeResolver::default()
+        }
+    }
+    #[test]
+    fn test_iterative_resolution() {
+        let resolver = start_servers();
+        let response = resolver.resolve("www.example.test", "A").unwrap();
+        assert_eq!(response.resource_records.len(), 1);
+        assert_eq!(response.resource_records[0].rdata, vec![192, 0, 2, 1]);
+
+        // CNAME into a zone delegated without glue
+        let response = resolver.resolve("alias.example.test", "A").unwrap();
+        assert_eq!(response.resource_records.len(), 2);
+        assert_eq!(response.resource_records[1].rdata, vec![192, 0, 2, 9]);
+
+        assert_eq!(resolver.resolve("missing.example.test", "A").unwrap().header.flags & 0b1111, 3);
+    }
+
+    #[test]
+    fn test_work_limits() {
+        let mut resolver = start_servers();
+        resolver.max_queries = 4;
+        assert!(resolver.resolve("alias.example.test", "A").is_err());
+
+        resolver.max_queries = 64;
+        resolver.max_depth = 0;
+        assert!(resolver.resolve("host.other", "A").is_err());
+        assert!(resolver.resolve("www.example.test", "A").is_ok());
+    }
+}
@@ -0,0 +1,200 @@
+pub(crate) mod address_sorting;
+pub mod cname_chain;
+pub mod mx_lookup;
+mod reverse_lookup;
+pub mod srv_lookup;
+pub mod svcb_lookup;
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::thread;
+
+#[cfg(feature = "tokio")]
+use crate::dns_util::dns_packet_structures::dns_packet::DNSPacket;
+use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+use crate::dns_util::dns_resolver::{LookupSource, Resolver};
+use address_sorting::{sort_addresses, system_source_address};
+
+/// Addresses of a host, as returned by `Resolver::lookup_ip`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpLookup {
+    /// IPv4 and IPv6 addresses, most preferred first (RFC 6724)
+    pub addresses: Vec<IpAddr>,
+    /// Smallest TTL of the answer records, after which the addresses
+    /// should be looked up again
+    pub ttl: u32,
+}
+
+impl Resolver {
+    /// Looks up the IPv4 addresses of `domain`, in the hosts file then with
+    /// the nameservers by default. A name that does not exist gives a
+    /// `NotFound` error, a name without A records an empty list.
+    pub fn lookup_ipv4(&self, domain: &str) -> io::Result<Vec<Ipv4Addr>> {
+        let (records, _) = self.lookup_addresses(domain, "A")?;
+        Ok(records.into_iter().filter_map(|address| match address {
+            IpAddr::V4(address) => Some(address),
+            IpAddr::V6(_) => None,
+        }).collect())
+    }
+
+    /// Looks up the IPv6 addresses of `domain`, with the same errors as
+    /// `lookup_ipv4`
+    pub fn lookup_ipv6(&self, domain: &str) -> io::Result<Vec<Ipv6Addr>> {
+        let (records, _) = self.lookup_addresses(domain, "AAAA")?;
+        Ok(records.into_iter().filter_map(|address| match address {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(address) => Some(address),
+        }).collect())
+    }
+
+    /// Looks up the IPv4 and IPv6 addresses of `domain` with parallel A and
+    /// AAAA queries. The addresses are sorted for destination address
+    /// selection (RFC 6724). Fails only if both queries fail.
+    pub fn lookup_ip(&self, domain: &str) -> io::Result<IpLookup> {
+        let (ipv4_result, ipv6_result) = thread::scope(|scope| {
+            let ipv6_lookup = scope.spawn(|| self.lookup_addresses(domain, "AAAA"));
+            let ipv4_result = self.lookup_addresses(domain, "A");
+            (ipv4_result, ipv6_lookup.join().expect("AAAA lookup panicked"))
+        });
+
+        let mut addresses: Vec<IpAddr> = Vec::new();
+        let mut ttl: Option<u32> = None;
+        let mut last_error: Option<io::Error> = None;
+        for result in [ipv6_result, ipv4_result] {
+            match result {
+                Ok((records, records_ttl)) => {
+                    addresses.extend(records);
+                    ttl = match (ttl, records_ttl) {
+                        (Some(ttl), Some(records_ttl)) => Some(ttl.min(records_ttl)),
+                        (ttl, records_ttl) => ttl.or(records_ttl),
+                    };
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        if let (Some(e), true) = (last_error, addresses.is_empty()) {
+            return Err(e);
+        }
+
+        sort_addresses(&mut addresses, system_source_address);
+        Ok(IpLookup {
+            addresses,
+            ttl: ttl.unwrap_or(0),
+        })
+    }
+
+    /// Looks up the A or AAAA addresses of `domain` in the sources of
+    /// `lookup_order`, returning them with the smallest TTL of the DNS
+    /// chain. Addresses of the hosts file have no TTL.
+    fn lookup_addresses(&self, domain: &str, query_type: &str) -> io::Result<(Vec<IpAddr>, Option<u32>)> {
+        let mut result = Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", domain)));
+        for source in &self.config.lookup_order {
+            match source {
+                LookupSource::Files => {
+                    let addresses: Vec<IpAddr> = self.hosts_addresses(domain).into_iter()
+                        .filter(|address| address.is_ipv4() == (query_type == "A"))
+                        .collect();
+                    if !addresses.is_empty() {
+                        return Ok((addresses, None));
+                    }
+                }
+                // A name unknown to the nameservers can still be in a
+                // later source
+                LookupSource::Dns => match self.resolve_chain(domain, query_type) {
+                    Ok(chain) if chain.records.is_empty() => result = Ok((Vec::new(), chain.ttl)),
+                    Ok(chain) => return Ok((addresses_in(&chain.records), chain.ttl)),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => result = Err(e),
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+        result
+    }
+}
+
+/// Addresses answering the question of `response`, following the aliases
+/// of its answer section
+#[cfg(feature = "tokio")]
+pub(crate) fn addresses_of(response: &DNSPacket) -> Vec<IpAddr> {
+    addresses_in(&cname_chain::answer_records(response, 16))
+}
+
+fn addresses_in(records: &[DNSResourceRecord]) -> Vec<IpAddr> {
+    records.iter()
+        .filter(|record| record.query_class == 1)
+        .filter_map(|record| match (record.query_type, record.rdata.len()) {
+            (1, 4) => Some(IpAddr::from(<[u8; 4]>::try_from(record.rdata.as_slice()).ok()?)),
+            (28, 16) => Some(IpAddr::from(<[u8; 16]>::try_from(record.rdata.as_slice()).ok()?)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use crate::dns_util::dns_resolver::ResolverConfig;
+    use crate::dns_util::test_server::{self, record, record_with_ttl, test_resolver, Answer};
+
+    /// Starts a UDP server answering A queries with 192.0.2.1 and 192.0.2.2
+    /// and AAAA queries with ::1, and any query for `missing.test` with
+    /// NXDOMAIN
+    fn start_server() -> SocketAddr {
+        test_server::start_server(|query| match query.query_type {
+            _ if query.name.starts_with("missing.") => Answer::response_code(3),
+            1 => Answer::records(vec![
+                record(&query.name, 1, vec![192, 0, 2, 1]),
+                record_with_ttl(&query.name, 1, 30, vec![192, 0, 2, 2]),
+            ]),
+            28 => Answer::records(vec![record_with_ttl(&query.name, 28, 256, Ipv6Addr::LOCALHOST.octets().to_vec())]),
+            _ => Answer::default(),
+        })
+    }
+
+    fn resolver() -> Resolver {
+        test_resolver(start_server())
+    }
+
+    #[test]
+    fn test_lookup_ipv4_and_ipv6() {
+        let resolver = resolver();
+        assert_eq!(resolver.lookup_ipv4("host.test").unwrap(), vec![Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)]);
+        assert_eq!(resolver.lookup_ipv6("host.test").unwrap(), vec![Ipv6Addr::LOCALHOST]);
+        assert_eq!(resolver.lookup_ipv4("missing.test").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        // Queries ending with an OPT record get the same answers
+        let resolver = Resolver::new(ResolverConfig { edns0: true, ..resolver.config });
+        assert_eq!(resolver.lookup_ipv4("host.test").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_hosts_file_order() {
+        let path = std::env::temp_dir().join(format!("r-dns-lookup-hosts-{}", std::process::id()));
+        std::fs::write(&path, "192.0.2.50 host.test\n192.0.2.70 missing.test\n").unwrap();
+        let mut resolver = Resolver::new(ResolverConfig {
+            hosts_file: Some(path.clone()),
+            ..resolver().config
+        });
+        assert_eq!(resolver.lookup_ipv4("host.test").unwrap(), vec![Ipv4Addr::new(192, 0, 2, 50)]);
+        // No IPv6 address in the file, the nameserver is asked
+        assert_eq!(resolver.lookup_ipv6("host.test").unwrap(), vec![Ipv6Addr::LOCALHOST]);
+        assert_eq!(resolver.reverse_lookup("192.0.2.50".parse().unwrap()).unwrap(), vec!["host.test".parse().unwrap()]);
+
+        resolver.config.lookup_order = vec![LookupSource::Dns, LookupSource::Files];
+        assert_eq!(resolver.lookup_ipv4("host.test").unwrap().len(), 2);
+        assert_eq!(resolver.lookup_ipv4("missing.test").unwrap(), vec![Ipv4Addr::new(192, 0, 2, 70)]);
+
+        resolver.config.lookup_order = vec![LookupSource::Dns];
+        assert!(resolver.lookup_ipv4("missing.test").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_lookup_ip() {
+        let lookup = resolver().lookup_ip("host.test").unwrap();
+        assert_eq!(lookup.addresses.len(), 3);
+        assert!(lookup.addresses.contains(&IpAddr::from(Ipv6Addr::LOCALHOST)));
+        assert_eq!(lookup.ttl, 30);
+    }
+}
@@ -0,0 +1,191 @@
+use std::cmp::Ordering;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, UdpSocket};
+
+/// Sorts destination addresses following RFC 6724 section 6, using the
+/// default policy table. `source_for` gives the source address the system
+/// would use to reach a destination, `None` meaning it is unreachable.
+pub(crate) fn sort_addresses(addresses: &mut [IpAddr], source_for: impl Fn(&IpAddr) -> Option<IpAddr>) {
+    let mut candidates: Vec<(IpAddr, Option<IpAddr>)> = addresses.iter()
+        .map(|address| (*address, source_for(address)))
+        .collect();
+    // Stable sort, rule 10 keeps the order of the DNS answer otherwise
+    candidates.sort_by(compare_destinations);
+    for (address, (candidate, _)) in addresses.iter_mut().zip(candidates) {
+        *address = candidate;
+    }
+}
+
+/// Source address chosen by the system to reach `destination`, found by
+/// connecting a UDP socket, which sends nothing
+pub(crate) fn system_source_address(destination: &IpAddr) -> Option<IpAddr> {
+    let bind_address: SocketAddr = match destination {
+        IpAddr::V4(_) => "0.0.0.0:0".parse().ok()?,
+        IpAddr::V6(_) => "[::]:0".parse().ok()?,
+    };
+    let socket = UdpSocket::bind(bind_address).ok()?;
+    socket.connect(SocketAddr::new(*destination, 9)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// `Less` when destination `a` is preferred over `b`
+fn compare_destinations(a: &(IpAddr, Option<IpAddr>), b: &(IpAddr, Option<IpAddr>)) -> Ordering {
+    let (destination_a, destination_b) = (to_ipv6(&a.0), to_ipv6(&b.0));
+
+    // Rule 1: avoid unusable destinations
+    let (source_a, source_b) = match (a.1, b.1) {
+        (Some(source_a), Some(source_b)) => (to_ipv6(&source_a), to_ipv6(&source_b)),
+        (Some(_), None) => return Ordering::Less,
+        (None, Some(_)) => return Ordering::Greater,
+        (None, None) => return Ordering::Equal,
+    };
+
+    // Rule 2: prefer matching scope
+    let scope_matches_a = scope(&destination_a) == scope(&source_a);
+    let scope_matches_b = scope(&destination_b) == scope(&source_b);
+    if scope_matches_a != scope_matches_b {
+        return if scope_matches_a { Ordering::Less } else { Ordering::Greater };
+    }
+
+    // Rules 3 and 4 need the deprecated and home address states, which
+    // are not known here
+
+    // Rule 5: prefer matching label
+    let label_matches_a = policy(&destination_a).1 == policy(&source_a).1;
+    let label_matches_b = policy(&destination_b).1 == policy(&source_b).1;
+    if label_matches_a != label_matches_b {
+        return if label_matches_a { Ordering::Less } else { Ordering::Greater };
+    }
+
+    // Rule 6: prefer higher precedence
+    let precedence_order = policy(&destination_b).0.cmp(&policy(&destination_a).0);
+    if precedence_order != Ordering::Equal {
+        return precedence_order;
+    }
+
+    // Rule 7 is covered by the labels of the transition prefixes
+
+    // Rule 8: prefer smaller scope
+    let scope_order = scope(&destination_a).cmp(&scope(&destination_b));
+    if scope_order != Ordering::Equal {
+        return scope_order;
+    }
+
+    // Rule 9: use longest matching prefix, within the same family
+    if a.0.is_ipv6() && b.0.is_ipv6() {
+        return common_prefix_length(&destination_b, &source_b).cmp(&common_prefix_length(&destination_a, &source_a));
+    }
+
+    // Rule 10: otherwise, leave the order unchanged
+    Ordering::Equal
+}
+
+/// IPv4 addresses are handled as IPv4-mapped IPv6 addresses
+fn to_ipv6(address: &IpAddr) -> Ipv6Addr {
+    match address {
+        IpAddr::V4(address) => address.to_ipv6_mapped(),
+        IpAddr::V6(address) => *address,
+    }
+}
+
+/// Scope values of RFC 6724 section 3.1, IPv4 addresses following
+/// section 3.2
+fn scope(address: &Ipv6Addr) -> u8 {
+    let segments = address.segments();
+    if let Some(ipv4) = address.to_ipv4_mapped() {
+        return if ipv4.is_loopback() || ipv4.is_link_local() { 0x2 } else { 0xe };
+    }
+    if segments[0] >> 8 == 0xff {
+        return (segments[0] & 0x000f) as u8;
+    }
+    if address.is_loopback() || segments[0] & 0xffc0 == 0xfe80 {
+        0x2
+    } else if segments[0] & 0xffc0 == 0xfec0 {
+        0x5
+    } else {
+        0xe
+    }
+}
+
+/// Precedence and label of the default policy table (RFC 6724 section 2.1)
+fn policy(address: &Ipv6Addr) -> (u8, u8) {
+    const POLICY_TABLE: [([u16; 8], u32, u8, u8); 9] = [
+        ([0, 0, 0, 0, 0, 0, 0, 1], 128, 50, 0),
+        ([0, 0, 0, 0, 0, 0xffff, 0, 0], 96, 35, 4),
+        ([0x2002, 0, 0, 0, 0, 0, 0, 0], 16, 30, 2),
+        ([0x2001, 0, 0, 0, 0, 0, 0, 0], 32, 5, 5),
+        ([0xfc00, 0, 0, 0, 0, 0, 0, 0], 7, 3, 13),
+        ([0, 0, 0, 0, 0, 0, 0, 0], 96, 1, 3),
+        ([0xfec0, 0, 0, 0, 0, 0, 0, 0], 10, 1, 11),
+        ([0x3ffe, 0, 0, 0, 0, 0, 0, 0], 16, 1, 12),
+        ([0, 0, 0, 0, 0, 0, 0, 0], 0, 40, 1),
+    ];
+    // Only ::1/128 and ::/96 overlap with another prefix, and they come
+    // before it, so the first match is the longest one
+    POLICY_TABLE.iter()
+        .find(|(prefix, length, _, _)| common_prefix_length(address, &Ipv6Addr::from(*prefix)) >= *length)
+        .map(|(_, _, precedence, label)| (*precedence, *label))
+        .unwrap_or((40, 1))
+}
+
+fn common_prefix_length(a: &Ipv6Addr, b: &Ipv6Addr) -> u32 {
+    (u128::from(*a) ^ u128::from(*b)).leading_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(addresses: &[&str], sources: &[(&str, &str)]) -> Vec<IpAddr> {
+        let mut addresses: Vec<IpAddr> = addresses.iter().map(|address| address.parse().unwrap()).collect();
+        sort_addresses(&mut addresses, |destination| {
+            sources.iter()
+                .find(|(candidate, _)| candidate.parse::<IpAddr>().unwrap() == *destination)
+                .map(|(_, source)| source.parse().unwrap())
+        });
+        addresses
+    }
+
+    fn parsed(addresses: &[&str]) -> Vec<IpAddr> {
+        addresses.iter().map(|address| address.parse().unwrap()).collect()
+    }
+
+    // Examples of RFC 6724 section 10.2
+    #[test]
+    fn test_prefer_matching_scope() {
+        assert_eq!(
+            sorted(&["2001:db8:1::1", "198.51.100.121"], &[("2001:db8:1::1", "2001:db8:1::2"), ("198.51.100.121", "169.254.13.78")]),
+            parsed(&["2001:db8:1::1", "198.51.100.121"])
+        );
+        assert_eq!(
+            sorted(&["2001:db8:1::1", "198.51.100.121"], &[("2001:db8:1::1", "fe80::1"), ("198.51.100.121", "198.51.100.117")]),
+            parsed(&["198.51.100.121", "2001:db8:1::1"])
+        );
+    }
+
+    #[test]
+    fn test_prefer_higher_precedence() {
+        assert_eq!(
+            sorted(&["198.51.100.121", "2001:db8:1::1"], &[("2001:db8:1::1", "2001:db8:1::2"), ("198.51.100.121", "198.51.100.117")]),
+            parsed(&["2001:db8:1::1", "198.51.100.121"])
+        );
+    }
+
+    #[test]
+    fn test_prefer_smaller_scope() {
+        assert_eq!(
+            sorted(&["2001:db8:1::1", "fe80::1"], &[("2001:db8:1::1", "2001:db8:1::2"), ("fe80::1", "fe80::2")]),
+            parsed(&["fe80::1", "2001:db8:1::1"])
+        );
+    }
+
+    #[test]
+    fn test_longest_matching_prefix_and_unusable() {
+        assert_eq!(
+            sorted(&["2001:db8:1::1", "2001:db8:3ffe::1", "2001:db8:4::1"], &[
+                ("2001:db8:1::1", "2001:db8:3f44::2"),
+                ("2001:db8:3ffe::1", "2001:db8:3f44::2"),
+            ]),
+            parsed(&["2001:db8:3ffe::1", "2001:db8:1::1", "2001:db8:4::1"])
+        );
+    }
+}
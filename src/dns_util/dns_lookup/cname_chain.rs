@@ -0,0 +1,265 @@
+use std::io;
+
+use crate::dns_util::dns_packet_structures::dns_packet::DNSPacket;
+use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::util::parse_query_type;
+use crate::dns_util::dns_resolver::Resolver;
+
+const CNAME: u16 = 5;
+const DNAME: u16 = 39;
+
+/// Outcome of `Resolver::resolve_chain`
+#[derive(Debug, Clone)]
+pub struct ResolvedChain {
+    /// Name at the end of the alias chain, owning the records
+    pub canonical_name: DomainName,
+    /// Names of the chain in the order they were followed, starting with the
+    /// queried name and ending with the canonical name
+    pub aliases: Vec<DomainName>,
+    /// CNAME and DNAME records followed, in the order of the chain
+    pub links: Vec<DNSResourceRecord>,
+    /// Records of the queried type owned by the canonical name, empty if it
+    /// has none
+    pub records: Vec<DNSResourceRecord>,
+    /// Smallest TTL of the records and of the links of the chain
+    pub ttl: Option<u32>,
+}
+
+impl Resolver {
+    /// Queries `domain` for records of `query_type`, following the CNAME
+    /// records and DNAME substitutions (RFC 6672) of the answers. When the
+    /// chain leaves the answer section the new name is queried, up to
+    /// `max_chain_length` links. A name of the chain that does not exist
+    /// gives a `NotFound` error, a loop or a chain too long `InvalidData`.
+    ///
+    /// Names that are not fully qualified are completed with the search
+    /// list of the configuration, moving on to the next candidate when a
+    /// name does not exist or has no records of `query_type`.
+    pub fn resolve_chain(&self, domain: &str, query_type: &str) -> io::Result<ResolvedChain> {
+        let mut no_data: Option<ResolvedChain> = None;
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", domain));
+        for name in self.config.qualified_names(domain) {
+            match self.resolve_qualified_chain(&name, query_type) {
+                Ok(chain) if chain.records.is_empty() => {
+                    no_data.get_or_insert(chain);
+                }
+                Ok(chain) => return Ok(chain),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => last_error = e,
+                Err(e) => return Err(e),
+            }
+        }
+        // As the C library, a name that exists without the records wanted
+        // takes precedence over names that do not exist
+        no_data.ok_or(last_error)
+    }
+
+    fn resolve_qualified_chain(&self, domain: &str, query_type: &str) -> io::Result<ResolvedChain> {
+        let name: DomainName = domain.parse()
+            .map_err(|e: String| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut chain = ResolvedChain::starting_at(name);
+
+        loop {
+            let queried_name = chain.canonical_name.clone();
+            let response = self.query(&queried_name.to_string(), query_type)?;
+            let complete = chain.follow(&response, parse_query_type(query_type), self.config.max_chain_length)?;
+            if response.header.flags & 0b1111 == 3 {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", chain.canonical_name)));
+            }
+            // Query again only if the response moved the chain forward but
+            // stopped before its end
+            if complete || chain.canonical_name == queried_name {
+                return Ok(chain);
+            }
+        }
+    }
+}
+
+/// Records answering the question of `response`, following only the
+/// aliases given in the response itself
+#[cfg(feature = "tokio")]
+pub(crate) fn answer_records(response: &DNSPacket, max_chain_length: usize) -> Vec<DNSResourceRecord> {
+    let Some(question) = response.questions.first() else {
+        return Vec::new();
+    };
+    let Some(name) = DomainName::from_labels(&question.query_name) else {
+        return Vec::new();
+    };
+    let mut chain = ResolvedChain::starting_at(name);
+    match chain.follow(response, question.query_type, max_chain_length) {
+        Ok(true) => chain.records,
+        _ => Vec::new(),
+    }
+}
+
+impl ResolvedChain {
+    /// Chain made of `name` alone, before any response
+    pub(crate) fn starting_at(name: DomainName) -> Self {
+        Self {
+            canonical_name: name.clone(),
+            aliases: vec![name],
+            links: Vec::new(),
+            records: Vec::new(),
+            ttl: None,
+        }
+    }
+
+    /// Walks the answer section of `response` from the current canonical
+    /// name. Returns true if records of `query_type` were found, false if
+    /// the chain leaves the response.
+    pub(crate) fn follow(&mut self, response: &DNSPacket, query_type: u16, max_chain_length: usize) -> io::Result<bool> {
+        let answers: Vec<(DomainName, &DNSResourceRecord)> = response.resource_records.iter()
+            .filter_map(|record| Some((DomainName::from_labels(&record.query_name)?, record)))
+            .collect();
+
+        loop {
+            let records: Vec<&DNSResourceRecord> = answers.iter()
+                .filter(|(owner, record)| record.query_type == query_type && *owner == self.canonical_name)
+                .map(|(_, record)| *record)
+                .collect();
+            if !records.is_empty() {
+                for record in &records {
+                    self.update_ttl(record);
+                }
+                self.records = records.into_iter().cloned().collect();
+                return Ok(true);
+            }
+
+            let cname = answers.iter()
+                .find(|(owner, record)| record.query_type == CNAME && query_type != CNAME && *owner == self.canonical_name);
+            // The deepest DNAME above the name applies
+            let dname = answers.iter()
+                .filter(|(owner, record)| {
+                    record.query_type == DNAME && query_type != DNAME
+                        && self.canonical_name.is_subdomain_of(owner) && *owner != self.canonical_name
+                })
+                .max_by_key(|(owner, _)| owner.label_count());
+
+            let (record, target) = if let Some((_, record)) = cname {
+                (*record, DomainName::from_labels(&record.rdata))
+            } else if let Some((owner, record)) = dname {
+                let target = DomainName::from_labels(&record.rdata)
+                    .and_then(|target| self.canonical_name.replace_suffix(owner, &target));
+                (*record, target)
+            } else {
+                return Ok(false);
+            };
+            let target = target.ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid alias target for {}", self.canonical_name),
+            ))?;
+            self.update_ttl(record);
+            self.push_link(target, max_chain_length)?;
+            self.links.push(record.clone());
+        }
+    }
+
+    fn push_link(&mut self, target: DomainName, max_chain_length: usize) -> io::Result<()> {
+        if self.aliases.contains(&target) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("alias loop through {}", target)));
+        }
+        if self.aliases.len() > max_chain_length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("alias chain of {} longer than {} links", self.aliases[0], max_chain_length),
+            ));
+        }
+        self.aliases.push(target.clone());
+        self.canonical_name = target;
+        Ok(())
+    }
+
+    fn update_ttl(&mut self, record: &DNSResourceRecord) {
+        let ttl = record.record_ttl.max(0) as u32;
+        self.ttl = Some(self.ttl.map_or(ttl, |current| current.min(ttl)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use crate::dns_util::dns_resolver::ResolverConfig;
+    use crate::dns_util::test_server::{self, name, record_with_ttl as record, test_resolver, Answer};
+
+    /// Starts a UDP server knowing the following records:
+    /// - www.example.test CNAME web.example.test, itself CNAME
+    ///   host.example.net, whose A record is only given when queried
+    /// - *.old.test DNAME new.test, new.test names having an A record
+    /// - loop1.test and loop2.test, CNAMEs of each other
+    /// - nothing below missing.test
+    fn start_server() -> SocketAddr {
+        test_server::start_server(|query| match query.name.as_str() {
+            "www.example.test" => Answer::records(vec![
+                record("www.example.test", CNAME, 300, name("web.example.test")),
+                record("web.example.test", CNAME, 60, name("host.example.net")),
+            ]),
+            "host.example.net" => Answer::records(vec![record("host.example.net", 1, 120, vec![192, 0, 2, 1])]),
+            "a.b.old.test" => Answer::records(vec![
+                record("old.test", DNAME, 200, name("new.test")),
+                record("a.b.new.test", 1, 100, vec![192, 0, 2, 2]),
+            ]),
+            "loop1.test" => Answer::records(vec![
+                record("loop1.test", CNAME, 10, name("loop2.test")),
+                record("loop2.test", CNAME, 10, name("loop1.test")),
+            ]),
+            "gone.test" => Answer {
+                response_code: 3,
+                answers: vec![record("gone.test", CNAME, 10, name("missing.test"))],
+                ..Answer::default()
+            },
+            name if name.ends_with("missing.test") => Answer::response_code(3),
+            _ => Answer::default(),
+        })
+    }
+
+    fn resolver() -> Resolver {
+        test_resolver(start_server())
+    }
+
+    #[test]
+    fn test_cname_chain_with_requery() {
+        let chain = resolver().resolve_chain("www.example.test", "A").unwrap();
+        assert_eq!(chain.canonical_name, "host.example.net".parse().unwrap());
+        assert_eq!(chain.aliases.len(), 3);
+        assert_eq!(chain.records.len(), 1);
+        assert_eq!(chain.records[0].rdata, vec![192, 0, 2, 1]);
+        assert_eq!(chain.ttl, Some(60));
+    }
+
+    #[test]
+    fn test_dname_substitution() {
+        let chain = resolver().resolve_chain("a.b.old.test", "A").unwrap();
+        assert_eq!(chain.canonical_name, "a.b.new.test".parse().unwrap());
+        assert_eq!(chain.records.len(), 1);
+        assert_eq!(chain.ttl, Some(100));
+    }
+
+    #[test]
+    fn test_search_list() {
+        let resolver = Resolver::new(ResolverConfig {
+            search: vec!["missing.test".to_string(), "example.test".to_string()],
+            ..resolver().config
+        });
+        let chain = resolver.resolve_chain("www", "A").unwrap();
+        assert_eq!(chain.aliases[0], "www.example.test".parse().unwrap());
+        assert_eq!(chain.canonical_name, "host.example.net".parse().unwrap());
+        // Fully qualified names skip the search list
+        assert!(resolver.resolve_chain("www.", "A").unwrap().records.is_empty());
+    }
+
+    #[test]
+    fn test_chain_errors() {
+        let resolver = resolver();
+        assert_eq!(resolver.resolve_chain("loop1.test", "A").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(resolver.resolve_chain("gone.test", "A").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let mut limited = Resolver::new(resolver.config.clone());
+        limited.config.max_chain_length = 1;
+        assert_eq!(limited.resolve_chain("www.example.test", "A").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let chain = resolver.resolve_chain("plain.test", "A").unwrap();
+        assert!(chain.records.is_empty());
+        assert_eq!(chain.aliases.len(), 1);
+    }
+}
@@ -0,0 +1,147 @@
+use std::io;
+use std::net::IpAddr;
+
+use rand::seq::SliceRandom;
+
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::mx_record::MXRecord;
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+use crate::dns_util::dns_resolver::Resolver;
+
+/// Host receiving the mail of a domain, with its addresses
+#[derive(Debug)]
+pub struct MailExchange {
+    pub preference: u16,
+    pub exchange: DomainName,
+    /// Addresses in order of preference (RFC 6724), or the error of their
+    /// lookup so that the next exchanges can be tried
+    pub addresses: io::Result<Vec<IpAddr>>,
+}
+
+/// Where to deliver the mail of a domain, as returned by
+/// `Resolver::mail_exchanges`
+#[derive(Debug)]
+pub enum MailExchanges {
+    /// Exchanges to try in order. Without MX records, the domain itself
+    /// with preference 0 (implicit MX, RFC 5321 section 5.1).
+    Exchanges(Vec<MailExchange>),
+    /// The domain declares that it accepts no mail (Null MX, RFC 7505)
+    NullMX,
+}
+
+impl Resolver {
+    /// MX records of `domain`, by increasing preference, the records of
+    /// equal preference in random order to spread the load. A name that
+    /// does not exist gives a `NotFound` error, a name without MX records
+    /// an empty list.
+    pub fn lookup_mx(&self, domain: &str) -> io::Result<Vec<MXRecord>> {
+        let chain = self.resolve_chain(domain, "MX")?;
+        let mut records: Vec<MXRecord> = chain.records.iter()
+            .filter(|record| record.query_type == MXRecord::RECORD_TYPE)
+            .map(|record| record.record_data())
+            .collect::<io::Result<_>>()?;
+        records.shuffle(&mut rand::thread_rng());
+        records.sort_by_key(|record| record.preference);
+        Ok(records)
+    }
+
+    /// Mail exchanges of `domain` with their addresses, from its MX records
+    /// or the implicit MX. A domain without MX records nor addresses gives
+    /// a `NotFound` error.
+    pub fn mail_exchanges(&self, domain: &str) -> io::Result<MailExchanges> {
+        let records = self.lookup_mx(domain)?;
+        if !records.is_empty() && records.iter().all(MXRecord::is_null_mx) {
+            return Ok(MailExchanges::NullMX);
+        }
+        if records.is_empty() {
+            let lookup = self.lookup_ip(domain)?;
+            if lookup.addresses.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("no mail exchange for {}", domain)));
+            }
+            let exchange = domain.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            return Ok(MailExchanges::Exchanges(vec![MailExchange { preference: 0, exchange, addresses: Ok(lookup.addresses) }]));
+        }
+        let exchanges = records.into_iter()
+            .filter(|record| !record.is_null_mx())
+            .map(|record| MailExchange {
+                // Fully qualified, the search list does not apply
+                addresses: self.lookup_ip(&record.exchange.to_fqdn_string()).map(|lookup| lookup.addresses),
+                preference: record.preference,
+                exchange: record.exchange,
+            })
+            .collect();
+        Ok(MailExchanges::Exchanges(exchanges))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use crate::dns_util::test_server::{self, record, test_resolver, Answer};
+
+    fn mx(preference: u16, exchange: &str) -> Vec<u8> {
+        MXRecord { preference, exchange: exchange.parse().unwrap() }.to_rdata()
+    }
+
+    /// Starts a UDP server where `example.test` has three exchanges,
+    /// `null.test` a Null MX, `implicit.test` only an address and
+    /// `nomail.test` nothing
+    fn start_server() -> SocketAddr {
+        test_server::start_server(|query| match (query.name.as_str(), query.query_type) {
+            ("example.test", 15) => Answer::records(vec![
+                // Exchange compressed as `backup` and the question name
+                record("example.test", 15, [&[0, 20, 6][..], b"backup", &[0xc0, 12]].concat()),
+                record("example.test", 15, mx(10, "mx1.example.test")),
+                record("example.test", 15, mx(10, "mx2.example.test")),
+            ]),
+            ("null.test", 15) => Answer::records(vec![record("null.test", 15, mx(0, "."))]),
+            ("mx1.example.test", 1) => Answer::records(vec![record("mx1.example.test", 1, vec![192, 0, 2, 1])]),
+            ("mx2.example.test", 1) => Answer::records(vec![record("mx2.example.test", 1, vec![192, 0, 2, 2])]),
+            ("implicit.test", 1) => Answer::records(vec![record("implicit.test", 1, vec![192, 0, 2, 3])]),
+            ("example.test" | "null.test" | "implicit.test" | "nomail.test" | "mx1.example.test" | "mx2.example.test", _) => Answer::default(),
+            _ => Answer::response_code(3),
+        })
+    }
+
+    fn resolver() -> Resolver {
+        test_resolver(start_server())
+    }
+
+    #[test]
+    fn test_lookup_mx_order() {
+        let resolver = resolver();
+        let mut first_exchanges: Vec<String> = Vec::new();
+        for _ in 0..20 {
+            let records = resolver.lookup_mx("example.test").unwrap();
+            let preferences: Vec<u16> = records.iter().map(|record| record.preference).collect();
+            assert_eq!(preferences, vec![10, 10, 20]);
+            first_exchanges.push(records[0].exchange.to_string());
+        }
+        // Equal preferences in random order
+        assert!(first_exchanges.contains(&"mx1.example.test".to_string()));
+        assert!(first_exchanges.contains(&"mx2.example.test".to_string()));
+        assert!(resolver.lookup_mx("implicit.test").unwrap().is_empty());
+        assert_eq!(resolver.lookup_mx("missing.test").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_mail_exchanges() {
+        let resolver = resolver();
+        let MailExchanges::Exchanges(exchanges) = resolver.mail_exchanges("example.test").unwrap() else {
+            panic!("no exchanges");
+        };
+        assert_eq!(exchanges.len(), 3);
+        assert!(exchanges[..2].iter().all(|exchange| exchange.addresses.as_ref().unwrap().len() == 1));
+        assert_eq!(exchanges[2].exchange, "backup.example.test".parse().unwrap());
+        assert_eq!(exchanges[2].addresses.as_ref().unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        assert!(matches!(resolver.mail_exchanges("null.test").unwrap(), MailExchanges::NullMX));
+        let MailExchanges::Exchanges(exchanges) = resolver.mail_exchanges("implicit.test").unwrap() else {
+            panic!("no implicit MX");
+        };
+        assert_eq!((exchanges[0].preference, &exchanges[0].exchange), (0, &"implicit.test".parse().unwrap()));
+        assert_eq!(exchanges[0].addresses.as_ref().unwrap(), &vec![IpAddr::from([192, 0, 2, 3])]);
+        assert_eq!(resolver.mail_exchanges("nomail.test").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
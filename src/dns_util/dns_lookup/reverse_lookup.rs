@@ -0,0 +1,86 @@
+use std::io;
+use std::net::IpAddr;
+
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_resolver::{LookupSource, Resolver};
+
+impl Resolver {
+    /// Host names of `address`, from the hosts file or given by the PTR
+    /// records of its `in-addr.arpa` or `ip6.arpa` name, in the order of
+    /// `lookup_order`. CNAMEs are followed, as used by classless delegations
+    /// (RFC 2317). An address without any name gives a `NotFound` error.
+    pub fn reverse_lookup(&self, address: IpAddr) -> io::Result<Vec<DomainName>> {
+        for source in &self.config.lookup_order {
+            let names = match source {
+                LookupSource::Files => self.hosts_names(address),
+                LookupSource::Dns => {
+                    let pointer = DomainName::reverse_pointer(address);
+                    // Fully qualified, the search list does not apply
+                    match self.resolve_chain(&format!("{}.", pointer), "PTR") {
+                        Ok(chain) => chain.records.iter()
+                            .filter_map(|record| DomainName::from_labels(&record.rdata))
+                            .collect(),
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                        Err(e) => return Err(e),
+                    }
+                }
+            };
+            if !names.is_empty() {
+                return Ok(names);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("no name for {}", address)))
+    }
+
+    /// Forward-confirmed reverse DNS: the names of `reverse_lookup` whose own
+    /// addresses include `address`. Names that fail to resolve are left out.
+    pub fn reverse_lookup_confirmed(&self, address: IpAddr) -> io::Result<Vec<DomainName>> {
+        let address = address.to_canonical();
+        let names: Vec<DomainName> = self.reverse_lookup(address)?.into_iter()
+            .filter(|name| {
+                let name = format!("{}.", name);
+                match address {
+                    IpAddr::V4(address) => self.lookup_ipv4(&name).is_ok_and(|addresses| addresses.contains(&address)),
+                    IpAddr::V6(address) => self.lookup_ipv6(&name).is_ok_and(|addresses| addresses.contains(&address)),
+                }
+            })
+            .collect();
+        if names.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no forward-confirmed name for {}", address)));
+        }
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use crate::dns_util::test_server::{self, name, record, test_resolver, Answer};
+
+    /// Starts a UDP server where 192.0.2.1 points to host.test, which
+    /// resolves back to it, and to spoofed.test, which does not
+    fn start_server() -> SocketAddr {
+        test_server::start_server(|query| match query.name.as_str() {
+            "1.2.0.192.in-addr.arpa" => Answer::records(vec![
+                record("1.2.0.192.in-addr.arpa", 12, name("host.test")),
+                record("1.2.0.192.in-addr.arpa", 12, name("spoofed.test")),
+            ]),
+            "host.test" => Answer::records(vec![record("host.test", 1, vec![192, 0, 2, 1])]),
+            "spoofed.test" => Answer::records(vec![record("spoofed.test", 1, vec![198, 51, 100, 1])]),
+            _ => Answer::response_code(3),
+        })
+    }
+
+    #[test]
+    fn test_reverse_lookup() {
+        let resolver = test_resolver(start_server());
+        let address: IpAddr = "192.0.2.1".parse().unwrap();
+        assert_eq!(resolver.reverse_lookup(address).unwrap(), vec![
+            "host.test".parse().unwrap(),
+            "spoofed.test".parse().unwrap(),
+        ]);
+        assert_eq!(resolver.reverse_lookup_confirmed(address).unwrap(), vec!["host.test".parse().unwrap()]);
+        assert_eq!(resolver.reverse_lookup("192.0.2.2".parse().unwrap()).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,145 @@
+use std::io;
+use std::net::IpAddr;
+
+use rand::Rng;
+
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+use crate::dns_util::dns_packet_structures::srv_record::SRVRecord;
+use crate::dns_util::dns_resolver::Resolver;
+
+/// Targets of a service in the order to try them, with their addresses
+/// looked up when reached. Targets whose lookup fails are returned with
+/// the error, so that the next ones can be tried.
+pub struct SRVTargets<'a> {
+    resolver: &'a Resolver,
+    records: std::vec::IntoIter<SRVRecord>,
+}
+
+impl Iterator for SRVTargets<'_> {
+    type Item = (SRVRecord, io::Result<Vec<IpAddr>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.next()?;
+        // Fully qualified, the search list does not apply
+        let addresses = self.resolver.lookup_ip(&record.target.to_fqdn_string()).map(|lookup| lookup.addresses);
+        Some((record, addresses))
+    }
+}
+
+impl Resolver {
+    /// SRV records of the `service` over `proto` at `domain`, such as
+    /// `("sip", "tcp", "example.com")` for `_sip._tcp.example.com`. A name
+    /// that does not exist gives a `NotFound` error, a name without SRV
+    /// records an empty list.
+    pub fn lookup_srv(&self, service: &str, proto: &str, domain: &str) -> io::Result<Vec<SRVRecord>> {
+        let name = format!("_{}._{}.{}", service.trim_start_matches('_'), proto.trim_start_matches('_'), domain);
+        let chain = self.resolve_chain(&name, "SRV")?;
+        chain.records.iter()
+            .filter(|record| record.query_type == SRVRecord::RECORD_TYPE)
+            .map(|record| record.record_data())
+            .collect()
+    }
+
+    /// Targets of the service in the order of RFC 2782, with their
+    /// addresses. A service declared not available with the `.` target has
+    /// no targets.
+    pub fn srv_targets(&self, service: &str, proto: &str, domain: &str) -> io::Result<SRVTargets<'_>> {
+        let records = self.lookup_srv(service, proto, domain)?;
+        Ok(SRVTargets { resolver: self, records: order_srv_records(&records).into_iter() })
+    }
+}
+
+/// `records` in the order to try them (RFC 2782): by increasing priority,
+/// then at random with chances proportional to their weights. The `.`
+/// target is left out.
+pub fn order_srv_records(records: &[SRVRecord]) -> Vec<SRVRecord> {
+    order_srv_records_with(records, &mut rand::thread_rng())
+}
+
+fn order_srv_records_with(records: &[SRVRecord], rng: &mut impl Rng) -> Vec<SRVRecord> {
+    let mut remaining: Vec<SRVRecord> = records.iter().filter(|record| !record.is_no_service()).cloned().collect();
+    // Records of weight 0 first, they only have a small chance of being
+    // selected before the others
+    remaining.sort_by_key(|record| (record.priority, record.weight != 0));
+    let mut ordered: Vec<SRVRecord> = Vec::with_capacity(remaining.len());
+    while let Some(priority) = remaining.first().map(|record| record.priority) {
+        let count = remaining.iter().take_while(|record| record.priority == priority).count();
+        let total: u32 = remaining[..count].iter().map(|record| record.weight as u32).sum();
+        let selected = rng.gen_range(0..=total);
+        let mut running_sum = 0;
+        let position = remaining[..count].iter()
+            .position(|record| {
+                running_sum += record.weight as u32;
+                running_sum >= selected
+            })
+            .unwrap_or(0);
+        ordered.push(remaining.remove(position));
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::dns_util::test_server::{self, record, test_resolver, Answer};
+
+    fn srv(priority: u16, weight: u16, target: &str) -> SRVRecord {
+        SRVRecord { priority, weight, port: 5060, target: target.parse().unwrap() }
+    }
+
+    /// Starts a UDP server with the SIP service of `example.test` on two
+    /// hosts, one of them without address, and no SMTP service
+    fn start_server() -> SocketAddr {
+        test_server::start_server(|query| match (query.name.as_str(), query.query_type) {
+            ("_sip._tcp.example.test", 33) => Answer::records(vec![
+                record("_sip._tcp.example.test", 33, srv(20, 0, "backup.example.test").to_rdata()),
+                record("_sip._tcp.example.test", 33, srv(10, 5, "sip.example.test").to_rdata()),
+            ]),
+            ("_smtp._tcp.example.test", 33) => Answer::records(vec![record("_smtp._tcp.example.test", 33, srv(0, 0, ".").to_rdata())]),
+            ("sip.example.test", 1) => Answer::records(vec![record("sip.example.test", 1, vec![192, 0, 2, 1])]),
+            ("sip.example.test" | "backup.example.test", _) => Answer::default(),
+            _ => Answer::response_code(3),
+        })
+    }
+
+    #[test]
+    fn test_order_srv_records() {
+        let records = vec![srv(20, 0, "c.test"), srv(10, 10, "b.test"), srv(10, 30, "a.test"), srv(30, 0, "."), srv(10, 0, "d.test")];
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut first_counts = [0; 3];
+        for _ in 0..1000 {
+            let ordered = order_srv_records_with(&records, &mut rng);
+            let priorities: Vec<u16> = ordered.iter().map(|record| record.priority).collect();
+            assert_eq!(priorities, vec![10, 10, 10, 20]);
+            match ordered[0].target.to_string().as_str() {
+                "a.test" => first_counts[0] += 1,
+                "b.test" => first_counts[1] += 1,
+                _ => first_counts[2] += 1,
+            }
+        }
+        // Chances of 30/41 and 10/41, the record of weight 0 being rarely
+        // first
+        assert!((630..830).contains(&first_counts[0]), "{:?}", first_counts);
+        assert!((150..350).contains(&first_counts[1]), "{:?}", first_counts);
+        assert!(first_counts[2] < 60, "{:?}", first_counts);
+    }
+
+    #[test]
+    fn test_srv_targets() {
+        let resolver = test_resolver(start_server());
+        assert_eq!(resolver.lookup_srv("sip", "tcp", "example.test").unwrap().len(), 2);
+
+        let targets: Vec<(SRVRecord, io::Result<Vec<IpAddr>>)> = resolver.srv_targets("_sip", "_tcp", "example.test").unwrap().collect();
+        assert_eq!(targets[0].0, srv(10, 5, "sip.example.test"));
+        assert_eq!(targets[0].1.as_ref().unwrap(), &vec![IpAddr::from([192, 0, 2, 1])]);
+        assert_eq!(targets[1].0.target, "backup.example.test".parse().unwrap());
+        assert!(targets[1].1.as_ref().unwrap().is_empty());
+
+        assert!(resolver.lookup_srv("smtp", "tcp", "example.test").unwrap()[0].is_no_service());
+        assert_eq!(resolver.srv_targets("smtp", "tcp", "example.test").unwrap().count(), 0);
+        assert_eq!(resolver.lookup_srv("xmpp", "tcp", "example.test").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,192 @@
+use std::io;
+
+use rand::seq::SliceRandom;
+
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+use crate::dns_util::dns_packet_structures::svcb_record::{HTTPSRecord, SVCBRecord};
+use crate::dns_util::dns_resolver::Resolver;
+
+/// Most AliasMode records followed in one lookup
+const MAX_ALIASES: usize = 8;
+
+/// Endpoints of a service, as returned by `Resolver::lookup_https` and
+/// `Resolver::lookup_svcb`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceBindings {
+    /// Name reached by following the AliasMode records, where to connect
+    /// the usual way when there are no ServiceMode records. The root when an
+    /// alias declares the service not available.
+    pub name: DomainName,
+    /// ServiceMode records by increasing priority, those of equal priority
+    /// in random order, with the `.` targets replaced by `name`
+    pub records: Vec<SVCBRecord>,
+}
+
+impl ServiceBindings {
+    pub fn is_available(&self) -> bool {
+        !self.name.is_root()
+    }
+}
+
+impl Resolver {
+    /// HTTPS records of `domain`, following the aliases. A domain that does
+    /// not exist gives a `NotFound` error, a loop of aliases an
+    /// `InvalidData` error.
+    pub fn lookup_https(&self, domain: &str) -> io::Result<ServiceBindings> {
+        self.service_bindings(domain, "HTTPS", HTTPSRecord::RECORD_TYPE)
+    }
+
+    /// HTTPS records of the origin `domain` on `port`, looked up at
+    /// `_<port>._https.<domain>` for another port than 443 (RFC 9460
+    /// section 9.1). When the prefixed name does not exist or has neither
+    /// aliases nor records, `name` is `domain`.
+    pub fn lookup_https_port(&self, domain: &str, port: u16) -> io::Result<ServiceBindings> {
+        if port == 443 {
+            return self.lookup_https(domain);
+        }
+        let origin: DomainName = domain.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let prefixed = format!("_{}._https.{}", port, domain);
+        let prefixed_name: DomainName = prefixed.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let bindings = match self.service_bindings(&prefixed, "HTTPS", HTTPSRecord::RECORD_TYPE) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ServiceBindings { name: origin, records: Vec::new() }),
+            bindings => bindings?,
+        };
+        if bindings.records.is_empty() && bindings.name == prefixed_name {
+            return Ok(ServiceBindings { name: origin, records: Vec::new() });
+        }
+        Ok(bindings)
+    }
+
+    /// SVCB records of `name`, such as `_dns.resolver.example` for DNS
+    /// servers (RFC 9461), following the aliases
+    pub fn lookup_svcb(&self, name: &str) -> io::Result<ServiceBindings> {
+        self.service_bindings(name, "SVCB", SVCBRecord::RECORD_TYPE)
+    }
+
+    fn service_bindings(&self, name: &str, query_type: &str, record_type: u16) -> io::Result<ServiceBindings> {
+        let mut query_name = name.to_string();
+        let mut owners: Vec<DomainName> = Vec::new();
+        loop {
+            let chain = match self.resolve_chain(&query_name, query_type) {
+                Ok(chain) => chain,
+                // An alias target without records is connected to directly
+                Err(e) if e.kind() == io::ErrorKind::NotFound && !owners.is_empty() => {
+                    let name = query_name.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    return Ok(ServiceBindings { name, records: Vec::new() });
+                }
+                Err(e) => return Err(e),
+            };
+            let owner = chain.canonical_name;
+            // Malformed records are ignored (RFC 9460 section 2.2), as are
+            // those with mandatory keys not supported
+            let records: Vec<SVCBRecord> = chain.records.iter()
+                .filter(|record| record.query_type == record_type)
+                .filter_map(|record| SVCBRecord::from_rdata(&record.rdata).ok())
+                .filter(|record| record.is_alias() || record.is_supported())
+                .collect();
+            // ServiceMode records next to an alias are ignored
+            if let Some(alias) = records.iter().find(|record| record.is_alias()) {
+                if alias.target.is_root() {
+                    return Ok(ServiceBindings { name: DomainName::root(), records: Vec::new() });
+                }
+                owners.push(owner);
+                if owners.contains(&alias.target) || owners.len() > MAX_ALIASES {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("alias loop or too many aliases for {}", name)));
+                }
+                // Fully qualified, the search list does not apply
+                query_name = alias.target.to_fqdn_string();
+                continue;
+            }
+            let mut records: Vec<SVCBRecord> = records.into_iter()
+                .map(|mut record| {
+                    if record.target.is_root() {
+                        record.target = owner.clone();
+                    }
+                    record
+                })
+                .collect();
+            records.shuffle(&mut rand::thread_rng());
+            records.sort_by_key(|record| record.priority);
+            return Ok(ServiceBindings { name: owner, records });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use crate::dns_util::test_server::{self, test_resolver, Answer};
+
+    fn record(owner: &str, record_type: u16, rdata: &str) -> Vec<u8> {
+        test_server::record(owner, record_type, rdata.parse::<SVCBRecord>().unwrap().to_rdata())
+    }
+
+    /// Starts a UDP server where the HTTPS records of `example.test` alias
+    /// `svc.example.test`, which has two endpoints
+    fn start_server() -> SocketAddr {
+        test_server::start_server(|query| match (query.name.as_str(), query.query_type) {
+            ("example.test", 65) => Answer::records(vec![
+                record("example.test", 65, "0 svc.example.test."),
+                // Ignored next to the alias
+                record("example.test", 65, "1 ignored.example.test."),
+            ]),
+            ("svc.example.test", 65) => Answer::records(vec![
+                record("svc.example.test", 65, "2 . alpn=h2"),
+                record("svc.example.test", 65, "1 backup.example.test. alpn=h3 port=8443"),
+            ]),
+            ("direct.test", 65) => Answer::records(vec![record("direct.test", 65, "1 .")]),
+            ("mandatory.test", 65) => Answer::records(vec![
+                record("mandatory.test", 65, "1 new.mandatory.test. mandatory=key123 key123=abc"),
+                record("mandatory.test", 65, "2 old.mandatory.test. mandatory=alpn alpn=h2"),
+            ]),
+            ("_8443._https.example.test", 65) => Answer::records(vec![record("_8443._https.example.test", 65, "1 alt.example.test. port=8443")]),
+            ("_8080._https.plain.test", 65) => Answer::default(),
+            ("unavailable.test", 65) => Answer::records(vec![record("unavailable.test", 65, "0 .")]),
+            ("loop.test", 65) => Answer::records(vec![record("loop.test", 65, "0 loop2.test.")]),
+            ("loop2.test", 65) => Answer::records(vec![record("loop2.test", 65, "0 loop.test.")]),
+            ("plain.test", 65) => Answer::records(vec![record("plain.test", 65, "0 www.plain.test.")]),
+            ("_dns.resolver.test", 64) => Answer::records(vec![record("_dns.resolver.test", 64, "1 dot.resolver.test. alpn=dot")]),
+            ("www.plain.test", _) => Answer::default(),
+            _ => Answer::response_code(3),
+        })
+    }
+
+    #[test]
+    fn test_lookup_https() {
+        let resolver = test_resolver(start_server());
+        let bindings = resolver.lookup_https("example.test").unwrap();
+        assert_eq!(bindings.name, "svc.example.test".parse().unwrap());
+        assert_eq!(bindings.records, vec![
+            "1 backup.example.test. alpn=h3 port=8443".parse().unwrap(),
+            "2 svc.example.test. alpn=h2".parse().unwrap(),
+        ]);
+
+        assert_eq!(resolver.lookup_https("direct.test").unwrap().records[0].target, "direct.test".parse().unwrap());
+        assert!(!resolver.lookup_https("unavailable.test").unwrap().is_available());
+        // An alias target without records is used as it is
+        let bindings = resolver.lookup_https("plain.test").unwrap();
+        assert_eq!((bindings.name, bindings.records), ("www.plain.test".parse().unwrap(), Vec::new()));
+        assert_eq!(resolver.lookup_https("loop.test").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(resolver.lookup_https("missing.test").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(resolver.lookup_svcb("_dns.resolver.test").unwrap().records[0].port(), None);
+
+        // Records with mandatory keys not supported are ignored
+        let bindings = resolver.lookup_https("mandatory.test").unwrap();
+        assert_eq!(bindings.records, vec!["2 old.mandatory.test. mandatory=alpn alpn=h2".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_lookup_https_port() {
+        let resolver = test_resolver(start_server());
+        let bindings = resolver.lookup_https_port("example.test", 8443).unwrap();
+        assert_eq!(bindings.records, vec!["1 alt.example.test. port=8443".parse().unwrap()]);
+        assert_eq!(resolver.lookup_https_port("example.test", 443).unwrap().name, "svc.example.test".parse().unwrap());
+        // Without records, the origin is connected to directly
+        let bindings = resolver.lookup_https_port("plain.test", 8080).unwrap();
+        assert_eq!((bindings.name, bindings.records), ("plain.test".parse().unwrap(), Vec::new()));
+        // Nor when the prefixed name does not exist
+        assert_eq!(resolver.lookup_https_port("direct.test", 8080).unwrap().name, "direct.test".parse().unwrap());
+    }
+}
@@ -0,0 +1,333 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::dns_util::dns_packet_structures::dns_resource_record::DNSResourceRecord;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::mx_record::MXRecord;
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+use crate::dns_util::dns_resolver::Resolver;
+
+pub mod dkim;
+pub mod dmarc;
+pub mod mta_sts;
+pub mod spf;
+pub mod tls_rpt;
+
+/// DNS lookups done by the mail authentication checks, answered by a
+/// `Resolver` or by any other source of records. A name that does not exist
+/// gives a `NotFound` error, a name without records of the type an empty
+/// list; other errors are treated as temporary.
+pub trait MailResolver {
+    /// TXT records of `name`, each as the concatenation of its
+    /// character-strings
+    fn txt_records(&self, name: &DomainName) -> io::Result<Vec<String>>;
+
+    fn ipv4_addresses(&self, name: &DomainName) -> io::Result<Vec<Ipv4Addr>>;
+
+    fn ipv6_addresses(&self, name: &DomainName) -> io::Result<Vec<Ipv6Addr>>;
+
+    fn mx_records(&self, name: &DomainName) -> io::Result<Vec<MXRecord>>;
+
+    /// Names given by the PTR records of `address`
+    fn ptr_names(&self, address: IpAddr) -> io::Result<Vec<DomainName>>;
+}
+
+impl MailResolver for Resolver {
+    fn txt_records(&self, name: &DomainName) -> io::Result<Vec<String>> {
+        Ok(self.dns_records(name, "TXT", 16)?.iter().map(|record| txt_string(&record.rdata)).collect())
+    }
+
+    fn ipv4_addresses(&self, name: &DomainName) -> io::Result<Vec<Ipv4Addr>> {
+        Ok(self.dns_records(name, "A", 1)?.iter()
+            .filter_map(|record| <[u8; 4]>::try_from(record.rdata.as_slice()).ok())
+            .map(Ipv4Addr::from)
+            .collect())
+    }
+
+    fn ipv6_addresses(&self, name: &DomainName) -> io::Result<Vec<Ipv6Addr>> {
+        Ok(self.dns_records(name, "AAAA", 28)?.iter()
+            .filter_map(|record| <[u8; 16]>::try_from(record.rdata.as_slice()).ok())
+            .map(Ipv6Addr::from)
+            .collect())
+    }
+
+    fn mx_records(&self, name: &DomainName) -> io::Result<Vec<MXRecord>> {
+        self.dns_records(name, "MX", MXRecord::RECORD_TYPE)?.iter()
+            .map(|record| record.record_data())
+            .collect()
+    }
+
+    fn ptr_names(&self, address: IpAddr) -> io::Result<Vec<DomainName>> {
+        Ok(self.dns_records(&DomainName::reverse_pointer(address), "PTR", 12)?.iter()
+            .filter_map(|record| DomainName::from_labels(&record.rdata))
+            .collect())
+    }
+}
+
+impl Resolver {
+    /// Records of `record_type` at the end of the CNAME chain of `name`,
+    /// without the hosts file nor the search list
+    fn dns_records(&self, name: &DomainName, query_type: &str, record_type: u16) -> io::Result<Vec<DNSResourceRecord>> {
+        let chain = self.resolve_chain(&name.to_fqdn_string(), query_type)?;
+        Ok(chain.records.into_iter().filter(|record| record.query_type == record_type).collect())
+    }
+}
+
+/// Concatenation of the character-strings of a TXT record (RFC 7208
+/// section 3.3), invalid UTF-8 being replaced
+fn txt_string(rdata: &[u8]) -> String {
+    let mut text: Vec<u8> = Vec::with_capacity(rdata.len());
+    let mut position = 0;
+    while let Some(&length) = rdata.get(position) {
+        let end = (position + 1 + length as usize).min(rdata.len());
+        text.extend(&rdata[position + 1..end]);
+        position = end;
+    }
+    String::from_utf8_lossy(&text).into_owned()
+}
+
+/// Error in a record made of `tag=value` pairs separated by `;`, such as
+/// DKIM keys (RFC 6376 section 3.2) or DMARC policies. Converts to an
+/// `InvalidData` I/O error carrying it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagError {
+    /// Not a valid list of tags, or the version tag not first
+    Syntax(String),
+    DuplicateTag(String),
+    MissingTag(String),
+    InvalidValue { tag: String, value: String },
+}
+
+impl fmt::Display for TagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TagError::Syntax(message) => write!(f, "invalid tag list: {}", message),
+            TagError::DuplicateTag(tag) => write!(f, "tag {} given twice", tag),
+            TagError::MissingTag(tag) => write!(f, "missing tag {}", tag),
+            TagError::InvalidValue { tag, value } => write!(f, "invalid value {:?} for tag {}", value, tag),
+        }
+    }
+}
+
+impl std::error::Error for TagError {}
+
+impl From<TagError> for io::Error {
+    fn from(error: TagError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// Tags of a record in order, names and values trimmed
+pub(crate) struct TagList<'a> {
+    tags: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> TagList<'a> {
+    pub(crate) fn parse(text: &'a str) -> Result<Self, TagError> {
+        let mut tags: Vec<(&str, &str)> = Vec::new();
+        for spec in text.split(';').filter(|spec| !spec.trim().is_empty()) {
+            let (name, value) = spec.split_once('=').ok_or_else(|| TagError::Syntax(format!("no value for {}", spec.trim())))?;
+            let (name, value) = (name.trim(), value.trim());
+            // ALPHA *(ALPHA / DIGIT / "_")
+            if !name.as_bytes().first().is_some_and(u8::is_ascii_alphabetic)
+                || !name.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+            {
+                return Err(TagError::Syntax(format!("invalid tag name {}", name)));
+            }
+            if tags.iter().any(|(other, _)| *other == name) {
+                return Err(TagError::DuplicateTag(name.to_string()));
+            }
+            tags.push((name, value));
+        }
+        Ok(Self { tags })
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&'a str> {
+        self.tags.iter().find(|(other, _)| *other == name).map(|(_, value)| *value)
+    }
+
+    pub(crate) fn required(&self, name: &str) -> Result<&'a str, TagError> {
+        self.get(name).ok_or_else(|| TagError::MissingTag(name.to_string()))
+    }
+
+    /// Value of the tag `name` converted by `parse`, `None` if absent
+    pub(crate) fn parse_value<T>(&self, name: &str, parse: impl FnOnce(&str) -> Option<T>) -> Result<Option<T>, TagError> {
+        self.get(name)
+            .map(|value| parse(value).ok_or_else(|| invalid_value(name, value)))
+            .transpose()
+    }
+
+    /// Checks that the first tag is `v=<version>`
+    pub(crate) fn check_version(&self, version: &str) -> Result<(), TagError> {
+        match self.tags.first() {
+            Some(&("v", value)) if value == version => Ok(()),
+            Some(&("v", value)) => Err(invalid_value("v", value)),
+            _ if self.get("v").is_some() => Err(TagError::Syntax("v is not the first tag".to_string())),
+            _ => Err(TagError::MissingTag("v".to_string())),
+        }
+    }
+}
+
+pub(crate) fn invalid_value(tag: &str, value: &str) -> TagError {
+    TagError::InvalidValue { tag: tag.to_string(), value: value.to_string() }
+}
+
+/// Values of a tag separated by `:`, `None` if one is empty or, when
+/// `allowed` is given, not among `allowed`
+pub(crate) fn colon_list(value: &str, allowed: Option<&[&str]>) -> Option<Vec<String>> {
+    let values: Vec<String> = value.split(':').map(|item| item.trim().to_string()).collect();
+    values.iter()
+        .all(|item| !item.is_empty() && allowed.is_none_or(|allowed| allowed.contains(&item.as_str())))
+        .then_some(values)
+}
+
+/// The only TXT record of `name` starting with `v=<version>`, the others
+/// being ignored. `NotFound` if there is none, `InvalidData` if there are
+/// several.
+pub(crate) fn versioned_record(resolver: &(impl MailResolver + ?Sized), name: &DomainName, version: &str) -> io::Result<String> {
+    let mut records: Vec<String> = resolver.txt_records(name)?.into_iter()
+        .filter(|record| {
+            record.split(';').next()
+                .and_then(|tag| tag.split_once('='))
+                .is_some_and(|(tag, value)| tag.trim() == "v" && value.trim() == version)
+        })
+        .collect();
+    match records.len() {
+        0 => Err(io::Error::new(io::ErrorKind::NotFound, format!("no {} record at {}", version, name))),
+        1 => Ok(records.remove(0)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("several {} records at {}", version, name))),
+    }
+}
+
+/// Records kept in memory, answering the lookups of the tests
+#[cfg(test)]
+pub(crate) mod memory {
+    use super::*;
+
+    #[derive(Default)]
+    pub(crate) struct MemoryResolver {
+        records: Vec<(DomainName, MemoryRecord)>,
+        /// Names whose lookups fail as if the nameservers timed out
+        failing: Vec<DomainName>,
+    }
+
+    enum MemoryRecord {
+        Txt(String),
+        Address(IpAddr),
+        MX(MXRecord),
+        Ptr(DomainName),
+    }
+
+    impl MemoryResolver {
+        fn with(mut self, owner: &str, record: MemoryRecord) -> Self {
+            self.records.push((owner.parse().unwrap(), record));
+            self
+        }
+
+        pub(crate) fn txt(self, owner: &str, text: &str) -> Self {
+            self.with(owner, MemoryRecord::Txt(text.to_string()))
+        }
+
+        pub(crate) fn address(self, owner: &str, address: &str) -> Self {
+            self.with(owner, MemoryRecord::Address(address.parse().unwrap()))
+        }
+
+        pub(crate) fn mx(self, owner: &str, preference: u16, exchange: &str) -> Self {
+            self.with(owner, MemoryRecord::MX(MXRecord { preference, exchange: exchange.parse().unwrap() }))
+        }
+
+        pub(crate) fn ptr(self, address: &str, name: &str) -> Self {
+            let owner = DomainName::reverse_pointer(address.parse().unwrap()).to_string();
+            self.with(&owner, MemoryRecord::Ptr(name.parse().unwrap()))
+        }
+
+        pub(crate) fn failing(mut self, name: &str) -> Self {
+            self.failing.push(name.parse().unwrap());
+            self
+        }
+
+        fn records<T>(&self, name: &DomainName, select: impl Fn(&MemoryRecord) -> Option<T>) -> io::Result<Vec<T>> {
+            if self.failing.contains(name) {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, format!("lookup of {} timed out", name)));
+            }
+            let mut records = self.records.iter().filter(|(owner, _)| owner == name).peekable();
+            if records.peek().is_none() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", name)));
+            }
+            Ok(records.filter_map(|(_, record)| select(record)).collect())
+        }
+    }
+
+    impl MailResolver for MemoryResolver {
+        fn txt_records(&self, name: &DomainName) -> io::Result<Vec<String>> {
+            self.records(name, |record| match record {
+                MemoryRecord::Txt(text) => Some(text.clone()),
+                _ => None,
+            })
+        }
+
+        fn ipv4_addresses(&self, name: &DomainName) -> io::Result<Vec<Ipv4Addr>> {
+            self.records(name, |record| match record {
+                MemoryRecord::Address(IpAddr::V4(address)) => Some(*address),
+                _ => None,
+            })
+        }
+
+        fn ipv6_addresses(&self, name: &DomainName) -> io::Result<Vec<Ipv6Addr>> {
+            self.records(name, |record| match record {
+                MemoryRecord::Address(IpAddr::V6(address)) => Some(*address),
+                _ => None,
+            })
+        }
+
+        fn mx_records(&self, name: &DomainName) -> io::Result<Vec<MXRecord>> {
+            self.records(name, |record| match record {
+                MemoryRecord::MX(record) => Some(record.clone()),
+                _ => None,
+            })
+        }
+
+        fn ptr_names(&self, address: IpAddr) -> io::Result<Vec<DomainName>> {
+            self.records(&DomainName::reverse_pointer(address), |record| match record {
+                MemoryRecord::Ptr(name) => Some(name.clone()),
+                _ => None,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_txt_string() {
+        assert_eq!(txt_string(b"\x0bv=spf1 -all"), "v=spf1 -all");
+        assert_eq!(txt_string(b"\x07v=spf1 \x04-all\x00"), "v=spf1 -all");
+        assert_eq!(txt_string(b""), "");
+        // Truncated string
+        assert_eq!(txt_string(b"\x09v=spf1"), "v=spf1");
+    }
+
+    #[test]
+    fn test_tag_list() {
+        let tags = TagList::parse(" v = DKIM1 ; k=rsa;p= MIGf MA0G ;").unwrap();
+        assert_eq!(tags.get("p"), Some("MIGf MA0G"));
+        assert_eq!(tags.required("x"), Err(TagError::MissingTag("x".to_string())));
+        assert_eq!(tags.check_version("DKIM1"), Ok(()));
+        assert_eq!(tags.parse_value("k", |value| (value == "rsa").then_some(1)), Ok(Some(1)));
+        assert_eq!(tags.parse_value("v", |value| value.parse::<u8>().ok()), Err(invalid_value("v", "DKIM1")));
+        assert!(TagList::parse("k=rsa; v=DKIM1").unwrap().check_version("DKIM1").is_err());
+
+        assert_eq!(TagList::parse("p=a; p=b").err(), Some(TagError::DuplicateTag("p".to_string())));
+        assert!(matches!(TagList::parse("v=DKIM1; p").err(), Some(TagError::Syntax(_))));
+        assert!(matches!(TagList::parse("1x=y").err(), Some(TagError::Syntax(_))));
+        assert_eq!(colon_list("y : s", None), Some(vec!["y".to_string(), "s".to_string()]));
+        assert_eq!(colon_list("y:", None), None);
+        assert_eq!(colon_list("0:2", Some(&["0", "1"])), None);
+        let error: io::Error = TagError::MissingTag("p".to_string()).into();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.get_ref().unwrap().downcast_ref::<TagError>(), Some(&TagError::MissingTag("p".to_string())));
+    }
+}
@@ -0,0 +1,122 @@
+use std::io;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::dns_util::dns_mail::{colon_list, invalid_value, MailResolver, TagError, TagList};
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+/// DKIM public key record, published at `<selector>._domainkey.<domain>`
+/// (RFC 6376 section 3.6.1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DKIMKey {
+    /// `rsa` or `ed25519` (RFC 8463)
+    pub key_type: String,
+    /// Hash algorithms allowed with the key, empty when all are
+    pub hash_algorithms: Vec<String>,
+    /// Key in the format of its type, empty when the key was revoked
+    pub public_key: Vec<u8>,
+    /// Service types the key applies to, `*` for all
+    pub service_types: Vec<String>,
+    /// The domain is testing DKIM, flag `y`
+    pub testing: bool,
+    /// The `i=` domain of signatures must be the `d=` domain itself, flag `s`
+    pub strict: bool,
+    pub notes: Option<String>,
+}
+
+impl DKIMKey {
+    pub fn is_revoked(&self) -> bool {
+        self.public_key.is_empty()
+    }
+}
+
+/// Parses a key record, unknown tags and flags being ignored
+impl FromStr for DKIMKey {
+    type Err = TagError;
+
+    fn from_str(record: &str) -> Result<Self, Self::Err> {
+        let tags = TagList::parse(record)?;
+        if tags.get("v").is_some() {
+            tags.check_version("DKIM1")?;
+        }
+        let key_type = tags.get("k").unwrap_or("rsa");
+        if !["rsa", "ed25519"].contains(&key_type) {
+            return Err(invalid_value("k", key_type));
+        }
+        let public_key = tags.required("p")?;
+        let encoded: String = public_key.split_whitespace().collect();
+        let public_key = STANDARD.decode(&encoded).map_err(|_| invalid_value("p", public_key))?;
+        // Unknown hash algorithms, service types and flags are ignored
+        let flags = tags.parse_value("t", |value| colon_list(value, None))?.unwrap_or_default();
+        Ok(Self {
+            key_type: key_type.to_string(),
+            hash_algorithms: tags.parse_value("h", |value| colon_list(value, None))?.unwrap_or_default(),
+            public_key,
+            service_types: tags.parse_value("s", |value| colon_list(value, None))?
+                .unwrap_or_else(|| vec!["*".to_string()]),
+            testing: flags.iter().any(|flag| flag == "y"),
+            strict: flags.iter().any(|flag| flag == "s"),
+            notes: tags.get("n").map(String::from),
+        })
+    }
+}
+
+/// DKIM key of `selector` for `domain`. A name without TXT record gives a
+/// `NotFound` error, an invalid record an `InvalidData` error carrying the
+/// `TagError`.
+pub fn lookup_dkim_key<R: MailResolver + ?Sized>(resolver: &R, selector: &str, domain: &str) -> io::Result<DKIMKey> {
+    let name: DomainName = format!("{}._domainkey.{}", selector, domain).parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let records = resolver.txt_records(&name)?;
+    match records.as_slice() {
+        [] => Err(io::Error::new(io::ErrorKind::NotFound, format!("no DKIM key at {}", name))),
+        [record] => Ok(record.parse()?),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("several DKIM keys at {}", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_util::dns_mail::memory::MemoryResolver;
+
+    #[test]
+    fn test_dkim_key() {
+        let key: DKIMKey = "v=DKIM1; k=ed25519; h=sha256; t=y:s; n=rotated yearly; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=".parse().unwrap();
+        assert_eq!(key.key_type, "ed25519");
+        assert_eq!(key.hash_algorithms, vec!["sha256"]);
+        assert_eq!(key.public_key.len(), 32);
+        assert_eq!(key.service_types, vec!["*"]);
+        assert!(key.testing && key.strict);
+        assert_eq!(key.notes.as_deref(), Some("rotated yearly"));
+
+        // Defaults, and a key split by whitespace
+        let key: DKIMKey = "p=MIGfMA0GCSqGSIb3 DQEBAQUAA4GN; s=email".parse().unwrap();
+        assert_eq!(key.key_type, "rsa");
+        assert_eq!(key.service_types, vec!["email"]);
+        assert!(!key.testing && !key.is_revoked());
+        assert!("v=DKIM1; p=".parse::<DKIMKey>().unwrap().is_revoked());
+
+        assert_eq!("k=rsa".parse::<DKIMKey>(), Err(TagError::MissingTag("p".to_string())));
+        assert_eq!("k=dsa; p=".parse::<DKIMKey>(), Err(invalid_value("k", "dsa")));
+        assert_eq!("p=not base64!".parse::<DKIMKey>(), Err(invalid_value("p", "not base64!")));
+        assert_eq!("h=sha256:; p=".parse::<DKIMKey>(), Err(invalid_value("h", "sha256:")));
+        assert_eq!("p=; v=DKIM1".parse::<DKIMKey>(), Err(TagError::Syntax("v is not the first tag".to_string())));
+    }
+
+    #[test]
+    fn test_lookup_dkim_key() {
+        let resolver = MemoryResolver::default()
+            .txt("mail._domainkey.example.com", "v=DKIM1; p=")
+            .txt("broken._domainkey.example.com", "v=DKIM2; p=")
+            .txt("twice._domainkey.example.com", "p=")
+            .txt("twice._domainkey.example.com", "p=");
+        assert!(lookup_dkim_key(&resolver, "mail", "example.com").unwrap().is_revoked());
+        assert_eq!(lookup_dkim_key(&resolver, "other", "example.com").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(lookup_dkim_key(&resolver, "twice", "example.com").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let error = lookup_dkim_key(&resolver, "broken", "example.com").unwrap_err();
+        assert_eq!(error.get_ref().unwrap().downcast_ref::<TagError>(), Some(&invalid_value("v", "DKIM2")));
+    }
+}
@@ -0,0 +1,214 @@
+use std::io;
+use std::str::FromStr;
+
+use crate::dns_util::dns_mail::{colon_list, versioned_record, MailResolver, TagError, TagList};
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+/// Most labels of the names queried when walking up the tree, past the
+/// domain itself
+const MAX_WALK_LABELS: usize = 7;
+
+/// What receivers should do with the messages failing DMARC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DMARCPolicy {
+    None,
+    Quarantine,
+    Reject,
+}
+
+fn parse_policy(value: &str) -> Option<DMARCPolicy> {
+    match value {
+        "none" => Some(DMARCPolicy::None),
+        "quarantine" => Some(DMARCPolicy::Quarantine),
+        "reject" => Some(DMARCPolicy::Reject),
+        _ => None,
+    }
+}
+
+/// How the DKIM or SPF domain must match the `From` domain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DMARCAlignment {
+    /// Same organizational domain
+    Relaxed,
+    /// Same domain
+    Strict,
+}
+
+fn parse_alignment(value: &str) -> Option<DMARCAlignment> {
+    match value {
+        "r" => Some(DMARCAlignment::Relaxed),
+        "s" => Some(DMARCAlignment::Strict),
+        _ => None,
+    }
+}
+
+/// DMARC policy record, published at `_dmarc.<domain>` (RFC 7489 section
+/// 6.3)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DMARCRecord {
+    pub policy: DMARCPolicy,
+    /// Policy of the subdomains, the policy by default
+    pub subdomain_policy: DMARCPolicy,
+    pub dkim_alignment: DMARCAlignment,
+    pub spf_alignment: DMARCAlignment,
+    /// Percentage of the failing messages the policy applies to
+    pub percentage: u8,
+    /// When to send failure reports: `0`, `1`, `d` or `s`
+    pub failure_options: Vec<String>,
+    pub report_formats: Vec<String>,
+    /// Seconds between aggregate reports
+    pub report_interval: u32,
+    /// Where to send aggregate reports, with their optional `!` size limit
+    pub aggregate_report_uris: Vec<String>,
+    /// Where to send failure reports
+    pub failure_report_uris: Vec<String>,
+}
+
+/// URIs separated by `,`, each with a scheme
+fn uri_list(value: &str) -> Option<Vec<String>> {
+    let uris: Vec<String> = value.split(',').map(|uri| uri.trim().to_string()).collect();
+    uris.iter()
+        .all(|uri| uri.split_once(':').is_some_and(|(scheme, rest)| !scheme.is_empty() && !rest.is_empty()))
+        .then_some(uris)
+}
+
+/// Parses a record, unknown tags being ignored. A record with reporting URIs
+/// but without valid policy is taken as `p=none` (RFC 7489 section 6.6.3).
+impl FromStr for DMARCRecord {
+    type Err = TagError;
+
+    fn from_str(record: &str) -> Result<Self, Self::Err> {
+        let tags = TagList::parse(record)?;
+        tags.check_version("DMARC1")?;
+        let aggregate_report_uris = tags.parse_value("rua", uri_list)?.unwrap_or_default();
+        let (policy, subdomain_policy) = match (tags.parse_value("p", parse_policy), tags.parse_value("sp", parse_policy)) {
+            (Ok(Some(policy)), Ok(subdomain_policy)) => (policy, subdomain_policy.unwrap_or(policy)),
+            _ if !aggregate_report_uris.is_empty() => (DMARCPolicy::None, DMARCPolicy::None),
+            (Ok(None), _) => return Err(TagError::MissingTag("p".to_string())),
+            (Err(e), _) | (_, Err(e)) => return Err(e),
+        };
+        Ok(Self {
+            policy,
+            subdomain_policy,
+            dkim_alignment: tags.parse_value("adkim", parse_alignment)?.unwrap_or(DMARCAlignment::Relaxed),
+            spf_alignment: tags.parse_value("aspf", parse_alignment)?.unwrap_or(DMARCAlignment::Relaxed),
+            percentage: tags.parse_value("pct", |value| value.parse().ok().filter(|pct| *pct <= 100))?.unwrap_or(100),
+            failure_options: tags.parse_value("fo", |value| colon_list(value, Some(&["0", "1", "d", "s"])))?
+                .unwrap_or_else(|| vec!["0".to_string()]),
+            report_formats: tags.parse_value("rf", |value| colon_list(value, None))?
+                .unwrap_or_else(|| vec!["afrf".to_string()]),
+            report_interval: tags.parse_value("ri", |value| value.parse().ok())?.unwrap_or(86400),
+            aggregate_report_uris,
+            failure_report_uris: tags.parse_value("ruf", uri_list)?.unwrap_or_default(),
+        })
+    }
+}
+
+/// DMARC record applying to a domain, as returned by `lookup_dmarc`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DMARCLookup {
+    /// Domain looked up
+    pub domain: DomainName,
+    /// Domain whose `_dmarc` name holds the record: the domain itself or
+    /// its organizational domain
+    pub policy_domain: DomainName,
+    pub record: DMARCRecord,
+}
+
+impl DMARCLookup {
+    /// Policy for the domain looked up, the subdomain policy when the
+    /// record is the one of its organizational domain
+    pub fn policy(&self) -> DMARCPolicy {
+        if self.policy_domain == self.domain {
+            self.record.policy
+        } else {
+            self.record.subdomain_policy
+        }
+    }
+}
+
+/// DMARC record of `domain`, else of its organizational domain. The
+/// organizational domain is found by walking up the tree as in DMARCbis
+/// rather than with the Public Suffix List: the names of at most 7 labels
+/// above `domain` are tried, up to the one below the top-level domain. A
+/// domain without record gives a `NotFound` error, several records or an
+/// invalid one an `InvalidData` error.
+pub fn lookup_dmarc<R: MailResolver + ?Sized>(resolver: &R, domain: &str) -> io::Result<DMARCLookup> {
+    let domain: DomainName = domain.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let parents = (2..domain.label_count().min(MAX_WALK_LABELS + 1)).rev().map(|count| domain.suffix(count));
+    for policy_domain in std::iter::once(domain.clone()).chain(parents) {
+        let Some(name) = policy_domain.prepend(b"_dmarc") else {
+            continue;
+        };
+        match versioned_record(resolver, &name, "DMARC1") {
+            Ok(record) => return Ok(DMARCLookup { domain, policy_domain, record: record.parse()? }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, format!("no DMARC record for {}", domain)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_util::dns_mail::invalid_value;
+    use crate::dns_util::dns_mail::memory::MemoryResolver;
+
+    #[test]
+    fn test_dmarc_record() {
+        let record: DMARCRecord = "v=DMARC1; p=reject; sp=quarantine; adkim=s; pct=20; fo=1:d; ri=3600; \
+            rua=mailto:dmarc@example.com!10m, https://reports.example.com; ruf=mailto:failures@example.com".parse().unwrap();
+        assert_eq!(record, DMARCRecord {
+            policy: DMARCPolicy::Reject,
+            subdomain_policy: DMARCPolicy::Quarantine,
+            dkim_alignment: DMARCAlignment::Strict,
+            spf_alignment: DMARCAlignment::Relaxed,
+            percentage: 20,
+            failure_options: vec!["1".to_string(), "d".to_string()],
+            report_formats: vec!["afrf".to_string()],
+            report_interval: 3600,
+            aggregate_report_uris: vec!["mailto:dmarc@example.com!10m".to_string(), "https://reports.example.com".to_string()],
+            failure_report_uris: vec!["mailto:failures@example.com".to_string()],
+        });
+        let record: DMARCRecord = "v=DMARC1; p=quarantine".parse().unwrap();
+        assert_eq!((record.subdomain_policy, record.percentage), (DMARCPolicy::Quarantine, 100));
+
+        // Reporting URIs make up for the policy
+        assert_eq!("v=DMARC1; p=block; rua=mailto:d@example.com".parse::<DMARCRecord>().unwrap().policy, DMARCPolicy::None);
+        assert_eq!("v=DMARC1; rua=mailto:d@example.com".parse::<DMARCRecord>().unwrap().policy, DMARCPolicy::None);
+        assert_eq!("v=DMARC1".parse::<DMARCRecord>(), Err(TagError::MissingTag("p".to_string())));
+        assert_eq!("v=DMARC1; p=block".parse::<DMARCRecord>(), Err(invalid_value("p", "block")));
+        assert_eq!("v=DMARC1; p=none; pct=101".parse::<DMARCRecord>(), Err(invalid_value("pct", "101")));
+        assert_eq!("v=DMARC1; p=none; fo=2".parse::<DMARCRecord>(), Err(invalid_value("fo", "2")));
+        assert_eq!("v=DMARC1; p=none; rua=dmarc@example.com".parse::<DMARCRecord>(), Err(invalid_value("rua", "dmarc@example.com")));
+        assert_eq!("p=none; v=DMARC1".parse::<DMARCRecord>(), Err(TagError::Syntax("v is not the first tag".to_string())));
+    }
+
+    #[test]
+    fn test_lookup_dmarc() {
+        let resolver = MemoryResolver::default()
+            .txt("_dmarc.example.com", "v=DMARC1; p=reject; sp=quarantine")
+            .txt("_dmarc.example.com", "some other record")
+            .txt("_dmarc.own.example.com", "v=DMARC1; p=none")
+            .txt("_dmarc.a.b.c.d.e.f.g.example.com", "v=DMARC1; p=none")
+            .txt("_dmarc.broken.example.net", "v=DMARC1; p=")
+            .failing("_dmarc.example.org");
+
+        let lookup = lookup_dmarc(&resolver, "example.com").unwrap();
+        assert_eq!(lookup.policy_domain, lookup.domain);
+        assert_eq!(lookup.policy(), DMARCPolicy::Reject);
+
+        let lookup = lookup_dmarc(&resolver, "mail.eu.example.com").unwrap();
+        assert_eq!(lookup.policy_domain, "example.com".parse().unwrap());
+        assert_eq!(lookup.policy(), DMARCPolicy::Quarantine);
+        assert_eq!(lookup_dmarc(&resolver, "mail.own.example.com").unwrap().policy_domain, "own.example.com".parse().unwrap());
+
+        // Names above the domain are tried from 7 labels
+        assert_eq!(lookup_dmarc(&resolver, "x.y.a.b.c.d.e.f.g.example.com").unwrap().policy_domain, "example.com".parse().unwrap());
+
+        assert_eq!(lookup_dmarc(&resolver, "mail.broken.example.net").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(lookup_dmarc(&resolver, "example.net").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(lookup_dmarc(&resolver, "mail.example.org").unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,62 @@
+use std::io;
+use std::str::FromStr;
+
+use crate::dns_util::dns_mail::{invalid_value, versioned_record, MailResolver, TagError, TagList};
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+/// MTA-STS record of a domain, published at `_mta-sts.<domain>` (RFC 8461
+/// section 3.1). It only announces the policy, which is served over HTTPS
+/// by `mta-sts.<domain>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MTASTSRecord {
+    /// Identifier of the current policy, changed with each update
+    pub id: String,
+}
+
+/// Parses a record, unknown fields being ignored
+impl FromStr for MTASTSRecord {
+    type Err = TagError;
+
+    fn from_str(record: &str) -> Result<Self, Self::Err> {
+        let tags = TagList::parse(record)?;
+        tags.check_version("STSv1")?;
+        let id = tags.required("id")?;
+        if !(1..=32).contains(&id.len()) || !id.bytes().all(|byte| byte.is_ascii_alphanumeric()) {
+            return Err(invalid_value("id", id));
+        }
+        Ok(Self { id: id.to_string() })
+    }
+}
+
+/// MTA-STS record of `domain`. A domain without record starting with
+/// `v=STSv1` gives a `NotFound` error, several records or an invalid one an
+/// `InvalidData` error, in which case the domain has no usable policy.
+pub fn lookup_mta_sts<R: MailResolver + ?Sized>(resolver: &R, domain: &str) -> io::Result<MTASTSRecord> {
+    let name: DomainName = format!("_mta-sts.{}", domain).parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(versioned_record(resolver, &name, "STSv1")?.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_util::dns_mail::memory::MemoryResolver;
+
+    #[test]
+    fn test_mta_sts_record() {
+        assert_eq!("v=STSv1; id=20160831085700Z;".parse::<MTASTSRecord>().unwrap().id, "20160831085700Z");
+        assert_eq!("v=STSv1; id=1; ext=x".parse::<MTASTSRecord>().unwrap().id, "1");
+        assert_eq!("v=STSv1;".parse::<MTASTSRecord>(), Err(TagError::MissingTag("id".to_string())));
+        assert_eq!("v=STSv1; id=2016-08-31".parse::<MTASTSRecord>(), Err(invalid_value("id", "2016-08-31")));
+        assert_eq!("id=1".parse::<MTASTSRecord>(), Err(TagError::MissingTag("v".to_string())));
+
+        let resolver = MemoryResolver::default()
+            .txt("_mta-sts.example.com", "v=STSv1; id=20190429T010101;")
+            .txt("_mta-sts.example.com", "v=spf1 -all")
+            .txt("_mta-sts.example.net", "v=STSv1; id=a")
+            .txt("_mta-sts.example.net", "v=STSv1; id=b");
+        assert_eq!(lookup_mta_sts(&resolver, "example.com").unwrap().id, "20190429T010101");
+        assert_eq!(lookup_mta_sts(&resolver, "example.net").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(lookup_mta_sts(&resolver, "example.org").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
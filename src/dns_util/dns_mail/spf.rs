@@ -0,0 +1,731 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dns_util::dns_mail::MailResolver;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+/// Most terms causing DNS lookups in one evaluation (RFC 7208 section 4.6.4)
+const MAX_LOOKUPS: usize = 10;
+/// Most lookups answered with nothing in one evaluation
+const MAX_VOID_LOOKUPS: usize = 2;
+/// Most MX records of an `mx` mechanism, and PTR names of a `ptr` mechanism
+const MAX_NAMES: usize = 10;
+/// Longest domain name after macro expansion (RFC 7208 section 7.3)
+const MAX_DOMAIN_LENGTH: usize = 253;
+
+/// Result of an SPF evaluation (RFC 7208 section 2.6)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SPFResult {
+    /// The domain has no SPF record, or is not a valid domain
+    None,
+    /// The domain makes no assertion about the client
+    Neutral,
+    Pass,
+    /// The client is not authorized, with the explanation given by the
+    /// domain
+    Fail(Option<String>),
+    /// The client is probably not authorized
+    SoftFail,
+    TempError(String),
+    PermError(String),
+}
+
+/// Name of the result, as used in `Received-SPF` header fields
+impl fmt::Display for SPFResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SPFResult::None => "none",
+            SPFResult::Neutral => "neutral",
+            SPFResult::Pass => "pass",
+            SPFResult::Fail(_) => "fail",
+            SPFResult::SoftFail => "softfail",
+            SPFResult::TempError(_) => "temperror",
+            SPFResult::PermError(_) => "permerror",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Whether the client at `ip` may send mail for `domain` according to its
+/// SPF record, `sender` being the `MAIL FROM` address (RFC 7208 section
+/// 4). A sender without local part is taken as `postmaster`. The `%{h}`
+/// macro expands to the domain of the sender.
+pub fn check_host<R: MailResolver + ?Sized>(resolver: &R, ip: IpAddr, domain: &str, sender: &str) -> SPFResult {
+    let Ok(domain) = domain.parse::<DomainName>() else {
+        return SPFResult::None;
+    };
+    Evaluation::new(resolver, ip, sender).check_host(&domain)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Qualifier {
+    Pass,
+    Fail,
+    SoftFail,
+    Neutral,
+}
+
+/// Mechanism of a directive, domain specifications being kept unexpanded
+#[derive(Debug)]
+enum Mechanism {
+    All,
+    Include(String),
+    /// Domain, IPv4 prefix length, IPv6 prefix length
+    A(Option<String>, u8, u8),
+    MX(Option<String>, u8, u8),
+    Ptr(Option<String>),
+    Ip4(Ipv4Addr, u8),
+    Ip6(Ipv6Addr, u8),
+    Exists(String),
+}
+
+/// Terms of an SPF record (RFC 7208 section 4.6.1), unknown modifiers
+/// being left out
+#[derive(Debug)]
+struct SPFRecord {
+    directives: Vec<(Qualifier, Mechanism)>,
+    redirect: Option<String>,
+    explanation: Option<String>,
+}
+
+impl SPFRecord {
+    /// Parses the whole record, any syntax error making it unusable
+    fn parse(record: &str) -> Result<Self, String> {
+        let mut parsed = Self { directives: Vec::new(), redirect: None, explanation: None };
+        // Past the version
+        for term in record.split(' ').filter(|term| !term.is_empty()).skip(1) {
+            if let Some((name, value)) = term.split_once('=').filter(|(name, _)| is_modifier_name(name)) {
+                parse_macro_string(value, false)?;
+                let modifier = match name.to_ascii_lowercase().as_str() {
+                    "redirect" => &mut parsed.redirect,
+                    "exp" => &mut parsed.explanation,
+                    _ => continue,
+                };
+                if value.is_empty() || modifier.replace(value.to_string()).is_some() {
+                    return Err(format!("invalid modifier {}", term));
+                }
+                continue;
+            }
+            let (qualifier, mechanism) = match term.as_bytes()[0] {
+                b'+' => (Qualifier::Pass, &term[1..]),
+                b'-' => (Qualifier::Fail, &term[1..]),
+                b'~' => (Qualifier::SoftFail, &term[1..]),
+                b'?' => (Qualifier::Neutral, &term[1..]),
+                _ => (Qualifier::Pass, term),
+            };
+            parsed.directives.push((qualifier, parse_mechanism(mechanism).ok_or_else(|| format!("invalid term {}", term))?));
+        }
+        Ok(parsed)
+    }
+}
+
+/// Modifier names start with a letter, followed by letters, digits, `-`,
+/// `_` and `.`
+fn is_modifier_name(name: &str) -> bool {
+    name.as_bytes().first().is_some_and(u8::is_ascii_alphabetic)
+        && name.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"-_.".contains(&byte))
+}
+
+fn parse_mechanism(term: &str) -> Option<Mechanism> {
+    let (name, argument) = term.split_at(term.find([':', '/']).unwrap_or(term.len()));
+    // Empty, or a domain specification after `:`
+    let domain = |argument: &str| -> Option<Option<String>> {
+        if argument.is_empty() {
+            return Some(None);
+        }
+        let spec = argument.strip_prefix(':').filter(|spec| !spec.is_empty())?;
+        parse_macro_string(spec, false).ok()?;
+        Some(Some(spec.to_string()))
+    };
+    let mechanism = match name.to_ascii_lowercase().as_str() {
+        "all" if argument.is_empty() => Mechanism::All,
+        "include" => Mechanism::Include(domain(argument)??),
+        "exists" => Mechanism::Exists(domain(argument)??),
+        "a" => {
+            let (argument, ip4, ip6) = split_dual_cidr(argument)?;
+            Mechanism::A(domain(argument)?, ip4, ip6)
+        }
+        "mx" => {
+            let (argument, ip4, ip6) = split_dual_cidr(argument)?;
+            Mechanism::MX(domain(argument)?, ip4, ip6)
+        }
+        "ptr" => Mechanism::Ptr(domain(argument)?),
+        "ip4" => {
+            let (address, length) = split_network(argument, 32)?;
+            Mechanism::Ip4(address.parse().ok()?, length)
+        }
+        "ip6" => {
+            let (address, length) = split_network(argument, 128)?;
+            Mechanism::Ip6(address.parse().ok()?, length)
+        }
+        _ => return None,
+    };
+    Some(mechanism)
+}
+
+fn prefix_length(digits: &str, max: u8) -> Option<u8> {
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok().filter(|length| *length <= max)
+}
+
+/// Splits the `/24//64` prefix lengths off the argument of `a` and `mx`,
+/// the full lengths being the default
+fn split_dual_cidr(argument: &str) -> Option<(&str, u8, u8)> {
+    let (argument, ip6) = match argument.rsplit_once("//") {
+        Some((rest, digits)) if digits.bytes().all(|byte| byte.is_ascii_digit()) => (rest, prefix_length(digits, 128)?),
+        _ => (argument, 128),
+    };
+    let (argument, ip4) = match argument.rsplit_once('/') {
+        Some((rest, digits)) if digits.bytes().all(|byte| byte.is_ascii_digit()) => (rest, prefix_length(digits, 32)?),
+        _ => (argument, 32),
+    };
+    Some((argument, ip4, ip6))
+}
+
+/// Address and prefix length of `ip4:` and `ip6:`
+fn split_network(argument: &str, max: u8) -> Option<(&str, u8)> {
+    let network = argument.strip_prefix(':')?;
+    match network.split_once('/') {
+        Some((address, digits)) => Some((address, prefix_length(digits, max)?)),
+        None => Some((network, max)),
+    }
+}
+
+enum MacroPart<'a> {
+    Literal(&'a str),
+    Macro {
+        letter: char,
+        /// Number of rightmost parts kept
+        digits: Option<usize>,
+        reverse: bool,
+        /// Characters splitting the value, `.` when empty
+        delimiters: &'a str,
+    },
+}
+
+/// Splits a macro string (RFC 7208 section 7.1). The `c`, `r` and `t`
+/// macros are only allowed in explanations.
+fn parse_macro_string(text: &str, explanation: bool) -> Result<Vec<MacroPart<'_>>, String> {
+    let invalid = || format!("invalid macro in {}", text);
+    let mut parts: Vec<MacroPart> = Vec::new();
+    let mut rest = text;
+    while let Some(position) = rest.find('%') {
+        parts.push(MacroPart::Literal(&rest[..position]));
+        let escaped = &rest[position + 1..];
+        let (part, length) = match escaped.as_bytes().first() {
+            Some(b'%') => (MacroPart::Literal("%"), 1),
+            Some(b'_') => (MacroPart::Literal(" "), 1),
+            Some(b'-') => (MacroPart::Literal("%20"), 1),
+            Some(b'{') => {
+                let end = escaped.find('}').ok_or_else(invalid)?;
+                let part = parse_macro(&escaped[1..end]).ok_or_else(invalid)?;
+                if matches!(part, MacroPart::Macro { letter: 'c' | 'r' | 't' | 'C' | 'R' | 'T', .. }) && !explanation {
+                    return Err(invalid());
+                }
+                (part, end + 1)
+            }
+            _ => return Err(invalid()),
+        };
+        parts.push(part);
+        rest = &escaped[length..];
+    }
+    parts.push(MacroPart::Literal(rest));
+    Ok(parts)
+}
+
+/// Parses the inside of `%{...}`: a letter, then the transformers and
+/// delimiters
+fn parse_macro(text: &str) -> Option<MacroPart<'_>> {
+    let letter = text.chars().next().filter(|letter| "slodiphcrtv".contains(letter.to_ascii_lowercase()))?;
+    let rest = &text[1..];
+    let digit_count = rest.bytes().take_while(u8::is_ascii_digit).count();
+    let digits = match digit_count {
+        0 => None,
+        _ => Some(rest[..digit_count].parse::<usize>().ok().filter(|digits| *digits > 0)?),
+    };
+    let rest = &rest[digit_count..];
+    let reverse = rest.starts_with(['r', 'R']);
+    let delimiters = &rest[reverse as usize..];
+    if !delimiters.chars().all(|delimiter| ".-+,/_=".contains(delimiter)) {
+        return None;
+    }
+    Some(MacroPart::Macro { letter, digits, reverse, delimiters })
+}
+
+/// Percent-encoding of the characters other than the unreserved ones of
+/// RFC 3986, for the macros in upper case
+fn url_encode(value: &str) -> String {
+    value.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// True if `ip` and `address` share their first `ip4` or `ip6` bits,
+/// depending on their family
+fn in_network(ip: IpAddr, address: IpAddr, ip4: u8, ip6: u8) -> bool {
+    let (difference, bits, length) = match (ip, address) {
+        (IpAddr::V4(ip), IpAddr::V4(address)) => ((u32::from(ip) ^ u32::from(address)) as u128, 32, ip4),
+        (IpAddr::V6(ip), IpAddr::V6(address)) => (u128::from(ip) ^ u128::from(address), 128, ip6),
+        _ => return false,
+    };
+    difference.checked_shr(bits - length as u32).unwrap_or(0) == 0
+}
+
+/// State of one `check_host` call, shared by the included and redirected
+/// records for the lookup limits
+struct Evaluation<'a, R: MailResolver + ?Sized> {
+    resolver: &'a R,
+    ip: IpAddr,
+    sender: String,
+    local_part: String,
+    sender_domain: String,
+    lookups: usize,
+    void_lookups: usize,
+}
+
+impl<'a, R: MailResolver + ?Sized> Evaluation<'a, R> {
+    fn new(resolver: &'a R, ip: IpAddr, sender: &str) -> Self {
+        let (local_part, sender_domain) = match sender.rsplit_once('@') {
+            Some((local_part, domain)) if !local_part.is_empty() => (local_part, domain),
+            Some((_, domain)) => ("postmaster", domain),
+            None => ("postmaster", sender),
+        };
+        Self {
+            resolver,
+            ip: ip.to_canonical(),
+            sender: format!("{}@{}", local_part, sender_domain),
+            local_part: local_part.to_string(),
+            sender_domain: sender_domain.to_string(),
+            lookups: 0,
+            void_lookups: 0,
+        }
+    }
+
+    fn check_host(&mut self, domain: &DomainName) -> SPFResult {
+        // Single labels are not valid mail domains (RFC 7208 section 4.3)
+        if domain.label_count() < 2 {
+            return SPFResult::None;
+        }
+        let record = match self.spf_record(domain) {
+            Ok(Some(record)) => record,
+            Ok(None) => return SPFResult::None,
+            Err(result) => return result,
+        };
+        let record = match SPFRecord::parse(&record) {
+            Ok(record) => record,
+            Err(e) => return SPFResult::PermError(format!("invalid SPF record for {}: {}", domain, e)),
+        };
+        match self.evaluate(&record, domain) {
+            Ok(result) | Err(result) => result,
+        }
+    }
+
+    /// The only TXT record of `domain` starting with `v=spf1`
+    /// (RFC 7208 section 4.5)
+    fn spf_record(&self, domain: &DomainName) -> Result<Option<String>, SPFResult> {
+        let records = match self.resolver.txt_records(domain) {
+            Ok(records) => records,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(SPFResult::TempError(format!("TXT lookup of {} failed: {}", domain, e))),
+        };
+        let mut records = records.into_iter().filter(|record| {
+            record.get(..6).is_some_and(|version| version.eq_ignore_ascii_case("v=spf1"))
+                && record[6..].chars().next().is_none_or(|next| next == ' ')
+        });
+        match (records.next(), records.next()) {
+            (Some(_), Some(_)) => Err(SPFResult::PermError(format!("several SPF records for {}", domain))),
+            (record, _) => Ok(record),
+        }
+    }
+
+    /// Result of the first matching directive, else of the redirect
+    /// (RFC 7208 section 4.6.2). Errors stop the evaluation.
+    fn evaluate(&mut self, record: &SPFRecord, domain: &DomainName) -> Result<SPFResult, SPFResult> {
+        for (qualifier, mechanism) in &record.directives {
+            if self.matches(mechanism, domain)? {
+                return Ok(match qualifier {
+                    Qualifier::Pass => SPFResult::Pass,
+                    Qualifier::Fail => SPFResult::Fail(self.explanation(record.explanation.as_deref(), domain)),
+                    Qualifier::SoftFail => SPFResult::SoftFail,
+                    Qualifier::Neutral => SPFResult::Neutral,
+                });
+            }
+        }
+        let Some(redirect) = &record.redirect else {
+            return Ok(SPFResult::Neutral);
+        };
+        self.count_lookup()?;
+        let target = self.expand_domain(redirect, domain)?;
+        match self.check_host(&target) {
+            SPFResult::None => Err(SPFResult::PermError(format!("no SPF record for redirect target {}", target))),
+            result => Ok(result),
+        }
+    }
+
+    fn matches(&mut self, mechanism: &Mechanism, domain: &DomainName) -> Result<bool, SPFResult> {
+        match mechanism {
+            Mechanism::All => Ok(true),
+            Mechanism::Include(spec) => {
+                self.count_lookup()?;
+                let target = self.expand_domain(spec, domain)?;
+                match self.check_host(&target) {
+                    SPFResult::Pass => Ok(true),
+                    SPFResult::Fail(_) | SPFResult::SoftFail | SPFResult::Neutral => Ok(false),
+                    SPFResult::None => Err(SPFResult::PermError(format!("no SPF record for included domain {}", target))),
+                    error => Err(error),
+                }
+            }
+            Mechanism::A(spec, ip4, ip6) => {
+                self.count_lookup()?;
+                let target = self.target(spec.as_deref(), domain)?;
+                let lookup = self.addresses(&target);
+                let addresses = self.checked(&target, lookup)?;
+                Ok(addresses.into_iter().any(|address| in_network(self.ip, address, *ip4, *ip6)))
+            }
+            Mechanism::MX(spec, ip4, ip6) => {
+                self.count_lookup()?;
+                let target = self.target(spec.as_deref(), domain)?;
+                let lookup = self.resolver.mx_records(&target);
+                let records = self.checked(&target, lookup)?;
+                if records.len() > MAX_NAMES {
+                    return Err(SPFResult::PermError(format!("more than {} MX records for {}", MAX_NAMES, target)));
+                }
+                for record in records.iter().filter(|record| !record.exchange.is_root()) {
+                    let addresses = match self.addresses(&record.exchange) {
+                        Ok(addresses) => addresses,
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                        Err(e) => return Err(SPFResult::TempError(format!("lookup of {} failed: {}", record.exchange, e))),
+                    };
+                    if addresses.into_iter().any(|address| in_network(self.ip, address, *ip4, *ip6)) {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Mechanism::Ptr(spec) => {
+                self.count_lookup()?;
+                let target = self.target(spec.as_deref(), domain)?;
+                Ok(self.validated_names().iter().any(|name| name.is_subdomain_of(&target)))
+            }
+            Mechanism::Ip4(address, length) => Ok(in_network(self.ip, IpAddr::V4(*address), *length, 0)),
+            Mechanism::Ip6(address, length) => Ok(in_network(self.ip, IpAddr::V6(*address), 0, *length)),
+            Mechanism::Exists(spec) => {
+                self.count_lookup()?;
+                let target = self.expand_domain(spec, domain)?;
+                // Always A records, whatever the family of the client
+                let lookup = self.resolver.ipv4_addresses(&target);
+                Ok(!self.checked(&target, lookup)?.is_empty())
+            }
+        }
+    }
+
+    fn count_lookup(&mut self) -> Result<(), SPFResult> {
+        self.lookups += 1;
+        if self.lookups > MAX_LOOKUPS {
+            return Err(SPFResult::PermError(format!("more than {} DNS lookups", MAX_LOOKUPS)));
+        }
+        Ok(())
+    }
+
+    /// Values of a lookup, counting it as void when the name does not exist
+    /// or has no records
+    fn checked<T>(&mut self, name: &DomainName, lookup: io::Result<Vec<T>>) -> Result<Vec<T>, SPFResult> {
+        match lookup {
+            Ok(values) if !values.is_empty() => Ok(values),
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(SPFResult::TempError(format!("lookup of {} failed: {}", name, e)))
+            }
+            _ => {
+                self.void_lookups += 1;
+                if self.void_lookups > MAX_VOID_LOOKUPS {
+                    return Err(SPFResult::PermError(format!("more than {} void lookups", MAX_VOID_LOOKUPS)));
+                }
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Addresses of `name` in the family of the client
+    fn addresses(&self, name: &DomainName) -> io::Result<Vec<IpAddr>> {
+        match self.ip {
+            IpAddr::V4(_) => Ok(self.resolver.ipv4_addresses(name)?.into_iter().map(IpAddr::V4).collect()),
+            IpAddr::V6(_) => Ok(self.resolver.ipv6_addresses(name)?.into_iter().map(IpAddr::V6).collect()),
+        }
+    }
+
+    /// PTR names of the client whose addresses include it (RFC 7208
+    /// section 5.5), failed lookups being ignored
+    fn validated_names(&self) -> Vec<DomainName> {
+        self.resolver.ptr_names(self.ip).unwrap_or_default().into_iter()
+            .take(MAX_NAMES)
+            .filter(|name| self.addresses(name).is_ok_and(|addresses| addresses.contains(&self.ip)))
+            .collect()
+    }
+
+    fn target(&self, spec: Option<&str>, domain: &DomainName) -> Result<DomainName, SPFResult> {
+        match spec {
+            Some(spec) => self.expand_domain(spec, domain),
+            None => Ok(domain.clone()),
+        }
+    }
+
+    /// Domain name of a domain specification, shortened from the left when
+    /// too long
+    fn expand_domain(&self, spec: &str, domain: &DomainName) -> Result<DomainName, SPFResult> {
+        let mut expanded = self.expand(spec, domain, false).map_err(SPFResult::PermError)?;
+        while expanded.len() > MAX_DOMAIN_LENGTH {
+            match expanded.split_once('.') {
+                Some((_, rest)) => expanded = rest.to_string(),
+                None => break,
+            }
+        }
+        expanded.parse().map_err(SPFResult::PermError)
+    }
+
+    /// Explanation of a `fail`, from the TXT record named by the `exp`
+    /// modifier (RFC 7208 section 6.2). Any error leaves it out.
+    fn explanation(&self, spec: Option<&str>, domain: &DomainName) -> Option<String> {
+        let name = self.expand_domain(spec?, domain).ok()?;
+        let records = self.resolver.txt_records(&name).ok()?;
+        let [record] = records.as_slice() else {
+            return None;
+        };
+        self.expand(record, domain, true).ok()
+    }
+
+    /// Macro expansion of `text` (RFC 7208 section 7)
+    fn expand(&self, text: &str, domain: &DomainName, explanation: bool) -> Result<String, String> {
+        let mut expanded = String::new();
+        for part in parse_macro_string(text, explanation)? {
+            let (letter, digits, reverse, delimiters) = match part {
+                MacroPart::Literal(literal) => {
+                    expanded.push_str(literal);
+                    continue;
+                }
+                MacroPart::Macro { letter, digits, reverse, delimiters } => (letter, digits, reverse, delimiters),
+            };
+            let value = self.macro_value(letter.to_ascii_lowercase(), domain);
+            let mut parts: Vec<&str> = value
+                .split(|c: char| if delimiters.is_empty() { c == '.' } else { delimiters.contains(c) })
+                .collect();
+            if reverse {
+                parts.reverse();
+            }
+            if let Some(digits) = digits {
+                parts.drain(..parts.len().saturating_sub(digits));
+            }
+            let value = parts.join(".");
+            if letter.is_ascii_uppercase() {
+                expanded.push_str(&url_encode(&value));
+            } else {
+                expanded.push_str(&value);
+            }
+        }
+        Ok(expanded)
+    }
+
+    fn macro_value(&self, letter: char, domain: &DomainName) -> String {
+        match letter {
+            's' => self.sender.clone(),
+            'l' => self.local_part.clone(),
+            'o' | 'h' => self.sender_domain.clone(),
+            'd' => domain.to_string(),
+            'i' => match self.ip {
+                IpAddr::V4(ip) => ip.to_string(),
+                // Dotted nibbles
+                IpAddr::V6(ip) => format!("{:032x}", u128::from(ip)).chars()
+                    .map(String::from)
+                    .collect::<Vec<String>>()
+                    .join("."),
+            },
+            'p' => {
+                let names = self.validated_names();
+                names.iter().find(|name| name.is_subdomain_of(domain))
+                    .or(names.first())
+                    .map_or_else(|| "unknown".to_string(), |name| name.to_string())
+            }
+            'v' => match self.ip {
+                IpAddr::V4(_) => "in-addr".to_string(),
+                IpAddr::V6(_) => "ip6".to_string(),
+            },
+            'c' => self.ip.to_string(),
+            'r' => "unknown".to_string(),
+            't' => SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs().to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_util::dns_mail::memory::MemoryResolver;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn test_macro_expansion() {
+        let resolver = MemoryResolver::default();
+        let domain: DomainName = "email.example.com".parse().unwrap();
+        let evaluation = Evaluation::new(&resolver, ip("192.0.2.3"), "strong-bad@email.example.com");
+        // Examples of RFC 7208 section 7.4
+        for (text, expanded) in [
+            ("%{s}", "strong-bad@email.example.com"),
+            ("%{o}", "email.example.com"),
+            ("%{d}", "email.example.com"),
+            ("%{d4}", "email.example.com"),
+            ("%{d3}", "email.example.com"),
+            ("%{d2}", "example.com"),
+            ("%{d1}", "com"),
+            ("%{dr}", "com.example.email"),
+            ("%{d2r}", "example.email"),
+            ("%{l}", "strong-bad"),
+            ("%{l-}", "strong.bad"),
+            ("%{lr}", "strong-bad"),
+            ("%{lr-}", "bad.strong"),
+            ("%{l1r-}", "strong"),
+            ("%{ir}.%{v}._spf.%{d2}", "3.2.0.192.in-addr._spf.example.com"),
+            ("%{lr-}.lp._spf.%{d2}", "bad.strong.lp._spf.example.com"),
+            ("%{lr-}.lp.%{ir}.%{v}._spf.%{d2}", "bad.strong.lp.3.2.0.192.in-addr._spf.example.com"),
+            ("%{ir}.%{v}.%{l1r-}.lp._spf.%{d2}", "3.2.0.192.in-addr.strong.lp._spf.example.com"),
+            ("%{d2}.trusted-domains.example.net", "example.com.trusted-domains.example.net"),
+            ("%%%_%-", "% %20"),
+            ("%{S}", "strong-bad%40email.example.com"),
+            ("%{p}", "unknown"),
+        ] {
+            assert_eq!(evaluation.expand(text, &domain, false).unwrap(), expanded, "{}", text);
+        }
+        let evaluation = Evaluation::new(&resolver, ip("2001:db8::cb01"), "strong-bad@email.example.com");
+        assert_eq!(
+            evaluation.expand("%{ir}.%{v}._spf.%{d2}", &domain, false).unwrap(),
+            "1.0.b.c.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6._spf.example.com"
+        );
+        assert_eq!(evaluation.expand("%{c}", &domain, true).unwrap(), "2001:db8::cb01");
+        // Explanation only macros, and invalid ones
+        for text in ["%{c}", "%{d0}", "%{x}", "%{d", "100%"] {
+            assert!(evaluation.expand(text, &domain, false).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_record() {
+        let record = SPFRecord::parse("v=spf1 a/24//64 -mx:%{d}/30 ~ip6:2001:db8::/32 ?all exp=explain.%{d} unknown=x").unwrap();
+        assert!(matches!(record.directives[0], (Qualifier::Pass, Mechanism::A(None, 24, 64))));
+        assert!(matches!(&record.directives[1], (Qualifier::Fail, Mechanism::MX(Some(spec), 30, 128)) if spec == "%{d}"));
+        assert!(matches!(record.directives[2], (Qualifier::SoftFail, Mechanism::Ip6(_, 32))));
+        assert!(matches!(record.directives[3], (Qualifier::Neutral, Mechanism::All)));
+        assert_eq!(record.explanation.as_deref(), Some("explain.%{d}"));
+        assert_eq!(record.redirect, None);
+
+        for record in [
+            "v=spf1 foo",
+            "v=spf1 ip4:192.0.2.0/33",
+            "v=spf1 ip4:192.0.2",
+            "v=spf1 include",
+            "v=spf1 all:example.com",
+            "v=spf1 a:%{c}.example.com",
+            "v=spf1 redirect=a.example redirect=b.example",
+        ] {
+            assert!(SPFRecord::parse(record).is_err(), "{}", record);
+        }
+    }
+
+    #[test]
+    fn test_mechanisms() {
+        let resolver = MemoryResolver::default()
+            .txt("example.com", "v=spf1 ip4:192.0.2.0/24 ip6:2001:db8::/32 a:mail.example.com/28 mx include:partner.test ptr exists:%{ir}.allow.example.com ~all")
+            .txt("example.com", "google-site-verification=abc")
+            .address("mail.example.com", "198.51.100.16")
+            .mx("example.com", 10, "mx.example.com")
+            .address("mx.example.com", "203.0.113.5")
+            .txt("partner.test", "v=spf1 ip4:198.18.0.1 -all")
+            .ptr("203.0.113.77", "host.example.com")
+            .address("host.example.com", "203.0.113.77")
+            .ptr("203.0.113.78", "unconfirmed.example.com")
+            .address("1.0.0.10.allow.example.com", "127.0.0.2");
+        let check = |address: &str| check_host(&resolver, ip(address), "example.com", "user@example.com");
+        for address in [
+            "192.0.2.10",
+            "::ffff:192.0.2.11",
+            "2001:db8::1",
+            "198.51.100.31",
+            "203.0.113.5",
+            "198.18.0.1",
+            "203.0.113.77",
+            "10.0.0.1",
+        ] {
+            assert_eq!(check(address), SPFResult::Pass, "{}", address);
+        }
+        for address in ["198.51.100.32", "203.0.113.78", "2001:db9::1"] {
+            assert_eq!(check(address), SPFResult::SoftFail, "{}", address);
+        }
+    }
+
+    #[test]
+    fn test_results() {
+        let resolver = MemoryResolver::default()
+            .txt("fail.test", "v=spf1 ip4:192.0.2.1 -all exp=explain.%{d}")
+            .txt("explain.fail.test", "%{i} is not one of %{d}'s designated mail servers.")
+            .txt("neutral.test", "v=spf1 ?all")
+            .txt("empty.test", "V=SPF1")
+            .txt("other.test", "v=spf10 -all")
+            .txt("redirect.test", "v=spf1 redirect=fail.test")
+            .txt("redirect-none.test", "v=spf1 redirect=other.test")
+            .txt("include-none.test", "v=spf1 include:other.test -all")
+            .txt("two.test", "v=spf1 -all")
+            .txt("two.test", "v=spf1 +all")
+            .txt("invalid.test", "v=spf1 ip4:192.0.2.1 foo:bar -all")
+            .txt("temporary.test", "v=spf1 a:broken.test -all")
+            .failing("broken.test")
+            .failing("unreachable.test");
+        let check = |domain: &str| check_host(&resolver, ip("192.0.2.99"), domain, "user@example.com");
+        assert_eq!(check("fail.test"), SPFResult::Fail(Some("192.0.2.99 is not one of fail.test's designated mail servers.".to_string())));
+        assert_eq!(check_host(&resolver, ip("192.0.2.1"), "fail.test", "example.com"), SPFResult::Pass);
+        assert_eq!(check("neutral.test"), SPFResult::Neutral);
+        assert_eq!(check("empty.test"), SPFResult::Neutral);
+        assert_eq!(check("redirect.test"), check("fail.test"));
+        for domain in ["other.test", "missing.test", "localhost", "a..test"] {
+            assert_eq!(check(domain), SPFResult::None, "{}", domain);
+        }
+        for domain in ["redirect-none.test", "include-none.test", "two.test", "invalid.test"] {
+            assert!(matches!(check(domain), SPFResult::PermError(_)), "{}", domain);
+        }
+        for domain in ["temporary.test", "unreachable.test"] {
+            assert!(matches!(check(domain), SPFResult::TempError(_)), "{}", domain);
+        }
+        assert_eq!(check("fail.test").to_string(), "fail");
+    }
+
+    #[test]
+    fn test_lookup_limits() {
+        let mut resolver = MemoryResolver::default()
+            .txt("ten.test", &format!("v=spf1 {} -all", (1..=10).map(|n| format!("a:a{}.test", n)).collect::<Vec<_>>().join(" ")))
+            .txt("eleven.test", &format!("v=spf1 {} -all", (1..=11).map(|n| format!("a:a{}.test", n)).collect::<Vec<_>>().join(" ")))
+            // The limit covers the included records
+            .txt("nested.test", "v=spf1 include:ten.test include:eleven.test -all")
+            .txt("two-void.test", "v=spf1 a:void1.test mx:void2.test -all")
+            .txt("three-void.test", "v=spf1 a:void1.test mx:void2.test exists:void3.test -all")
+            .txt("many-mx.test", "v=spf1 mx -all");
+        for n in 1..=11 {
+            resolver = resolver.address(&format!("a{}.test", n), "198.51.100.1");
+        }
+        for n in 1..=11 {
+            resolver = resolver.mx("many-mx.test", n, &format!("a{}.test", n));
+        }
+        let check = |domain: &str| check_host(&resolver, ip("192.0.2.1"), domain, "user@example.com");
+        assert_eq!(check("ten.test"), SPFResult::Fail(None));
+        assert_eq!(check("two-void.test"), SPFResult::Fail(None));
+        for domain in ["eleven.test", "nested.test", "three-void.test", "many-mx.test"] {
+            assert!(matches!(check(domain), SPFResult::PermError(_)), "{}", domain);
+        }
+    }
+}
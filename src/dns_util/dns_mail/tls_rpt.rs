@@ -0,0 +1,66 @@
+use std::io;
+use std::str::FromStr;
+
+use crate::dns_util::dns_mail::{invalid_value, versioned_record, MailResolver, TagError, TagList};
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+/// SMTP TLS reporting record of a domain, published at `_smtp._tls.<domain>`
+/// (RFC 8460 section 3)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TLSRPTRecord {
+    /// Where to send the reports, `mailto:` or `https:` URIs
+    pub report_uris: Vec<String>,
+}
+
+/// Parses a record, unknown fields being ignored
+impl FromStr for TLSRPTRecord {
+    type Err = TagError;
+
+    fn from_str(record: &str) -> Result<Self, Self::Err> {
+        let tags = TagList::parse(record)?;
+        tags.check_version("TLSRPTv1")?;
+        let rua = tags.required("rua")?;
+        let report_uris: Vec<String> = rua.split(',').map(|uri| uri.trim().to_string()).collect();
+        let valid = |uri: &String| {
+            uri.split_once(':').is_some_and(|(scheme, rest)| {
+                (scheme.eq_ignore_ascii_case("mailto") || scheme.eq_ignore_ascii_case("https")) && !rest.is_empty()
+            })
+        };
+        if !report_uris.iter().all(valid) {
+            return Err(invalid_value("rua", rua));
+        }
+        Ok(Self { report_uris })
+    }
+}
+
+/// TLS reporting record of `domain`. A domain without record starting with
+/// `v=TLSRPTv1` gives a `NotFound` error, several records or an invalid one
+/// an `InvalidData` error.
+pub fn lookup_tls_rpt<R: MailResolver + ?Sized>(resolver: &R, domain: &str) -> io::Result<TLSRPTRecord> {
+    let name: DomainName = format!("_smtp._tls.{}", domain).parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(versioned_record(resolver, &name, "TLSRPTv1")?.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_util::dns_mail::memory::MemoryResolver;
+
+    #[test]
+    fn test_tls_rpt_record() {
+        let record: TLSRPTRecord = "v=TLSRPTv1; rua=mailto:reports@example.com, https://reporting.example.com/v1/".parse().unwrap();
+        assert_eq!(record.report_uris, vec!["mailto:reports@example.com", "https://reporting.example.com/v1/"]);
+        assert_eq!("v=TLSRPTv1".parse::<TLSRPTRecord>(), Err(TagError::MissingTag("rua".to_string())));
+        assert_eq!("v=TLSRPTv1; rua=ftp://example.com".parse::<TLSRPTRecord>(), Err(invalid_value("rua", "ftp://example.com")));
+        assert_eq!("v=TLSRPTv2; rua=mailto:a@example.com".parse::<TLSRPTRecord>(), Err(invalid_value("v", "TLSRPTv2")));
+
+        let resolver = MemoryResolver::default()
+            .txt("_smtp._tls.example.com", "v=TLSRPTv1; rua=mailto:tlsrpt@example.com")
+            .txt("_smtp._tls.example.net", "v=TLSRPTv1; rua=");
+        assert_eq!(lookup_tls_rpt(&resolver, "example.com").unwrap().report_uris, vec!["mailto:tlsrpt@example.com"]);
+        let error = lookup_tls_rpt(&resolver, "example.net").unwrap_err();
+        assert_eq!(error.get_ref().unwrap().downcast_ref::<TagError>(), Some(&invalid_value("rua", "")));
+        assert_eq!(lookup_tls_rpt(&resolver, "example.org").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -2,4 +2,10 @@ pub mod dns_resource_record;
 pub mod dns_question;
 pub mod dns_header;
 pub mod dns_packet;
-mod util;
\ No newline at end of file
+pub mod domain_name;
+pub mod record_data;
+pub mod srv_record;
+pub mod mx_record;
+pub mod svcb_record;
+pub(crate) mod util;
+pub(crate) mod presentation;
\ No newline at end of file
@@ -1,4 +1,5 @@
 use std::fmt;
+use std::io;
 use rand::Rng;
 pub struct DNSHeader {
     //// Unique ID of the transaction. Query and response should have the same.
@@ -36,22 +37,25 @@ impl DNSHeader {
         Self {
             query_id: rng.gen::<u16>(),
             flags: 0b0000_0001_0000_0000,
-            questions_count: questions_count,
+            questions_count,
             answers_count: 0,
             authority_count: 0,
             additional_count: 0,
         }
     }
 
-    pub fn parse_header_from_response(response: &Vec<u8>) -> Self {
-        Self {
+    pub fn parse_header_from_response(response: &[u8]) -> io::Result<Self> {
+        let Some(response) = response.get(..12) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "response shorter than a header"));
+        };
+        Ok(Self {
             query_id: u16::from_le_bytes([response[1], response[0]]),
             flags: u16::from_le_bytes([response[3], response[2]]),
             questions_count: u16::from_le_bytes([response[5], response[4]]),  
             answers_count: u16::from_le_bytes([response[7], response[6]]),
             authority_count: u16::from_le_bytes([response[9], response[8]]),
             additional_count: u16::from_le_bytes([response[11], response[10]]),
-        }
+        })
     }
 
     pub fn prepare(&self) -> Vec<u8> {
@@ -88,58 +92,19 @@ impl DNSHeader {
 
 impl fmt::Debug for DNSHeader {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match writeln!(f, "query_id: {}", self.query_id){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "flags:"){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "\tquery: {:b}", self.flags >> 15 & 1){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "\toperation code: {}", self.flags >> 11 & 0b0111_1){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "\tauthoritative answer: {}", self.flags >> 10 & 0b0000_01){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "\ttruncation: {}", self.flags >> 9 & 0b0000_001){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "\trecursion desired: {}", self.flags >> 8 & 0b0000_0001){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "\trecursion available: {}", self.flags >> 7 & 0b0000_0000_1){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "\treserved: {}", self.flags >> 4 & 0b0000_0000_0111){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "\tresponse code: {}", self.flags & 0b0000_0000_0000_1111){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "questions count: {}", self.questions_count){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "answer count: {}", self.answers_count){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "authority count: {}", self.authority_count){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
+        writeln!(f, "query_id: {}", self.query_id)?;
+        writeln!(f, "flags:")?;
+        writeln!(f, "\tquery: {:b}", self.flags >> 15 & 1)?;
+        writeln!(f, "\toperation code: {}", self.flags >> 11 & 0b1111)?;
+        writeln!(f, "\tauthoritative answer: {}", self.flags >> 10 & 0b1)?;
+        writeln!(f, "\ttruncation: {}", self.flags >> 9 & 0b1)?;
+        writeln!(f, "\trecursion desired: {}", self.flags >> 8 & 0b1)?;
+        writeln!(f, "\trecursion available: {}", self.flags >> 7 & 0b1)?;
+        writeln!(f, "\treserved: {}", self.flags >> 4 & 0b0111)?;
+        writeln!(f, "\tresponse code: {}", self.flags & 0b0000_0000_0000_1111)?;
+        writeln!(f, "questions count: {}", self.questions_count)?;
+        writeln!(f, "answer count: {}", self.answers_count)?;
+        writeln!(f, "authority count: {}", self.authority_count)?;
         writeln!(f, "additional count: {}", self.additional_count)
     }
 }
\ No newline at end of file
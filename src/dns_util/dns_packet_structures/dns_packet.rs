@@ -1,3 +1,5 @@
+use std::io;
+
 use super::{
     dns_header::DNSHeader,
     dns_question::DNSQuestion,
@@ -13,31 +15,42 @@ impl DNSPacket {
         };
         DNSPacket {
             header: DNSHeader::create_query_header(domains.len() as u16),
-            questions: questions,
+            questions,
             resource_records: Vec::new(),
+            authority_records: Vec::new(),
+            additional_records: Vec::new(),
         }
     }
 
-    pub fn parse_response(response: Vec<u8>) -> Self {
-        let header = DNSHeader::parse_header_from_response(&response);
+    /// Parses a message in wire format. Fails if the message ends before
+    /// the last of the records its header counts, or holds an unreadable
+    /// name.
+    pub fn parse_response(response: Vec<u8>) -> io::Result<Self> {
+        let header = DNSHeader::parse_header_from_response(&response)?;
         let mut current_start: usize = 12; // header length
         let mut questions: Vec<DNSQuestion> = Vec::new();
         for _i in 0..header.questions_count {
-            let (parsed_length, question) = DNSQuestion::parse_question_from_response(&response, current_start);
+            let (parsed_length, question) = DNSQuestion::parse_question_from_response(&response, current_start)?;
             current_start += parsed_length;
             questions.push(question);
         };
-        let mut answers: Vec<DNSResourceRecord> = Vec::new();
-        for _i in 0..header.answers_count {
-            let (parsed_length, answer) = DNSResourceRecord::parse_rr_from_response(&response, current_start);
-            current_start += parsed_length;
-            answers.push(answer);
-        };
-        Self {
-            header: header,
-            questions: questions,
-            resource_records: answers
+        let mut sections: [Vec<DNSResourceRecord>; 3] = [Vec::new(), Vec::new(), Vec::new()];
+        let counts = [header.answers_count, header.authority_count, header.additional_count];
+        for (section, count) in sections.iter_mut().zip(counts) {
+            for _i in 0..count {
+                let (parsed_length, record) = DNSResourceRecord::parse_rr_from_response(&response, current_start)?;
+                current_start += parsed_length;
+                section.push(record);
+            };
         }
+        let [answers, authorities, additionals] = sections;
+        Ok(Self {
+            header,
+            questions,
+            resource_records: answers,
+            authority_records: authorities,
+            additional_records: additionals,
+        })
     }
 
     pub fn prepare(&self) -> Vec<u8> {
@@ -48,8 +61,8 @@ impl DNSPacket {
         for question in &self.questions {
             prepared_packet.append(&mut question.prepare());
         };
-        for answer in &self.resource_records {
-            prepared_packet.append(&mut answer.prepare());
+        for record in self.resource_records.iter().chain(&self.authority_records).chain(&self.additional_records) {
+            prepared_packet.append(&mut record.prepare());
         };
 
         prepared_packet
@@ -60,5 +73,70 @@ impl DNSPacket {
 pub struct DNSPacket {
     pub header: DNSHeader,
     pub questions: Vec<DNSQuestion>,
-    pub resource_records: Vec<DNSResourceRecord>
-}
\ No newline at end of file
+    /// Records of the answer section
+    pub resource_records: Vec<DNSResourceRecord>,
+    /// Records of the authority section, such as the SOA of a negative
+    /// answer or the NS records of a referral
+    pub authority_records: Vec<DNSResourceRecord>,
+    /// Records of the additional section, such as glue addresses or the OPT
+    /// pseudo-record of EDNS
+    pub additional_records: Vec<DNSResourceRecord>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Response for google.com A with an MX record in the authority section,
+    /// whose names are compressed
+    fn response() -> Vec<u8> {
+        [
+            [29, 221, 129, 128, 0, 1, 0, 1, 0, 1, 0, 0].to_vec(),
+            b"\x06google\x03com\x00\x00\x01\x00\x01".to_vec(),
+            [192, 12, 0, 1, 0, 1, 0, 0, 1, 40, 0, 4, 142, 250, 203, 110].to_vec(),
+            [192, 12, 0, 15, 0, 1, 0, 0, 1, 40, 0, 9, 0, 10, 4].to_vec(),
+            b"smtp".to_vec(),
+            [192, 12].to_vec(),
+        ].concat()
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let packet = DNSPacket::parse_response(response()).unwrap();
+        assert_eq!(packet.questions[0].query_name, b"\x06google\x03com\x00".to_vec());
+        assert_eq!(packet.resource_records[0].rdata, [142, 250, 203, 110].to_vec());
+        assert_eq!(packet.resource_records[0].record_ttl, 296);
+        assert_eq!(packet.authority_records[0].rdata, b"\x00\x0a\x04smtp\x06google\x03com\x00".to_vec());
+    }
+
+    #[test]
+    fn test_parse_malformed_response() {
+        let response = response();
+        for length in 0..response.len() {
+            let error = DNSPacket::parse_response(response[..length].to_vec()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+
+        // Query echoed back with an answer count but no answer
+        let mut query = DNSPacket::create_query_packet(vec!["example.com"], "A").prepare();
+        query[7] = 1;
+        assert!(DNSPacket::parse_response(query).is_err());
+
+        // Garbage and damaged responses fail or parse, without panicking
+        let mut rng = StdRng::seed_from_u64(53);
+        for _i in 0..2000 {
+            let length = rng.gen_range(0..64);
+            let garbage: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
+            let _ = DNSPacket::parse_response(garbage);
+
+            let mut damaged = response.clone();
+            for _j in 0..rng.gen_range(1..4) {
+                let position = rng.gen_range(0..damaged.len());
+                damaged[position] = rng.gen();
+            }
+            let _ = DNSPacket::parse_response(damaged);
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use std::fmt;
+use std::io;
 
 use crate::dns_util::dns_packet_structures::util::labels_to_domains;
 
-use super::util::domain_to_labels;
+use super::util::{dns_decompression, domain_to_labels, malformed_message};
 
 pub struct DNSQuestion {
     /// Set of labels preceeded by their length. Ends with \x00.
@@ -24,7 +25,15 @@ pub struct DNSQuestion {
     /// --> 13 = HINFO  - host information
     /// --> 14 = MINFO  - mailbox or mail list information
     /// --> 15 = MX     - mail exchange
-    /// --> 16 = TXT   - text strings
+    /// --> 16 = TXT    - text strings
+    /// --> 28 = AAAA   - an IPv6 host address
+    /// --> 39 = DNAME  - redirection of a whole subtree
+    /// --> 43 = DS     - delegation signer (DNSSEC)
+    /// --> 46 = RRSIG  - signature of a set of records (DNSSEC)
+    /// --> 47 = NSEC   - next secure record, proving non-existence (DNSSEC)
+    /// --> 48 = DNSKEY - public key of a zone (DNSSEC)
+    /// --> 50 = NSEC3  - hashed next secure record (DNSSEC)
+    /// --> 51 = NSEC3PARAM - parameters of the NSEC3 chain (DNSSEC)
     pub query_type: u16,
     /// Class fo query. Set to 0x01 for Internet.
     pub query_class: u16,
@@ -34,18 +43,21 @@ impl DNSQuestion {
     pub fn create_question(domain: &str, query_type: u16) -> Self {
         Self {
             query_name: domain_to_labels(domain),
-            query_type: query_type,
+            query_type,
             query_class: 1,
         }
     }
 
-    pub fn parse_question_from_response(response: &Vec<u8>, question_start: usize) -> (usize, Self) {
-        let query_name_end = question_start + response[question_start..].iter().position(|&r| r == 0).unwrap();
-        (query_name_end - question_start + 5, Self {
-            query_name: response[question_start..query_name_end+1].to_vec(),
-            query_type: u16::from_le_bytes([response[query_name_end + 2], response[query_name_end + 1]]),
-            query_class: u16::from_le_bytes([response[query_name_end + 4], response[query_name_end + 3]]),
-        })
+    /// Reads the question at `question_start`, returning its length. Fails
+    /// if the message ends within the question.
+    pub fn parse_question_from_response(response: &[u8], question_start: usize) -> io::Result<(usize, Self)> {
+        let (query_name_end, query_name) = dns_decompression(response, question_start).ok_or_else(malformed_message)?;
+        let fixed_part = response.get(query_name_end + 1..query_name_end + 5).ok_or_else(malformed_message)?;
+        Ok((query_name_end - question_start + 5, Self {
+            query_name,
+            query_type: u16::from_be_bytes([fixed_part[0], fixed_part[1]]),
+            query_class: u16::from_be_bytes([fixed_part[2], fixed_part[3]]),
+        }))
     }
 
     pub fn prepare(&self) -> Vec<u8> {
@@ -65,14 +77,8 @@ impl DNSQuestion {
 
 impl fmt::Debug for DNSQuestion {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match writeln!(f, "querry_name: {}", labels_to_domains(&self.query_name)){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "querry_type: {:?}", self.query_type){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
+        writeln!(f, "querry_name: {}", labels_to_domains(&self.query_name))?;
+        writeln!(f, "querry_type: {:?}", self.query_type)?;
         writeln!(f, "querry_class: {:?}", self.query_class)
     }
 }
\ No newline at end of file
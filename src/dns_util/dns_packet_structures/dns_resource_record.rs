@@ -1,9 +1,14 @@
 use std::fmt;
+use std::io;
 
-use crate::dns_util::dns_packet_structures::util::dns_decompression;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::presentation::{class_name, rdata_to_string};
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+use crate::dns_util::dns_packet_structures::util::{decompress_rdata, dns_decompression, malformed_message, query_type_name};
 
 use super::util::labels_to_domains;
 
+#[derive(Clone)]
 pub struct DNSResourceRecord {
     /// Name queried, in the same format as in DNSQuestion
     pub query_name: Vec<u8>,
@@ -16,22 +21,63 @@ pub struct DNSResourceRecord {
     /// Length of the rdata field
     pub rdata_length: u16,
     /// Actual data received from the answer. Specific content depends on the 
-    /// query type
+    /// query type. Compressed names are expanded when parsing.
     pub rdata: Vec<u8>,
 }
 
 impl DNSResourceRecord {
-    pub fn parse_rr_from_response (response: &Vec<u8>, answer_start:usize) -> (usize, Self) {
-        let (query_name_end, query_name) = dns_decompression(response, answer_start);
-        let rdata_length = u16::from_le_bytes([response[query_name_end + 10], response[query_name_end + 9]]);
-        (query_name_end - answer_start + 11 + rdata_length as usize, Self {
-            query_name: query_name,
-            query_type: u16::from_le_bytes([response[query_name_end + 2], response[query_name_end + 1]]),
-            query_class: u16::from_le_bytes([response[query_name_end + 4], response[query_name_end + 3]]),
-            record_ttl: i32::from_le_bytes([response[query_name_end + 6], response[query_name_end + 5], response[query_name_end + 8], response[query_name_end + 7]]),
-            rdata_length: rdata_length,
-            rdata: response[query_name_end + 11..query_name_end + 11 + rdata_length as usize].to_vec(),
-        })
+    /// Creates the OPT pseudo-record of EDNS (RFC 6891), advertising the
+    /// largest UDP response accepted. Goes in the additional section.
+    pub fn create_opt_record(udp_payload_size: u16) -> Self {
+        Self {
+            query_name: vec![0],
+            query_type: 41,
+            query_class: udp_payload_size,
+            record_ttl: 0,
+            rdata_length: 0,
+            rdata: Vec::new(),
+        }
+    }
+
+    /// Creates an `IN` record holding typed `data`, such as a `DNSKeyRecord`
+    pub fn from_record_data<T: RecordData>(name: &DomainName, ttl: u32, data: &T) -> Self {
+        let rdata = data.to_rdata();
+        Self {
+            query_name: name.to_labels(),
+            query_type: T::RECORD_TYPE,
+            query_class: 1,
+            record_ttl: ttl as i32,
+            rdata_length: rdata.len() as u16,
+            rdata,
+        }
+    }
+
+    /// Typed content of the rdata. Fails if the record is of another type or
+    /// the rdata is malformed.
+    pub fn record_data<T: RecordData>(&self) -> io::Result<T> {
+        if self.query_type != T::RECORD_TYPE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "record of another type"));
+        }
+        T::from_rdata(&self.rdata)
+    }
+
+    /// Reads the record at `answer_start`, returning its length. Fails if
+    /// the message ends within the record.
+    pub fn parse_rr_from_response (response: &[u8], answer_start:usize) -> io::Result<(usize, Self)> {
+        let (query_name_end, query_name) = dns_decompression(response, answer_start).ok_or_else(malformed_message)?;
+        let fixed_part = response.get(query_name_end + 1..query_name_end + 11).ok_or_else(malformed_message)?;
+        let query_type = u16::from_be_bytes([fixed_part[0], fixed_part[1]]);
+        let rdata_length = u16::from_be_bytes([fixed_part[8], fixed_part[9]]);
+        // Names in the rdata may point elsewhere in the response
+        let rdata = decompress_rdata(response, query_type, query_name_end + 11, rdata_length as usize).ok_or_else(malformed_message)?;
+        Ok((query_name_end - answer_start + 11 + rdata_length as usize, Self {
+            query_name,
+            query_type,
+            query_class: u16::from_be_bytes([fixed_part[2], fixed_part[3]]),
+            record_ttl: i32::from_be_bytes([fixed_part[4], fixed_part[5], fixed_part[6], fixed_part[7]]),
+            rdata_length: rdata.len() as u16,
+            rdata,
+        }))
     }
 
     pub fn prepare (&self) -> Vec<u8> {
@@ -47,10 +93,10 @@ impl DNSResourceRecord {
             self.query_class.to_le_bytes()[0]
         ]);
         prepared_rr.extend([
-            self.record_ttl.to_le_bytes()[1],
-            self.record_ttl.to_le_bytes()[0],
             self.record_ttl.to_le_bytes()[3],
-            self.record_ttl.to_le_bytes()[2]
+            self.record_ttl.to_le_bytes()[2],
+            self.record_ttl.to_le_bytes()[1],
+            self.record_ttl.to_le_bytes()[0]
         ]);
         prepared_rr.extend([
             self.rdata_length.to_le_bytes()[1],
@@ -62,28 +108,30 @@ impl DNSResourceRecord {
     }
 }
 
+/// Zone file line of the record: owner, TTL, class, type and rdata
+impl fmt::Display for DNSResourceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let owner = DomainName::from_labels(&self.query_name)
+            .map(|owner| owner.to_fqdn_string())
+            .unwrap_or_else(|| labels_to_domains(&self.query_name));
+        write!(
+            f, "{} {} {} {} {}",
+            owner,
+            self.record_ttl.max(0),
+            class_name(self.query_class),
+            query_type_name(self.query_type),
+            rdata_to_string(self.query_type, &self.rdata),
+        )
+    }
+}
+
 impl fmt::Debug for DNSResourceRecord {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match writeln!(f, "querry name: {}", labels_to_domains(&self.query_name)){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "querry type: {}", self.query_type){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "querry class: {}", self.query_class){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "querry type: {}", self.query_type){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
-        match writeln!(f, "rdata length: {}", self.rdata_length){
-            Err(e) => println!("{:?}", e),
-            _ => ()
-        };
+        writeln!(f, "querry name: {}", labels_to_domains(&self.query_name))?;
+        writeln!(f, "querry type: {}", self.query_type)?;
+        writeln!(f, "querry class: {}", self.query_class)?;
+        writeln!(f, "querry type: {}", self.query_type)?;
+        writeln!(f, "rdata length: {}", self.rdata_length)?;
         writeln!(f, "rdata: {:?}", self.rdata)
     }
 }
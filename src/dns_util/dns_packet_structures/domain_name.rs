@@ -0,0 +1,297 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Longest name allowed on the wire, length bytes included (RFC 1035 2.3.4)
+pub const MAX_NAME_LENGTH: usize = 255;
+/// Longest label allowed
+pub const MAX_LABEL_LENGTH: usize = 63;
+
+/// Fully qualified domain name, stored as its labels without the root.
+/// Comparisons ignore ASCII case, as DNS does.
+#[derive(Clone, Default)]
+pub struct DomainName {
+    labels: Vec<Vec<u8>>,
+}
+
+impl DomainName {
+    /// The root name, `.`
+    pub fn root() -> Self {
+        Self { labels: Vec::new() }
+    }
+
+    /// Builds a name from its labels, most specific first. Fails if a label
+    /// is empty or too long, or the whole name too long.
+    pub fn from_label_list(labels: Vec<Vec<u8>>) -> Option<Self> {
+        if labels.iter().any(|label| label.is_empty() || label.len() > MAX_LABEL_LENGTH) {
+            return None;
+        }
+        let name = Self { labels };
+        if name.wire_length() > MAX_NAME_LENGTH {
+            return None;
+        }
+        Some(name)
+    }
+
+    /// Reads an uncompressed name in wire format, as found in
+    /// `DNSQuestion::query_name`, ignoring anything after the root label.
+    pub fn from_labels(wire: &[u8]) -> Option<Self> {
+        let mut labels: Vec<Vec<u8>> = Vec::new();
+        let mut position = 0;
+        loop {
+            let length = *wire.get(position)? as usize;
+            if length == 0 {
+                return Self::from_label_list(labels);
+            }
+            labels.push(wire.get(position + 1..position + 1 + length)?.to_vec());
+            position += 1 + length;
+        }
+    }
+
+    /// Name of the PTR records of `address`, under `in-addr.arpa` for IPv4
+    /// (RFC 1035 section 3.5) and in nibble format under `ip6.arpa` for IPv6
+    /// (RFC 3596 section 2.5). IPv4-mapped IPv6 addresses use the IPv4 form.
+    pub fn reverse_pointer(address: IpAddr) -> Self {
+        let labels: Vec<Vec<u8>> = match address.to_canonical() {
+            IpAddr::V4(address) => address.octets().iter().rev()
+                .map(|octet| octet.to_string().into_bytes())
+                .chain([b"in-addr".to_vec(), b"arpa".to_vec()])
+                .collect(),
+            IpAddr::V6(address) => address.octets().iter().rev()
+                .flat_map(|octet| [octet & 0x0f, octet >> 4])
+                .map(|nibble| format!("{:x}", nibble).into_bytes())
+                .chain([b"ip6".to_vec(), b"arpa".to_vec()])
+                .collect(),
+        };
+        Self { labels }
+    }
+
+    /// Wire format of the name, ending with the root label
+    pub fn to_labels(&self) -> Vec<u8> {
+        let mut wire: Vec<u8> = Vec::with_capacity(self.wire_length());
+        for label in &self.labels {
+            wire.push(label.len() as u8);
+            wire.extend(label);
+        }
+        wire.push(0);
+        wire
+    }
+
+    pub fn labels(&self) -> &[Vec<u8>] {
+        &self.labels
+    }
+
+    pub fn label_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Length of the wire format
+    pub fn wire_length(&self) -> usize {
+        self.labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1
+    }
+
+    /// True if the name is `other` or below it
+    pub fn is_subdomain_of(&self, other: &DomainName) -> bool {
+        other.labels.len() <= self.labels.len()
+            && self.labels[self.labels.len() - other.labels.len()..].iter()
+                .zip(&other.labels)
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    /// Name made of the `count` rightmost labels
+    pub fn suffix(&self, count: usize) -> DomainName {
+        Self { labels: self.labels[self.labels.len() - count.min(self.labels.len())..].to_vec() }
+    }
+
+    /// Name one label up, `None` for the root
+    pub fn parent(&self) -> Option<DomainName> {
+        if self.is_root() {
+            return None;
+        }
+        Some(Self { labels: self.labels[1..].to_vec() })
+    }
+
+    /// Name with `label` added in front
+    pub fn prepend(&self, label: &[u8]) -> Option<DomainName> {
+        let mut labels = vec![label.to_vec()];
+        labels.extend(self.labels.iter().cloned());
+        Self::from_label_list(labels)
+    }
+
+    /// Replaces the suffix `old_suffix` of the name by `new_suffix`, as done
+    /// for DNAME substitution (RFC 6672 section 2.2). `None` if the name is
+    /// not below `old_suffix` or the result would be too long.
+    pub fn replace_suffix(&self, old_suffix: &DomainName, new_suffix: &DomainName) -> Option<DomainName> {
+        if !self.is_subdomain_of(old_suffix) {
+            return None;
+        }
+        let mut labels = self.labels[..self.labels.len() - old_suffix.labels.len()].to_vec();
+        labels.extend(new_suffix.labels.iter().cloned());
+        Self::from_label_list(labels)
+    }
+
+    /// Copy with every label in lower case, the canonical form of DNSSEC
+    pub fn to_lowercase(&self) -> DomainName {
+        Self { labels: self.labels.iter().map(|label| label.to_ascii_lowercase()).collect() }
+    }
+
+    /// Presentation format with the final dot, as written in zone files
+    pub fn to_fqdn_string(&self) -> String {
+        if self.is_root() {
+            ".".to_string()
+        } else {
+            format!("{}.", self)
+        }
+    }
+
+    /// Canonical order of DNSSEC (RFC 4034 section 6.1): names are compared
+    /// label by label from the root, each label as lower case bytes
+    pub fn canonical_cmp(&self, other: &DomainName) -> Ordering {
+        self.labels.iter().rev().map(|label| label.to_ascii_lowercase())
+            .cmp(other.labels.iter().rev().map(|label| label.to_ascii_lowercase()))
+    }
+}
+
+impl PartialEq for DomainName {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels.len() == other.labels.len()
+            && self.labels.iter().zip(&other.labels).all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for DomainName {}
+
+impl Hash for DomainName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for label in &self.labels {
+            label.to_ascii_lowercase().hash(state);
+        }
+    }
+}
+
+/// Parses the presentation format, with or without the final dot. `\.` and
+/// `\DDD` escapes are understood.
+impl FromStr for DomainName {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if name == "." || name.is_empty() {
+            return Ok(Self::root());
+        }
+        let mut labels: Vec<Vec<u8>> = Vec::new();
+        let mut label: Vec<u8> = Vec::new();
+        let mut bytes = name.bytes();
+        let mut ended_with_dot = false;
+        while let Some(byte) = bytes.next() {
+            ended_with_dot = false;
+            match byte {
+                b'.' => {
+                    labels.push(std::mem::take(&mut label));
+                    ended_with_dot = true;
+                }
+                b'\\' => {
+                    let escaped = bytes.next().ok_or_else(|| format!("dangling escape in {}", name))?;
+                    if escaped.is_ascii_digit() {
+                        let digits = [escaped, bytes.next().unwrap_or(0), bytes.next().unwrap_or(0)];
+                        let value = std::str::from_utf8(&digits).ok()
+                            .and_then(|digits| digits.parse::<u8>().ok())
+                            .ok_or_else(|| format!("invalid escape in {}", name))?;
+                        label.push(value);
+                    } else {
+                        label.push(escaped);
+                    }
+                }
+                byte => label.push(byte),
+            }
+        }
+        if !ended_with_dot {
+            labels.push(label);
+        }
+        Self::from_label_list(labels).ok_or_else(|| format!("invalid domain name {}", name))
+    }
+}
+
+/// Presentation format without the final dot, `.` for the root
+impl fmt::Display for DomainName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+        for (i, label) in self.labels.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+            for byte in label {
+                match byte {
+                    b'.' | b'\\' => write!(f, "\\{}", *byte as char)?,
+                    0x21..=0x7e => write!(f, "{}", *byte as char)?,
+                    _ => write!(f, "\\{:03}", byte)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DomainName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        let name: DomainName = "WWW.Example.com.".parse().unwrap();
+        assert_eq!(name.to_labels(), b"\x03WWW\x07Example\x03com\x00".to_vec());
+        assert_eq!(name, "www.example.com".parse().unwrap());
+        assert_eq!(name.to_string(), "WWW.Example.com");
+        assert_eq!("a\\.b.c".parse::<DomainName>().unwrap().label_count(), 2);
+        assert_eq!("a\\032b.c".parse::<DomainName>().unwrap().to_string(), "a\\032b.c");
+        assert!("a..b".parse::<DomainName>().is_err());
+        assert!(format!("{}.com", "a".repeat(64)).parse::<DomainName>().is_err());
+        assert_eq!(DomainName::from_labels(b"\x03www\x07example\x03com\x00").unwrap(), name);
+    }
+
+    #[test]
+    fn test_reverse_pointer() {
+        assert_eq!(DomainName::reverse_pointer("192.0.2.1".parse().unwrap()).to_string(), "1.2.0.192.in-addr.arpa");
+        assert_eq!(DomainName::reverse_pointer("::ffff:192.0.2.1".parse().unwrap()).to_string(), "1.2.0.192.in-addr.arpa");
+        // Example of RFC 3596 section 2.5
+        assert_eq!(
+            DomainName::reverse_pointer("4321:0:1:2:3:4:567:89ab".parse().unwrap()).to_string(),
+            "b.a.9.8.7.6.5.0.4.0.0.0.3.0.0.0.2.0.0.0.1.0.0.0.0.0.0.0.1.2.3.4.ip6.arpa"
+        );
+    }
+
+    #[test]
+    fn test_replace_suffix() {
+        let name: DomainName = "a.b.example.com".parse().unwrap();
+        let replaced = name.replace_suffix(&"example.com".parse().unwrap(), &"example.net".parse().unwrap()).unwrap();
+        assert_eq!(replaced.to_string(), "a.b.example.net");
+        assert!(name.replace_suffix(&"example.org".parse().unwrap(), &DomainName::root()).is_none());
+        assert!(name.is_subdomain_of(&DomainName::root()));
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        // Example of RFC 4034 section 6.1
+        let names: Vec<DomainName> = ["example", "a.example", "yljkjljk.a.example", "Z.a.example", "zABC.a.EXAMPLE", "z.example", "\\001.z.example", "*.z.example", "\\200.z.example"]
+            .iter()
+            .map(|name| name.parse().unwrap())
+            .collect();
+        let mut sorted = names.clone();
+        sorted.reverse();
+        sorted.sort_by(|a, b| a.canonical_cmp(b));
+        assert_eq!(sorted, names);
+    }
+}
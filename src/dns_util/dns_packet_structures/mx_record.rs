@@ -0,0 +1,64 @@
+use std::fmt;
+use std::io;
+
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+
+/// Mail exchange of a domain (RFC 1035 section 3.3.9)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MXRecord {
+    /// Exchanges of lower preference are tried first
+    pub preference: u16,
+    pub exchange: DomainName,
+}
+
+impl MXRecord {
+    /// True for the record `0 .` of a domain accepting no mail (RFC 7505)
+    pub fn is_null_mx(&self) -> bool {
+        self.preference == 0 && self.exchange.is_root()
+    }
+}
+
+impl RecordData for MXRecord {
+    const RECORD_TYPE: u16 = 15;
+
+    /// The exchange must be uncompressed, as in the records of a parsed
+    /// response
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid MX record");
+        let fixed = rdata.get(..2).ok_or_else(invalid)?;
+        let exchange = DomainName::from_labels(&rdata[2..]).ok_or_else(invalid)?;
+        if 2 + exchange.wire_length() != rdata.len() {
+            return Err(invalid());
+        }
+        Ok(Self { preference: u16::from_be_bytes([fixed[0], fixed[1]]), exchange })
+    }
+
+    fn to_rdata(&self) -> Vec<u8> {
+        [self.preference.to_be_bytes().to_vec(), self.exchange.to_labels()].concat()
+    }
+}
+
+impl fmt::Display for MXRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.preference, self.exchange.to_fqdn_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mx(preference: u16, exchange: &str) -> Vec<u8> {
+        MXRecord { preference, exchange: exchange.parse().unwrap() }.to_rdata()
+    }
+
+    #[test]
+    fn test_mx_record() {
+        let record = MXRecord::from_rdata(&mx(10, "mail.example.com")).unwrap();
+        assert_eq!(record.to_string(), "10 mail.example.com.");
+        assert!(!record.is_null_mx());
+        assert!(MXRecord::from_rdata(&mx(0, ".")).unwrap().is_null_mx());
+        assert!(MXRecord::from_rdata(&[0]).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt::Display;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::dns_util::dns_dnssec::records::{DNSKeyRecord, DSRecord, NSEC3ParamRecord, NSEC3Record, NSECRecord, RRSIGRecord};
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+use crate::dns_util::dns_packet_structures::svcb_record::{HTTPSRecord, SVCBRecord};
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+
+/// Mnemonic of a class, `CLASSn` for the unknown ones (RFC 3597 section 5)
+pub(crate) fn class_name(class: u16) -> String {
+    match class {
+        1 => "IN".to_string(),
+        3 => "CH".to_string(),
+        4 => "HS".to_string(),
+        class => format!("CLASS{}", class),
+    }
+}
+
+/// Presentation format of an uncompressed rdata, as written in zone files.
+/// Types not known, or whose rdata is malformed, use the generic `\# length
+/// hex` format (RFC 3597 section 5).
+pub(crate) fn rdata_to_string(rdata_type: u16, rdata: &[u8]) -> String {
+    known_rdata_to_string(rdata_type, rdata).unwrap_or_else(|| {
+        let hex: String = rdata.iter().map(|byte| format!("{:02X}", byte)).collect();
+        format!("\\# {} {}", rdata.len(), hex).trim_end().to_string()
+    })
+}
+
+fn known_rdata_to_string(rdata_type: u16, rdata: &[u8]) -> Option<String> {
+    let mut reader = RdataReader { rdata, position: 0 };
+    let fields: Vec<String> = match rdata_type {
+        1 => vec![Ipv4Addr::from(<[u8; 4]>::try_from(reader.bytes(4)?).ok()?).to_string()],
+        28 => vec![Ipv6Addr::from(<[u8; 16]>::try_from(reader.bytes(16)?).ok()?).to_string()],
+        // NS, MD, MF, CNAME, MB, MG, MR, PTR, DNAME
+        2..=5 | 7..=9 | 12 | 39 => vec![reader.name()?],
+        6 => vec![reader.name()?, reader.name()?, reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?],
+        // MINFO, RP
+        14 | 17 => vec![reader.name()?, reader.name()?],
+        // MX, AFSDB, RT, KX
+        15 | 18 | 21 | 36 => vec![reader.u16()?, reader.name()?],
+        26 => vec![reader.u16()?, reader.name()?, reader.name()?],
+        33 => vec![reader.u16()?, reader.u16()?, reader.u16()?, reader.name()?],
+        // TXT, SPF
+        16 | 99 => {
+            let mut strings: Vec<String> = Vec::new();
+            while reader.position < rdata.len() {
+                strings.push(reader.character_string()?);
+            }
+            strings
+        }
+        DSRecord::RECORD_TYPE => return typed::<DSRecord>(rdata),
+        RRSIGRecord::RECORD_TYPE => return typed::<RRSIGRecord>(rdata),
+        NSECRecord::RECORD_TYPE => return typed::<NSECRecord>(rdata),
+        DNSKeyRecord::RECORD_TYPE => return typed::<DNSKeyRecord>(rdata),
+        NSEC3Record::RECORD_TYPE => return typed::<NSEC3Record>(rdata),
+        NSEC3ParamRecord::RECORD_TYPE => return typed::<NSEC3ParamRecord>(rdata),
+        SVCBRecord::RECORD_TYPE => return typed::<SVCBRecord>(rdata),
+        HTTPSRecord::RECORD_TYPE => return typed::<HTTPSRecord>(rdata),
+        _ => return None,
+    };
+    if reader.position != rdata.len() {
+        return None;
+    }
+    Some(fields.join(" "))
+}
+
+fn typed<T: RecordData + Display>(rdata: &[u8]) -> Option<String> {
+    T::from_rdata(rdata).ok().map(|data| data.to_string())
+}
+
+struct RdataReader<'a> {
+    rdata: &'a [u8],
+    position: usize,
+}
+
+impl RdataReader<'_> {
+    fn bytes(&mut self, count: usize) -> Option<&[u8]> {
+        let bytes = self.rdata.get(self.position..self.position + count)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<String> {
+        let bytes = self.bytes(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]).to_string())
+    }
+
+    fn u32(&mut self) -> Option<String> {
+        let bytes = self.bytes(4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_string())
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let name = DomainName::from_labels(&self.rdata[self.position..])?;
+        self.position += name.wire_length();
+        Some(name.to_fqdn_string())
+    }
+
+    /// Quoted string, with `"`, `\` and non printable bytes escaped
+    fn character_string(&mut self) -> Option<String> {
+        let length = *self.bytes(1)?.first()? as usize;
+        let mut quoted = String::from("\"");
+        for byte in self.bytes(length)? {
+            match byte {
+                b'"' | b'\\' => quoted.extend(['\\', *byte as char]),
+                0x20..=0x7e => quoted.push(*byte as char),
+                byte => quoted.push_str(&format!("\\{:03}", byte)),
+            }
+        }
+        quoted.push('"');
+        Some(quoted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rdata_to_string() {
+        assert_eq!(rdata_to_string(1, &[192, 0, 2, 1]), "192.0.2.1");
+        assert_eq!(rdata_to_string(15, b"\x00\x0a\x04mail\x07example\x00"), "10 mail.example.");
+        assert_eq!(rdata_to_string(16, b"\x05a \"b\"\x01\x07"), "\"a \\\"b\\\"\" \"\\007\"");
+        assert_eq!(rdata_to_string(6, &[b"\x02ns\x00\x00".to_vec(), [0, 0, 0, 1].repeat(5)].concat()), "ns. . 1 1 1 1 1");
+        assert_eq!(rdata_to_string(65, b"\x00\x01\x00\x00\x03\x00\x02\x01\xbb"), "1 . port=443");
+        // Unknown types and malformed rdata
+        assert_eq!(rdata_to_string(65280, &[1, 2]), "\\# 2 0102");
+        assert_eq!(rdata_to_string(1, &[1, 2]), "\\# 2 0102");
+        assert_eq!(rdata_to_string(10, &[]), "\\# 0");
+    }
+}
@@ -0,0 +1,13 @@
+use std::io;
+
+/// Typed content of the rdata of a record type
+pub trait RecordData: Sized {
+    /// Value of the type in `DNSResourceRecord::query_type`
+    const RECORD_TYPE: u16;
+
+    /// Reads the wire format of the rdata
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self>;
+
+    /// Wire format of the rdata, with uncompressed names
+    fn to_rdata(&self) -> Vec<u8>;
+}
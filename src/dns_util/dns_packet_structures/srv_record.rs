@@ -0,0 +1,77 @@
+use std::fmt;
+use std::io;
+
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+
+/// Location of a service (RFC 2782)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SRVRecord {
+    /// Targets of lower priority are tried first
+    pub priority: u16,
+    /// Relative chance of a target among those of the same priority
+    pub weight: u16,
+    pub port: u16,
+    /// Host of the service, the root for a service not available
+    pub target: DomainName,
+}
+
+impl SRVRecord {
+    /// True for the target `.`, meaning that the service is not available
+    /// at the domain
+    pub fn is_no_service(&self) -> bool {
+        self.target.is_root()
+    }
+}
+
+impl RecordData for SRVRecord {
+    const RECORD_TYPE: u16 = 33;
+
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid SRV record");
+        let fixed = rdata.get(..6).ok_or_else(invalid)?;
+        let target = DomainName::from_labels(&rdata[6..]).ok_or_else(invalid)?;
+        if 6 + target.wire_length() != rdata.len() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            priority: u16::from_be_bytes([fixed[0], fixed[1]]),
+            weight: u16::from_be_bytes([fixed[2], fixed[3]]),
+            port: u16::from_be_bytes([fixed[4], fixed[5]]),
+            target,
+        })
+    }
+
+    fn to_rdata(&self) -> Vec<u8> {
+        let mut rdata: Vec<u8> = Vec::new();
+        rdata.extend(self.priority.to_be_bytes());
+        rdata.extend(self.weight.to_be_bytes());
+        rdata.extend(self.port.to_be_bytes());
+        rdata.extend(self.target.to_labels());
+        rdata
+    }
+}
+
+impl fmt::Display for SRVRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.priority, self.weight, self.port, self.target.to_fqdn_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn srv(priority: u16, weight: u16, target: &str) -> SRVRecord {
+        SRVRecord { priority, weight, port: 5060, target: target.parse().unwrap() }
+    }
+
+    #[test]
+    fn test_srv_record() {
+        let record = srv(10, 60, "sip.example.com");
+        assert_eq!(SRVRecord::from_rdata(&record.to_rdata()).unwrap(), record);
+        assert_eq!(record.to_string(), "10 60 5060 sip.example.com.");
+        assert!(srv(0, 0, ".").is_no_service());
+        assert!(SRVRecord::from_rdata(&[0, 1, 0, 2]).is_err());
+    }
+}
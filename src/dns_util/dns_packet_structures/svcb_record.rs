@@ -0,0 +1,530 @@
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_packet_structures::record_data::RecordData;
+
+/// Names of the keys defined by RFC 9460, by value
+const KEY_NAMES: [&str; 7] = ["mandatory", "alpn", "no-default-alpn", "port", "ipv4hint", "ech", "ipv6hint"];
+
+/// Reserved "invalid key"
+const INVALID_KEY: u16 = 65535;
+
+/// Name of a key, `key<n>` for the unknown ones
+fn key_name(key: u16) -> String {
+    KEY_NAMES.get(key as usize).map_or_else(|| format!("key{}", key), |name| name.to_string())
+}
+
+/// Value of a key given by its name or in the `key<n>` form
+fn key_number(name: &str) -> Option<u16> {
+    if let Some(key) = KEY_NAMES.iter().position(|known| *known == name) {
+        return Some(key as u16);
+    }
+    let digits = name.strip_prefix("key").filter(|digits| !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit()))?;
+    digits.parse().ok().filter(|key| *key != INVALID_KEY)
+}
+
+/// Parameter of a service binding (RFC 9460 section 7)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SvcParam {
+    /// Keys that clients must support to use the record, in increasing order
+    Mandatory(Vec<u16>),
+    /// Application protocols supported, as TLS ALPN protocol IDs of 1 to
+    /// 255 bytes. `SvcParam::alpn` checks the list.
+    ALPN(Vec<Vec<u8>>),
+    /// The default protocol of the scheme is not supported
+    NoDefaultALPN,
+    Port(u16),
+    /// Addresses of the target, to connect before its lookup completes
+    IPv4Hint(Vec<Ipv4Addr>),
+    /// Encrypted ClientHello configuration list
+    ECH(Vec<u8>),
+    IPv6Hint(Vec<Ipv6Addr>),
+    /// Key without typed value, with its raw value
+    Unknown(u16, Vec<u8>),
+}
+
+impl SvcParam {
+    /// ALPN parameter, `None` when `ids` is empty or an ID is empty or
+    /// longer than 255 bytes
+    pub fn alpn(ids: Vec<Vec<u8>>) -> Option<Self> {
+        (!ids.is_empty() && ids.iter().all(|id| (1..=255).contains(&id.len()))).then_some(SvcParam::ALPN(ids))
+    }
+
+    pub fn key(&self) -> u16 {
+        match self {
+            SvcParam::Mandatory(_) => 0,
+            SvcParam::ALPN(_) => 1,
+            SvcParam::NoDefaultALPN => 2,
+            SvcParam::Port(_) => 3,
+            SvcParam::IPv4Hint(_) => 4,
+            SvcParam::ECH(_) => 5,
+            SvcParam::IPv6Hint(_) => 6,
+            SvcParam::Unknown(key, _) => *key,
+        }
+    }
+
+    /// Wire format of the value. The IDs of `ALPN` must fit the length
+    /// byte, as `alpn` checks.
+    fn value(&self) -> Vec<u8> {
+        match self {
+            SvcParam::Mandatory(keys) => keys.iter().flat_map(|key| key.to_be_bytes()).collect(),
+            SvcParam::ALPN(ids) => ids.iter().flat_map(|id| [&[id.len() as u8][..], id].concat()).collect(),
+            SvcParam::NoDefaultALPN => Vec::new(),
+            SvcParam::Port(port) => port.to_be_bytes().to_vec(),
+            SvcParam::IPv4Hint(addresses) => addresses.iter().flat_map(|address| address.octets()).collect(),
+            SvcParam::ECH(config) => config.clone(),
+            SvcParam::IPv6Hint(addresses) => addresses.iter().flat_map(|address| address.octets()).collect(),
+            SvcParam::Unknown(_, value) => value.clone(),
+        }
+    }
+
+    /// Reads the wire format of the value of `key`
+    fn from_value(key: u16, value: &[u8]) -> Option<Self> {
+        let param = match key {
+            0 if !value.is_empty() && value.len().is_multiple_of(2) => {
+                SvcParam::Mandatory(value.chunks(2).map(|key| u16::from_be_bytes([key[0], key[1]])).collect())
+            }
+            1 => {
+                let mut ids: Vec<Vec<u8>> = Vec::new();
+                let mut position = 0;
+                while position < value.len() {
+                    let length = value[position] as usize;
+                    ids.push(value.get(position + 1..position + 1 + length)?.to_vec());
+                    position += 1 + length;
+                }
+                SvcParam::alpn(ids)?
+            }
+            2 if value.is_empty() => SvcParam::NoDefaultALPN,
+            3 => SvcParam::Port(u16::from_be_bytes(value.try_into().ok()?)),
+            4 if value.len().is_multiple_of(4) => {
+                SvcParam::IPv4Hint(value.chunks(4).map(|octets| Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])).collect()).non_empty()?
+            }
+            5 => SvcParam::ECH(value.to_vec()),
+            6 if value.len().is_multiple_of(16) => {
+                SvcParam::IPv6Hint(value.chunks(16).map(|octets| Ipv6Addr::from(<[u8; 16]>::try_from(octets).unwrap())).collect()).non_empty()?
+            }
+            0..=6 | INVALID_KEY => return None,
+            key => SvcParam::Unknown(key, value.to_vec()),
+        };
+        Some(param)
+    }
+
+    /// Reads the presentation format of the value of `key`, already
+    /// unescaped
+    fn parse_value(key: u16, value: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(value).ok();
+        let list = || text.map(|text| text.split(','));
+        let param = match key {
+            0 => {
+                let mut keys: Vec<u16> = list()?.map(key_number).collect::<Option<_>>()?;
+                keys.sort_unstable();
+                SvcParam::Mandatory(keys)
+            }
+            1 => SvcParam::alpn(split_value_list(value)?)?,
+            2 if value.is_empty() => SvcParam::NoDefaultALPN,
+            3 => SvcParam::Port(text?.parse().ok()?),
+            4 => SvcParam::IPv4Hint(list()?.map(|address| address.parse().ok()).collect::<Option<_>>()?),
+            5 => SvcParam::ECH(STANDARD.decode(value).ok()?),
+            6 => SvcParam::IPv6Hint(list()?.map(|address| address.parse().ok()).collect::<Option<_>>()?),
+            0..=6 | INVALID_KEY => return None,
+            key => SvcParam::Unknown(key, value.to_vec()),
+        };
+        Some(param)
+    }
+
+    /// `None` for the lists that must not be empty when they are
+    fn non_empty(self) -> Option<Self> {
+        let empty = match &self {
+            SvcParam::IPv4Hint(addresses) => addresses.is_empty(),
+            SvcParam::IPv6Hint(addresses) => addresses.is_empty(),
+            _ => false,
+        };
+        (!empty).then_some(self)
+    }
+}
+
+/// `key=value` in presentation format, or only the key when the value is
+/// empty
+impl fmt::Display for SvcParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |items: Vec<String>| items.join(",");
+        let value = match self {
+            SvcParam::Mandatory(keys) => join(keys.iter().map(|key| key_name(*key)).collect()),
+            SvcParam::ALPN(ids) => {
+                // Commas and backslashes of the IDs escaped first, then the
+                // whole list as a character-string
+                let list: Vec<Vec<u8>> = ids.iter()
+                    .map(|id| id.iter().flat_map(|&byte| if byte == b',' || byte == b'\\' { vec![b'\\', byte] } else { vec![byte] }).collect())
+                    .collect();
+                escape_value(&list.join(&b','))
+            }
+            SvcParam::NoDefaultALPN => String::new(),
+            SvcParam::Port(port) => port.to_string(),
+            SvcParam::IPv4Hint(addresses) => join(addresses.iter().map(Ipv4Addr::to_string).collect()),
+            SvcParam::ECH(config) => STANDARD.encode(config),
+            SvcParam::IPv6Hint(addresses) => join(addresses.iter().map(Ipv6Addr::to_string).collect()),
+            SvcParam::Unknown(_, value) => escape_value(value),
+        };
+        if value.is_empty() {
+            write!(f, "{}", key_name(self.key()))
+        } else {
+            write!(f, "{}={}", key_name(self.key()), value)
+        }
+    }
+}
+
+/// Character-string escaping, spaces included so that values need no quotes
+fn escape_value(value: &[u8]) -> String {
+    value.iter()
+        .map(|&byte| match byte {
+            b'"' | b'\\' | b';' | b'(' | b')' => format!("\\{}", byte as char),
+            0x21..=0x7e => (byte as char).to_string(),
+            byte => format!("\\{:03}", byte),
+        })
+        .collect()
+}
+
+/// Reverse of `escape_value`, for a value with its quotes removed
+fn unescape_value(value: &str) -> Option<Vec<u8>> {
+    let mut unescaped: Vec<u8> = Vec::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.extend(c.to_string().bytes());
+            continue;
+        }
+        let escaped = chars.next()?;
+        if escaped.is_ascii_digit() {
+            let digits: String = [Some(escaped), chars.next(), chars.next()].into_iter().collect::<Option<_>>()?;
+            unescaped.push(digits.parse().ok()?);
+        } else {
+            unescaped.extend(escaped.to_string().bytes());
+        }
+    }
+    Some(unescaped)
+}
+
+/// Items of a value separated by `,`, where `\` escapes commas and
+/// backslashes (RFC 9460 appendix A.1)
+fn split_value_list(value: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut items: Vec<Vec<u8>> = vec![Vec::new()];
+    let mut bytes = value.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'\\' => items.last_mut()?.push(*bytes.next()?),
+            b',' => items.push(Vec::new()),
+            byte => items.last_mut()?.push(byte),
+        }
+    }
+    Some(items)
+}
+
+/// Splits the presentation format at the spaces outside quotes, removing
+/// the quotes and the parentheses of multi-line records but keeping the
+/// escapes
+fn split_fields(text: &str) -> Result<Vec<String>, String> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                field.push(c);
+                field.push(chars.next().ok_or_else(|| format!("dangling escape in {}", text))?);
+            }
+            '"' => quoted = !quoted,
+            '(' | ')' if !quoted => {}
+            c if c.is_whitespace() && !quoted => {
+                if !field.is_empty() {
+                    fields.push(std::mem::take(&mut field));
+                }
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("unterminated quote in {}", text));
+    }
+    if !field.is_empty() {
+        fields.push(field);
+    }
+    Ok(fields)
+}
+
+/// Checks that the keys are in increasing order and the parameters
+/// consistent (RFC 9460 sections 2.2, 7 and 8)
+fn check_params(params: &[SvcParam]) -> Result<(), String> {
+    if let Some(pair) = params.windows(2).find(|pair| pair[0].key() >= pair[1].key()) {
+        return Err(format!("{} not in increasing order or given twice", key_name(pair[1].key())));
+    }
+    let has_key = |key: u16| params.iter().any(|param| param.key() == key);
+    if let Some(SvcParam::Mandatory(keys)) = params.first() {
+        if keys.windows(2).any(|pair| pair[0] >= pair[1]) || keys.contains(&0) {
+            return Err("invalid mandatory keys".to_string());
+        }
+        if let Some(key) = keys.iter().find(|key| !has_key(**key)) {
+            return Err(format!("mandatory key {} missing", key_name(*key)));
+        }
+    }
+    if has_key(2) && !has_key(1) {
+        return Err("no-default-alpn without alpn".to_string());
+    }
+    Ok(())
+}
+
+/// Service binding of a name (RFC 9460)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SVCBRecord {
+    /// 0 for an AliasMode record, else the priority of the ServiceMode
+    /// record, lower first
+    pub priority: u16,
+    /// Name of the endpoint. The root means the owner name in ServiceMode,
+    /// and that the service is not available in AliasMode.
+    pub target: DomainName,
+    /// Parameters by increasing key
+    pub params: Vec<SvcParam>,
+}
+
+impl SVCBRecord {
+    /// True for an AliasMode record, giving another name for the service
+    pub fn is_alias(&self) -> bool {
+        self.priority == 0
+    }
+
+    pub fn param(&self, key: u16) -> Option<&SvcParam> {
+        self.params.iter().find(|param| param.key() == key)
+    }
+
+    /// False when the mandatory keys include keys unknown to this library,
+    /// making the record unusable (RFC 9460 section 8)
+    pub fn is_supported(&self) -> bool {
+        match self.param(0) {
+            Some(SvcParam::Mandatory(keys)) => keys.iter().all(|key| (*key as usize) < KEY_NAMES.len()),
+            _ => true,
+        }
+    }
+
+    /// Port of the endpoint, `None` for the default port of the scheme
+    pub fn port(&self) -> Option<u16> {
+        match self.param(3) {
+            Some(SvcParam::Port(port)) => Some(*port),
+            _ => None,
+        }
+    }
+}
+
+impl RecordData for SVCBRecord {
+    const RECORD_TYPE: u16 = 64;
+
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self> {
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid SVCB record: {}", reason));
+        let fixed = rdata.get(..2).ok_or_else(|| invalid("truncated"))?;
+        let target = DomainName::from_labels(&rdata[2..]).ok_or_else(|| invalid("invalid target"))?;
+        let mut params: Vec<SvcParam> = Vec::new();
+        let mut position = 2 + target.wire_length();
+        while position < rdata.len() {
+            let header = rdata.get(position..position + 4).ok_or_else(|| invalid("truncated"))?;
+            let key = u16::from_be_bytes([header[0], header[1]]);
+            let length = u16::from_be_bytes([header[2], header[3]]) as usize;
+            let value = rdata.get(position + 4..position + 4 + length).ok_or_else(|| invalid("truncated"))?;
+            params.push(SvcParam::from_value(key, value).ok_or_else(|| invalid(&format!("invalid {}", key_name(key))))?);
+            position += 4 + length;
+        }
+        check_params(&params).map_err(|e| invalid(&e))?;
+        Ok(Self { priority: u16::from_be_bytes([fixed[0], fixed[1]]), target, params })
+    }
+
+    fn to_rdata(&self) -> Vec<u8> {
+        let mut rdata: Vec<u8> = Vec::new();
+        rdata.extend(self.priority.to_be_bytes());
+        rdata.extend(self.target.to_labels());
+        for param in &self.params {
+            let value = param.value();
+            rdata.extend(param.key().to_be_bytes());
+            rdata.extend((value.len() as u16).to_be_bytes());
+            rdata.extend(value);
+        }
+        rdata
+    }
+}
+
+impl fmt::Display for SVCBRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.priority, self.target.to_fqdn_string())?;
+        for param in &self.params {
+            write!(f, " {}", param)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the presentation format of the rdata, the parameters in any
+/// order
+impl FromStr for SVCBRecord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let fields = split_fields(text)?;
+        if fields.len() < 2 {
+            return Err(format!("missing fields in SVCB {}", text));
+        }
+        let mut params: Vec<SvcParam> = Vec::new();
+        for field in &fields[2..] {
+            let (name, value) = field.split_once('=').unwrap_or((field, ""));
+            let key = key_number(name).ok_or_else(|| format!("unknown SVCB key {}", name))?;
+            let param = unescape_value(value)
+                .and_then(|value| SvcParam::parse_value(key, &value))
+                .ok_or_else(|| format!("invalid SVCB parameter {}", field))?;
+            params.push(param);
+        }
+        params.sort_by_key(SvcParam::key);
+        check_params(&params)?;
+        Ok(Self {
+            priority: fields[0].parse().map_err(|_| format!("invalid SVCB priority {}", fields[0]))?,
+            target: fields[1].parse()?,
+            params,
+        })
+    }
+}
+
+/// Service binding of an HTTPS origin, with the format of SVCB (RFC 9460
+/// section 9)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HTTPSRecord(pub SVCBRecord);
+
+impl RecordData for HTTPSRecord {
+    const RECORD_TYPE: u16 = 65;
+
+    fn from_rdata(rdata: &[u8]) -> io::Result<Self> {
+        SVCBRecord::from_rdata(rdata).map(Self)
+    }
+
+    fn to_rdata(&self) -> Vec<u8> {
+        self.0.to_rdata()
+    }
+}
+
+impl fmt::Display for HTTPSRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for HTTPSRecord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        text.parse().map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_svcb_test_vectors() {
+        // RFC 9460 appendix D, as presentation, wire format and printed form
+        let foo_com = "03666f6f076578616d706c6503636f6d00";
+        let foo_org = "03666f6f076578616d706c65036f726700";
+        for (text, wire, printed) in [
+            ("0 foo.example.com.", format!("0000{}", foo_com), "0 foo.example.com."),
+            ("1 .", "000100".to_string(), "1 ."),
+            ("16 foo.example.com. port=53", format!("0010{}000300020035", foo_com), "16 foo.example.com. port=53"),
+            ("1 foo.example.com. key667=hello", format!("0001{}029b000568656c6c6f", foo_com), "1 foo.example.com. key667=hello"),
+            (r#"1 foo.example.com. key667="hello\210qoo""#, format!("0001{}029b000968656c6c6fd2716f6f", foo_com), r"1 foo.example.com. key667=hello\210qoo"),
+            (
+                r#"1 foo.example.com. ipv6hint="2001:db8::1,2001:db8::53:1""#,
+                format!("0001{}0006002020010db800000000000000000000000120010db8000000000000000000530001", foo_com),
+                "1 foo.example.com. ipv6hint=2001:db8::1,2001:db8::53:1",
+            ),
+            (
+                "1 example.com. ipv6hint=\"2001:db8:122:344::192.0.2.33\"",
+                "0001076578616d706c6503636f6d000006001020010db80122034400000000c0000221".to_string(),
+                "1 example.com. ipv6hint=2001:db8:122:344::c000:221",
+            ),
+            (
+                "16 foo.example.org. (\n    alpn=h2,h3-19 mandatory=ipv4hint,alpn\n    ipv4hint=192.0.2.1\n)",
+                format!("0010{}0000000400010004000100090268320568332d313900040004c0000201", foo_org),
+                "16 foo.example.org. mandatory=alpn,ipv4hint alpn=h2,h3-19 ipv4hint=192.0.2.1",
+            ),
+            // ALPN IDs "f\oo,bar" and "h2"
+            (
+                r#"16 foo.example.org. alpn="f\\\\oo\\,bar,h2""#,
+                format!("0010{}0001000c08665c6f6f2c626172026832", foo_org),
+                r"16 foo.example.org. alpn=f\\\\oo\\,bar,h2",
+            ),
+        ] {
+            let record: SVCBRecord = text.parse().unwrap();
+            assert_eq!(record.to_rdata(), hex(&wire), "{}", text);
+            assert_eq!(SVCBRecord::from_rdata(&hex(&wire)).unwrap(), record, "{}", text);
+            assert_eq!(record.to_string(), printed);
+            assert_eq!(printed.parse::<SVCBRecord>().unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn test_invalid_svcb() {
+        // Failure cases of RFC 9460 appendix D.3, and others
+        for text in [
+            "1 foo.example.com. key123=abc key123=def",
+            "1 foo.example.com. mandatory",
+            "1 foo.example.com. alpn",
+            "1 foo.example.com. port",
+            "1 foo.example.com. ipv4hint",
+            "1 foo.example.com. ipv6hint",
+            "1 foo.example.com. no-default-alpn=abc",
+            "1 foo.example.com. mandatory=key123",
+            "1 foo.example.com. mandatory=mandatory",
+            "1 foo.example.com. ipv6hint=192.0.2.1",
+            "1 foo.example.com. mandatory=key123,key123 key123=abc",
+            "1 foo.example.com. no-default-alpn",
+            "1 foo.example.com. key65535=x",
+            "1 foo.example.com. dohpath=/q",
+            "1 foo.example.com. alpn=\"h2",
+            "1",
+        ] {
+            assert!(text.parse::<SVCBRecord>().is_err(), "{}", text);
+        }
+        // Keys out of order, and a port of 3 bytes
+        assert!(SVCBRecord::from_rdata(&hex("00010000030002003500010003026832")).is_err());
+        assert!(SVCBRecord::from_rdata(&hex("0001000003000300350a")).is_err());
+    }
+
+    #[test]
+    fn test_svcb_params() {
+        let record: SVCBRecord = "1 . alpn=h3 no-default-alpn port=8443 ech=AEX+DQ== key65000".parse().unwrap();
+        assert_eq!(record.params, vec![
+            SvcParam::ALPN(vec![b"h3".to_vec()]),
+            SvcParam::NoDefaultALPN,
+            SvcParam::Port(8443),
+            SvcParam::ECH(vec![0x00, 0x45, 0xfe, 0x0d]),
+            SvcParam::Unknown(65000, Vec::new()),
+        ]);
+        assert_eq!(record.port(), Some(8443));
+        assert_eq!(record.to_string(), "1 . alpn=h3 no-default-alpn port=8443 ech=AEX+DQ== key65000");
+        // Known keys in the generic form, and spaces in values
+        let record: SVCBRecord = r#"2 svc.example. key3=443 key1="a b""#.parse().unwrap();
+        assert_eq!(record.params, vec![SvcParam::ALPN(vec![b"a b".to_vec()]), SvcParam::Port(443)]);
+        assert_eq!(record.to_string(), r"2 svc.example. alpn=a\032b port=443");
+        assert!("0 svc.example.".parse::<HTTPSRecord>().unwrap().0.is_alias());
+
+        // ALPN IDs must fit their length byte
+        assert_eq!(SvcParam::alpn(vec![b"h2".to_vec()]), Some(SvcParam::ALPN(vec![b"h2".to_vec()])));
+        assert_eq!(SvcParam::alpn(vec![vec![b'a'; 256]]), None);
+        assert_eq!(SvcParam::alpn(vec![Vec::new()]), None);
+        assert_eq!(SvcParam::alpn(Vec::new()), None);
+        assert!(format!("1 . alpn={}", "a".repeat(256)).parse::<SVCBRecord>().is_err());
+    }
+}
@@ -1,12 +1,20 @@
+use std::io;
 use std::str;
 
+use super::domain_name::MAX_NAME_LENGTH;
+
 pub(super) fn domain_to_labels(domain: &str) -> Vec<u8> {
+    // A trailing dot only marks the name as fully qualified
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+    if domain.is_empty() {
+        return vec![0];
+    }
     let mut labels: Vec<u8> = Vec::new();
     let mut counters: Vec<u8> = Vec::new();
 
     let mut counter: u8 = 0;
     // compute the lengths of the parts of the domain
-    for (_i, elem) in domain.as_bytes().iter().enumerate() {
+    for elem in domain.as_bytes().iter() {
         if *elem == 46 {
             counters.push(counter);
             counter += 1;
@@ -17,19 +25,19 @@ pub(super) fn domain_to_labels(domain: &str) -> Vec<u8> {
     }
     // Adds the parts of the domain to the vector
     let mut previous_position = 0;
-    for (_i, elem) in counters.iter().enumerate() {
+    for elem in counters.iter() {
         labels.push(*elem - previous_position);
-        labels.extend(domain[previous_position as usize..(*elem) as usize].as_bytes());
+        labels.extend(&domain.as_bytes()[previous_position as usize..(*elem) as usize]);
         previous_position = *elem + 1;
     }
     // Pushes the last part of the domain
     labels.push(domain.len() as u8 - previous_position);
-    labels.extend(domain[previous_position as usize..].as_bytes());
+    labels.extend(&domain.as_bytes()[previous_position as usize..]);
     labels.push(0);
     labels
 }
 
-pub(super) fn labels_to_domains (labels: &Vec<u8>) -> String {
+pub(super) fn labels_to_domains (labels: &[u8]) -> String {
     let mut current_position = 0;
     let mut domain:String = "".to_string();
     while current_position < labels.len() - 1 { // -1 because of the last null byte
@@ -44,53 +52,226 @@ pub(super) fn labels_to_domains (labels: &Vec<u8>) -> String {
     domain
 }
 
-pub(super) fn dns_decompression(response: &Vec<u8>, query_name_start: usize) -> (usize, Vec<u8>) {
-    let mut query_name:Vec<u8> = Vec::new();
-
-    // End of labels 
-    if response[query_name_start] == 0 {
-        query_name.push(0);
-        return (query_name_start, query_name)
-    }
-    // Decompression needed
-    else if response[query_name_start] >> 6 == 0b11 {
-        query_name.extend(dns_decompression(
-            response, 
-            u16::from_le_bytes([response[query_name_start + 1], response[query_name_start] & 0b0011_1111]) as usize
-        ).1);
-        return (query_name_start+1, query_name)
-    }
-    // 
-    else {
-        let label_length:usize = response[query_name_start] as usize;
-        query_name.push(response[query_name_start]);
-        query_name.extend(response[query_name_start + 1..query_name_start + 1 + label_length].to_vec());
-        let (next_label_end, next_label) = dns_decompression(response, query_name_start + 1 + label_length);
-        query_name.extend(next_label);
-        return (next_label_end, query_name)
+/// Error of a message ending before its last field, or holding a name that
+/// cannot be read
+pub(super) fn malformed_message() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed DNS message")
+}
+
+/// Reads the name at `query_name_start`, following its compression
+/// pointers. Returns the position of the last byte of the name with the
+/// name, `None` if the name runs past the end of the message, holds a
+/// pointer going forward or in a loop, or is longer than 255 bytes.
+pub(super) fn dns_decompression(response: &[u8], query_name_start: usize) -> Option<(usize, Vec<u8>)> {
+    let mut query_name: Vec<u8> = Vec::new();
+    let mut position = query_name_start;
+    // Every pointer must lead before the previous one, so that a loop of
+    // pointers cannot be followed forever
+    let mut limit = query_name_start;
+    // The name ends where its first pointer is, if any
+    let mut query_name_end = None;
+    loop {
+        let length = *response.get(position)?;
+        // End of labels
+        if length == 0 {
+            query_name.push(0);
+            return Some((query_name_end.unwrap_or(position), query_name));
+        }
+        // Decompression needed
+        if length >> 6 == 0b11 {
+            let pointer = u16::from_be_bytes([length & 0b0011_1111, *response.get(position + 1)?]) as usize;
+            query_name_end.get_or_insert(position + 1);
+            if pointer >= limit {
+                return None;
+            }
+            position = pointer;
+            limit = pointer;
+            continue;
+        }
+        query_name.extend(response.get(position..position + 1 + length as usize)?);
+        if query_name.len() >= MAX_NAME_LENGTH {
+            return None;
+        }
+        position += 1 + length as usize;
     }
 }
 
-pub(super) fn parse_query_type(query_type: &str) -> u16 {
-    match query_type {
-        "A"       => 1,
-        "NS"      => 2,
-        "MD"      => 3,
-        "MF"      => 4,
-        "CNAME"   => 5,
-        "SOA"     => 6,
-        "MB"      => 7,
-        "MG"      => 8,
-        "MR"      => 9,
-        "NULL"    => 10,
-        "WKS"     => 11,
-        "PTR"     => 12,
-        "HINFO"   => 13,
-        "MINFO"   => 14,
-        "MX"      => 15,
-        "TXT"     => 16,
-        _         => 0,
+/// Copies the rdata of a record found at `rdata_start` in `response`,
+/// expanding the compressed names it may contain so that it can be read
+/// without the rest of the message. Only the types whose names can be
+/// compressed (RFC 3597 section 4), plus SRV and DNAME which some servers
+/// compress anyway, are rewritten. Returns `None` if the rdata runs past the
+/// end of the message.
+pub(super) fn decompress_rdata(response: &[u8], rdata_type: u16, rdata_start: usize, rdata_length: usize) -> Option<Vec<u8>> {
+    let rdata_end = rdata_start + rdata_length;
+    let raw_rdata = response.get(rdata_start..rdata_end)?;
+    let Some((prefix_length, names_count)) = rdata_names(rdata_type) else {
+        return Some(raw_rdata.to_vec());
+    };
+    if rdata_length < prefix_length + names_count {
+        return Some(raw_rdata.to_vec());
     }
+
+    let mut rdata: Vec<u8> = raw_rdata[..prefix_length].to_vec();
+    let mut position = rdata_start + prefix_length;
+    for _i in 0..names_count {
+        match skip_name(&response[..rdata_end], position) {
+            Some(name_end) if name_end <= rdata_end => {
+                match dns_decompression(response, position) {
+                    Some((_, name)) => rdata.extend(name),
+                    None => return Some(raw_rdata.to_vec()),
+                }
+                position = name_end;
+            }
+            _ => return Some(raw_rdata.to_vec()),
+        }
+    }
+    rdata.extend(&response[position..rdata_end]);
+    Some(rdata)
+}
+
+/// Canonical form of an uncompressed rdata for DNSSEC, the names it holds
+/// being in lower case (RFC 4034 section 6.2, without NSEC as updated by
+/// RFC 6840 section 5.1)
+pub(crate) fn canonical_rdata(rdata_type: u16, rdata: &[u8]) -> Vec<u8> {
+    let mut canonical = rdata.to_vec();
+    let Some((prefix_length, names_count)) = rdata_names(rdata_type) else {
+        return canonical;
+    };
+    let mut position = prefix_length;
+    for _i in 0..names_count {
+        while let Some(&length) = canonical.get(position) {
+            if length == 0 || length >> 6 != 0 {
+                break;
+            }
+            let label_end = (position + 1 + length as usize).min(canonical.len());
+            canonical[position + 1..label_end].make_ascii_lowercase();
+            position = label_end;
+        }
+        position += 1;
+    }
+    canonical
+}
+
+/// Length of the fixed fields before the names of an rdata type, and number
+/// of names following them
+fn rdata_names(rdata_type: u16) -> Option<(usize, usize)> {
+    match rdata_type {
+        // NS, MD, MF, CNAME, MB, MG, MR, PTR, DNAME
+        2..=5 | 7..=9 | 12 | 39 => Some((0, 1)),
+        // SOA, MINFO, RP
+        6 | 14 | 17 => Some((0, 2)),
+        // MX, AFSDB, RT, KX
+        15 | 18 | 21 | 36 => Some((2, 1)),
+        // PX
+        26 => Some((2, 2)),
+        // SRV
+        33 => Some((6, 1)),
+        _ => None,
+    }
+}
+
+/// Returns the position right after the (possibly compressed) name starting
+/// at `name_start`, without following the compression pointers
+pub(crate) fn skip_name(message: &[u8], name_start: usize) -> Option<usize> {
+    let mut position = name_start;
+    loop {
+        let length = *message.get(position)?;
+        if length == 0 {
+            return Some(position + 1);
+        }
+        if length >> 6 == 0b11 {
+            return Some(position + 2);
+        }
+        position += 1 + length as usize;
+    }
+}
+
+/// Applies `rewrite` to the TTL of every resource record of a wire format
+/// message, in place. OPT pseudo-records are left untouched since their TTL
+/// field holds flags. Returns `None` if the message is malformed.
+pub(crate) fn rewrite_record_ttls(message: &mut [u8], rewrite: impl Fn(u32) -> u32) -> Option<()> {
+    if message.len() < 12 {
+        return None;
+    }
+    let questions_count = u16::from_be_bytes([message[4], message[5]]);
+    let records_count = u16::from_be_bytes([message[6], message[7]]) as usize
+        + u16::from_be_bytes([message[8], message[9]]) as usize
+        + u16::from_be_bytes([message[10], message[11]]) as usize;
+
+    let mut position = 12;
+    for _i in 0..questions_count {
+        position = skip_name(message, position)? + 4;
+    }
+    for _i in 0..records_count {
+        position = skip_name(message, position)?;
+        let fixed_part = message.get_mut(position..position + 10)?;
+        if u16::from_be_bytes([fixed_part[0], fixed_part[1]]) != 41 {
+            let ttl = u32::from_be_bytes([fixed_part[4], fixed_part[5], fixed_part[6], fixed_part[7]]);
+            fixed_part[4..8].copy_from_slice(&rewrite(ttl).to_be_bytes());
+        }
+        position += 10 + u16::from_be_bytes([fixed_part[8], fixed_part[9]]) as usize;
+    }
+    if position > message.len() {
+        return None;
+    }
+    Some(())
+}
+
+/// Mnemonics of the record types, with their value
+const QUERY_TYPES: [(&str, u16); 34] = [
+    ("A", 1),
+    ("NS", 2),
+    ("MD", 3),
+    ("MF", 4),
+    ("CNAME", 5),
+    ("SOA", 6),
+    ("MB", 7),
+    ("MG", 8),
+    ("MR", 9),
+    ("NULL", 10),
+    ("WKS", 11),
+    ("PTR", 12),
+    ("HINFO", 13),
+    ("MINFO", 14),
+    ("MX", 15),
+    ("TXT", 16),
+    ("RP", 17),
+    ("AFSDB", 18),
+    ("RT", 21),
+    ("PX", 26),
+    ("AAAA", 28),
+    ("SRV", 33),
+    ("KX", 36),
+    ("DNAME", 39),
+    ("OPT", 41),
+    ("DS", 43),
+    ("RRSIG", 46),
+    ("NSEC", 47),
+    ("DNSKEY", 48),
+    ("NSEC3", 50),
+    ("NSEC3PARAM", 51),
+    ("SVCB", 64),
+    ("HTTPS", 65),
+    ("SPF", 99),
+];
+
+/// Value of a record type given by its mnemonic, or in the `TYPE<n>` form
+/// of RFC 3597. 0 if unknown.
+pub(crate) fn parse_query_type(query_type: &str) -> u16 {
+    QUERY_TYPES.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(query_type))
+        .map(|(_, value)| *value)
+        .or_else(|| query_type.strip_prefix("TYPE")?.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Mnemonic of a record type, `TYPE<n>` when it has none (RFC 3597)
+pub(crate) fn query_type_name(query_type: u16) -> String {
+    QUERY_TYPES.iter()
+        .find(|(_, value)| *value == query_type)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("TYPE{}", query_type))
 }
 
 
@@ -98,17 +279,36 @@ pub(super) fn parse_query_type(query_type: &str) -> u16 {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_domain_to_labels_trailing_dot() {
+        assert_eq!(domain_to_labels("example.com."), b"\x07example\x03com\x00".to_vec());
+        assert_eq!(domain_to_labels("example.com"), b"\x07example\x03com\x00".to_vec());
+        assert_eq!(domain_to_labels("."), vec![0]);
+    }
+
+    #[test]
+    fn test_query_type_names() {
+        assert_eq!(parse_query_type("DNSKEY"), 48);
+        assert_eq!(parse_query_type("aaaa"), 28);
+        assert_eq!(parse_query_type("TYPE65"), 65);
+        assert_eq!(parse_query_type("https"), 65);
+        assert_eq!(parse_query_type("BOGUS"), 0);
+        assert_eq!(query_type_name(46), "RRSIG");
+        assert_eq!(query_type_name(64), "SVCB");
+        assert_eq!(query_type_name(65280), "TYPE65280");
+    }
+
     #[test]
     fn test_dns_decompression_0() {
         let no_decompression:Vec<u8> = [15, 97, 108, 116, 101, 114, 45, 115, 111, 108, 117, 116, 105, 111, 110, 115, 2, 100, 101, 0].to_vec();
-        let decompressed = dns_decompression(&no_decompression, 0).1;
+        let decompressed = dns_decompression(&no_decompression, 0).unwrap().1;
         assert_eq!(no_decompression, decompressed);
     }
 
     #[test] 
     fn test_dns_decompression_1() {
         let simple_compression:Vec<u8> = [0, 0, 0, 0, 0, 3, 44, 45, 46, 0, 0, 0, 0, 0, 0, 3, 41, 42, 43, 0b1100_0000, 5].to_vec();
-        let (query_name_end, decompressed) = dns_decompression(&simple_compression, 15);
+        let (query_name_end, decompressed) = dns_decompression(&simple_compression, 15).unwrap();
         assert_eq!(decompressed, [3, 41, 42, 43, 3, 44, 45, 46, 0].to_vec());
         assert_eq!(query_name_end, 20);
     }
@@ -116,7 +316,7 @@ mod tests {
     #[test]
     fn test_dns_decompression_2() {
         let google_answer: Vec<u8> = [29, 221, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 6, 103, 111, 111, 103, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 1, 0, 1, 0, 0, 1, 40, 0, 4, 142, 250, 203, 110, 0].to_vec();
-        let (query_name_end, decompressed) = dns_decompression(&google_answer, 28);
+        let (query_name_end, decompressed) = dns_decompression(&google_answer, 28).unwrap();
         assert_eq!(decompressed, [6, 103, 111, 111, 103, 108, 101, 3, 99, 111, 109, 0].to_vec());
         assert_eq!(query_name_end, 29);
     }
@@ -125,8 +325,57 @@ mod tests {
     fn test_dns_decompression_3() {
         let multiple_decompressions = [3, 41,42,43, 0,0,0,0,0,0, 3, 44, 45, 46, 0b1100_0000, 0, 0,0,0,0, 3, 47, 48, 49, 0b1100_0000, 10, 0,0,0,0, 0b1100_0000, 20].to_vec();
         println!("{}", multiple_decompressions[20]);
-        let (query_name_end, decompressed) = dns_decompression(&multiple_decompressions, 30);
+        let (query_name_end, decompressed) = dns_decompression(&multiple_decompressions, 30).unwrap();
         assert_eq!(decompressed, [3, 47, 48, 49, 3, 44, 45, 46, 3, 41, 42, 43, 0].to_vec());
         assert_eq!(query_name_end, 31);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_decompress_rdata() {
+        // MX record of preference 10 pointing at mail. + the name at offset 0
+        let response: Vec<u8> = [
+            b"\x07example\x03com\x00".to_vec(),
+            [0, 10, 4].to_vec(), b"mail".to_vec(), [0xc0, 0].to_vec(),
+        ].concat();
+        assert_eq!(decompress_rdata(&response, 15, 13, 9), Some([
+            [0, 10, 4].to_vec(), b"mail\x07example\x03com\x00".to_vec(),
+        ].concat()));
+        // Opaque types are copied as is
+        assert_eq!(decompress_rdata(&response, 16, 13, 9), Some(response[13..].to_vec()));
+        assert_eq!(decompress_rdata(&response, 15, 13, 10), None);
+    }
+
+    #[test]
+    fn test_canonical_rdata() {
+        let soa: Vec<u8> = [b"\x02NS\x07Example\x00\x04Host\x00".to_vec(), [0; 20].to_vec()].concat();
+        assert_eq!(canonical_rdata(6, &soa), [b"\x02ns\x07example\x00\x04host\x00".to_vec(), [0; 20].to_vec()].concat());
+        assert_eq!(canonical_rdata(16, b"\x02AB"), b"\x02AB".to_vec());
+    }
+
+    #[test]
+    fn test_dns_decompression_pointer_loop() {
+        let response: Vec<u8> = [1, b'a', 0xc0, 0].to_vec();
+        assert_eq!(dns_decompression(&response, 0), None);
+        // Pointer going forward
+        assert_eq!(dns_decompression(&[0xc0, 2, 1, b'a', 0], 0), None);
+    }
+
+    #[test]
+    fn test_dns_decompression_malformed() {
+        // Label or pointer past the end of the message
+        assert_eq!(dns_decompression(&[3, b'a', b'b'], 0), None);
+        assert_eq!(dns_decompression(&[1, b'a', 0xc0], 0), None);
+        assert_eq!(dns_decompression(&[1, b'a'], 2), None);
+        // Name longer than 255 bytes
+        let long_name: Vec<u8> = [[63; 64].repeat(4), vec![0]].concat();
+        assert_eq!(dns_decompression(&long_name, 0), None);
+    }
+
+    #[test]
+    fn test_rewrite_record_ttls() {
+        let mut google_answer: Vec<u8> = [29, 221, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 6, 103, 111, 111, 103, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 1, 0, 1, 0, 0, 1, 40, 0, 4, 142, 250, 203, 110].to_vec();
+        rewrite_record_ttls(&mut google_answer, |ttl| ttl - 200).unwrap();
+        assert_eq!(google_answer[34..38], [0, 0, 0, 96]);
+        assert!(rewrite_record_ttls(&mut google_answer[..40], |ttl| ttl).is_none());
+    }
+}
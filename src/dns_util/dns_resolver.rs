@@ -0,0 +1,569 @@
+pub mod resolv_conf;
+pub mod hosts_file;
+mod search_list;
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::dns_util::dns_cache::{DNSCache, Freshness};
+use crate::dns_util::dns_packet_structures::{dns_packet::DNSPacket, dns_resource_record::DNSResourceRecord, domain_name::DomainName};
+use crate::dns_util::dns_transports::{tcp::TCPTransport, udp::UDPTransport};
+#[cfg(feature = "tokio")]
+use crate::dns_util::dns_transports::asynchronous::{AsyncTCPTransport, AsyncUDPTransport};
+use hosts_file::{HostsFileCache, HOSTS_PATH};
+
+/// Order in which the nameservers are tried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerSelection {
+    /// Always start with the first nameserver, the others are fallbacks
+    Sequential,
+    /// Start each query with the server following the one used to start the
+    /// previous query, spreading the load
+    Rotate,
+}
+
+/// Source of host names and addresses, as in the `hosts` line of
+/// nsswitch.conf(5)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupSource {
+    /// The hosts file
+    Files,
+    /// The nameservers
+    Dns,
+}
+
+/// Retry and failover policy of a `Resolver`
+#[derive(Debug, Clone)]
+pub struct ResolverConfig {
+    /// Servers to query, in order of preference
+    pub nameservers: Vec<SocketAddr>,
+    /// Time given to a server to answer a single attempt
+    pub timeout: Duration,
+    /// Time after which the query fails, whatever the attempts left
+    pub deadline: Duration,
+    /// Number of rounds over the whole list of nameservers
+    pub attempts: usize,
+    /// Pause after the first round failed, doubled after each round
+    pub backoff: Duration,
+    /// Upper bound of the pause between rounds
+    pub max_backoff: Duration,
+    pub selection: ServerSelection,
+    /// Domains appended to names that are not fully qualified
+    pub search: Vec<String>,
+    /// Number of dots from which a name is tried as is before the search
+    /// list
+    pub ndots: usize,
+    /// Advertise a larger UDP payload with an EDNS OPT record
+    pub edns0: bool,
+    /// Ask for the DNSSEC records with the DO bit of EDNS (RFC 3225), which
+    /// implies `edns0`
+    pub dnssec_ok: bool,
+    /// Query over TCP instead of UDP
+    pub use_tcp: bool,
+    /// Local address to query from. Only used for the nameservers of the
+    /// same family.
+    pub source_address: Option<IpAddr>,
+    /// Network interface to query through (Linux only)
+    pub source_interface: Option<String>,
+    /// Number of CNAME and DNAME links followed before giving up
+    pub max_chain_length: usize,
+    /// Static table of host names, read again when it changes
+    pub hosts_file: Option<PathBuf>,
+    /// Sources of the address and reverse lookups, tried in order until
+    /// one knows the name
+    pub lookup_order: Vec<LookupSource>,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            nameservers: vec![SocketAddr::from(([1, 1, 1, 1], 53))],
+            timeout: Duration::new(5, 0),
+            deadline: Duration::new(30, 0),
+            attempts: 2,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::new(2, 0),
+            selection: ServerSelection::Sequential,
+            search: Vec::new(),
+            ndots: 1,
+            edns0: false,
+            dnssec_ok: false,
+            use_tcp: false,
+            source_address: None,
+            source_interface: None,
+            max_chain_length: 16,
+            hosts_file: Some(PathBuf::from(HOSTS_PATH)),
+            lookup_order: vec![LookupSource::Files, LookupSource::Dns],
+        }
+    }
+}
+
+/// Sends queries to the nameservers of a `ResolverConfig`, moving on to the
+/// next server when one does not answer or reports a failure.
+pub struct Resolver {
+    pub config: ResolverConfig,
+    /// Index of the server starting the next query, for `ServerSelection::Rotate`
+    next_server: AtomicUsize,
+    hosts: HostsFileCache,
+    /// Answers kept between queries, can be shared between resolvers
+    pub cache: Option<Arc<DNSCache>>,
+}
+
+impl Resolver {
+    pub fn new(config: ResolverConfig) -> Self {
+        Self {
+            config,
+            next_server: AtomicUsize::new(0),
+            hosts: HostsFileCache::default(),
+            cache: None,
+        }
+    }
+
+    /// Resolver answering from `cache` when it can, and filling it with the
+    /// responses of the nameservers
+    pub fn with_cache(config: ResolverConfig, cache: Arc<DNSCache>) -> Self {
+        Self {
+            cache: Some(cache),
+            ..Self::new(config)
+        }
+    }
+
+    /// Queries `domain` for records of `query_type`. A response is accepted
+    /// unless the server reports a server failure or a refusal, NXDOMAIN
+    /// being a valid answer. Truncated UDP responses are retried over TCP.
+    ///
+    /// With a cache, cached answers are returned without querying, popular
+    /// ones being refreshed in the background before they expire. When no
+    /// nameserver answers, a stale answer is returned if the cache still
+    /// has one (RFC 8767). A name that cannot be sent in a question gives
+    /// an `InvalidInput` error.
+    pub fn query(&self, domain: &str, query_type: &str) -> io::Result<DNSPacket> {
+        check_name(domain)?;
+        let Some(cache) = &self.cache else {
+            return self.query_nameservers(domain, query_type);
+        };
+        match cache.lookup_with_freshness(domain, query_type) {
+            Some((response, Freshness::Prefetch)) => {
+                self.prefetch(domain, query_type, cache.clone());
+                return Ok(response);
+            }
+            Some((response, _)) => return Ok(response),
+            None => (),
+        }
+        match self.query_nameservers(domain, query_type) {
+            Ok(response) => {
+                cache.insert(&response);
+                Ok(response)
+            }
+            Err(e) => cache.lookup_stale(domain, query_type).ok_or(e),
+        }
+    }
+
+    /// Queries the nameservers again on the prefetch worker of `cache` and
+    /// updates it with the response
+    fn prefetch(&self, domain: &str, query_type: &str, cache: Arc<DNSCache>) {
+        let resolver = Resolver::new(self.config.clone());
+        let (job_domain, job_query_type) = (domain.to_string(), query_type.to_string());
+        let job_cache = cache.clone();
+        let job = Box::new(move || {
+            if let Ok(response) = resolver.query_nameservers(&job_domain, &job_query_type) {
+                job_cache.insert(&response);
+            }
+            job_cache.finish_prefetch(&job_domain, &job_query_type);
+        });
+        if !cache.schedule_prefetch(job) {
+            cache.finish_prefetch(domain, query_type);
+        }
+    }
+
+    fn query_nameservers(&self, domain: &str, query_type: &str) -> io::Result<DNSPacket> {
+        let mut last_error = io::Error::new(io::ErrorKind::TimedOut, "no attempt made before the deadline");
+        for step in self.schedule()? {
+            match step {
+                Step::Wait(duration) => thread::sleep(duration),
+                Step::Query(server, timeout) => match self.query_server(domain, query_type, server, timeout) {
+                    Ok(response) => return Ok(response),
+                    Err(e) => last_error = e,
+                },
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Schedule of the queries of one resolution, starting with the next
+    /// server when the nameservers are rotated
+    fn schedule(&self) -> io::Result<Schedule<'_>> {
+        let servers_count = self.config.nameservers.len();
+        if servers_count == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no nameserver configured"));
+        }
+        let first_server = match self.config.selection {
+            ServerSelection::Sequential => 0,
+            ServerSelection::Rotate => self.next_server.fetch_add(1, Ordering::Relaxed) % servers_count,
+        };
+        Ok(Schedule {
+            config: &self.config,
+            start: Instant::now(),
+            first_server,
+            attempt: 0,
+            offset: 0,
+            backoff: self.config.backoff,
+        })
+    }
+
+    /// Query for `domain` with the EDNS options of the configuration
+    fn build_query(&self, domain: &str, query_type: &str) -> DNSPacket {
+        let mut query = DNSPacket::create_query_packet(vec![domain], query_type);
+        if self.config.edns0 || self.config.dnssec_ok {
+            let mut opt = DNSResourceRecord::create_opt_record(1232);
+            if self.config.dnssec_ok {
+                // DO bit, in the flags held by the TTL field
+                opt.record_ttl = 0x8000;
+            }
+            query.additional_records.push(opt);
+            query.header.additional_count = 1;
+        }
+        query
+    }
+
+    fn query_server(&self, domain: &str, query_type: &str, server: SocketAddr, timeout: Duration) -> io::Result<DNSPacket> {
+        let query = self.build_query(domain, query_type);
+        let source_address = self.config.source_address
+            .filter(|address| address.is_ipv4() == server.is_ipv4());
+        let mut tcp_transport = TCPTransport::new(server);
+        tcp_transport.timeout = timeout;
+        tcp_transport.source_address = source_address;
+        tcp_transport.source_interface = self.config.source_interface.clone();
+        let response = if self.config.use_tcp {
+            crate::send_dns_query(&query, &tcp_transport)?
+        } else {
+            let mut udp_transport = UDPTransport::new(server);
+            udp_transport.timeout = timeout;
+            udp_transport.source_address = source_address;
+            udp_transport.source_interface = self.config.source_interface.clone();
+            let response = crate::send_dns_query(&query, &udp_transport)?;
+            // Truncation flag
+            if response.header.flags >> 9 & 1 == 1 {
+                crate::send_dns_query(&query, &tcp_transport)?
+            } else {
+                response
+            }
+        };
+
+        check_response_code(response, server)
+    }
+
+    /// Asynchronous version of `query`, without the cache. The nameservers
+    /// are tried with the same attempts, backoff and deadline.
+    #[cfg(feature = "tokio")]
+    pub async fn query_async(&self, domain: &str, query_type: &str) -> io::Result<DNSPacket> {
+        check_name(domain)?;
+        let mut last_error = io::Error::new(io::ErrorKind::TimedOut, "no attempt made before the deadline");
+        for step in self.schedule()? {
+            match step {
+                Step::Wait(duration) => tokio::time::sleep(duration).await,
+                Step::Query(server, timeout) => match self.query_server_async(domain, query_type, server, timeout).await {
+                    Ok(response) => return Ok(response),
+                    Err(e) => last_error = e,
+                },
+            }
+        }
+        Err(last_error)
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn query_server_async(&self, domain: &str, query_type: &str, server: SocketAddr, timeout: Duration) -> io::Result<DNSPacket> {
+        let query = self.build_query(domain, query_type);
+        let source_address = self.config.source_address
+            .filter(|address| address.is_ipv4() == server.is_ipv4());
+        let mut tcp_transport = AsyncTCPTransport::new(server);
+        tcp_transport.timeout = timeout;
+        tcp_transport.source_address = source_address;
+        tcp_transport.source_interface = self.config.source_interface.clone();
+        let response = if self.config.use_tcp {
+            crate::send_dns_query_async(&query, &tcp_transport).await?
+        } else {
+            let mut udp_transport = AsyncUDPTransport::new(server);
+            udp_transport.timeout = timeout;
+            udp_transport.source_address = source_address;
+            udp_transport.source_interface = self.config.source_interface.clone();
+            let response = crate::send_dns_query_async(&query, &udp_transport).await?;
+            // Truncation flag
+            if response.header.flags >> 9 & 1 == 1 {
+                crate::send_dns_query_async(&query, &tcp_transport).await?
+            } else {
+                response
+            }
+        };
+        check_response_code(response, server)
+    }
+}
+
+/// Step of the resolution of a query
+enum Step {
+    /// Pause before the next round over the nameservers
+    Wait(Duration),
+    /// Query of a nameserver, with the time left to it
+    Query(SocketAddr, Duration),
+}
+
+/// Rounds over the nameservers of a configuration, with the backoff between
+/// the rounds. Ends when the attempts are exhausted or the deadline passed.
+struct Schedule<'a> {
+    config: &'a ResolverConfig,
+    start: Instant,
+    first_server: usize,
+    attempt: usize,
+    /// Index of the next server in the current round
+    offset: usize,
+    backoff: Duration,
+}
+
+impl Iterator for Schedule<'_> {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        let servers_count = self.config.nameservers.len();
+        if self.attempt >= self.config.attempts {
+            return None;
+        }
+        let remaining = self.config.deadline.saturating_sub(self.start.elapsed());
+        if self.offset == servers_count {
+            self.attempt += 1;
+            self.offset = 0;
+            if self.attempt >= self.config.attempts {
+                return None;
+            }
+            let pause = self.backoff.min(remaining);
+            self.backoff = (self.backoff * 2).min(self.config.max_backoff);
+            return Some(Step::Wait(pause));
+        }
+        if remaining.is_zero() {
+            return None;
+        }
+        let server = self.config.nameservers[(self.first_server + self.offset) % servers_count];
+        self.offset += 1;
+        Some(Step::Query(server, self.config.timeout.min(remaining)))
+    }
+}
+
+/// Error unless `domain` can be sent in a question
+fn check_name(domain: &str) -> io::Result<()> {
+    domain.parse::<DomainName>()
+        .map(|_| ())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Error for the responses reporting a server failure or a refusal
+fn check_response_code(response: DNSPacket, server: SocketAddr) -> io::Result<DNSPacket> {
+    match response.header.flags & 0b1111 {
+        2 => Err(io::Error::other(format!("server failure from {}", server))),
+        5 => Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("query refused by {}", server))),
+        _ => Ok(response),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use std::sync::mpsc;
+    use crate::dns_util::test_server::{self, Answer};
+
+    /// Starts a UDP server answering every query with the query itself
+    /// flagged as a response with the given response code. Each query is
+    /// reported through the channel.
+    pub(crate) fn start_udp_server(response_code: u8) -> (SocketAddr, mpsc::Receiver<Vec<u8>>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf: [u8; 512] = [0; 512];
+            while let Ok((length, client)) = socket.recv_from(&mut buf) {
+                let mut message = buf[..length].to_vec();
+                message[2] |= 0b1000_0000;
+                message[3] |= response_code;
+                if sender.send(buf[..length].to_vec()).is_err() {
+                    break;
+                }
+                let _ = socket.send_to(&message, client);
+            }
+        });
+        (address, receiver)
+    }
+
+    /// Address of a UDP socket that never answers
+    pub(crate) fn silent_server() -> (SocketAddr, UdpSocket) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        (socket.local_addr().unwrap(), socket)
+    }
+
+    fn config(nameservers: Vec<SocketAddr>) -> ResolverConfig {
+        ResolverConfig {
+            nameservers,
+            timeout: Duration::from_millis(100),
+            backoff: Duration::from_millis(10),
+            ..ResolverConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_failover_to_second_server() {
+        let (silent, _socket) = silent_server();
+        let (live, queries) = start_udp_server(0);
+        let resolver = Resolver::new(config(vec![silent, live]));
+        let response = resolver.query("example.com", "A").unwrap();
+        assert_eq!(response.header.flags >> 15, 1);
+        assert_eq!(queries.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_server_failure_is_retried() {
+        let (failing, failing_queries) = start_udp_server(2);
+        let (live, _queries) = start_udp_server(3);
+        let resolver = Resolver::new(config(vec![failing, live]));
+        let response = resolver.query("example.com", "A").unwrap();
+        // NXDOMAIN is an answer, not a failure
+        assert_eq!(response.header.flags & 0b1111, 3);
+        assert_eq!(failing_queries.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_attempts_and_deadline() {
+        let (failing, queries) = start_udp_server(2);
+        let resolver = Resolver::new(config(vec![failing]));
+        assert!(resolver.query("example.com", "A").is_err());
+        assert_eq!(queries.try_iter().count(), 2);
+
+        let (silent, _socket) = silent_server();
+        let mut config = config(vec![silent]);
+        config.attempts = 10;
+        config.deadline = Duration::from_millis(250);
+        let start = Instant::now();
+        assert!(Resolver::new(config).query("example.com", "A").is_err());
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_schedule() {
+        let (first, second) = (SocketAddr::from(([192, 0, 2, 1], 53)), SocketAddr::from(([192, 0, 2, 2], 53)));
+        let mut config = config(vec![first, second]);
+        config.backoff = Duration::from_millis(300);
+        config.max_backoff = Duration::from_millis(500);
+        config.attempts = 3;
+        config.selection = ServerSelection::Rotate;
+        let mut resolver = Resolver::new(config);
+        // Rotation starts the next schedule with the second server
+        resolver.schedule().unwrap();
+        let steps: Vec<String> = resolver.schedule().unwrap()
+            .map(|step| match step {
+                Step::Wait(duration) => format!("wait {}", duration.as_millis()),
+                Step::Query(server, _) => server.ip().to_string(),
+            })
+            .collect();
+        assert_eq!(steps, ["192.0.2.2", "192.0.2.1", "wait 300", "192.0.2.2", "192.0.2.1", "wait 500", "192.0.2.2", "192.0.2.1"]);
+
+        resolver.config.nameservers.clear();
+        assert_eq!(resolver.schedule().err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_edns0() {
+        let (server, queries) = start_udp_server(0);
+        let mut config = config(vec![server]);
+        config.edns0 = true;
+        let response = Resolver::new(config).query("example.com", "A").unwrap();
+        assert_eq!(response.additional_records[0].query_type, 41);
+        let query = queries.recv().unwrap();
+        assert_eq!(query[10..12], [0, 1]);
+        assert_eq!(query[query.len() - 11..], [0, 0, 41, 4, 208, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_invalid_name() {
+        let (server, queries) = start_udp_server(0);
+        let resolver = Resolver::new(config(vec![server]));
+        assert_eq!(resolver.query(&"a".repeat(300), "A").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(resolver.query(&format!("{}.example", "a".repeat(70)), "A").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(queries.try_iter().count(), 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_failover_async() {
+        let (silent, _socket) = silent_server();
+        let (failing, failing_queries) = start_udp_server(2);
+        let (live, queries) = start_udp_server(0);
+        let resolver = Resolver::new(config(vec![silent, failing, live]));
+        let response = resolver.query_async("example.com", "A").await.unwrap();
+        assert_eq!(response.header.flags >> 15, 1);
+        assert_eq!((failing_queries.try_iter().count(), queries.try_iter().count()), (1, 1));
+        assert!(resolver.query_async(&"a".repeat(300), "A").await.is_err());
+    }
+
+    #[test]
+    fn test_rotate() {
+        let (first, first_queries) = start_udp_server(0);
+        let (second, second_queries) = start_udp_server(0);
+        let mut config = config(vec![first, second]);
+        config.selection = ServerSelection::Rotate;
+        let resolver = Resolver::new(config);
+        for _i in 0..4 {
+            resolver.query("example.com", "A").unwrap();
+        }
+        assert_eq!(first_queries.try_iter().count(), 2);
+        assert_eq!(second_queries.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn test_cache() {
+        let (sender, queries) = mpsc::channel();
+        let server = test_server::start_server(move |query| {
+            let _ = sender.send(());
+            Answer::records(vec![test_server::record(&query.name, 1, vec![192, 0, 2, 1])])
+        });
+
+        let cache = Arc::new(DNSCache::default());
+        let resolver = Resolver::with_cache(config(vec![server]), cache.clone());
+        resolver.query("example.com", "A").unwrap();
+        let response = resolver.query("example.com", "A").unwrap();
+        assert_eq!(response.resource_records[0].rdata, vec![192, 0, 2, 1]);
+        assert!(response.resource_records[0].record_ttl <= 60);
+        assert_eq!(queries.try_iter().count(), 1);
+
+        // The cache is shared with other resolvers
+        Resolver::with_cache(config(vec![server]), cache).query("example.com", "A").unwrap();
+        assert_eq!(queries.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_serve_stale() {
+        let (silent, _socket) = silent_server();
+        let mut cache = DNSCache::default();
+        cache.stale_window = 3600;
+        // Record already expired
+        let mut response = DNSPacket::create_query_packet(vec!["example.com"], "A");
+        response.header.flags |= 0b1000_0000_0000_0000;
+        response.resource_records.push(DNSResourceRecord {
+            query_name: b"\x07example\x03com\x00".to_vec(),
+            query_type: 1,
+            query_class: 1,
+            record_ttl: 1,
+            rdata_length: 4,
+            rdata: vec![192, 0, 2, 1],
+        });
+        cache.insert(&response);
+        thread::sleep(Duration::from_millis(1100));
+
+        let resolver = Resolver::with_cache(config(vec![silent]), Arc::new(cache));
+        let response = resolver.query("example.com", "A").unwrap();
+        assert_eq!(response.resource_records[0].rdata, vec![192, 0, 2, 1]);
+        assert_eq!(response.resource_records[0].record_ttl, 30);
+        assert!(resolver.query("example.org", "A").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use super::Resolver;
+
+/// Location of the system hosts file
+pub const HOSTS_PATH: &str = "/etc/hosts";
+
+/// Static table of host names, in the hosts(5) format
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostsFile {
+    /// Addresses with their canonical name followed by their aliases, in
+    /// the order of the file
+    pub entries: Vec<(IpAddr, Vec<DomainName>)>,
+}
+
+impl HostsFile {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parses lines made of an address followed by names, `#` starting a
+    /// comment. Lines with an invalid address are ignored, as are invalid
+    /// names. The zone of scoped IPv6 addresses is dropped.
+    pub fn parse(contents: &str) -> Self {
+        let entries = contents.lines()
+            .filter_map(|line| {
+                let mut words = line.split('#').next().unwrap_or("").split_whitespace();
+                let address: IpAddr = words.next()?.split('%').next()?.parse().ok()?;
+                let names: Vec<DomainName> = words.filter_map(|name| name.parse().ok()).collect();
+                if names.is_empty() {
+                    return None;
+                }
+                Some((address, names))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Addresses of `name`, in the order of the file
+    pub fn addresses(&self, name: &DomainName) -> Vec<IpAddr> {
+        let mut addresses: Vec<IpAddr> = Vec::new();
+        for (address, names) in &self.entries {
+            if names.contains(name) && !addresses.contains(address) {
+                addresses.push(*address);
+            }
+        }
+        addresses
+    }
+
+    /// Names of `address`, canonical names first
+    pub fn names(&self, address: IpAddr) -> Vec<DomainName> {
+        let mut found: Vec<DomainName> = Vec::new();
+        for (_, names) in self.entries.iter().filter(|(candidate, _)| *candidate == address) {
+            for name in names {
+                if !found.contains(name) {
+                    found.push(name.clone());
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Hosts file read on first use and read again whenever its modification
+/// time changes
+#[derive(Debug, Default)]
+pub(crate) struct HostsFileCache {
+    loaded: Mutex<Option<(PathBuf, Option<SystemTime>, HostsFile)>>,
+}
+
+impl HostsFileCache {
+    /// Calls `f` with the current content of the file at `path`, an
+    /// unreadable file counting as empty
+    pub(crate) fn with<R>(&self, path: &Path, f: impl FnOnce(&HostsFile) -> R) -> R {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        let up_to_date = matches!(&*loaded, Some((loaded_path, loaded_modified, _))
+            if loaded_path == path && *loaded_modified == modified && modified.is_some());
+        if !up_to_date {
+            let hosts = HostsFile::from_file(path).unwrap_or_default();
+            *loaded = Some((path.to_path_buf(), modified, hosts));
+        }
+        match &*loaded {
+            Some((_, _, hosts)) => f(hosts),
+            None => f(&HostsFile::default()),
+        }
+    }
+}
+
+impl Resolver {
+    /// Addresses of `domain` in the hosts file of the configuration. The
+    /// name is looked up as given, without the search list.
+    pub(crate) fn hosts_addresses(&self, domain: &str) -> Vec<IpAddr> {
+        match (&self.config.hosts_file, domain.parse::<DomainName>()) {
+            (Some(path), Ok(name)) => self.hosts.with(path, |hosts| hosts.addresses(&name)),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Names of `address` in the hosts file of the configuration
+    pub(crate) fn hosts_names(&self, address: IpAddr) -> Vec<DomainName> {
+        match &self.config.hosts_file {
+            Some(path) => self.hosts.with(path, |hosts| hosts.names(address)),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse() {
+        let hosts = HostsFile::parse("\
+# Static table
+127.0.0.1   localhost
+::1         localhost ip6-localhost   # loopback
+192.0.2.10  db01.corp.example db01
+fe80::1%eth0 router
+not-an-address name
+192.0.2.11
+192.0.2.12  DB01.corp.example
+");
+        assert_eq!(hosts.entries.len(), 5);
+        assert_eq!(hosts.addresses(&"localhost".parse().unwrap()), vec![
+            "127.0.0.1".parse::<IpAddr>().unwrap(),
+            "::1".parse().unwrap(),
+        ]);
+        assert_eq!(hosts.addresses(&"db01.corp.example.".parse().unwrap()), vec![
+            "192.0.2.10".parse::<IpAddr>().unwrap(),
+            "192.0.2.12".parse().unwrap(),
+        ]);
+        assert_eq!(hosts.addresses(&"router".parse().unwrap()), vec!["fe80::1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(hosts.names("192.0.2.10".parse().unwrap()), vec![
+            "db01.corp.example".parse().unwrap(),
+            "db01".parse().unwrap(),
+        ]);
+        assert!(hosts.names("192.0.2.11".parse().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_reload_on_change() {
+        let path = std::env::temp_dir().join(format!("r-dns-hosts-{}", std::process::id()));
+        fs::write(&path, "192.0.2.1 first\n").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1)).unwrap();
+
+        let cache = HostsFileCache::default();
+        let name: DomainName = "first".parse().unwrap();
+        assert_eq!(cache.with(&path, |hosts| hosts.addresses(&name)).len(), 1);
+
+        fs::write(&path, "192.0.2.2 second\n").unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(2)).unwrap();
+        assert!(cache.with(&path, |hosts| hosts.addresses(&name)).is_empty());
+
+        fs::remove_file(&path).unwrap();
+        assert!(cache.with(&path, |hosts| hosts.entries.is_empty()));
+    }
+}
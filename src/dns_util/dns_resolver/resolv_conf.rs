@@ -0,0 +1,136 @@
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::dns_util::dns_transports::ToNameServer;
+use super::{ResolverConfig, ServerSelection};
+
+/// Location of the system resolver configuration
+pub const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+impl ResolverConfig {
+    /// Configuration of the system, read from `/etc/resolv.conf`. Falls back
+    /// to `ResolverConfig::default()` when the file cannot be read.
+    pub fn system() -> Self {
+        Self::from_resolv_conf_file(RESOLV_CONF_PATH).unwrap_or_default()
+    }
+
+    /// Reads a configuration file in the resolv.conf(5) format
+    pub fn from_resolv_conf_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_resolv_conf(&fs::read_to_string(path)?))
+    }
+
+    /// Parses a configuration in the resolv.conf(5) format. Unknown keywords
+    /// and malformed values are ignored, as the C library does. Without any
+    /// `nameserver` line the local host is queried.
+    pub fn from_resolv_conf(contents: &str) -> Self {
+        let mut config = Self {
+            nameservers: Vec::new(),
+            ..Self::default()
+        };
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => {
+                    // Addresses only, the C library does not resolve names
+                    // here, but scoped IPv6 addresses are accepted
+                    let address = words.next()
+                        .filter(|address| address.split('%').next().unwrap_or("").parse::<IpAddr>().is_ok())
+                        .and_then(|address| address.to_name_server().ok());
+                    if let Some(address) = address {
+                        config.nameservers.push(address);
+                    }
+                }
+                // `domain` and `search` override each other, the last one wins
+                Some("domain") => {
+                    config.search = words.next().map(|domain| vec![domain.to_string()]).unwrap_or_default();
+                }
+                Some("search") => {
+                    config.search = words.map(|domain| domain.to_string()).collect();
+                }
+                Some("options") => {
+                    for option in words {
+                        config.apply_resolv_conf_option(option);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if config.nameservers.is_empty() {
+            config.nameservers.push(SocketAddr::from(([127, 0, 0, 1], 53)));
+        }
+        // The C library has no overall deadline, every attempt is allowed
+        // to run to its timeout
+        config.deadline = config.timeout * (config.attempts * config.nameservers.len()) as u32;
+        config
+    }
+
+    fn apply_resolv_conf_option(&mut self, option: &str) {
+        let (name, value) = match option.split_once(':') {
+            Some((name, value)) => (name, value.parse::<u32>().ok()),
+            None => (option, None),
+        };
+        match (name, value) {
+            ("ndots", Some(ndots)) => self.ndots = ndots.min(15) as usize,
+            ("timeout", Some(timeout)) => self.timeout = Duration::from_secs(timeout.clamp(1, 30) as u64),
+            ("attempts", Some(attempts)) => self.attempts = attempts.clamp(1, 5) as usize,
+            ("rotate", None) => self.selection = ServerSelection::Rotate,
+            ("edns0", None) => self.edns0 = true,
+            ("use-vc", None) => self.use_tcp = true,
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_resolv_conf() {
+        let config = ResolverConfig::from_resolv_conf("\
+# Generated by NetworkManager
+domain ignored.example
+search corp.example lab.example
+nameserver 10.0.0.53
+; comment
+nameserver 2001:db8::53
+nameserver not-an-address
+nameserver fe80::1%1
+options ndots:2 timeout:3 attempts:4 rotate edns0 use-vc unknown:1
+");
+        assert_eq!(config.nameservers, vec![
+            "10.0.0.53:53".parse().unwrap(),
+            "[2001:db8::53]:53".parse().unwrap(),
+            "[fe80::1%1]:53".parse().unwrap(),
+        ]);
+        assert_eq!(config.search, vec!["corp.example".to_string(), "lab.example".to_string()]);
+        assert_eq!(config.ndots, 2);
+        assert_eq!(config.timeout, Duration::from_secs(3));
+        assert_eq!(config.attempts, 4);
+        assert_eq!(config.deadline, Duration::from_secs(36));
+        assert_eq!(config.selection, ServerSelection::Rotate);
+        assert!(config.edns0);
+        assert!(config.use_tcp);
+    }
+
+    #[test]
+    fn test_from_resolv_conf_defaults() {
+        let config = ResolverConfig::from_resolv_conf("search first.example\ndomain last.example\noptions ndots:40 attempts:0\n");
+        assert_eq!(config.nameservers, vec!["127.0.0.1:53".parse().unwrap()]);
+        assert_eq!(config.search, vec!["last.example".to_string()]);
+        assert_eq!(config.ndots, 15);
+        assert_eq!(config.attempts, 1);
+        assert_eq!(config.timeout, Duration::from_secs(5));
+        assert_eq!(config.selection, ServerSelection::Sequential);
+        assert!(!config.edns0);
+    }
+}
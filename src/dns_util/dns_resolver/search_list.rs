@@ -0,0 +1,47 @@
+use super::ResolverConfig;
+
+impl ResolverConfig {
+    /// Names to query for `domain`, in order, following the search
+    /// algorithm of resolv.conf(5). A name ending with a dot is fully
+    /// qualified and queried alone. Otherwise a name with at least `ndots`
+    /// dots is tried as is before the `search` domains, and a shorter one
+    /// after them.
+    pub fn qualified_names(&self, domain: &str) -> Vec<String> {
+        if let Some(absolute) = domain.strip_suffix('.') {
+            return vec![absolute.to_string()];
+        }
+        let searched = self.search.iter()
+            .map(|suffix| suffix.trim_end_matches('.'))
+            .filter(|suffix| !suffix.is_empty())
+            .map(|suffix| format!("{}.{}", domain, suffix));
+
+        let mut names: Vec<String> = Vec::new();
+        if domain.matches('.').count() >= self.ndots {
+            names.push(domain.to_string());
+            names.extend(searched);
+        } else {
+            names.extend(searched);
+            names.push(domain.to_string());
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qualified_names() {
+        let config = ResolverConfig {
+            search: vec!["corp.example".to_string(), "lab.example.".to_string()],
+            ndots: 2,
+            ..ResolverConfig::default()
+        };
+        assert_eq!(config.qualified_names("db01"), vec!["db01.corp.example", "db01.lab.example", "db01"]);
+        assert_eq!(config.qualified_names("db01.eu"), vec!["db01.eu.corp.example", "db01.eu.lab.example", "db01.eu"]);
+        assert_eq!(config.qualified_names("www.example.com"), vec!["www.example.com", "www.example.com.corp.example", "www.example.com.lab.example"]);
+        assert_eq!(config.qualified_names("db01."), vec!["db01"]);
+        assert_eq!(ResolverConfig::default().qualified_names("localhost"), vec!["localhost"]);
+    }
+}
@@ -0,0 +1,207 @@
+pub mod udp;
+pub mod tcp;
+pub mod tls;
+pub mod https;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+#[cfg(feature = "quic")]
+pub mod quic;
+
+use std::ffi::CString;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs};
+
+use socket2::{Domain, Socket, Type};
+
+/// Carries a prepared DNS message to a server and brings back the answer.
+/// Implementors only move bytes around, the packet structures are built and
+/// parsed by the caller.
+pub trait DNSTransport {
+    /// Sends the wire format of `query` and returns the wire format of the
+    /// response
+    fn exchange(&self, query: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// Port of plain DNS
+pub const DNS_PORT: u16 = 53;
+
+/// Conversion into the socket address of a nameserver, port 53 being used
+/// when none is given
+pub trait ToNameServer {
+    fn to_name_server(&self) -> io::Result<SocketAddr>;
+
+    /// Hostname `to_name_server` would resolve, for callers that cannot
+    /// block on the resolver of the system
+    fn host_name(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl ToNameServer for SocketAddr {
+    fn to_name_server(&self) -> io::Result<SocketAddr> {
+        Ok(*self)
+    }
+}
+
+impl ToNameServer for IpAddr {
+    fn to_name_server(&self) -> io::Result<SocketAddr> {
+        Ok(SocketAddr::new(*self, DNS_PORT))
+    }
+}
+
+impl ToNameServer for Ipv4Addr {
+    fn to_name_server(&self) -> io::Result<SocketAddr> {
+        Ok(SocketAddr::from((*self, DNS_PORT)))
+    }
+}
+
+impl ToNameServer for Ipv6Addr {
+    fn to_name_server(&self) -> io::Result<SocketAddr> {
+        Ok(SocketAddr::from((*self, DNS_PORT)))
+    }
+}
+
+/// Accepts `192.0.2.1`, `192.0.2.1:5353`, `2001:db8::1`, `[2001:db8::1]:5353`
+/// and scoped link-local addresses such as `fe80::1%eth0` or
+/// `[fe80::1%2]:53`. Anything else is resolved as a hostname by the system.
+impl ToNameServer for str {
+    fn to_name_server(&self) -> io::Result<SocketAddr> {
+        if let Some(address) = parse_name_server(self) {
+            return Ok(address);
+        }
+        (self, DNS_PORT).to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("couldn't resolve the server address {}", self)))
+    }
+
+    fn host_name(&self) -> Option<&str> {
+        parse_name_server(self).is_none().then_some(self)
+    }
+}
+
+impl ToNameServer for String {
+    fn to_name_server(&self) -> io::Result<SocketAddr> {
+        self.as_str().to_name_server()
+    }
+
+    fn host_name(&self) -> Option<&str> {
+        self.as_str().host_name()
+    }
+}
+
+impl<T: ToNameServer + ?Sized> ToNameServer for &T {
+    fn to_name_server(&self) -> io::Result<SocketAddr> {
+        (**self).to_name_server()
+    }
+
+    fn host_name(&self) -> Option<&str> {
+        (**self).host_name()
+    }
+}
+
+fn parse_name_server(server: &str) -> Option<SocketAddr> {
+    let (host, port) = match server.strip_prefix('[') {
+        Some(rest) => {
+            let (host, port) = rest.split_once(']')?;
+            match port {
+                "" => (host, DNS_PORT),
+                port => (host, port.strip_prefix(':')?.parse().ok()?),
+            }
+        }
+        None => match server.split_once(':') {
+            // A single colon separates an IPv4 address from its port
+            Some((host, port)) if !port.contains(':') => (host, port.parse().ok()?),
+            _ => (server, DNS_PORT),
+        },
+    };
+    let (address, zone) = match host.split_once('%') {
+        Some((address, zone)) => (address, Some(zone)),
+        None => (host, None),
+    };
+    match (address.parse::<IpAddr>().ok()?, zone) {
+        (address, None) => Some(SocketAddr::new(address, port)),
+        (IpAddr::V6(address), Some(zone)) => {
+            Some(SocketAddrV6::new(address, port, 0, interface_index(zone)?).into())
+        }
+        (IpAddr::V4(_), Some(_)) => None,
+    }
+}
+
+/// Index of a network interface, given by name or directly by number
+fn interface_index(interface: &str) -> Option<u32> {
+    if let Ok(index) = interface.parse::<u32>() {
+        return Some(index);
+    }
+    let name = CString::new(interface).ok()?;
+    // SAFETY: `name` is a valid NUL-terminated string
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => None,
+        index => Some(index),
+    }
+}
+
+/// Creates a socket of the family of `server`, bound to `source_address` if
+/// given (any address of the family otherwise) and to `source_interface`.
+/// Binding to an interface is only supported on Linux and Android.
+pub(crate) fn bound_socket(
+    server: &SocketAddr,
+    socket_type: Type,
+    source_address: Option<IpAddr>,
+    source_interface: Option<&str>,
+) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(*server), socket_type, None)?;
+    if let Some(interface) = source_interface {
+        bind_device(&socket, interface)?;
+    }
+    let source_address = match (source_address, server) {
+        (None, SocketAddr::V4(_)) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        (None, SocketAddr::V6(_)) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        (Some(address), _) if address.is_ipv4() == server.is_ipv4() => address,
+        (Some(address), _) => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("source address {} cannot reach {}", address, server)));
+        }
+    };
+    // Port 0 lets the system pick a random ephemeral port
+    socket.bind(&SocketAddr::new(source_address, 0).into())?;
+    Ok(socket)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(socket: &Socket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_device(_socket: &Socket, interface: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, format!("cannot bind to interface {}", interface)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_server_formats() {
+        assert_eq!("192.0.2.1".to_name_server().unwrap(), "192.0.2.1:53".parse().unwrap());
+        assert_eq!("192.0.2.1:5353".to_name_server().unwrap(), "192.0.2.1:5353".parse().unwrap());
+        assert_eq!("2606:4700:4700::1111".to_name_server().unwrap(), "[2606:4700:4700::1111]:53".parse().unwrap());
+        assert_eq!("[2001:db8::1]:5353".to_name_server().unwrap(), "[2001:db8::1]:5353".parse().unwrap());
+        assert_eq!("[2001:db8::1]".to_name_server().unwrap(), "[2001:db8::1]:53".parse().unwrap());
+        assert_eq!("fe80::1%3".to_name_server().unwrap(), "[fe80::1%3]:53".parse().unwrap());
+        assert_eq!("[fe80::1%lo]:5353".to_name_server().unwrap(), "[fe80::1%1]:5353".parse().unwrap());
+        assert_eq!(Ipv4Addr::new(1, 1, 1, 1).to_name_server().unwrap(), "1.1.1.1:53".parse().unwrap());
+        assert!(parse_name_server("fe80::1%no-such-interface").is_none());
+        assert!(parse_name_server("192.0.2.1%1").is_none());
+        assert_eq!("one.one.one.one".host_name(), Some("one.one.one.one"));
+        assert_eq!("192.0.2.1:5353".host_name(), None);
+        assert_eq!(Ipv4Addr::new(1, 1, 1, 1).host_name(), None);
+    }
+
+    #[test]
+    fn test_bound_socket_family() {
+        let server: SocketAddr = "[::1]:53".parse().unwrap();
+        let socket = bound_socket(&server, Type::DGRAM, None, None).unwrap();
+        assert!(socket.local_addr().unwrap().as_socket().unwrap().is_ipv6());
+        assert!(bound_socket(&server, Type::DGRAM, Some(Ipv4Addr::LOCALHOST.into()), None).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use socket2::Type;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpSocket, UdpSocket};
+use tokio::time::timeout;
+
+use super::bound_socket;
+
+/// Asynchronous counterpart of `DNSTransport`. The returned futures hold the
+/// sockets, dropping them cancels the exchange and closes the sockets.
+pub trait AsyncDNSTransport {
+    /// Sends the wire format of `query` and returns the wire format of the
+    /// response
+    fn exchange(&self, query: &[u8]) -> impl Future<Output = io::Result<Vec<u8>>> + Send;
+}
+
+/// DNS over UDP on the tokio runtime
+pub struct AsyncUDPTransport {
+    /// Address of the DNS server
+    pub server: SocketAddr,
+    /// Timeout for the whole exchange
+    pub timeout: Duration,
+    /// Local address to send from, of the same family as `server`
+    pub source_address: Option<IpAddr>,
+    /// Network interface to send through (Linux only)
+    pub source_interface: Option<String>,
+}
+
+impl AsyncUDPTransport {
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server,
+            timeout: Duration::new(5, 0),
+            source_address: None,
+            source_interface: None,
+        }
+    }
+}
+
+impl AsyncDNSTransport for AsyncUDPTransport {
+    fn exchange(&self, query: &[u8]) -> impl Future<Output = io::Result<Vec<u8>>> + Send {
+        let server = self.server;
+        let query = query.to_vec();
+        let socket = bound_socket(&server, Type::DGRAM, self.source_address, self.source_interface.as_deref());
+        with_timeout(self.timeout, async move {
+            let socket = socket?;
+            socket.set_nonblocking(true)?;
+            let socket = UdpSocket::from_std(socket.into())?;
+            // Datagrams coming from other addresses are discarded
+            socket.connect(server).await?;
+            socket.send(&query).await?;
+
+            let mut buf: [u8; 1232] = [0; 1232];
+            let length = socket.recv(&mut buf).await?;
+            Ok(buf[..length].to_vec())
+        })
+    }
+}
+
+/// DNS over TCP on the tokio runtime
+pub struct AsyncTCPTransport {
+    /// Address of the DNS server
+    pub server: SocketAddr,
+    /// Timeout for the whole exchange, connection included
+    pub timeout: Duration,
+    /// Local address to connect from, of the same family as `server`
+    pub source_address: Option<IpAddr>,
+    /// Network interface to connect through (Linux only)
+    pub source_interface: Option<String>,
+}
+
+impl AsyncTCPTransport {
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server,
+            timeout: Duration::new(5, 0),
+            source_address: None,
+            source_interface: None,
+        }
+    }
+}
+
+impl AsyncDNSTransport for AsyncTCPTransport {
+    fn exchange(&self, query: &[u8]) -> impl Future<Output = io::Result<Vec<u8>>> + Send {
+        let server = self.server;
+        let query = query.to_vec();
+        let socket = bound_socket(&server, Type::STREAM, self.source_address, self.source_interface.as_deref());
+        with_timeout(self.timeout, async move {
+            let socket = socket?;
+            socket.set_nonblocking(true)?;
+            let mut stream = TcpSocket::from_std_stream(socket.into()).connect(server).await?;
+            let length = u16::try_from(query.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DNS message too long"))?;
+            let mut framed_query: Vec<u8> = Vec::with_capacity(query.len() + 2);
+            framed_query.extend(length.to_be_bytes());
+            framed_query.extend(query);
+            stream.write_all(&framed_query).await?;
+
+            let length = stream.read_u16().await?;
+            let mut response = vec![0; length as usize];
+            stream.read_exact(&mut response).await?;
+            Ok(response)
+        })
+    }
+}
+
+async fn with_timeout<T>(duration: Duration, future: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    timeout(duration, future).await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "DNS exchange timed out"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_udp_exchange() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let transport = AsyncUDPTransport::new(server.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut buf: [u8; 512] = [0; 512];
+            let (length, client) = server.recv_from(&mut buf).await.unwrap();
+            buf[2] |= 0b1000_0000;
+            server.send_to(&buf[..length], client).await.unwrap();
+        });
+        let response = transport.exchange(&[0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).await.unwrap();
+        assert_eq!(response, [0x12, 0x34, 0b1000_0001, 0, 0, 0, 0, 0, 0, 0, 0, 0].to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_udp_timeout() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut transport = AsyncUDPTransport::new(server.local_addr().unwrap());
+        transport.timeout = Duration::from_millis(50);
+        let error = transport.exchange(&[0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_source_address() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut transport = AsyncUDPTransport::new(server.local_addr().unwrap());
+        transport.source_address = Some("127.0.0.1".parse().unwrap());
+        tokio::spawn(async move {
+            let mut buf: [u8; 512] = [0; 512];
+            let (length, client) = server.recv_from(&mut buf).await.unwrap();
+            assert!(client.ip().is_loopback());
+            server.send_to(&buf[..length], client).await.unwrap();
+        });
+        transport.exchange(&[0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).await.unwrap();
+
+        let mut transport = AsyncTCPTransport::new("127.0.0.1:53".parse().unwrap());
+        transport.source_address = Some("::1".parse().unwrap());
+        let error = transport.exchange(&[0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_exchange() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let transport = AsyncTCPTransport::new(listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let length = stream.read_u16().await.unwrap();
+            let mut message = vec![0; length as usize];
+            stream.read_exact(&mut message).await.unwrap();
+            message[2] |= 0b1000_0000;
+            stream.write_u16(length).await.unwrap();
+            stream.write_all(&message).await.unwrap();
+        });
+        let response = transport.exchange(&[0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).await.unwrap();
+        assert_eq!(response, [0x12, 0x34, 0b1000_0001, 0, 0, 0, 0, 0, 0, 0, 0, 0].to_vec());
+    }
+}
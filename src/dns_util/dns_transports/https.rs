@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, StreamOwned};
+
+use crate::dns_util::dns_packet_structures::util::rewrite_record_ttls;
+use super::tls::{client_config, TLSOptions};
+use super::DNSTransport;
+
+/// Largest body accepted, the largest DNS message
+const MAX_BODY_LENGTH: usize = 65535;
+/// Longest status or header line accepted
+const MAX_LINE_LENGTH: usize = 8192;
+/// Largest status line and headers accepted, together
+const MAX_HEADER_LENGTH: usize = 65536;
+/// Most responses kept by the cache of a transport
+const MAX_CACHED_RESPONSES: usize = 1024;
+
+/// HTTP method used to carry the queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HTTPMethod {
+    /// Query sent as the body of the request
+    Post,
+    /// Query sent base64url encoded in the `dns` parameter. Responses can be
+    /// cached by HTTP caches along the way, and by the transport itself.
+    Get,
+}
+
+/// DNS over HTTPS (RFC 8484), using HTTP/1.1 with one connection per query
+pub struct HTTPSTransport {
+    /// Address of the DoH server
+    pub server: SocketAddr,
+    /// Connection, read and write timeout
+    pub timeout: Duration,
+    pub method: HTTPMethod,
+    /// Value of the Host header, taken from the URL
+    authority: String,
+    /// Path of the URL, without the `dns` parameter
+    path: String,
+    server_name: ServerName<'static>,
+    config: Arc<ClientConfig>,
+    /// Fresh GET responses, keyed by query (with ID 0)
+    cache: Mutex<HashMap<Vec<u8>, CachedResponse>>,
+}
+
+struct CachedResponse {
+    /// Body with the TTLs adjusted to the time of reception
+    body: Vec<u8>,
+    received: Instant,
+    /// Freshness lifetime left when received
+    lifetime: Duration,
+}
+
+impl HTTPSTransport {
+    /// `url` is the URI template of the server without variables, such as
+    /// `https://dns.example/dns-query`
+    pub fn new(server: SocketAddr, url: &str, options: &TLSOptions) -> io::Result<Self> {
+        let (authority, path) = url.strip_prefix("https://")
+            .map(|rest| match rest.find('/') {
+                Some(position) => (rest[..position].to_string(), rest[position..].to_string()),
+                None => (rest.to_string(), "/".to_string()),
+            })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "DoH URL must use https"))?;
+        let server_name = ServerName::try_from(options.server_name.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut config = client_config(options, rustls::DEFAULT_VERSIONS)?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Self {
+            server,
+            timeout: Duration::new(5, 0),
+            method: HTTPMethod::Post,
+            authority,
+            path,
+            server_name,
+            config: Arc::new(config),
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn build_request(&self, query: &[u8]) -> Vec<u8> {
+        let mut request: Vec<u8> = Vec::new();
+        match self.method {
+            HTTPMethod::Post => {
+                request.extend(format!(
+                    "POST {} HTTP/1.1\r\nHost: {}\r\nAccept: application/dns-message\r\n\
+                     Content-Type: application/dns-message\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    self.path, self.authority, query.len()
+                ).as_bytes());
+                request.extend(query);
+            }
+            HTTPMethod::Get => {
+                let separator = if self.path.contains('?') { '&' } else { '?' };
+                request.extend(format!(
+                    "GET {}{}dns={} HTTP/1.1\r\nHost: {}\r\nAccept: application/dns-message\r\nConnection: close\r\n\r\n",
+                    self.path, separator, URL_SAFE_NO_PAD.encode(query), self.authority
+                ).as_bytes());
+            }
+        }
+        request
+    }
+
+    fn fetch(&self, query: &[u8]) -> io::Result<HTTPResponse> {
+        let stream = TcpStream::connect_timeout(&self.server, self.timeout)?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.set_read_timeout(Some(self.timeout))?;
+
+        let connection = ClientConnection::new(self.config.clone(), self.server_name.clone())
+            .map_err(io::Error::other)?;
+        let mut tls_stream = StreamOwned::new(connection, stream);
+        tls_stream.write_all(&self.build_request(query))?;
+        tls_stream.flush()?;
+
+        read_http_response(&mut BufReader::new(tls_stream))
+    }
+
+    /// Returns the cached response to `query` if it is still fresh, with the
+    /// TTLs reduced by the time spent in the cache
+    fn cached_response(&self, query: &[u8]) -> Option<Vec<u8>> {
+        let mut cache = self.cache.lock().unwrap();
+        let cached = cache.get(query)?;
+        let elapsed = cached.received.elapsed();
+        if elapsed >= cached.lifetime {
+            cache.remove(query);
+            return None;
+        }
+        let mut body = cached.body.clone();
+        rewrite_record_ttls(&mut body, |ttl| ttl.saturating_sub(elapsed.as_secs() as u32))?;
+        Some(body)
+    }
+
+    /// Keeps `body` as the response to `query` for `lifetime`. The expired
+    /// responses are dropped first, then the one expiring first if the
+    /// cache is still full.
+    fn cache_response(&self, query: &[u8], body: &[u8], lifetime: Duration) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, cached| cached.received.elapsed() < cached.lifetime);
+        if cache.len() >= MAX_CACHED_RESPONSES && !cache.contains_key(query) {
+            let first_expiring = cache.iter()
+                .min_by_key(|(_, cached)| cached.received + cached.lifetime)
+                .map(|(query, _)| query.clone());
+            if let Some(first_expiring) = first_expiring {
+                cache.remove(&first_expiring);
+            }
+        }
+        cache.insert(query.to_vec(), CachedResponse { body: body.to_vec(), received: Instant::now(), lifetime });
+    }
+}
+
+impl DNSTransport for HTTPSTransport {
+    fn exchange(&self, query: &[u8]) -> io::Result<Vec<u8>> {
+        if query.len() < 12 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "query shorter than a header"));
+        }
+        // The ID is set to 0 on the wire so that identical queries get
+        // identical URLs, and put back in the response for the caller
+        let query_id = [query[0], query[1]];
+        let mut query = query.to_vec();
+        query[0..2].copy_from_slice(&[0, 0]);
+
+        let mut response = match self.cached_response(&query) {
+            Some(response) => response,
+            None => {
+                let http_response = self.fetch(&query)?;
+                if http_response.status != 200 {
+                    return Err(io::Error::other(format!("DoH server answered with status {}", http_response.status)));
+                }
+                if !http_response.content_type.eq_ignore_ascii_case("application/dns-message") {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "DoH response is not a DNS message"));
+                }
+                let mut body = http_response.body;
+                // RFC 8484 section 5.1, the records must not outlive the
+                // HTTP freshness lifetime, and the time already spent in
+                // caches is deducted from their TTL
+                let age = http_response.age;
+                let max_age = http_response.max_age;
+                rewrite_record_ttls(&mut body, |ttl| max_age.map_or(ttl, |max_age| ttl.min(max_age)).saturating_sub(age))
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed DNS message"))?;
+                if let (HTTPMethod::Get, Some(max_age), false) = (self.method, max_age, http_response.no_store) {
+                    if max_age > age {
+                        self.cache_response(&query, &body, Duration::from_secs((max_age - age) as u64));
+                    }
+                }
+                body
+            }
+        };
+
+        if response.len() < 12 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "response shorter than a header"));
+        }
+        response[0..2].copy_from_slice(&query_id);
+        Ok(response)
+    }
+}
+
+struct HTTPResponse {
+    status: u16,
+    content_type: String,
+    /// `max-age` directive of Cache-Control
+    max_age: Option<u32>,
+    /// Cache-Control forbids reusing the response without revalidation
+    no_store: bool,
+    /// Value of the Age header
+    age: u32,
+    body: Vec<u8>,
+}
+
+fn read_http_response<R: BufRead>(reader: &mut R) -> io::Result<HTTPResponse> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let mut header_length = 0;
+    let status_line = read_header_line(reader, &mut header_length)?;
+    let status = status_line.split(' ')
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| invalid("invalid HTTP status line"))?;
+
+    let mut response = HTTPResponse {
+        status,
+        content_type: String::new(),
+        max_age: None,
+        no_store: false,
+        age: 0,
+        body: Vec::new(),
+    };
+    let mut content_length: Option<usize> = None;
+    let mut chunked = false;
+    loop {
+        let line = read_header_line(reader, &mut header_length)?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid("invalid HTTP header"))?;
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-type" => response.content_type = value.split(';').next().unwrap_or("").trim().to_string(),
+            "content-length" => content_length = Some(value.parse().map_err(|_| invalid("invalid Content-Length"))?),
+            "transfer-encoding" => chunked = value.to_ascii_lowercase().contains("chunked"),
+            "age" => response.age = value.parse().unwrap_or(0),
+            "cache-control" => {
+                for directive in value.split(',').map(|directive| directive.trim().to_ascii_lowercase()) {
+                    if let Some(max_age) = directive.strip_prefix("max-age=") {
+                        response.max_age = max_age.trim_matches('"').parse().ok();
+                    } else if directive == "no-store" || directive == "no-cache" {
+                        response.no_store = true;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if chunked {
+        loop {
+            let size_line = read_line(reader)?;
+            let size = usize::from_str_radix(size_line.split(';').next().unwrap_or("").trim(), 16)
+                .map_err(|_| invalid("invalid chunk size"))?;
+            if size == 0 {
+                break;
+            }
+            if size > MAX_BODY_LENGTH - response.body.len() {
+                return Err(invalid("HTTP body longer than a DNS message"));
+            }
+            let mut chunk = vec![0; size];
+            reader.read_exact(&mut chunk)?;
+            response.body.extend(chunk);
+            read_line(reader)?;
+        }
+    } else if let Some(content_length) = content_length {
+        if content_length > MAX_BODY_LENGTH {
+            return Err(invalid("HTTP body longer than a DNS message"));
+        }
+        response.body = vec![0; content_length];
+        reader.read_exact(&mut response.body)?;
+    } else {
+        reader.take(MAX_BODY_LENGTH as u64 + 1).read_to_end(&mut response.body)?;
+        if response.body.len() > MAX_BODY_LENGTH {
+            return Err(invalid("HTTP body longer than a DNS message"));
+        }
+    }
+    Ok(response)
+}
+
+/// Reads a line of the status line and headers, adding its length to
+/// `header_length`. Fails once they grow past `MAX_HEADER_LENGTH`.
+fn read_header_line<R: BufRead>(reader: &mut R, header_length: &mut usize) -> io::Result<String> {
+    let line = read_line(reader)?;
+    *header_length += line.len() + 2;
+    if *header_length > MAX_HEADER_LENGTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "HTTP headers too long"));
+    }
+    Ok(line)
+}
+
+/// Reads a CRLF terminated line, without the line break. Fails on lines
+/// longer than `MAX_LINE_LENGTH`.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line: Vec<u8> = Vec::new();
+    if reader.take(MAX_LINE_LENGTH as u64 + 2).read_until(b'\n', &mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    if !line.ends_with(b"\n") && line.len() > MAX_LINE_LENGTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "HTTP line too long"));
+    }
+    let line = String::from_utf8(line).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "HTTP line is not UTF-8"))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use rustls::crypto::ring as provider;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+    use rustls::{ServerConfig, ServerConnection};
+
+    const QUERY: [u8; 28] = [0x12, 0x34, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 6, 103, 111, 111, 103, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1];
+
+    /// Starts a DoH server for `connections` connections. Every request is
+    /// sent back through the channel, and answered with one A record with a
+    /// TTL of 300 and the given extra headers.
+    fn start_server(connections: usize, headers: &'static str) -> (SocketAddr, Vec<u8>, mpsc::Receiver<Vec<u8>>) {
+        let certified_key = rcgen::generate_simple_self_signed(vec!["doh.test".to_string()]).unwrap();
+        let certificate = certified_key.cert.der().to_vec();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified_key.signing_key.serialize_der()));
+        let config = Arc::new(ServerConfig::builder_with_provider(Arc::new(provider::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![CertificateDer::from(certificate.clone())], key)
+            .unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for _i in 0..connections {
+                let (stream, _) = listener.accept().unwrap();
+                let connection = ServerConnection::new(config.clone()).unwrap();
+                let mut tls_stream = StreamOwned::new(connection, stream);
+                let mut request: Vec<u8> = Vec::new();
+                let mut buf: [u8; 1024] = [0; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") || (request.starts_with(b"POST") && !request.ends_with(&QUERY[2..])) {
+                    let length = tls_stream.read(&mut buf).unwrap();
+                    request.extend(&buf[..length]);
+                }
+                sender.send(request).unwrap();
+
+                let mut body = QUERY.to_vec();
+                body[0..2].copy_from_slice(&[0, 0]);
+                body[2] |= 0b1000_0000;
+                body[7] = 1;
+                body.extend([192, 12, 0, 1, 0, 1, 0, 0, 1, 44, 0, 4, 1, 2, 3, 4]);
+                let mut response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: {}\r\n{}\r\n",
+                    body.len(), headers
+                ).into_bytes();
+                response.extend(body);
+                tls_stream.write_all(&response).unwrap();
+                tls_stream.flush().unwrap();
+            }
+        });
+        (address, certificate, receiver)
+    }
+
+    fn transport(address: SocketAddr, certificate: Vec<u8>, method: HTTPMethod) -> HTTPSTransport {
+        let mut options = TLSOptions::new("doh.test");
+        options.root_certificates.push(certificate);
+        let mut transport = HTTPSTransport::new(address, "https://doh.test/dns-query", &options).unwrap();
+        transport.method = method;
+        transport
+    }
+
+    #[test]
+    fn test_post() {
+        let (address, certificate, requests) = start_server(1, "");
+        let response = transport(address, certificate, HTTPMethod::Post).exchange(&QUERY).unwrap();
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with(b"POST /dns-query HTTP/1.1\r\nHost: doh.test\r\n"));
+        assert!(request.ends_with(&[&[0, 0], &QUERY[2..]].concat()));
+        assert_eq!(response[0..2], [0x12, 0x34]);
+        assert_eq!(response[34..38], [0, 0, 1, 44]);
+    }
+
+    #[test]
+    fn test_get_with_cache_headers() {
+        let (address, certificate, requests) = start_server(1, "Cache-Control: max-age=60\r\nAge: 20\r\n");
+        let transport = transport(address, certificate, HTTPMethod::Get);
+        let response = transport.exchange(&QUERY).unwrap();
+        let request = String::from_utf8(requests.recv().unwrap()).unwrap();
+        assert!(request.starts_with("GET /dns-query?dns=AAABAAABAAAAAAAABmdvb2dsZQNjb20AAAEAAQ HTTP/1.1\r\n"));
+        assert_eq!(response[0..2], [0x12, 0x34]);
+        assert_eq!(response[34..38], [0, 0, 0, 40]);
+
+        // The server only accepts one connection, this one comes from the cache
+        let cached = transport.exchange(&QUERY).unwrap();
+        assert_eq!(cached[34..38], [0, 0, 0, 40]);
+    }
+
+    #[test]
+    fn test_chunked_response() {
+        let mut reader = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Type: application/dns-message\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n".as_bytes();
+        let response = read_http_response(&mut reader).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"abcde".to_vec());
+    }
+
+    #[test]
+    fn test_oversized_body() {
+        let mut reader = "HTTP/1.1 200 OK\r\nContent-Length: 4294967296\r\n\r\n".as_bytes();
+        assert!(matches!(read_http_response(&mut reader), Err(e) if e.kind() == io::ErrorKind::InvalidData));
+        let mut reader = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffff\r\n".as_bytes();
+        assert!(matches!(read_http_response(&mut reader), Err(e) if e.kind() == io::ErrorKind::InvalidData));
+        // Chunks adding up past the limit
+        let chunks = format!("8000\r\n{}\r\n8000\r\n", "a".repeat(0x8000));
+        let response = format!("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{}", chunks);
+        assert!(matches!(read_http_response(&mut response.as_bytes()), Err(e) if e.kind() == io::ErrorKind::InvalidData));
+        let response = [b"HTTP/1.1 200 OK\r\n\r\n".to_vec(), vec![0; 70000]].concat();
+        assert!(matches!(read_http_response(&mut response.as_slice()), Err(e) if e.kind() == io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn test_oversized_headers() {
+        let response = format!("HTTP/1.1 200 OK\r\nX-Padding: {}\r\n\r\n", "a".repeat(10000));
+        assert!(matches!(read_http_response(&mut response.as_bytes()), Err(e) if e.kind() == io::ErrorKind::InvalidData));
+        // Lines short enough, but too many of them
+        let response = format!("HTTP/1.1 200 OK\r\n{}\r\n", "X-Padding: a\r\n".repeat(10000));
+        assert!(matches!(read_http_response(&mut response.as_bytes()), Err(e) if e.kind() == io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn test_cache_limit() {
+        let transport = HTTPSTransport::new("127.0.0.1:443".parse().unwrap(), "https://doh.test/dns-query", &TLSOptions::new("doh.test")).unwrap();
+        transport.cache_response(b"expired", b"", Duration::ZERO);
+        transport.cache_response(b"first", b"", Duration::from_secs(10));
+        assert_eq!(transport.cache.lock().unwrap().len(), 1);
+        for i in 0..MAX_CACHED_RESPONSES {
+            transport.cache_response(&i.to_be_bytes(), b"", Duration::from_secs(60));
+        }
+        // The response expiring first made room for the last one
+        let cache = transport.cache.lock().unwrap();
+        assert_eq!(cache.len(), MAX_CACHED_RESPONSES);
+        assert!(!cache.contains_key(b"first".as_slice()));
+    }
+}
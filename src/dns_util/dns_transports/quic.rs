@@ -0,0 +1,228 @@
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::{ClientConfig, Connection, Endpoint};
+use tokio::runtime::{Builder, Handle, Runtime};
+
+use super::tls::{client_config, TLSOptions};
+use super::DNSTransport;
+
+/// Port assigned to DNS over QUIC
+pub const DNS_OVER_QUIC_PORT: u16 = 853;
+
+/// Largest response accepted on a stream: a DNS message and its length
+const MAX_RESPONSE_LENGTH: usize = 65537;
+
+/// DNS over QUIC (RFC 9250). Each query gets its own bidirectional stream on
+/// a connection kept open between queries. When the connection has to be
+/// re-established, the TLS session of the previous one is resumed and the
+/// query is sent as 0-RTT data if the server allows it.
+pub struct QUICTransport {
+    /// Address of the DoQ server
+    pub server: SocketAddr,
+    /// Timeout for a whole exchange, handshake included
+    pub timeout: Duration,
+    server_name: String,
+    runtime: Runtime,
+    endpoint: Endpoint,
+    connection: Mutex<Option<Connection>>,
+}
+
+impl QUICTransport {
+    pub fn new(server: SocketAddr, options: &TLSOptions) -> io::Result<Self> {
+        let mut tls_config = client_config(options, &[&rustls::version::TLS13])?;
+        tls_config.alpn_protocols = vec![b"doq".to_vec()];
+        tls_config.enable_early_data = true;
+        let quic_config = QuicClientConfig::try_from(Arc::new(tls_config))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let bind_address: SocketAddr = match server {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let mut endpoint = {
+            let _guard = runtime.enter();
+            Endpoint::client(bind_address)?
+        };
+        endpoint.set_default_client_config(ClientConfig::new(Arc::new(quic_config)));
+
+        Ok(Self {
+            server,
+            timeout: Duration::new(5, 0),
+            server_name: options.server_name.clone(),
+            runtime,
+            endpoint,
+            connection: Mutex::new(None),
+        })
+    }
+
+    async fn exchange_async(&self, query: &[u8]) -> io::Result<Vec<u8>> {
+        let open_connection = self.connection.lock().unwrap().clone()
+            .filter(|connection| connection.close_reason().is_none());
+        if let Some(connection) = open_connection {
+            // The server may have closed the connection since, in which case
+            // a new one is opened below
+            if let Ok(response) = send_on_stream(&connection, query).await {
+                return Ok(response);
+            }
+        }
+
+        let connecting = self.endpoint.connect(self.server, &self.server_name)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let response = match connecting.into_0rtt() {
+            Ok((connection, zero_rtt_accepted)) => {
+                *self.connection.lock().unwrap() = Some(connection.clone());
+                match send_on_stream(&connection, query).await {
+                    Ok(response) => response,
+                    // Data sent in rejected 0-RTT is lost, the query is sent
+                    // again once the handshake is complete
+                    Err(_) if !zero_rtt_accepted.await => send_on_stream(&connection, query).await?,
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(connecting) => {
+                let connection = connecting.await.map_err(io::Error::other)?;
+                *self.connection.lock().unwrap() = Some(connection.clone());
+                send_on_stream(&connection, query).await?
+            }
+        };
+        Ok(response)
+    }
+}
+
+impl DNSTransport for QUICTransport {
+    /// Blocks on the runtime of the transport, and so fails when called
+    /// from a task of another tokio runtime
+    fn exchange(&self, query: &[u8]) -> io::Result<Vec<u8>> {
+        if Handle::try_current().is_ok() {
+            return Err(io::Error::other("QUICTransport cannot block within a tokio runtime"));
+        }
+        if query.len() < 12 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "query shorter than a header"));
+        }
+        // RFC 9250 section 4.2.1, the ID is 0 on the wire since the stream
+        // already identifies the exchange
+        let query_id = [query[0], query[1]];
+        let mut query = query.to_vec();
+        query[0..2].copy_from_slice(&[0, 0]);
+
+        let mut response = self.runtime.block_on(async {
+            tokio::time::timeout(self.timeout, self.exchange_async(&query)).await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "DoQ exchange timed out"))?
+        })?;
+
+        if response.len() < 12 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "response shorter than a header"));
+        }
+        response[0..2].copy_from_slice(&query_id);
+        Ok(response)
+    }
+}
+
+/// Sends the query on a new stream, closing the sending side right after as
+/// required by RFC 9250, and reads the length-prefixed response
+async fn send_on_stream(connection: &Connection, query: &[u8]) -> io::Result<Vec<u8>> {
+    let (mut send, mut recv) = connection.open_bi().await.map_err(io::Error::other)?;
+    let length = u16::try_from(query.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DNS message too long"))?;
+    let mut framed_query: Vec<u8> = Vec::with_capacity(query.len() + 2);
+    framed_query.extend(length.to_be_bytes());
+    framed_query.extend(query);
+    send.write_all(&framed_query).await.map_err(io::Error::other)?;
+    send.finish().map_err(io::Error::other)?;
+
+    let framed_response = recv.read_to_end(MAX_RESPONSE_LENGTH).await.map_err(io::Error::other)?;
+    if framed_response.len() < 2 || u16::from_be_bytes([framed_response[0], framed_response[1]]) as usize != framed_response.len() - 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "DoQ response length mismatch"));
+    }
+    Ok(framed_response[2..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use quinn::crypto::rustls::QuicServerConfig;
+    use quinn::ServerConfig;
+    use rustls::crypto::ring as provider;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    /// ID of a query seen by the server, and whether it came as 0-RTT data
+    type ReceivedQuery = ([u8; 2], bool);
+
+    /// Starts a DoQ server with a self-signed certificate for `doq.test`,
+    /// answering every stream with the query flagged as a response. The
+    /// queries seen by the server are sent through the channel.
+    fn start_server() -> (SocketAddr, Vec<u8>, mpsc::Receiver<ReceivedQuery>) {
+        let certified_key = rcgen::generate_simple_self_signed(vec!["doq.test".to_string()]).unwrap();
+        let certificate = certified_key.cert.der().to_vec();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified_key.signing_key.serialize_der()));
+        let mut tls_config = rustls::ServerConfig::builder_with_provider(Arc::new(provider::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![CertificateDer::from(certificate.clone())], key)
+            .unwrap();
+        tls_config.alpn_protocols = vec![b"doq".to_vec()];
+        tls_config.max_early_data_size = u32::MAX;
+        let server_config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls_config).unwrap()));
+
+        let (address_sender, address_receiver) = mpsc::channel();
+        let (id_sender, id_receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+            runtime.block_on(async move {
+                let endpoint = Endpoint::server(server_config, "127.0.0.1:0".parse().unwrap()).unwrap();
+                address_sender.send(endpoint.local_addr().unwrap()).unwrap();
+                while let Some(incoming) = endpoint.accept().await {
+                    let connection = incoming.accept().unwrap().into_0rtt().unwrap().0;
+                    while let Ok((mut send, mut recv)) = connection.accept_bi().await {
+                        let mut message = recv.read_to_end(MAX_RESPONSE_LENGTH).await.unwrap();
+                        id_sender.send(([message[2], message[3]], recv.is_0rtt())).unwrap();
+                        message[4] |= 0b1000_0000;
+                        send.write_all(&message).await.unwrap();
+                        send.finish().unwrap();
+                    }
+                }
+            });
+        });
+        (address_receiver.recv().unwrap(), certificate, id_receiver)
+    }
+
+    #[test]
+    fn test_exchange_and_reconnection() {
+        let (address, certificate, ids) = start_server();
+        let mut options = TLSOptions::new("doq.test");
+        options.root_certificates.push(certificate);
+        let transport = QUICTransport::new(address, &options).unwrap();
+        let query: [u8; 12] = [0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        let response = transport.exchange(&query).unwrap();
+        assert_eq!(response, [0x12, 0x34, 0b1000_0001, 0, 0, 0, 0, 0, 0, 0, 0, 0].to_vec());
+        assert_eq!(ids.recv().unwrap(), ([0, 0], false));
+
+        // Second query on the same connection
+        assert!(transport.exchange(&query).is_ok());
+        assert_eq!(ids.recv().unwrap(), ([0, 0], false));
+
+        // The connection is closed, the next query resumes the session and
+        // goes as early data
+        transport.connection.lock().unwrap().take().unwrap().close(0u32.into(), b"");
+        let response = transport.exchange(&query).unwrap();
+        assert_eq!(response[0..2], [0x12, 0x34]);
+        assert_eq!(ids.recv().unwrap(), ([0, 0], true));
+    }
+
+    #[test]
+    fn test_exchange_within_runtime() {
+        let transport = QUICTransport::new("127.0.0.1:853".parse().unwrap(), &TLSOptions::new("doq.test")).unwrap();
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let result = runtime.block_on(async { transport.exchange(&[0; 12]) });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Other);
+    }
+}
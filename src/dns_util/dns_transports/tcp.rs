@@ -0,0 +1,84 @@
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::Duration;
+
+use socket2::Type;
+
+use super::{bound_socket, DNSTransport};
+
+/// DNS over TCP (RFC 1035 section 4.2.2, RFC 7766). Every message is
+/// preceded by its length on two bytes.
+pub struct TCPTransport {
+    /// Address of the DNS server
+    pub server: SocketAddr,
+    /// Connection, read and write timeout
+    pub timeout: Duration,
+    /// Local address to connect from, of the same family as `server`
+    pub source_address: Option<IpAddr>,
+    /// Network interface to connect through (Linux only)
+    pub source_interface: Option<String>,
+}
+
+impl TCPTransport {
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server,
+            timeout: Duration::new(5, 0),
+            source_address: None,
+            source_interface: None,
+        }
+    }
+}
+
+impl DNSTransport for TCPTransport {
+    fn exchange(&self, query: &[u8]) -> io::Result<Vec<u8>> {
+        let socket = bound_socket(&self.server, Type::STREAM, self.source_address, self.source_interface.as_deref())?;
+        socket.connect_timeout(&self.server.into(), self.timeout)?;
+        let mut stream: TcpStream = socket.into();
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.set_read_timeout(Some(self.timeout))?;
+
+        write_framed_message(&mut stream, query)?;
+        read_framed_message(&mut stream)
+    }
+}
+
+/// Writes `message` preceded by its length, as a single write so that the
+/// length and the message end up in the same segment.
+pub(crate) fn write_framed_message<W: Write>(stream: &mut W, message: &[u8]) -> io::Result<()> {
+    let length = u16::try_from(message.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DNS message too long"))?;
+    let mut framed_message: Vec<u8> = Vec::with_capacity(message.len() + 2);
+    framed_message.extend(length.to_be_bytes());
+    framed_message.extend(message);
+    stream.write_all(&framed_message)?;
+    stream.flush()
+}
+
+/// Reads one length-prefixed message
+pub(crate) fn read_framed_message<R: Read>(stream: &mut R) -> io::Result<Vec<u8>> {
+    let mut length: [u8; 2] = [0; 2];
+    stream.read_exact(&mut length)?;
+    let mut message = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut message)?;
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framing_round_trip() {
+        let mut framed: Vec<u8> = Vec::new();
+        write_framed_message(&mut framed, &[1, 2, 3]).unwrap();
+        assert_eq!(framed, [0, 3, 1, 2, 3].to_vec());
+        assert_eq!(read_framed_message(&mut framed.as_slice()).unwrap(), [1, 2, 3].to_vec());
+    }
+
+    #[test]
+    fn test_framing_truncated() {
+        let framed: Vec<u8> = [0, 4, 1, 2].to_vec();
+        assert!(read_framed_message(&mut framed.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ring::digest;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{ring as provider, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore,
+    SignatureScheme, StreamOwned, SupportedProtocolVersion,
+};
+
+use super::tcp::{read_framed_message, write_framed_message};
+use super::DNSTransport;
+
+/// Port assigned to DNS over TLS
+pub const DNS_OVER_TLS_PORT: u16 = 853;
+
+/// How the server is authenticated. The strict profile of RFC 7858 uses the
+/// hostname, the pinning profile SPKI fingerprints. Both can be combined, and
+/// leaving both out gives the opportunistic profile (encryption without
+/// authentication).
+pub struct TLSOptions {
+    /// Name sent in the SNI extension and checked against the certificate
+    pub server_name: String,
+    /// Validate the certificate chain and check that it covers `server_name`
+    pub verify_hostname: bool,
+    /// SHA-256 digests of accepted SubjectPublicKeyInfo, one of them has to
+    /// appear in the chain presented by the server
+    pub spki_pins: Vec<[u8; 32]>,
+    /// DER certificates trusted for chain validation. The Mozilla root
+    /// store is used when empty.
+    pub root_certificates: Vec<Vec<u8>>,
+}
+
+impl TLSOptions {
+    pub fn new(server_name: &str) -> Self {
+        Self {
+            server_name: server_name.to_string(),
+            verify_hostname: true,
+            spki_pins: Vec::new(),
+            root_certificates: Vec::new(),
+        }
+    }
+}
+
+/// DNS over TLS (RFC 7858). Messages are framed as for TCP inside a TLS
+/// session.
+pub struct TLSTransport {
+    /// Address of the DNS server, usually on port 853
+    pub server: SocketAddr,
+    /// Connection, read and write timeout
+    pub timeout: Duration,
+    server_name: ServerName<'static>,
+    config: Arc<ClientConfig>,
+}
+
+impl TLSTransport {
+    pub fn new(server: SocketAddr, options: &TLSOptions) -> io::Result<Self> {
+        let server_name = ServerName::try_from(options.server_name.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(Self {
+            server,
+            timeout: Duration::new(5, 0),
+            server_name,
+            config: Arc::new(client_config(options, rustls::DEFAULT_VERSIONS)?),
+        })
+    }
+}
+
+impl DNSTransport for TLSTransport {
+    fn exchange(&self, query: &[u8]) -> io::Result<Vec<u8>> {
+        let stream = TcpStream::connect_timeout(&self.server, self.timeout)?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.set_read_timeout(Some(self.timeout))?;
+
+        let connection = ClientConnection::new(self.config.clone(), self.server_name.clone())
+            .map_err(io::Error::other)?;
+        let mut tls_stream = StreamOwned::new(connection, stream);
+
+        write_framed_message(&mut tls_stream, query)?;
+        read_framed_message(&mut tls_stream)
+    }
+}
+
+/// Builds a rustls configuration enforcing `options`, for the given TLS
+/// versions
+pub(crate) fn client_config(
+    options: &TLSOptions,
+    versions: &[&'static SupportedProtocolVersion],
+) -> io::Result<ClientConfig> {
+    let provider = Arc::new(provider::default_provider());
+
+    let webpki_verifier = if options.verify_hostname {
+        let mut roots = RootCertStore::empty();
+        if options.root_certificates.is_empty() {
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        }
+        for certificate in &options.root_certificates {
+            roots.add(CertificateDer::from(certificate.clone()))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        }
+        Some(WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?)
+    } else {
+        None
+    };
+
+    let verifier = PinningVerifier {
+        webpki_verifier,
+        spki_pins: options.spki_pins.clone(),
+        provider: provider.clone(),
+    };
+
+    Ok(ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(versions)
+        .map_err(io::Error::other)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth())
+}
+
+/// Computes the SHA-256 digest of the SubjectPublicKeyInfo of a DER
+/// certificate, as used by `TLSOptions::spki_pins`
+pub fn spki_sha256(certificate: &[u8]) -> io::Result<[u8; 32]> {
+    let certificate = CertificateDer::from(certificate);
+    let parsed = webpki::EndEntityCert::try_from(&certificate)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut pin: [u8; 32] = [0; 32];
+    pin.copy_from_slice(digest::digest(&digest::SHA256, parsed.subject_public_key_info().as_ref()).as_ref());
+    Ok(pin)
+}
+
+#[derive(Debug)]
+struct PinningVerifier {
+    /// Chain and hostname validation, skipped when `None`
+    webpki_verifier: Option<Arc<WebPkiServerVerifier>>,
+    spki_pins: Vec<[u8; 32]>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(webpki_verifier) = &self.webpki_verifier {
+            webpki_verifier.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+        if !self.spki_pins.is_empty() {
+            let pinned = std::iter::once(end_entity)
+                .chain(intermediates)
+                .filter_map(|certificate| spki_sha256(certificate).ok())
+                .any(|pin| self.spki_pins.contains(&pin));
+            if !pinned {
+                return Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure));
+            }
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    use rustls::{ServerConfig, ServerConnection};
+
+    /// Starts a DoT server for one connection with a self-signed certificate
+    /// for `dns.test`, answering with the query bytes flagged as a response.
+    /// Returns its address and the certificate.
+    fn start_server() -> (SocketAddr, Vec<u8>) {
+        let certified_key = rcgen::generate_simple_self_signed(vec!["dns.test".to_string()]).unwrap();
+        let certificate = certified_key.cert.der().to_vec();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified_key.signing_key.serialize_der()));
+        let config = ServerConfig::builder_with_provider(Arc::new(provider::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![CertificateDer::from(certificate.clone())], key)
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let connection = ServerConnection::new(Arc::new(config)).unwrap();
+            let mut tls_stream = StreamOwned::new(connection, stream);
+            if let Ok(mut message) = read_framed_message(&mut tls_stream) {
+                message[2] |= 0b1000_0000;
+                let _ = write_framed_message(&mut tls_stream, &message);
+            }
+        });
+        (address, certificate)
+    }
+
+    #[test]
+    fn test_hostname_verification() {
+        let (address, certificate) = start_server();
+        let mut options = TLSOptions::new("dns.test");
+        options.root_certificates.push(certificate);
+        let transport = TLSTransport::new(address, &options).unwrap();
+        let response = transport.exchange(&[0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(response, [0x12, 0x34, 0b1000_0001, 0, 0, 0, 0, 0, 0, 0, 0, 0].to_vec());
+    }
+
+    #[test]
+    fn test_hostname_mismatch() {
+        let (address, certificate) = start_server();
+        let mut options = TLSOptions::new("other.test");
+        options.root_certificates.push(certificate);
+        let transport = TLSTransport::new(address, &options).unwrap();
+        assert!(transport.exchange(&[0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_spki_pinning() {
+        let (address, certificate) = start_server();
+        let mut options = TLSOptions::new("dns.test");
+        options.verify_hostname = false;
+        options.spki_pins.push(spki_sha256(&certificate).unwrap());
+        let transport = TLSTransport::new(address, &options).unwrap();
+        assert!(transport.exchange(&[0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_ok());
+    }
+
+    #[test]
+    fn test_spki_pin_mismatch() {
+        let (address, _) = start_server();
+        let mut options = TLSOptions::new("dns.test");
+        options.verify_hostname = false;
+        options.spki_pins.push([0; 32]);
+        let transport = TLSTransport::new(address, &options).unwrap();
+        assert!(transport.exchange(&[0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+}
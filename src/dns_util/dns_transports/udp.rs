@@ -0,0 +1,71 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use socket2::Type;
+
+use super::{bound_socket, DNSTransport};
+
+/// Classic DNS over UDP, one datagram per query.
+pub struct UDPTransport {
+    /// Address of the DNS server
+    pub server: SocketAddr,
+    /// Read and write timeout of the socket
+    pub timeout: Duration,
+    /// Local address to send from, of the same family as `server`
+    pub source_address: Option<IpAddr>,
+    /// Network interface to send through (Linux only)
+    pub source_interface: Option<String>,
+}
+
+impl UDPTransport {
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server,
+            timeout: Duration::new(5, 0),
+            source_address: None,
+            source_interface: None,
+        }
+    }
+}
+
+impl DNSTransport for UDPTransport {
+    fn exchange(&self, query: &[u8]) -> io::Result<Vec<u8>> {
+        let socket: UdpSocket = bound_socket(&self.server, Type::DGRAM, self.source_address, self.source_interface.as_deref())?.into();
+
+        socket.set_write_timeout(Some(self.timeout))?;
+        socket.set_read_timeout(Some(self.timeout))?;
+
+        // Datagrams coming from other addresses are discarded
+        socket.connect(self.server)?;
+        socket.send(query)?;
+
+        let mut buf: [u8; 1232] = [0; 1232];
+        let length = socket.recv(&mut buf)?;
+        Ok(buf[..length].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_ipv6_exchange() {
+        let server = match UdpSocket::bind("[::1]:0") {
+            Ok(server) => server,
+            // No IPv6 loopback in this environment
+            Err(_) => return,
+        };
+        let transport = UDPTransport::new(server.local_addr().unwrap());
+        thread::spawn(move || {
+            let mut buf: [u8; 512] = [0; 512];
+            let (length, client) = server.recv_from(&mut buf).unwrap();
+            buf[2] |= 0b1000_0000;
+            server.send_to(&buf[..length], client).unwrap();
+        });
+        let response = transport.exchange(&[0x12, 0x34, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(response, [0x12, 0x34, 0b1000_0001, 0, 0, 0, 0, 0, 0, 0, 0, 0].to_vec());
+    }
+}
@@ -0,0 +1,102 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use crate::dns_util::dns_packet_structures::dns_question::DNSQuestion;
+use crate::dns_util::dns_packet_structures::domain_name::DomainName;
+use crate::dns_util::dns_resolver::{Resolver, ResolverConfig};
+
+/// Question of a query received by a test server
+pub(crate) struct Query {
+    /// Name asked, without the final dot
+    pub name: String,
+    pub query_type: u16,
+    pub recursion_desired: bool,
+}
+
+/// Response of a test server: records in wire format for each section
+#[derive(Default)]
+pub(crate) struct Answer {
+    pub authoritative: bool,
+    pub response_code: u8,
+    pub answers: Vec<Vec<u8>>,
+    pub authority: Vec<Vec<u8>>,
+    pub additional: Vec<Vec<u8>>,
+}
+
+impl Answer {
+    pub(crate) fn records(answers: Vec<Vec<u8>>) -> Self {
+        Self { answers, ..Self::default() }
+    }
+
+    pub(crate) fn response_code(response_code: u8) -> Self {
+        Self { response_code, ..Self::default() }
+    }
+}
+
+/// Record in wire format, of class IN and with a TTL of 60 seconds
+pub(crate) fn record(owner: &str, record_type: u16, rdata: Vec<u8>) -> Vec<u8> {
+    record_with_ttl(owner, record_type, 60, rdata)
+}
+
+pub(crate) fn record_with_ttl(owner: &str, record_type: u16, ttl: u32, rdata: Vec<u8>) -> Vec<u8> {
+    let mut wire = name(owner);
+    wire.extend(record_type.to_be_bytes());
+    wire.extend([0, 1]);
+    wire.extend(ttl.to_be_bytes());
+    wire.extend((rdata.len() as u16).to_be_bytes());
+    wire.extend(rdata);
+    wire
+}
+
+/// Uncompressed wire format of `name`, as the rdata of CNAME, NS or PTR
+/// records
+pub(crate) fn name(name: &str) -> Vec<u8> {
+    name.parse::<DomainName>().unwrap().to_labels()
+}
+
+/// Starts a UDP server on a free port of 127.0.0.1 answering each query
+/// with `answer`. The response repeats the header and the question of the
+/// query, dropping what follows them such as an OPT record, so that the
+/// records given may compress names against the question.
+pub(crate) fn start_server(answer: impl Fn(&Query) -> Answer + Send + 'static) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut buf: [u8; 512] = [0; 512];
+        while let Ok((length, client)) = socket.recv_from(&mut buf) {
+            let Ok((question_length, question)) = DNSQuestion::parse_question_from_response(&buf[..length], 12) else {
+                continue;
+            };
+            let query = Query {
+                name: DomainName::from_labels(&question.query_name).unwrap().to_string(),
+                query_type: question.query_type,
+                recursion_desired: buf[2] & 1 == 1,
+            };
+            let answer = answer(&query);
+            let mut message = buf[..12 + question_length].to_vec();
+            message[2] |= 0b1000_0000;
+            if answer.authoritative {
+                message[2] |= 0b100;
+            }
+            message[3] = answer.response_code;
+            message[4..6].copy_from_slice(&1u16.to_be_bytes());
+            message[6..8].copy_from_slice(&(answer.answers.len() as u16).to_be_bytes());
+            message[8..10].copy_from_slice(&(answer.authority.len() as u16).to_be_bytes());
+            message[10..12].copy_from_slice(&(answer.additional.len() as u16).to_be_bytes());
+            message.extend([answer.answers, answer.authority, answer.additional].concat().concat());
+            let _ = socket.send_to(&message, client);
+        }
+    });
+    address
+}
+
+/// Resolver asking only `server`, without hosts file
+pub(crate) fn test_resolver(server: SocketAddr) -> Resolver {
+    Resolver::new(ResolverConfig {
+        nameservers: vec![server],
+        timeout: Duration::from_millis(500),
+        hosts_file: None,
+        ..ResolverConfig::default()
+    })
+}
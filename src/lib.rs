@@ -1,41 +1,195 @@
 pub mod dns_util;
 
-use std::net::UdpSocket;
-use std::time::Duration;
-use rand::{self, Rng};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 pub use crate::dns_util::dns_packet_structures::{
     dns_header::DNSHeader,
     dns_resource_record::DNSResourceRecord,
     dns_question::DNSQuestion,
-    dns_packet::DNSPacket
+    dns_packet::DNSPacket,
+    domain_name::DomainName,
+    record_data::RecordData,
+    srv_record::SRVRecord,
+    mx_record::MXRecord,
+    svcb_record::{SVCBRecord, HTTPSRecord, SvcParam}
 };
+pub use crate::dns_util::dns_transports::{
+    DNSTransport,
+    ToNameServer,
+    DNS_PORT,
+    udp::UDPTransport,
+    tcp::TCPTransport,
+    tls::{TLSTransport, TLSOptions, DNS_OVER_TLS_PORT},
+    https::{HTTPSTransport, HTTPMethod}
+};
+pub use crate::dns_util::dns_resolver::{Resolver, ResolverConfig, ServerSelection, LookupSource, hosts_file::HostsFile};
+pub use crate::dns_util::dns_lookup::{IpLookup, cname_chain::ResolvedChain, srv_lookup::{SRVTargets, order_srv_records}, mx_lookup::{MailExchange, MailExchanges}, svcb_lookup::ServiceBindings};
+pub use crate::dns_util::dns_iterative::{IterativeResolver, RootHint, ROOT_HINTS};
+pub use crate::dns_util::dns_cache::DNSCache;
+pub use crate::dns_util::dns_mail::{MailResolver, TagError, spf::{SPFResult, check_host}, dkim::{DKIMKey, lookup_dkim_key}, dmarc::{DMARCRecord, DMARCPolicy, DMARCAlignment, DMARCLookup, lookup_dmarc}, mta_sts::{MTASTSRecord, lookup_mta_sts}, tls_rpt::{TLSRPTRecord, lookup_tls_rpt}};
+pub use crate::dns_util::dns_dnssec::{DNSKeyRecord, RRSIGRecord, DSRecord, NSECRecord, NSEC3Record, NSEC3ParamRecord, nsec3_hash, TrustAnchors, AnchorTracker, AnchorState, TrackedKey, Validator, ValidationStatus, ValidatedResponse, SigningKey, KeyFile, KeyTimings, KeyManager, KeyPolicy, KeyEvent, ZoneSigner, DenialOfExistence, zone_file};
+#[cfg(feature = "quic")]
+pub use crate::dns_util::dns_transports::quic::{QUICTransport, DNS_OVER_QUIC_PORT};
+#[cfg(feature = "tokio")]
+pub use crate::dns_util::dns_transports::asynchronous::{AsyncDNSTransport, AsyncUDPTransport, AsyncTCPTransport};
 
+/// First IPv4 address of `domain`, `(0, 0, 0, 0)` if there is none. Use
+/// `lookup_ipv4` to get all of them and the errors.
 pub fn resolve_ipv4(domain: &str) -> (u8, u8, u8, u8) {
-    let response = make_dns_request(domain, "A", "1.1.1.1");
-    println!("{:?}", response);
-    (0,0,0,0)
+    let [a, b, c, d] = lookup_ipv4(domain).ok()
+        .and_then(|addresses| addresses.first().copied())
+        .unwrap_or(Ipv4Addr::UNSPECIFIED)
+        .octets();
+    (a, b, c, d)
+}
+
+/// IPv4 addresses of `domain`, using the nameservers of the system
+pub fn lookup_ipv4(domain: &str) -> io::Result<Vec<Ipv4Addr>> {
+    Resolver::new(ResolverConfig::system()).lookup_ipv4(domain)
+}
+
+/// IPv6 addresses of `domain`, using the nameservers of the system
+pub fn lookup_ipv6(domain: &str) -> io::Result<Vec<Ipv6Addr>> {
+    Resolver::new(ResolverConfig::system()).lookup_ipv6(domain)
+}
+
+/// IPv4 and IPv6 addresses of `domain` in order of preference, using the
+/// nameservers of the system
+pub fn lookup_ip(domain: &str) -> io::Result<IpLookup> {
+    Resolver::new(ResolverConfig::system()).lookup_ip(domain)
+}
+
+/// Host names of `address` from its PTR records, using the nameservers of
+/// the system
+pub fn reverse_lookup(address: IpAddr) -> io::Result<Vec<DomainName>> {
+    Resolver::new(ResolverConfig::system()).reverse_lookup(address)
+}
+
+/// Host names of `address` that resolve back to it, using the nameservers
+/// of the system
+pub fn reverse_lookup_confirmed(address: IpAddr) -> io::Result<Vec<DomainName>> {
+    Resolver::new(ResolverConfig::system()).reverse_lookup_confirmed(address)
+}
+
+/// SRV records of `_service._proto.domain`, using the nameservers of the
+/// system
+pub fn lookup_srv(service: &str, proto: &str, domain: &str) -> io::Result<Vec<SRVRecord>> {
+    Resolver::new(ResolverConfig::system()).lookup_srv(service, proto, domain)
+}
+
+/// MX records of `domain` in order of preference, using the nameservers of
+/// the system
+pub fn lookup_mx(domain: &str) -> io::Result<Vec<MXRecord>> {
+    Resolver::new(ResolverConfig::system()).lookup_mx(domain)
+}
+
+/// Endpoints of the HTTPS origin `domain` from its HTTPS records, using the
+/// nameservers of the system
+pub fn lookup_https(domain: &str) -> io::Result<ServiceBindings> {
+    Resolver::new(ResolverConfig::system()).lookup_https(domain)
+}
+
+/// SPF result for the client at `ip` sending mail for `domain` with the
+/// `MAIL FROM` address `sender`, using the nameservers of the system
+pub fn check_spf(ip: IpAddr, domain: &str, sender: &str) -> SPFResult {
+    check_host(&Resolver::new(ResolverConfig::system()), ip, domain, sender)
 }
 
-pub fn make_dns_request(domain: &str, query_type: &str, dns_server: &str) -> DNSPacket {
-    let mut rng = rand::thread_rng();
-    
+/// Queries `dns_server` over UDP. The server is given as an address with an
+/// optional port (53 by default), such as `"1.1.1.1"`, `"[2606:4700:4700::1111]:53"`,
+/// `"fe80::1%eth0"`, or as an `IpAddr` or `SocketAddr`. The domain is sent
+/// as is, the lookup functions complete short names with the search list.
+pub fn make_dns_request<S: ToNameServer>(domain: &str, query_type: &str, dns_server: S) -> DNSPacket {
+    let server = dns_server.to_name_server()
+        .expect("couldn't resolve the server address");
+
+    make_dns_request_with_transport(domain, query_type, &UDPTransport::new(server))
+        .expect("Could not exchange with the server")
+}
+
+/// Same as `make_dns_request`, answering from `cache` when it can and
+/// storing the response of the server in it
+pub fn make_dns_request_cached<S: ToNameServer>(domain: &str, query_type: &str, dns_server: S, cache: &DNSCache) -> DNSPacket {
+    if let Some(response) = cache.lookup(domain, query_type) {
+        return response;
+    }
+    let response = make_dns_request(domain, query_type, dns_server);
+    cache.insert(&response);
+    response
+}
+
+/// Same as `make_dns_request`, the query being carried by `transport`
+pub fn make_dns_request_with_transport(domain: &str, query_type: &str, transport: &dyn DNSTransport) -> io::Result<DNSPacket> {
     let packet = DNSPacket::create_query_packet(vec![domain], query_type);
-    let socket = UdpSocket::bind(("0.0.0.0", rng.gen_range(1024..65535)))
-        .expect("couldn't bind to address");
+    send_dns_query(&packet, transport)
+}
 
-    let _ = socket.set_write_timeout(Some(Duration::new(5, 0)));
-    let _ = socket.set_read_timeout(Some(Duration::new(5, 0)));
-    
-    socket.send_to(&packet.prepare(), (dns_server, 53))
-        .expect("Could not send");
+/// Sends an already built query through `transport` and parses the response
+pub fn send_dns_query(query: &DNSPacket, transport: &dyn DNSTransport) -> io::Result<DNSPacket> {
+    let response = transport.exchange(&query.prepare())?;
+    parse_response_to(query, response)
+}
 
-    let mut buf: [u8; 1232] = [0; 1232];
+/// Asynchronous version of `resolve_ipv4`, giving the first IPv4 address of
+/// `domain` or `(0, 0, 0, 0)` if there is none. The search list and the
+/// nameservers of the system are used as by `resolve_ipv4`, but not the
+/// hosts file.
+#[cfg(feature = "tokio")]
+pub async fn resolve_ipv4_async(domain: &str) -> (u8, u8, u8, u8) {
+    // Reading the configuration blocks on the file system
+    let Ok(config) = tokio::task::spawn_blocking(ResolverConfig::system).await else {
+        return (0, 0, 0, 0);
+    };
+    let resolver = Resolver::new(config);
+    for name in resolver.config.qualified_names(domain) {
+        let Ok(response) = resolver.query_async(&name, "A").await else {
+            break;
+        };
+        if let Some(IpAddr::V4(address)) = dns_util::dns_lookup::addresses_of(&response).into_iter().find(IpAddr::is_ipv4) {
+            let [a, b, c, d] = address.octets();
+            return (a, b, c, d);
+        }
+    }
+    (0, 0, 0, 0)
+}
 
-    socket.recv_from(&mut buf)
-        .expect("Could not receive");
-    println!("{:?}", buf);
-    DNSPacket::parse_response(buf.to_vec())
+/// Asynchronous version of `make_dns_request`. The request is cancelled when
+/// the future is dropped, and fails after 5 seconds without response. A
+/// server given by hostname is resolved without blocking the runtime.
+#[cfg(feature = "tokio")]
+pub async fn make_dns_request_async<S: ToNameServer>(domain: &str, query_type: &str, dns_server: S) -> io::Result<DNSPacket> {
+    let server = match dns_server.host_name() {
+        Some(host) => tokio::net::lookup_host((host, DNS_PORT)).await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("couldn't resolve the server address {}", host)))?,
+        None => dns_server.to_name_server()?,
+    };
+
+    make_dns_request_with_transport_async(domain, query_type, &AsyncUDPTransport::new(server)).await
+}
+
+/// Asynchronous version of `make_dns_request_with_transport`
+#[cfg(feature = "tokio")]
+pub async fn make_dns_request_with_transport_async(domain: &str, query_type: &str, transport: &impl AsyncDNSTransport) -> io::Result<DNSPacket> {
+    let packet = DNSPacket::create_query_packet(vec![domain], query_type);
+    send_dns_query_async(&packet, transport).await
+}
+
+/// Asynchronous version of `send_dns_query`
+#[cfg(feature = "tokio")]
+pub async fn send_dns_query_async(query: &DNSPacket, transport: &impl AsyncDNSTransport) -> io::Result<DNSPacket> {
+    let response = transport.exchange(&query.prepare()).await?;
+    parse_response_to(query, response)
+}
+
+/// Parses `response` after checking that it answers `query`
+fn parse_response_to(query: &DNSPacket, response: Vec<u8>) -> io::Result<DNSPacket> {
+    let response = DNSPacket::parse_response(response)?;
+    if response.header.query_id != query.header.query_id {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "response ID does not match the query"));
+    }
+    Ok(response)
 }
 
 #[cfg(test)]